@@ -0,0 +1,62 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Build script which records the git commit that the controller was built from, so that every
+//! log can be traced back to the exact build which produced it.
+
+use std::process::Command;
+
+/// Run git with `args`, returning its trimmed output if it succeeds.
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+}
+
+fn main() {
+    // a build outside of a git checkout (such as from a source archive) has no known commit
+    let commit = git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map_or(false, |output| !output.stdout.is_empty());
+
+    println!(
+        "cargo:rustc-env=SLONK_GIT_COMMIT={commit}{}",
+        if dirty { "-dirty" } else { "" }
+    );
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    // a new commit on the current branch moves the branch's ref, not HEAD
+    if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+        if let Some(ref_path) = git(&["rev-parse", "--git-path", &branch]) {
+            println!("cargo:rerun-if-changed={ref_path}");
+        }
+    }
+    // refs are moved into this file when git packs them
+    println!("cargo:rerun-if-changed=.git/packed-refs");
+    // naming any path disables cargo's default of rerunning on every change, so a change to the
+    // sources must be named too, or a dirty build would keep a stale dirty flag
+    println!("cargo:rerun-if-changed=src");
+}
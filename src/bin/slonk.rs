@@ -16,7 +16,9 @@
   along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use slonk::{server::RaspberryPi, ControllerError};
+use std::process::ExitCode;
+
+use slonk::server::RaspberryPi;
 
 /// The main function for the `slonk` controller.
 ///
@@ -24,10 +26,24 @@ use slonk::{server::RaspberryPi, ControllerError};
 ///
 /// The first argument to this executable (via `std::env::args`) is the path to a configuration JSON
 /// file, formatted according to the specification in `api.md`.
+/// If it is `-`, the configuration is read from standard input instead.
+/// If only one argument is given, the configuration JSON is read from the `SLONK_CONFIG`
+/// environment variable, and that argument is the path to the logs directory.
 ///
 /// The second argument to this executable is a path to a directory where log files should be
 /// created.
 /// If the directory does not exist, it will be created.
-fn main() -> Result<(), ControllerError> {
-    slonk::server::run::<RaspberryPi>()
+///
+/// # Exit status
+///
+/// If the controller stops due to an error, the error is printed to standard error and the
+/// executable exits with a failure status.
+fn main() -> ExitCode {
+    match slonk::server::run::<RaspberryPi>() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("slonk: {e}");
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,952 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! A compact binary format for sensor logs.
+//!
+//! At high sample rates, CSV logs with a full timestamp on every row are very wasteful.
+//! A binary log instead consists of a short header followed by a stream of fixed-width records.
+//!
+//! The header is laid out as follows, with all numbers in little-endian order:
+//!
+//! 1. The 8 magic bytes `SLONKBIN`.
+//! 1. The format version, as a single byte.
+//! 1. The start time of the log, in nanoseconds since the UNIX epoch, as a `u64`.
+//! 1. The kind of calibration of the sensor, as a single byte: 0 for a linear calibration, or 1
+//!    for a thermistor.
+//! 1. For a linear calibration, the slope per raw reading and then the intercept, each as an
+//!    `f64`.
+//!    For a thermistor, the series resistance, the reference voltage, and the Steinhart-Hart
+//!    coefficients `A`, `B`, and `C`, each as an `f64`.
+//! 1. The CRC-32 checksum of all of the above, as a `u32`.
+//!
+//! Each record then begins with its kind, as a single byte.
+//! A record of a reading has kind 0 if the reading was truly read, or 1 if it was injected, and is
+//! laid out as follows:
+//!
+//! 1. The kind of the record.
+//! 1. The time of the reading, in nanoseconds since the UNIX epoch, as a `u64`.
+//! 1. The raw reading, as a `u16`.
+//! 1. The CRC-32 checksum of the kind, time, and reading, as a `u32`.
+//!
+//! A record of kind 2 marks a change in the calibration of the sensor, such as when it is
+//! recalibrated or the configuration is reloaded, and applies to every reading after it.
+//! It is laid out as follows:
+//!
+//! 1. The kind of the record.
+//! 1. The time of the change, in nanoseconds since the UNIX epoch, as a `u64`.
+//! 1. The new calibration, laid out as it is in the header.
+//! 1. The CRC-32 checksum of all of the above, as a `u32`.
+//!
+//! A record of kind 3 is a marker, such as the start or end of a pause in logging, and is decoded
+//! as a marker row.
+//! It is laid out as follows:
+//!
+//! 1. The kind of the record.
+//! 1. The time of the marker, in nanoseconds since the UNIX epoch, as a `u64`.
+//! 1. The length of the marker's text in bytes, as a single byte.
+//! 1. The marker's text, in UTF-8.
+//! 1. The CRC-32 checksum of all of the above, as a `u32`.
+//!
+//! The checksums let the decoder detect records corrupted on disk (for instance, by a bit flip on
+//! an SD card).
+//! Since every record carries its own full timestamp, a corrupted record can be dropped without
+//! affecting the records around it.
+//!
+//! Version 1 of the format had no checksums, and stored the time of each record as the time since
+//! the previous record.
+//! Versions 1 and 2 could only describe a linear calibration, and stored its slope and intercept
+//! before the start time.
+//! Versions 1 through 3 had no record kinds, and instead marked an injected reading by setting the
+//! highest bit of its raw reading, so they could not record raw readings of 32768 or more.
+//! Logs in versions 1 through 3 can still be decoded.
+
+use std::{
+    fmt::Display,
+    io::{self, Read, Write},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    config::{Sensor, Thermistor},
+    data::{write_sensor_log, write_sensor_marker},
+    reload::SensorTuning,
+};
+
+/// The magic bytes at the start of every binary log.
+const MAGIC: [u8; 8] = *b"SLONKBIN";
+
+/// The version of the binary log format written by this module.
+const VERSION: u8 = 4;
+
+/// The length of a header in versions 1 and 2 of the format, excluding its checksum.
+const LINEAR_HEADER_LEN: usize = 33;
+
+/// The length of the magic bytes, version, and start time which begin every header.
+const HEADER_PREFIX_LEN: usize = 17;
+
+/// The byte marking a linear calibration in a header.
+const LINEAR_KIND: u8 = 0;
+
+/// The byte marking a thermistor calibration in a header.
+const THERMISTOR_KIND: u8 = 1;
+
+/// The length of a record, excluding its checksum.
+const RECORD_LEN: usize = 11;
+
+/// The length of a record in versions 1 through 3 of the format, excluding its checksum.
+const UNKINDED_RECORD_LEN: usize = 10;
+
+/// The length of a CRC-32 checksum.
+const CRC_LEN: usize = 4;
+
+/// The lookup table for computing CRC-32 checksums one byte at a time.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// The kind of a record of a reading which was truly read.
+const READ_KIND: u8 = 0;
+
+/// The kind of a record of a reading which was injected.
+const INJECTED_KIND: u8 = 1;
+
+/// The kind of a record of a change in calibration.
+const CALIBRATION_KIND: u8 = 2;
+
+/// The kind of a record of a marker.
+const MARKER_KIND: u8 = 3;
+
+/// The bit of a record's reading which marked that the reading was injected, in versions 1 through
+/// 3 of the format.
+const INJECTED_BIT: u16 = 1 << 15;
+
+#[derive(Debug)]
+/// The errors which can occur while decoding a binary log.
+pub enum Error {
+    /// The log did not begin with the magic bytes, so it is probably not a binary log.
+    BadMagic,
+    /// The log was written with a version of the format that we cannot decode.
+    UnsupportedVersion(u8),
+    /// The log ended partway through a header or record.
+    /// The value inside is the byte offset of the incomplete record.
+    Truncated(u64),
+    /// The header of the log failed its checksum, so the calibration of the log is unknown.
+    CorruptHeader,
+    /// The header of the log named a kind of calibration that we cannot decode.
+    /// The value inside is the byte marking the kind.
+    UnknownCalibration(u8),
+    /// A record of the log was of a kind that we cannot decode.
+    /// The values inside are the byte offset of the record and the byte marking its kind.
+    UnknownRecord(u64, u8),
+    /// Some records failed their checksums, and were left out of the decoded output.
+    /// Every other record was still decoded.
+    /// The value inside is the byte offset of each corrupted record.
+    CorruptRecords(Vec<u64>),
+    /// An I/O error occurred while reading the log or writing the decoded output.
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The calibration of a sensor, as recorded in the header of its binary log.
+enum Calibration {
+    /// A linear calibration of the raw reading.
+    Linear {
+        /// The slope of the calibration per raw reading.
+        slope: f64,
+        /// The intercept of the calibration.
+        intercept: f64,
+    },
+    /// A thermistor, whose readings are converted into degrees Celsius.
+    Thermistor(Thermistor),
+}
+
+impl Calibration {
+    /// Get the calibration which is applied to the readings of `sensor` when it has the tuning
+    /// `tuning`.
+    fn of(sensor: &Sensor, tuning: &SensorTuning) -> Calibration {
+        match sensor.thermistor {
+            Some(thermistor) => Calibration::Thermistor(thermistor),
+            // the decoder applies the calibration to raw readings, even if the sensor calibrates
+            // volts
+            None => Calibration::Linear {
+                slope: tuning.calibration_slope * sensor.calibration_input(1),
+                intercept: tuning.calibration_intercept,
+            },
+        }
+    }
+
+    /// Append the kind of this calibration and its parameters to `bytes`.
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let (kind, params) = match self {
+            Calibration::Linear { slope, intercept } => (LINEAR_KIND, vec![*slope, *intercept]),
+            Calibration::Thermistor(thermistor) => (
+                THERMISTOR_KIND,
+                vec![
+                    thermistor.series_resistance,
+                    thermistor.reference_voltage,
+                    thermistor.a,
+                    thermistor.b,
+                    thermistor.c,
+                ],
+            ),
+        };
+        bytes.push(kind);
+        for param in params {
+            bytes.extend_from_slice(&param.to_le_bytes());
+        }
+    }
+
+    /// Get the number of parameters of a calibration of kind `kind`.
+    fn param_count(kind: u8) -> Result<usize, Error> {
+        match kind {
+            LINEAR_KIND => Ok(2),
+            THERMISTOR_KIND => Ok(5),
+            _ => Err(Error::UnknownCalibration(kind)),
+        }
+    }
+
+    /// Construct a calibration of kind `kind` from its parameters.
+    fn decode(kind: u8, params: &[f64]) -> Result<Calibration, Error> {
+        match (kind, params) {
+            (LINEAR_KIND, &[slope, intercept]) => Ok(Calibration::Linear { slope, intercept }),
+            (THERMISTOR_KIND, &[series_resistance, reference_voltage, a, b, c]) => {
+                Ok(Calibration::Thermistor(Thermistor {
+                    series_resistance,
+                    reference_voltage,
+                    a,
+                    b,
+                    c,
+                }))
+            }
+            _ => Err(Error::UnknownCalibration(kind)),
+        }
+    }
+
+    /// Calibrate the raw reading `reading`.
+    fn apply(&self, reading: u16) -> f64 {
+        match self {
+            Calibration::Linear { slope, intercept } => f64::from(reading) * slope + intercept,
+            Calibration::Thermistor(thermistor) => thermistor.temperature(reading),
+        }
+    }
+}
+
+/// Write the header of a binary log for `sensor`, whose current tuning is `tuning`.
+///
+/// # Errors
+///
+/// This function will return an error if writing to `log_file` fails.
+///
+/// # Panics
+///
+/// This function will panic if `start` is before the UNIX epoch.
+pub fn write_header(
+    log_file: &mut impl Write,
+    sensor: &Sensor,
+    tuning: &SensorTuning,
+    start: SystemTime,
+) -> io::Result<()> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC);
+    header.push(VERSION);
+    header.extend_from_slice(&nanos_since_epoch(start).to_le_bytes());
+    Calibration::of(sensor, tuning).encode(&mut header);
+    header.extend_from_slice(&crc32(&header).to_le_bytes());
+    log_file.write_all(&header)?;
+
+    log_file.flush()
+}
+
+/// Write a sequence of readings as records to a binary log.
+/// The header of `log_file` must already have been written with `write_header`.
+///
+/// # Errors
+///
+/// This function will return an error if writing to `log_file` fails.
+///
+/// # Panics
+///
+/// This function will panic if the time of a reading is before the UNIX epoch.
+pub fn write_records<'a>(
+    log_file: &mut impl Write,
+    adc_readings: impl IntoIterator<Item = &'a (SystemTime, u16, f64, bool)>,
+) -> io::Result<()> {
+    for &(sys_time, reading, _, injected) in adc_readings {
+        let mut record = [0; RECORD_LEN + CRC_LEN];
+        record[0] = if injected { INJECTED_KIND } else { READ_KIND };
+        record[1..9].copy_from_slice(&nanos_since_epoch(sys_time).to_le_bytes());
+        record[9..RECORD_LEN].copy_from_slice(&reading.to_le_bytes());
+        let crc = crc32(&record[..RECORD_LEN]);
+        record[RECORD_LEN..].copy_from_slice(&crc.to_le_bytes());
+        log_file.write_all(&record)?;
+    }
+
+    log_file.flush()
+}
+
+/// Record in a binary log that the tuning of `sensor` changed to `tuning` at time `time`, so that
+/// every reading written after it is decoded with the new calibration.
+/// The header of `log_file` must already have been written with `write_header`.
+///
+/// # Errors
+///
+/// This function will return an error if writing to `log_file` fails.
+///
+/// # Panics
+///
+/// This function will panic if `time` is before the UNIX epoch.
+pub fn write_calibration(
+    log_file: &mut impl Write,
+    sensor: &Sensor,
+    tuning: &SensorTuning,
+    time: SystemTime,
+) -> io::Result<()> {
+    let mut record = vec![CALIBRATION_KIND];
+    record.extend_from_slice(&nanos_since_epoch(time).to_le_bytes());
+    Calibration::of(sensor, tuning).encode(&mut record);
+    record.extend_from_slice(&crc32(&record).to_le_bytes());
+    log_file.write_all(&record)?;
+
+    log_file.flush()
+}
+
+/// Record in a binary log that `marker` happened at time `time`.
+/// The marker is decoded as a marker row, exactly as `write_sensor_marker` writes it to a CSV log.
+/// The header of `log_file` must already have been written with `write_header`.
+///
+/// # Errors
+///
+/// This function will return an error if `marker` is longer than 255 bytes, or if writing to
+/// `log_file` fails.
+///
+/// # Panics
+///
+/// This function will panic if `time` is before the UNIX epoch.
+pub fn write_marker(log_file: &mut impl Write, time: SystemTime, marker: &str) -> io::Result<()> {
+    let marker_len = u8::try_from(marker.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "marker is too long"))?;
+    let mut record = vec![MARKER_KIND];
+    record.extend_from_slice(&nanos_since_epoch(time).to_le_bytes());
+    record.push(marker_len);
+    record.extend_from_slice(marker.as_bytes());
+    record.extend_from_slice(&crc32(&record).to_le_bytes());
+    log_file.write_all(&record)?;
+
+    log_file.flush()
+}
+
+#[allow(clippy::too_many_lines)]
+/// Decode a binary log from `src` and write it as CSV to `dst`.
+///
+/// The CSV output is exactly the same as what would have been written had the log been recorded
+/// in CSV format.
+/// Records which fail their checksums are left out of the output, but do not stop the rest of the
+/// log from being decoded.
+///
+/// # Errors
+///
+/// This function will return an error if `src` is not a valid binary log, or if an I/O error
+/// occurs.
+/// If any records were corrupted, this function will return `Error::CorruptRecords` once every
+/// other record has been written to `dst`.
+pub fn decode(src: &mut impl Read, dst: &mut impl Write) -> Result<(), Error> {
+    let mut header = vec![0; 9];
+    read_exact_or_truncated(src, &mut header, 0)?;
+    if header[..8] != MAGIC {
+        return Err(Error::BadMagic);
+    }
+    let version = header[8];
+    // version 1 logs have no checksums
+    let crc_len = match version {
+        1 => 0,
+        2..=VERSION => CRC_LEN,
+        _ => return Err(Error::UnsupportedVersion(version)),
+    };
+    let (mut calibration, start) = if version < 3 {
+        header.resize(LINEAR_HEADER_LEN + crc_len, 0);
+        read_exact_or_truncated(src, &mut header[9..], 0)?;
+        if !checksum_matches(&header, crc_len) {
+            return Err(Error::CorruptHeader);
+        }
+        let calibration = Calibration::Linear {
+            slope: f64_at(&header, 9),
+            intercept: f64_at(&header, 17),
+        };
+        (calibration, u64_at(&header, 25))
+    } else {
+        header.resize(HEADER_PREFIX_LEN + 1, 0);
+        read_exact_or_truncated(src, &mut header[9..], 0)?;
+        let calibration = read_calibration(src, &mut header, 0)?.ok_or(Error::CorruptHeader)?;
+        (calibration, u64_at(&header, 9))
+    };
+    let start = SystemTime::UNIX_EPOCH + Duration::from_nanos(start);
+
+    let mut time = start;
+    let mut offset = header.len() as u64;
+    // records before version 4 have no kind
+    let record_len = if version < 4 {
+        UNKINDED_RECORD_LEN
+    } else {
+        RECORD_LEN
+    };
+    let mut record = [0; RECORD_LEN + CRC_LEN];
+    let record = &mut record[..record_len + crc_len];
+    let mut corrupted = Vec::new();
+    loop {
+        // check for a clean end of file before reading the next record
+        let n_read = src.read(&mut record[..1])?;
+        if n_read == 0 {
+            break;
+        }
+        if version >= 4 && record[0] == CALIBRATION_KIND {
+            // a change in calibration has no time of its own in the decoded output
+            let mut change = vec![CALIBRATION_KIND; 10];
+            read_exact_or_truncated(src, &mut change[1..], offset)?;
+            match read_calibration(src, &mut change, offset)? {
+                Some(new_calibration) => calibration = new_calibration,
+                None => corrupted.push(offset),
+            }
+            offset += change.len() as u64;
+            continue;
+        }
+        if version >= 4 && record[0] == MARKER_KIND {
+            let mut marker = vec![MARKER_KIND; 10];
+            read_exact_or_truncated(src, &mut marker[1..], offset)?;
+            marker.resize(10 + usize::from(marker[9]) + CRC_LEN, 0);
+            read_exact_or_truncated(src, &mut marker[10..], offset)?;
+            match std::str::from_utf8(&marker[10..marker.len() - CRC_LEN]) {
+                Ok(text) if checksum_matches(&marker, CRC_LEN) => {
+                    let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(u64_at(&marker, 1));
+                    write_sensor_marker(dst, time, text)?;
+                }
+                _ => corrupted.push(offset),
+            }
+            offset += marker.len() as u64;
+            continue;
+        }
+        read_exact_or_truncated(src, &mut record[1..], offset)?;
+        if !checksum_matches(record, crc_len) {
+            corrupted.push(offset);
+            offset += record.len() as u64;
+            continue;
+        }
+
+        let (kind, body) = if version < 4 {
+            (None, &record[..])
+        } else {
+            (Some(record[0]), &record[1..])
+        };
+        let stamp = Duration::from_nanos(u64_at(body, 0));
+        time = if version == 1 {
+            // version 1 stores the time since the previous record
+            time + stamp
+        } else {
+            SystemTime::UNIX_EPOCH + stamp
+        };
+        let raw = u16::from_le_bytes(body[8..10].try_into().unwrap());
+        let (reading, injected) = match kind {
+            None => (raw & !INJECTED_BIT, raw & INJECTED_BIT != 0),
+            Some(READ_KIND) => (raw, false),
+            Some(INJECTED_KIND) => (raw, true),
+            Some(kind) => return Err(Error::UnknownRecord(offset, kind)),
+        };
+
+        write_sensor_log(
+            dst,
+            [&(time, reading, calibration.apply(reading), injected)],
+        )?;
+        offset += record.len() as u64;
+    }
+
+    if corrupted.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::CorruptRecords(corrupted))
+    }
+}
+
+/// Read the parameters of a calibration, and then a checksum, from `src` onto the end of `bytes`,
+/// whose last byte is the kind of the calibration.
+/// The bytes being read begin at byte offset `offset`.
+/// Returns `None` if the checksum of `bytes` does not match.
+///
+/// # Errors
+///
+/// This function will return an error if the kind of the calibration is unknown, or if reading
+/// from `src` fails.
+fn read_calibration(
+    src: &mut impl Read,
+    bytes: &mut Vec<u8>,
+    offset: u64,
+) -> Result<Option<Calibration>, Error> {
+    let kind = bytes[bytes.len() - 1];
+    let params_start = bytes.len();
+    let param_count = Calibration::param_count(kind)?;
+    bytes.resize(params_start + 8 * param_count + CRC_LEN, 0);
+    read_exact_or_truncated(src, &mut bytes[params_start..], offset)?;
+    if !checksum_matches(bytes, CRC_LEN) {
+        return Ok(None);
+    }
+    let params: Vec<f64> = (0..param_count)
+        .map(|i| f64_at(bytes, params_start + 8 * i))
+        .collect();
+    Calibration::decode(kind, &params).map(Some)
+}
+
+/// Determine whether the last `crc_len` bytes of `bytes` are the CRC-32 checksum of the rest.
+/// If `crc_len` is zero, there is no checksum, and `bytes` is assumed to be intact.
+fn checksum_matches(bytes: &[u8], crc_len: usize) -> bool {
+    if crc_len == 0 {
+        return true;
+    }
+    let (body, crc) = bytes.split_at(bytes.len() - crc_len);
+    crc32(body).to_le_bytes() == crc
+}
+
+/// Compute the CRC-32 checksum (as used by Ethernet, zlib, and PNG) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (crc >> 8) ^ CRC_TABLE[usize::from(crc.to_le_bytes()[0] ^ byte)]
+    })
+}
+
+#[allow(clippy::cast_possible_truncation)]
+/// Construct the lookup table for the reflected CRC-32 polynomial `0xEDB88320`.
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < table.len() {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xEDB8_8320
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Read the little-endian `f64` at byte offset `offset` of `bytes`.
+fn f64_at(bytes: &[u8], offset: usize) -> f64 {
+    f64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Read the little-endian `u64` at byte offset `offset` of `bytes`.
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Fill `buf` from `src`, converting an early end of file into `Error::Truncated` at `offset`.
+fn read_exact_or_truncated(src: &mut impl Read, buf: &mut [u8], offset: u64) -> Result<(), Error> {
+    src.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::Truncated(offset),
+        _ => Error::Io(e),
+    })
+}
+
+/// Get the number of nanoseconds since the UNIX epoch of `time`.
+///
+/// # Panics
+///
+/// This function will panic if `time` is before the UNIX epoch.
+fn nanos_since_epoch(time: SystemTime) -> u64 {
+    saturating_nanos(time.duration_since(SystemTime::UNIX_EPOCH).unwrap())
+}
+
+/// Convert a duration to a number of nanoseconds, saturating at `u64::MAX`.
+fn saturating_nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BadMagic => write!(f, "File is not a slonk binary log"),
+            Error::UnsupportedVersion(v) => write!(f, "Unsupported binary log version {v}"),
+            Error::Truncated(offset) => write!(f, "Log is truncated at byte offset {offset}"),
+            Error::CorruptHeader => write!(f, "Log header is corrupted"),
+            Error::UnknownCalibration(kind) => {
+                write!(f, "Log header has unknown calibration kind {kind}")
+            }
+            Error::UnknownRecord(offset, kind) => write!(
+                f,
+                "Log has a record of unknown kind {kind} at byte offset {offset}"
+            ),
+            Error::CorruptRecords(offsets) => write!(
+                f,
+                "{} corrupted record(s) were left out, at byte offset(s) {offsets:?}",
+                offsets.len()
+            ),
+            Error::Io(e) => write!(f, "I/O error while decoding log: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Construct a sensor with some calibration for testing.
+    fn sensor() -> Sensor {
+        Sensor {
+            label: "PT_FEED".into(),
+            color: "#ef3b9e".into(),
+            units: "psi".into(),
+            range: None,
+            calibration_intercept: 92.3,
+            calibration_slope: -302.4,
+            rolling_average_width: None,
+            full_scale_min: None,
+            full_scale_max: None,
+            display_min: None,
+            display_max: None,
+            oversample: None,
+            discard_first: 0,
+            log_raw: false,
+            vref: None,
+            iio_channel: None,
+            iio_scale: None,
+            thermistor: None,
+            abort_action: None,
+            simulation: None,
+            adc: 0,
+            channel: 1,
+        }
+    }
+
+    /// Construct the tuning of the sensor from `sensor`.
+    fn tuning() -> SensorTuning {
+        SensorTuning::from(&sensor())
+    }
+
+    /// Construct a sequence of readings, sampled at 10 kHz.
+    fn readings(count: u16) -> Vec<(SystemTime, u16, f64, bool)> {
+        let sensor = sensor();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_651_355_351);
+        (0..count)
+            .map(|i| {
+                let reading = i.wrapping_mul(7) % 4096;
+                (
+                    start + Duration::from_micros(100) * u32::from(i),
+                    reading,
+                    f64::from(reading) * sensor.calibration_slope + sensor.calibration_intercept,
+                    i == 3,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Test that decoding a binary log gives exactly the same result as logging in CSV.
+    fn round_trip() {
+        let readings = readings(100);
+        let start = readings[0].0;
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &readings).unwrap();
+
+        let mut binary = Vec::new();
+        write_header(&mut binary, &sensor(), &tuning(), start).unwrap();
+        write_records(&mut binary, &readings[..50]).unwrap();
+        write_records(&mut binary, &readings[50..]).unwrap();
+
+        let mut decoded = Vec::new();
+        decode(&mut Cursor::new(binary), &mut decoded).unwrap();
+
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    /// Measure the reduction in file size from using a binary log over a CSV log.
+    fn size_reduction() {
+        let readings = readings(10_000);
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &readings).unwrap();
+
+        let mut binary = Vec::new();
+        write_header(&mut binary, &sensor(), &tuning(), readings[0].0).unwrap();
+        write_records(&mut binary, &readings).unwrap();
+
+        println!(
+            "CSV log: {} bytes, binary log: {} bytes ({:.1}x smaller)",
+            csv.len(),
+            binary.len(),
+            csv.len() as f64 / binary.len() as f64
+        );
+        assert_eq!(binary.len(), 38 + 15 * readings.len());
+        assert!(binary.len() * 2 < csv.len());
+    }
+
+    #[test]
+    /// Test that raw readings which use every bit of a `u16`, as IIO channels may return, are
+    /// never confused with injected readings.
+    fn full_width_readings() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_651_355_351);
+        let readings: Vec<_> = [
+            (40_000, false),
+            (40_000, true),
+            (7, true),
+            (u16::MAX, false),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (reading, injected))| {
+            (
+                start + Duration::from_millis(10) * u32::try_from(i).unwrap(),
+                reading,
+                f64::from(reading) * sensor().calibration_slope + sensor().calibration_intercept,
+                injected,
+            )
+        })
+        .collect();
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &readings).unwrap();
+
+        let mut binary = Vec::new();
+        write_header(&mut binary, &sensor(), &tuning(), start).unwrap();
+        write_records(&mut binary, &readings).unwrap();
+
+        let mut decoded = Vec::new();
+        decode(&mut Cursor::new(binary), &mut decoded).unwrap();
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+    }
+
+    #[test]
+    /// Test that a change in calibration partway through a log applies to every reading after it,
+    /// and that a corrupted change is reported without stopping the log from being decoded.
+    fn calibration_change() {
+        let recalibrated = SensorTuning {
+            calibration_slope: 2.5,
+            calibration_intercept: -4.0,
+            ..tuning()
+        };
+        let original = readings(6);
+        let mut readings = original.clone();
+        for reading in &mut readings[3..] {
+            reading.2 = f64::from(reading.1) * 2.5 - 4.0;
+        }
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &readings).unwrap();
+
+        let mut binary = Vec::new();
+        write_header(&mut binary, &sensor(), &tuning(), readings[0].0).unwrap();
+        write_records(&mut binary, &readings[..3]).unwrap();
+        write_calibration(&mut binary, &sensor(), &recalibrated, readings[3].0).unwrap();
+        write_records(&mut binary, &readings[3..]).unwrap();
+
+        let mut decoded = Vec::new();
+        decode(&mut Cursor::new(binary.clone()), &mut decoded).unwrap();
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+
+        // flip a bit in the slope of the change, which is then left out
+        binary[38 + 3 * 15 + 12] ^= 0x10;
+        let mut decoded = Vec::new();
+        let result = decode(&mut Cursor::new(binary), &mut decoded);
+        assert!(matches!(result, Err(Error::CorruptRecords(offsets)) if offsets == [83]));
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &original).unwrap();
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+    }
+
+    #[test]
+    /// Test that a marker in a binary log is decoded as the same marker row as in a CSV log.
+    fn marker() {
+        let readings = readings(4);
+        let marker_time = readings[1].0 + Duration::from_micros(50);
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &readings[..2]).unwrap();
+        write_sensor_marker(&mut csv, marker_time, "paused").unwrap();
+        write_sensor_log(&mut csv, &readings[2..]).unwrap();
+
+        let mut binary = Vec::new();
+        write_header(&mut binary, &sensor(), &tuning(), readings[0].0).unwrap();
+        write_records(&mut binary, &readings[..2]).unwrap();
+        write_marker(&mut binary, marker_time, "paused").unwrap();
+        write_records(&mut binary, &readings[2..]).unwrap();
+
+        let mut decoded = Vec::new();
+        decode(&mut Cursor::new(binary), &mut decoded).unwrap();
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+    }
+
+    #[test]
+    /// Test that a log cut off in the middle of a record is reported as truncated.
+    fn truncated() {
+        let readings = readings(2);
+        let mut binary = Vec::new();
+        write_header(&mut binary, &sensor(), &tuning(), readings[0].0).unwrap();
+        write_records(&mut binary, &readings).unwrap();
+        binary.pop();
+
+        assert!(matches!(
+            decode(&mut Cursor::new(binary), &mut Vec::new()),
+            Err(Error::Truncated(53))
+        ));
+    }
+
+    #[test]
+    /// Test the CRC-32 implementation against its standard check value.
+    fn crc_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    /// Test that a flipped bit in a record is flagged at exactly that record, and that every other
+    /// record is still decoded with the right time.
+    fn corrupt_record() {
+        let readings = readings(5);
+        let mut binary = Vec::new();
+        write_header(&mut binary, &sensor(), &tuning(), readings[0].0).unwrap();
+        write_records(&mut binary, &readings).unwrap();
+        // flip a bit in the time of the third record
+        binary[38 + 2 * 15 + 3] ^= 0x10;
+
+        let mut decoded = Vec::new();
+        let result = decode(&mut Cursor::new(binary), &mut decoded);
+        assert!(matches!(result, Err(Error::CorruptRecords(offsets)) if offsets == [68]));
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, readings.iter().take(2).chain(&readings[3..])).unwrap();
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+    }
+
+    #[test]
+    /// Test that the log of a thermistor is decoded into temperatures, exactly as it would have
+    /// been logged in CSV.
+    fn thermistor_round_trip() {
+        let thermistor = Thermistor {
+            series_resistance: 10_000.0,
+            reference_voltage: 3.3,
+            a: 1.009_249_522e-3,
+            b: 2.378_405_444e-4,
+            c: 2.019_202_697e-7,
+        };
+        let sensor = Sensor {
+            thermistor: Some(thermistor),
+            ..sensor()
+        };
+        let readings: Vec<_> = readings(20)
+            .into_iter()
+            .map(|(time, reading, _, injected)| {
+                (time, reading, thermistor.temperature(reading), injected)
+            })
+            .collect();
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &readings).unwrap();
+
+        let mut binary = Vec::new();
+        write_header(&mut binary, &sensor, &tuning(), readings[0].0).unwrap();
+        write_records(&mut binary, &readings).unwrap();
+        assert_eq!(binary.len(), 62 + 15 * readings.len());
+
+        let mut decoded = Vec::new();
+        decode(&mut Cursor::new(binary), &mut decoded).unwrap();
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+    }
+
+    #[test]
+    /// Test that logs written in version 2 of the format, with a linear calibration before the
+    /// start time, can still be decoded.
+    fn decode_version_2() {
+        let readings = readings(3);
+        let mut binary = Vec::new();
+        binary.extend_from_slice(&MAGIC);
+        binary.push(2);
+        binary.extend_from_slice(&sensor().calibration_slope.to_le_bytes());
+        binary.extend_from_slice(&sensor().calibration_intercept.to_le_bytes());
+        binary.extend_from_slice(&nanos_since_epoch(readings[0].0).to_le_bytes());
+        binary.extend_from_slice(&crc32(&binary).to_le_bytes());
+        for &(time, reading, _, injected) in &readings {
+            let start = binary.len();
+            binary.extend_from_slice(&nanos_since_epoch(time).to_le_bytes());
+            let flagged = if injected {
+                reading | INJECTED_BIT
+            } else {
+                reading
+            };
+            binary.extend_from_slice(&flagged.to_le_bytes());
+            binary.extend_from_slice(&crc32(&binary[start..]).to_le_bytes());
+        }
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &readings).unwrap();
+        let mut decoded = Vec::new();
+        decode(&mut Cursor::new(binary), &mut decoded).unwrap();
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+    }
+
+    #[test]
+    /// Test that logs written in version 1 of the format, without checksums, can still be decoded.
+    fn decode_version_1() {
+        let readings = readings(3);
+        let mut binary = Vec::new();
+        binary.extend_from_slice(&MAGIC);
+        binary.push(1);
+        binary.extend_from_slice(&sensor().calibration_slope.to_le_bytes());
+        binary.extend_from_slice(&sensor().calibration_intercept.to_le_bytes());
+        binary.extend_from_slice(&nanos_since_epoch(readings[0].0).to_le_bytes());
+        let mut last_time = readings[0].0;
+        for &(time, reading, _, injected) in &readings {
+            let delta = time.duration_since(last_time).unwrap();
+            last_time = time;
+            binary.extend_from_slice(&saturating_nanos(delta).to_le_bytes());
+            let flagged = if injected {
+                reading | INJECTED_BIT
+            } else {
+                reading
+            };
+            binary.extend_from_slice(&flagged.to_le_bytes());
+        }
+
+        let mut csv = Vec::new();
+        write_sensor_log(&mut csv, &readings).unwrap();
+        let mut decoded = Vec::new();
+        decode(&mut Cursor::new(binary), &mut decoded).unwrap();
+        assert_eq!(String::from_utf8(decoded), String::from_utf8(csv));
+    }
+
+    #[test]
+    /// Test that a file which is not a binary log is rejected.
+    fn bad_magic() {
+        assert!(matches!(
+            decode(
+                &mut Cursor::new(b"1000000500,42,1.24\n1000000600,43,1.25\n".to_vec()),
+                &mut Vec::new()
+            ),
+            Err(Error::BadMagic)
+        ));
+    }
+}
@@ -0,0 +1,398 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Two-point calibration of sensors against known reference values.
+//!
+//! Every calibration computed by the controller is saved to the calibration file named in the
+//! configuration.
+//! The saved calibrations override those in the configuration whenever it is loaded, so that a
+//! calibration done in the field survives a restart.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::Display,
+    fs::{rename, File},
+    io::{self, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Configuration, execution};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+/// The coefficients of a linear calibration function `y = mx + b`.
+pub struct LinearCalibration {
+    /// The intercept of the calibration function, `b`.
+    pub calibration_intercept: f64,
+    /// The slope of the calibration function, `m`.
+    pub calibration_slope: f64,
+}
+
+/// The calibrations saved in a calibration file, indexed first by the label of each sensor group
+/// and then by the label of each sensor.
+type CalibrationTable = BTreeMap<String, BTreeMap<String, LinearCalibration>>;
+
+/// A reference point of a calibration: a raw reading, and the known value it corresponds to.
+type ReferencePoint = (u16, f64);
+
+/// The first reference point of every calibration in progress.
+pub struct Calibrator {
+    /// The first reference point of each sensor, indexed first by group ID and then by sensor ID.
+    /// A sensor whose calibration is not in progress has no point.
+    first_points: Mutex<Vec<Vec<Option<ReferencePoint>>>>,
+    /// The calibration input of a raw reading of 1 for each sensor, indexed first by group ID and
+    /// then by sensor ID.
+    /// This converts a calibration computed on raw readings into one on calibration inputs.
+    input_scales: Vec<Vec<f64>>,
+}
+
+#[derive(Debug)]
+/// The errors which can occur when loading or saving calibrations.
+pub enum Error {
+    /// The calibration file could not be read or written.
+    Io(io::Error),
+    /// The calibration file did not contain a valid set of calibrations.
+    Malformed(serde_json::Error),
+    /// The calibration file contained a calibration for a sensor which is not in the
+    /// configuration, such as one which was renamed.
+    /// The values inside this variant are the labels of the group and of the sensor.
+    NoSuchSensor(String, String),
+}
+
+impl LinearCalibration {
+    #[must_use]
+    /// Compute the calibration passing through two reference points.
+    /// Returns `None` if both points have the same raw reading, since the slope is then unknown.
+    pub fn through(first: ReferencePoint, second: ReferencePoint) -> Option<LinearCalibration> {
+        if first.0 == second.0 {
+            return None;
+        }
+        let slope = (second.1 - first.1) / (f64::from(second.0) - f64::from(first.0));
+        Some(LinearCalibration {
+            calibration_intercept: first.1 - slope * f64::from(first.0),
+            calibration_slope: slope,
+        })
+    }
+}
+
+impl Calibrator {
+    #[must_use]
+    /// Construct a new `Calibrator` for the sensors in `configuration`, none of which are being
+    /// calibrated.
+    pub fn new(configuration: &Configuration) -> Calibrator {
+        Calibrator {
+            first_points: Mutex::new(
+                configuration
+                    .sensor_groups
+                    .iter()
+                    .map(|group| vec![None; group.sensors.len()])
+                    .collect(),
+            ),
+            input_scales: configuration
+                .sensor_groups
+                .iter()
+                .map(|group| {
+                    group
+                        .sensors
+                        .iter()
+                        .map(|sensor| sensor.calibration_input(1))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Record a reference point for sensor `sensor_id` in group `group_id`, at which the raw
+    /// reading `reading` corresponds to the value `known_value`.
+    ///
+    /// Returns `None` if this is the first point of the sensor's calibration.
+    /// Otherwise, returns the calibration passing through both points, and the calibration is no
+    /// longer in progress.
+    /// The returned calibration applies to the sensor's calibration input, which is in volts if the
+    /// sensor has a reference voltage.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sensor does not exist, if the second point has
+    /// the same raw reading as the first (in which case the first point is kept), or if the
+    /// internal lock is poisoned.
+    pub fn add_point(
+        &self,
+        group_id: u8,
+        sensor_id: u8,
+        reading: u16,
+        known_value: f64,
+    ) -> Result<Option<LinearCalibration>, execution::Error> {
+        let mut first_points = self.first_points.lock()?;
+        let first_point = first_points
+            .get_mut(usize::from(group_id))
+            .and_then(|group| group.get_mut(usize::from(sensor_id)))
+            .ok_or(execution::Error::SensorOutOfBounds)?;
+
+        match *first_point {
+            None => {
+                *first_point = Some((reading, known_value));
+                Ok(None)
+            }
+            Some(first) => {
+                let mut calibration = LinearCalibration::through(first, (reading, known_value))
+                    .ok_or(execution::Error::DegenerateCalibration(reading))?;
+                calibration.calibration_slope /=
+                    self.input_scales[usize::from(group_id)][usize::from(sensor_id)];
+                *first_point = None;
+                Ok(Some(calibration))
+            }
+        }
+    }
+}
+
+/// Read the calibration file at `path`.
+/// A file which does not exist contains no calibrations.
+///
+/// # Errors
+///
+/// This function will return an error if the file exists but cannot be read or parsed.
+fn read_table(path: &Path) -> Result<CalibrationTable, Error> {
+    match File::open(path) {
+        Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(CalibrationTable::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Override the calibration of every sensor in `configuration` which has a calibration saved in
+/// `configuration.calibration_file`.
+///
+/// Returns the number of sensors whose calibration was overridden.
+///
+/// # Errors
+///
+/// This function will return an error if the calibration file exists but cannot be read or parsed,
+/// or if it contains a calibration for a sensor which is not in `configuration`.
+/// In that case, no calibration is overridden.
+pub fn apply_saved(configuration: &mut Configuration) -> Result<usize, Error> {
+    let Some(path) = &configuration.calibration_file else {
+        return Ok(0);
+    };
+    let table = read_table(path)?;
+
+    // check every entry before applying any, so that a bad file leaves the configuration untouched
+    for (group_label, saved_group) in &table {
+        let group = configuration
+            .sensor_groups
+            .iter()
+            .find(|group| &group.label == group_label);
+        for sensor_label in saved_group.keys() {
+            if !group.map_or(false, |group| {
+                group
+                    .sensors
+                    .iter()
+                    .any(|sensor| &sensor.label == sensor_label)
+            }) {
+                return Err(Error::NoSuchSensor(
+                    group_label.clone(),
+                    sensor_label.clone(),
+                ));
+            }
+        }
+    }
+
+    let mut n_applied = 0;
+    for group in &mut configuration.sensor_groups {
+        let Some(saved_group) = table.get(&group.label) else {
+            continue;
+        };
+        for sensor in &mut group.sensors {
+            if let Some(calibration) = saved_group.get(&sensor.label) {
+                sensor.calibration_intercept = calibration.calibration_intercept;
+                sensor.calibration_slope = calibration.calibration_slope;
+                n_applied += 1;
+            }
+        }
+    }
+
+    Ok(n_applied)
+}
+
+/// Save `calibration` as the calibration of sensor `sensor_id` in group `group_id` in
+/// `configuration.calibration_file`, keeping every other saved calibration.
+/// Does nothing if no calibration file is configured.
+///
+/// The file is replaced in a single step, so a failure partway through never corrupts it.
+///
+/// # Errors
+///
+/// This function will return an error if the calibration file cannot be read, parsed, or written.
+///
+/// # Panics
+///
+/// This function will panic if the sensor does not exist.
+pub fn save(
+    configuration: &Configuration,
+    group_id: u8,
+    sensor_id: u8,
+    calibration: LinearCalibration,
+) -> Result<(), Error> {
+    let Some(path) = &configuration.calibration_file else {
+        return Ok(());
+    };
+    let group = &configuration.sensor_groups[usize::from(group_id)];
+    let sensor = &group.sensors[usize::from(sensor_id)];
+
+    let mut table = read_table(path)?;
+    table
+        .entry(group.label.clone())
+        .or_default()
+        .insert(sensor.label.clone(), calibration);
+
+    let mut temp_path = OsString::from(path);
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    serde_json::to_writer_pretty(&mut writer, &table)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Malformed(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "unable to access calibration file: {e}"),
+            Error::Malformed(e) => write!(f, "calibration file is malformed: {e}"),
+            Error::NoSuchSensor(group, sensor) => write!(
+                f,
+                "calibration file has a calibration for sensor {sensor} in group {group}, which is not in the configuration"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+
+    /// Parse a configuration with one group, `FAST`, containing one sensor, `PT1`, whose inline
+    /// calibration has an intercept of 0 and a slope of 1, and whose saved calibrations are in the
+    /// file at `path`.
+    fn config_with_file(path: &Path) -> Configuration {
+        Configuration::parse(&mut Cursor::new(format!(
+            r#"{{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [{{
+                "label": "FAST",
+                "frequency_standby": 10,
+                "frequency_ignition": 10,
+                "frequency_transmission": 10,
+                "sensors": [{{
+                    "label": "PT1",
+                    "color": "red",
+                    "units": "psi",
+                    "calibration_intercept": 0,
+                    "calibration_slope": 1,
+                    "adc": 0,
+                    "channel": 0
+                }}]
+            }}],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0,
+            "calibration_file": {path:?}
+        }}"#
+        )))
+        .unwrap()
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    /// Test that the calibrations in the calibration file take precedence over those in the
+    /// configuration, and that a file naming an unknown sensor is rejected without applying any
+    /// of its calibrations.
+    fn external_file_precedence() {
+        let path =
+            std::env::temp_dir().join(format!("slonk_calibration_file_{}", std::process::id()));
+
+        fs::write(
+            &path,
+            r#"{"FAST": {"PT1": {"calibration_intercept": -12.5, "calibration_slope": 0.25}}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_file(&path);
+        assert_eq!(apply_saved(&mut config).unwrap(), 1);
+        let sensor = &config.sensor_groups[0].sensors[0];
+        assert_eq!(sensor.calibration_intercept, -12.5);
+        assert_eq!(sensor.calibration_slope, 0.25);
+
+        fs::write(
+            &path,
+            r#"{"FAST": {
+                "PT1": {"calibration_intercept": -12.5, "calibration_slope": 0.25},
+                "PT9": {"calibration_intercept": 0, "calibration_slope": 2}
+            }}"#,
+        )
+        .unwrap();
+        let mut config = config_with_file(&path);
+        assert!(matches!(
+            apply_saved(&mut config),
+            Err(Error::NoSuchSensor(group, sensor)) if group == "FAST" && sensor == "PT9"
+        ));
+        assert_eq!(config.sensor_groups[0].sensors[0].calibration_slope, 1.0);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    /// Test that a calibration passes through both of its reference points, and that two points
+    /// at the same reading are rejected.
+    fn two_point_calibration() {
+        let calibration = LinearCalibration::through((1000, 0.0), (3000, 100.0)).unwrap();
+        assert_eq!(calibration.calibration_slope, 0.05);
+        assert_eq!(calibration.calibration_intercept, -50.0);
+
+        assert_eq!(LinearCalibration::through((1000, 0.0), (1000, 100.0)), None);
+    }
+}
@@ -18,20 +18,37 @@
 
 //! Loading and validating configurations for the engine controller.
 
-use std::{collections::HashSet, io::Read, time::Duration};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    io::Read,
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
 
 use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
-use crate::hardware::{ListenerPin, Mcp3208};
+use crate::{
+    expression::{self, Expression},
+    hardware::{counts_to_volts, ListenerPin, Mcp3208},
+    scheduling::{MAX_PRIORITY, MIN_PRIORITY},
+};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
 /// A configuration for the entire engine controller.
 /// Contains all necessary data for both the controller and dashboard to operate correctly.
 pub struct Configuration {
     /// The frequency at which driver and system status updates should be sent to the dashboard.
     pub frequency_status: u32,
+    /// The period, in milliseconds, between each `DriverValue` message sent to the dashboard.
+    /// Drivers are still sampled and logged at `frequency_status`, and every change in the level of
+    /// a driver is still sent as it happens.
+    /// If `None`, a `DriverValue` message is sent with every sample.
+    pub driver_status_interval_ms: Option<u32>,
     /// The size that a log buffer should be.
     /// When a log buffer fills up, its readings are saved to a log file.
     pub log_buffer_size: usize,
@@ -58,23 +75,306 @@ pub struct Configuration {
     pub ignition_sequence: Vec<Action>,
     /// The sequence of actions to be performed during emergency stop.
     pub estop_sequence: Vec<Action>,
+    #[serde(default)]
+    /// The sequence of actions to be performed after every emergency stop or abort action, such as
+    /// purging residual propellant with an inert gas.
+    /// It may not energize any hazardous driver.
+    /// Defaults to an empty sequence, so the controller returns to standby straight away.
+    pub purge_sequence: Vec<Action>,
+    /// The path of the GPIO chip device from which every GPIO line is requested.
+    /// If `None`, `/dev/gpiochip0` is used.
+    pub gpio_chip: Option<PathBuf>,
     /// The Master Output / Slave Input pin ID for the SPI bus.
     pub spi_mosi: u8,
     /// The Master Input / Slave Output pin ID for the SPI bus.
     pub spi_miso: u8,
     /// The clock pin ID for the SPI bus.
     pub spi_clk: u8,
-    /// The operating frequency of the SPI bus clock.
+    /// The operating frequency of the SPI bus clock when talking to each ADC.
     /// Can be no less than 10 kHz for the ADCs to operate correctly.
     pub spi_frequency_clk: u32,
     /// The chip select pins for each device.
     /// For now, we assume that all ADCs are MCP3208s.
     pub adc_cs: Vec<u8>,
+    #[serde(default)]
+    /// The time, in microseconds, to wait after asserting each ADC's chip select before clocking
+    /// the conversion, so that high-impedance sensors have time to settle.
+    /// Defaults to 0.
+    pub cs_settle_us: u32,
     /// The GPIO pin ID of the heartbeat LED.
     pub pin_heartbeat: u8,
+    /// The GPIO pin ID of the armed-state indicator (such as a warning light or siren), which is
+    /// driven high whenever the controller is igniting or emergency stopping.
+    /// The indicator is driven only by the controller's state, and cannot be commanded.
+    /// If `None`, there is no indicator.
+    pub pin_armed_indicator: Option<u8>,
+    /// The number of bytes to preallocate for each sensor log file when it is created.
+    /// If `None`, no space is preallocated.
+    ///
+    /// Preallocating reduces write latency jitter on slow media (such as SD cards), at the cost of
+    /// wasted space on disk.
+    pub log_preallocate_size: Option<u64>,
+    /// The period, in milliseconds, at which sensor log files are synced to disk via `fsync`.
+    /// If `None`, sensor logs are only flushed, and the OS decides when to write them to disk.
+    pub log_sync_period: Option<u32>,
+    #[serde(default)]
+    /// The format in which sensor logs are written.
+    /// Defaults to CSV.
+    pub log_format: LogFormat,
+    /// The size, in bytes, past which a sensor log is closed and continued in a new segment.
+    /// If `None`, each sensor log is a single file.
+    ///
+    /// Only CSV logs may be rotated, since each binary log must begin with its own header.
+    pub log_rotate_size: Option<u64>,
+    #[serde(default)]
+    /// Whether each closed segment of a rotated sensor log is compressed with gzip.
+    /// Defaults to `false`.
+    pub log_compress_rotated: bool,
+    /// The address, as a host name or IP address followed by a port, to which sensor values will
+    /// additionally be streamed as UDP datagrams.
+    /// If `None`, sensor values are only sent over the TCP connection to the dashboard.
+    pub udp_telemetry_addr: Option<String>,
+    /// The period, in seconds, at which the host name in `udp_telemetry_addr` is resolved again,
+    /// so that telemetry follows a receiver which moves to a new address.
+    /// If `None`, it is resolved again every 30 seconds.
+    pub udp_telemetry_resolve_s: Option<u32>,
+    /// The address of a remote syslog server to which every entry in the user log is additionally
+    /// forwarded.
+    /// If `None`, the user log is only written locally.
+    pub syslog_addr: Option<SocketAddr>,
+    #[serde(default)]
+    /// The transport over which entries are forwarded to the syslog server.
+    /// Defaults to UDP.
+    pub syslog_transport: SyslogTransport,
+    /// The TCP port on which controller metrics are served over HTTP at `/metrics`, for scraping
+    /// by Prometheus.
+    /// If `None`, no metrics are served.
+    pub metrics_port: Option<u16>,
+    #[serde(default)]
+    /// What to do if `SO_REUSEPORT` cannot be set on the dashboard listener.
+    /// Defaults to logging a warning and opening the listener without it.
+    pub reuse_port_failure: ReusePortFailure,
+    /// The time, in seconds, for which a dashboard connection may be silent before the controller
+    /// starts probing it with TCP keepalives.
+    /// If the probes go unanswered, the connection is treated as lost.
+    /// If `None`, keepalives are not used, and a dashboard which vanishes without closing its
+    /// connection is only noticed when it reconnects.
+    pub dashboard_keepalive_s: Option<u32>,
+    /// The time, in milliseconds, for which the controller waits for a lost dashboard to reconnect
+    /// before declaring it disconnected.
+    /// If `None`, a lost dashboard is declared disconnected immediately.
+    pub disconnect_grace_ms: Option<u32>,
+    /// The name of the abort action (in `abort_actions`) which is performed when the dashboard is
+    /// declared disconnected.
+    /// If `None`, the controller keeps running as it was until a dashboard reconnects.
+    pub disconnect_action: Option<String>,
+    /// The number of sent messages which may wait to be written to the message log.
+    /// If set, the message log is written in the background, so a slow disk never delays messages
+    /// to the dashboard; messages sent while the queue is full are not logged.
+    /// If `None`, each message is logged as it is sent.
+    pub message_log_queue_size: Option<usize>,
+    #[serde(default)]
+    /// Whether commands which actuate hardware are rejected until every sensor has been read.
+    /// Defaults to `false`.
+    pub ready_gate: bool,
+    #[serde(default)]
+    /// The templates used to name log files.
+    /// Defaults to sensor logs at `{group}/{sensor}` and every other log at `{name}`.
+    pub log_layout: LogLayout,
+    /// The length of time, in milliseconds, for which the most recent readings from every sensor
+    /// are kept in memory.
+    /// When an emergency stop occurs, the kept readings are written to `pretrigger.csv` so that the
+    /// lead-up to the stop is captured.
+    /// If `None`, no readings are kept and no pre-trigger log is created.
+    pub pretrigger_duration_ms: Option<u32>,
+    /// The number of most recent readings from each sensor which are kept in memory, so that they
+    /// can be sent to a dashboard which requests them.
+    /// If `None`, no readings are kept.
+    pub history_size: Option<usize>,
+    /// The path to the file in which sensor calibrations computed by the controller are saved.
+    /// Saved calibrations override those given in this configuration.
+    /// If `None`, computed calibrations are applied but not saved, and are lost on a restart.
+    pub calibration_file: Option<PathBuf>,
+    /// The time, in milliseconds, for which a sensor group may go without beginning a new loop
+    /// before it is reported as stalled.
+    /// This must be longer than the slowest sampling period of any group.
+    /// If `None`, stalled sensor groups are not detected.
+    pub stall_timeout_ms: Option<u32>,
+    #[serde(default)]
+    /// Whether a stalled sensor group triggers an emergency stop, in addition to being reported.
+    /// Defaults to `false`.
+    pub stall_estop: bool,
+    #[serde(default)]
+    /// Whether a well-formed command of an unsupported type is answered with an `Unsupported`
+    /// message (and a failed acknowledgement, if it had a `command_id`), so that a newer dashboard
+    /// can detect which commands the controller supports.
+    /// Such commands are ignored either way.
+    /// Defaults to `false`.
+    pub ack_unsupported_commands: bool,
+    /// The largest delay, in microseconds, in the start of a sensor group's loop past the time it
+    /// was due which is not warned about.
+    /// Warnings are given at most once every ten seconds for each group.
+    /// If `None`, loop jitter is not monitored.
+    pub jitter_threshold_us: Option<u32>,
+    /// The time, in milliseconds, for which a sensor's raw reading may stay at exactly zero or
+    /// exactly full scale before a warning is given that its input may be disconnected or shorted.
+    /// Sensors read through IIO are not checked.
+    /// If `None`, pinned readings are not detected.
+    pub pinned_warn_ms: Option<u32>,
+    /// The age, in milliseconds, beyond which a sensor's latest reading is reported to the
+    /// dashboard as stale.
+    /// A sensor which has produced no new reading by the time its group next transmits has its
+    /// last reading sent again, flagged as stale, once that reading is older than this.
+    /// If `None`, readings are never flagged as stale.
+    pub stale_reading_ms: Option<u32>,
+    #[serde(default)]
+    /// Which values of each sensor reading are sent to the dashboard and over UDP telemetry.
+    /// A dashboard may choose differently for its own connection with a `SetSensorFields`
+    /// command.
+    /// Defaults to both the raw and calibrated values.
+    pub sensor_value_fields: SensorValueFields,
+    /// The period, in milliseconds, at which every driver with a sense pin which is commanded to
+    /// its safe (unpowered) level is read back during an ignition.
+    /// A driver whose sense pin reads as powered triggers an emergency stop.
+    /// If `None`, drivers are not read back.
+    pub readback_interval_ms: Option<u32>,
+    #[serde(default)]
+    /// The policy deciding which sequences may run at the same time.
+    /// Defaults to `SequencePolicy::Disjoint`.
+    pub sequence_policy: SequencePolicy,
+    /// The greatest number of commands which a single client may send in any one-second window.
+    /// Commands beyond the limit are dropped, except for emergency stops and holds, which are
+    /// never limited.
+    /// If `None`, clients may send commands at any rate.
+    pub command_rate_limit: Option<u32>,
+    /// The oldest version of the dashboard protocol which a dashboard may speak.
+    /// If given, every dashboard must declare its protocol version when it authenticates, before
+    /// sending any other command except an emergency stop.
+    /// If `None`, a dashboard need not declare its version, but is still rejected if it declares
+    /// a version which the controller cannot speak.
+    pub min_protocol_version: Option<u32>,
+    /// The time, in milliseconds, after an emergency stop or abort action ends during which an
+    /// ignition is rejected, giving the operator time to inspect the system.
+    /// If `None`, an ignition may begin as soon as the controller returns to standby.
+    pub estop_cooldown_ms: Option<u32>,
+    #[serde(default)]
+    /// The named abort actions, which can be referenced by sensors to respond to going out of
+    /// range with something other than the emergency stop sequence.
+    /// Each action is a list of drivers and the logic levels they are set to.
+    /// Defaults to no actions.
+    pub abort_actions: BTreeMap<String, Vec<AbortTarget>>,
+    #[serde(default)]
+    /// The sets of drivers which must never be energized together by a command.
+    /// An interlock may be overridden by the operator until it is restored.
+    /// Defaults to no interlocks.
+    pub interlocks: Vec<Interlock>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+/// A set of drivers of which at most one may be energized by a command at a time, such as the fill
+/// and vent valves of a tank.
+pub struct Interlock {
+    /// The human-readable label of the interlock.
+    pub label: String,
+    /// The identifiers (i.e. indices) of the drivers in the interlock.
+    pub drivers: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+/// The level which a single driver is set to by an abort action.
+pub struct AbortTarget {
+    /// The identifier (i.e. index) of the driver.
+    pub driver_id: u8,
+    /// The logic level that the driver will be set to.
+    pub value: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(default)]
+/// Templates for the paths of log files, relative to the logs directory.
+///
+/// Templates may contain the following tokens, which are substituted when the files are created:
+///
+/// * `{group}`: the label of the sensor group (sensor logs only).
+/// * `{sensor}`: the label of the sensor (sensor logs only).
+/// * `{name}`: the default name of the log file, such as `commands.csv` (other logs only).
+/// * `{timestamp}`: the time at which the controller started, in seconds since the UNIX epoch.
+/// * `{run_id}`: the run ID given to the controller with the `--run-id` flag.
+pub struct LogLayout {
+    /// The template for the path of each sensor log.
+    /// The file extension is appended automatically, based on the log format.
+    pub sensor: String,
+    /// The template for the path of every other log file, such as the command log.
+    pub other: String,
+}
+
+/// Values which are substituted into a `LogLayout` for a single run of the controller.
+pub struct RunInfo<'a> {
+    /// The time at which the controller started, in seconds since the UNIX epoch.
+    pub timestamp: u64,
+    /// The run ID given on the command line, if any.
+    pub run_id: Option<&'a str>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+/// The formats in which sensor data can be logged.
+pub enum LogFormat {
+    /// Human-readable CSV, with one row per reading.
+    #[default]
+    Csv,
+    /// Compact fixed-width binary records, as described in the `binary_log` module.
+    Binary,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+/// The transports over which log entries can be forwarded to a syslog server.
+pub enum SyslogTransport {
+    /// Each entry is sent as a single UDP datagram.
+    #[default]
+    Udp,
+    /// Entries are sent over a TCP connection, framed by octet counting.
+    Tcp,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+/// The responses to being unable to set `SO_REUSEPORT` on the dashboard listener.
+///
+/// `SO_REUSEPORT` is only available on Linux 3.9 and later and on the BSDs, and may be rejected by
+/// sandboxes.
+pub enum ReusePortFailure {
+    /// A warning is logged and the listener is opened without `SO_REUSEPORT`.
+    #[default]
+    Warn,
+    /// The controller fails to start.
+    Abort,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+/// The values of each sensor reading which are sent in a `SensorValue` message.
+pub enum SensorValueFields {
+    /// Both the raw ADC reading and the calibrated value are sent.
+    #[default]
+    Both,
+    /// Only the raw ADC reading is sent, as is useful while calibrating.
+    Raw,
+    /// Only the calibrated value is sent.
+    Calibrated,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+/// The policies deciding which sequences may run at the same time.
+/// A sequence is any command which controls drivers over a period of time: an ignition or a test
+/// fire.
+pub enum SequencePolicy {
+    /// Sequences may run at the same time only if they control disjoint sets of drivers.
+    #[default]
+    Disjoint,
+    /// Only one sequence may run at a time.
+    Exclusive,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 /// Information required to define a driver.
 pub struct Driver {
     /// The human-readable name of the driver.
@@ -85,8 +385,83 @@ pub struct Driver {
     pub label_deactuate: String,
     /// The pin actuated by the driver.
     pub pin: u8,
+    /// The pin of an input which senses whether the load driven by this driver is actually
+    /// powered, such as a relay's auxiliary contact, so that a stuck load can be detected.
+    /// If `None`, the driver is not read back.
+    pub pin_sense: Option<u8>,
     /// Whether this driver is protected from user access.
     pub protected: bool,
+    #[serde(default)]
+    /// Whether this driver controls something hazardous, such as an igniter or a main valve.
+    /// Hazardous drivers may only be actuated by a confirmed command during an ignition, and must
+    /// be deactuated by the emergency stop sequence.
+    /// Defaults to `false`.
+    pub hazardous: bool,
+    #[serde(default)]
+    /// The kind of load attached to this driver, which determines how its state is reported to
+    /// the dashboard.
+    /// Defaults to `DriverKind::Binary`.
+    pub kind: DriverKind,
+    /// The minimum time, in milliseconds, between two actuation commands for this driver.
+    /// Actuation commands arriving sooner than this after the previous one are rejected.
+    /// Emergency stops are never throttled.
+    pub min_actuation_interval_ms: Option<u32>,
+    /// The longest time, in milliseconds, allowed between keep-alive commands while this driver is
+    /// energized by an actuation command.
+    /// If keep-alives stop arriving, the driver is driven safe (unpowered).
+    /// If `None`, the driver has no dead man's switch.
+    pub deadman_interval_ms: Option<u32>,
+    #[serde(default)]
+    /// The logic level to which this driver is set when the controller first acquires it.
+    /// Defaults to `false` (unpowered).
+    pub startup_level: bool,
+    #[serde(default)]
+    /// The logic level to which this driver is set when the controller exits.
+    /// Defaults to `false` (unpowered).
+    pub shutdown_level: bool,
+    #[serde(default)]
+    /// A condition on a sensor which must hold for a command to energize this driver.
+    /// If `None`, the driver may be energized regardless of any sensor.
+    pub precondition: Option<Precondition>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+/// A range in which a sensor's latest calibrated value must lie for a command to energize a
+/// driver, such as a minimum tank pressure before a main valve may be opened.
+pub struct Precondition {
+    /// The ID of the sensor group containing the sensor.
+    pub group_id: u8,
+    /// The ID of the sensor within its group.
+    pub sensor_id: u8,
+    /// The smallest value at which the driver may be energized.
+    /// If `None`, there is no lower bound.
+    pub min: Option<f64>,
+    /// The largest value at which the driver may be energized.
+    /// If `None`, there is no upper bound.
+    pub max: Option<f64>,
+    /// The greatest age, in milliseconds, of a reading on which the driver may be energized.
+    /// If `None`, any reading which is not stale may be used.
+    pub max_age_ms: Option<u32>,
+}
+
+impl Precondition {
+    #[must_use]
+    /// Determine whether `value` lies within the range of this precondition.
+    pub fn admits(&self, value: f64) -> bool {
+        self.min.map_or(true, |min| min <= value) && self.max.map_or(true, |max| value <= max)
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+/// The kinds of load which a driver can control.
+pub enum DriverKind {
+    /// A load which is either on or off, such as a solenoid valve.
+    #[default]
+    Binary,
+    /// A PWM-controlled load, whose state is reported as a duty cycle.
+    Pwm,
+    /// A servo, whose state is reported as a position.
+    Servo,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -114,13 +489,70 @@ pub struct SensorGroup {
     pub label: String,
     /// The frequency at which data should be collected while in standby mode.
     pub frequency_standby: u32,
-    /// The frequency at which data should be collected while in ignition mode.
+    /// The frequency at which data should be collected while in ignition mode (that is, in any
+    /// state other than standby).
+    /// A group switches to this frequency as soon as it leaves standby.
     pub frequency_ignition: u32,
     /// The frequency at which data should be transmitted to the dashboard from this sensor group.
     /// If no data is available, no new data will be sent.
     pub frequency_transmission: u32,
     /// The set of sensors managed by this sensor group.
     pub sensors: Vec<Sensor>,
+    /// The real-time (`SCHED_FIFO`) priority of the thread managing this group, from 1 to 99.
+    /// If `None`, the thread uses the default scheduling policy.
+    pub realtime_priority: Option<i32>,
+    /// The index of the CPU core to which the thread managing this group should be pinned.
+    /// If `None`, the thread may run on any core.
+    pub cpu_core: Option<usize>,
+    #[serde(default)]
+    /// Whether every sensor in this group is read back-to-back before any reading is processed,
+    /// so that the readings share a single group timestamp.
+    /// Each reading then reports its offset from the group timestamp.
+    /// Defaults to `false`.
+    pub aligned: bool,
+    #[serde(default)]
+    /// The digital inputs, such as limit switches, sampled alongside the sensors in this group.
+    /// Defaults to no inputs.
+    pub digital_inputs: Vec<DigitalInput>,
+    /// The factor by which readings sent to the dashboard are decimated: only every Nth reading of
+    /// each sensor may be sent.
+    /// Every reading is still logged and checked against its sensor's range.
+    /// If `None`, every reading may be sent.
+    pub dashboard_decimation: Option<u32>,
+    #[serde(default)]
+    /// The virtual sensors whose values are computed from the readings of this group's sensors.
+    /// Defaults to no virtual sensors.
+    pub virtual_sensors: Vec<VirtualSensor>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// Information needed to define a virtual sensor, whose value is derived from the calibrated
+/// values of other sensors in the same group rather than read from an ADC.
+pub struct VirtualSensor {
+    /// The human-readable label of the virtual sensor.
+    pub label: String,
+    /// The units of the virtual sensor's value.
+    pub units: String,
+    /// The expression giving the virtual sensor's value, as described in the `expression`
+    /// module.
+    /// For instance, `[PT3: Injector] - [PT1: Combustion chamber]` is the pressure drop across an
+    /// injector.
+    pub expression: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// Information needed to define a digital input, such as a limit switch, read from a GPIO line.
+pub struct DigitalInput {
+    /// The human-readable label of the input.
+    pub label: String,
+    /// The GPIO pin ID of the input's line.
+    pub pin: u8,
+    #[serde(default)]
+    /// The time, in milliseconds, for which the input must hold a new level before the change is
+    /// reported.
+    /// Bounces shorter than this are ignored.
+    /// Defaults to zero, so every change is reported.
+    pub debounce_ms: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -130,7 +562,9 @@ pub struct Sensor {
     pub label: String,
     /// The color that this sensor should be displayed with.
     pub color: String,
-    /// The units of the sensor's calibrated value.
+    #[serde(default)]
+    /// The units of the sensor's calibrated value, displayed alongside it on the dashboard.
+    /// If not given, the value is displayed without units.
     pub units: String,
     /// The minimum and maximum allowable range of values that the calibrated
     /// value of this sensor can take be allowed to take on until an estop is triggered.
@@ -141,8 +575,28 @@ pub struct Sensor {
     /// The slope of the linear calibration function for this sensor.
     /// In the expression of the calibration function `y = mx + b`, this would be `m`.
     pub calibration_slope: f64,
+    /// The reference voltage of the ADC which this sensor is read from, in volts.
+    /// If given, the linear calibration is applied to the voltage at the ADC input rather than to
+    /// the raw reading, so that its coefficients carry over between boards with different
+    /// references.
+    /// Sensors read through IIO may not have a reference voltage.
+    pub vref: Option<f64>,
     /// The width of a rolling average for this device, used to filter data on the controller side.
     pub rolling_average_width: Option<u32>,
+    /// The lowest calibrated value which this sensor can physically measure.
+    /// Readings with a calibrated value below this are flagged as saturated.
+    pub full_scale_min: Option<f64>,
+    /// The highest calibrated value which this sensor can physically measure.
+    /// Readings with a calibrated value above this are flagged as saturated.
+    pub full_scale_max: Option<f64>,
+    /// The lowest calibrated value which the dashboard should show on this sensor's axis.
+    /// If `None`, the dashboard chooses its own bounds.
+    /// Has no effect on the controller.
+    pub display_min: Option<f64>,
+    /// The highest calibrated value which the dashboard should show on this sensor's axis.
+    /// If `None`, the dashboard chooses its own bounds.
+    /// Has no effect on the controller.
+    pub display_max: Option<f64>,
     /// The ID of the ADC used by this device.
     /// This maps to the field `adc_cs` in `Configuration`.
     /// For instance, if the value of `adc` is 2, and `adc[2]` is 33, then this sensor uses the ADC
@@ -150,6 +604,112 @@ pub struct Sensor {
     pub adc: u8,
     /// The channel on the ADC to to read raw sensor data from.
     pub channel: u8,
+    /// The raw attribute file of the Linux Industrial I/O (IIO) channel which this sensor reads,
+    /// such as `/sys/bus/iio/devices/iio:device0/in_voltage0_raw`.
+    /// If given, the sensor is read through IIO rather than from an SPI ADC, and `adc` and
+    /// `channel` are ignored.
+    /// A thermistor, or a sensor with a reference voltage, may not be read through IIO.
+    pub iio_channel: Option<PathBuf>,
+    #[serde(skip)]
+    /// The scale of the IIO channel which this sensor reads, as the value of one raw count.
+    /// This is not configured, but filled in once the channel is opened, so that the linear
+    /// calibration applies to the channel's scaled value.
+    pub iio_scale: Option<f64>,
+    /// The number of times the ADC channel is read back-to-back for each reported reading.
+    /// The reads are averaged into a single reading, trading sample rate for lower noise.
+    /// If `None`, each reported reading is a single read.
+    pub oversample: Option<u8>,
+    #[serde(default)]
+    /// The number of readings taken from this sensor and thrown away when its group starts, so
+    /// that the settling transient of its ADC is never reported or checked against its range.
+    /// Defaults to zero.
+    pub discard_first: u8,
+    #[serde(default)]
+    /// Whether the raw bytes of every SPI transaction with this sensor's ADC channel are written to
+    /// a debug log alongside the decoded reading.
+    /// Ignored for sensors read through IIO.
+    /// Defaults to `false`.
+    pub log_raw: bool,
+    /// The parameters of the thermistor which this sensor reads, if it is a thermistor.
+    /// If given, the calibrated value of this sensor is its temperature in degrees Celsius, and the
+    /// linear calibration is ignored.
+    pub thermistor: Option<Thermistor>,
+    /// The name of the abort action (in `abort_actions` in `Configuration`) performed when this
+    /// sensor goes out of range.
+    /// If `None`, the emergency stop sequence is performed instead.
+    pub abort_action: Option<String>,
+    /// The synthetic waveform which this sensor reads when the controller is run without hardware.
+    /// If `None`, the sensor always reads the ID of its ADC.
+    /// Ignored when running on real hardware.
+    pub simulation: Option<Waveform>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+/// The parameters of an NTC thermistor read through a voltage divider.
+///
+/// The thermistor is wired between the ADC input and ground, and a fixed series resistor is wired
+/// between the ADC input and the reference voltage.
+/// Its temperature is computed from its resistance using the Steinhart-Hart equation:
+/// `1 / T = A + B ln(R) + C ln(R)^3`, where `T` is in kelvin and `R` is in ohms.
+pub struct Thermistor {
+    /// The resistance of the fixed series resistor, in ohms.
+    pub series_resistance: f64,
+    /// The voltage across the voltage divider, in volts.
+    /// This must also be the reference voltage of the ADC.
+    pub reference_voltage: f64,
+    /// The coefficient `A` of the Steinhart-Hart equation.
+    pub a: f64,
+    /// The coefficient `B` of the Steinhart-Hart equation.
+    pub b: f64,
+    /// The coefficient `C` of the Steinhart-Hart equation.
+    pub c: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+/// A synthetic waveform giving the raw ADC reading of a simulated sensor over time.
+///
+/// All times are in seconds since the controller started, and all readings are raw ADC readings,
+/// which are calibrated like any other reading.
+pub enum Waveform {
+    /// A reading which never changes.
+    Constant {
+        /// The reading.
+        reading: f64,
+    },
+    /// A sinusoid.
+    Sine {
+        /// The reading at the center of the sinusoid.
+        offset: f64,
+        /// The distance from the center of the sinusoid to its peaks.
+        amplitude: f64,
+        /// The period of the sinusoid.
+        period: f64,
+    },
+    /// A linear ramp between two readings, which holds its final reading once it is done.
+    Ramp {
+        /// The reading at the start of the ramp.
+        start: f64,
+        /// The reading at the end of the ramp.
+        end: f64,
+        /// The time taken to ramp from `start` to `end`.
+        duration: f64,
+    },
+    /// A sudden step from one reading to another.
+    Step {
+        /// The reading before the step.
+        before: f64,
+        /// The reading after the step.
+        after: f64,
+        /// The time at which the step occurs.
+        time: f64,
+    },
+    /// A replay, looped forever, of the raw readings in a CSV sensor log recorded by the
+    /// controller.
+    Replay {
+        /// The path to the sensor log.
+        path: PathBuf,
+    },
 }
 
 #[derive(Debug)]
@@ -162,6 +722,22 @@ pub enum Error {
     NoSuchAdc(u8),
     /// A sensor's definition referred to a channel which is out of bounds on an ADC.
     BadChannel(u8),
+    /// Two sensors read the same channel of the same ADC.
+    /// The values inside this variant are the index of the ADC and the channel.
+    DuplicateChannel(u8, u8),
+    /// A sensor's definition requested zero reads per reported reading.
+    ZeroOversample,
+    /// A sensor read through IIO was a thermistor or had a reference voltage, both of which require
+    /// the resolution of its raw readings, which is unknown.
+    /// The value inside this variant is the label of the sensor.
+    IioResolution(String),
+    /// A sensor's reference voltage was not positive.
+    /// The value inside this variant is the label of the sensor.
+    BadVref(String),
+    /// A thermistor's series resistance or reference voltage was not positive.
+    BadThermistor,
+    /// A simulated sensor's waveform had a period which was not positive or a negative duration.
+    BadWaveform,
     /// The SPI clock frequency was set too slow.
     ClockTooSlow,
     /// A procedure references a driver which does not exist.
@@ -170,6 +746,93 @@ pub enum Error {
     DuplicatePin(u8),
     /// A pin is used for
     ReservedPin(u8),
+    /// A sensor group requested a real-time priority outside the legal range.
+    BadPriority(i32),
+    /// A hazardous driver is not deactuated by the emergency stop sequence or by an abort action.
+    UnsafeHazard(u8),
+    /// The purge sequence energizes a hazardous driver.
+    HazardousPurge(u8),
+    /// A hazardous driver is powered when the controller starts or exits.
+    HazardousRest(u8),
+    /// A log file template contains an unknown token, or names a path outside the logs directory.
+    /// The string is the offending template.
+    BadTemplate(String),
+    /// A log file template uses the `{run_id}` token, but no run ID was given.
+    MissingRunId,
+    /// A sensor or the disconnect action referred to an abort action which is not defined.
+    /// The string is the name of the action.
+    NoSuchAbortAction(String),
+    /// A sensor group has a sampling or transmission frequency of zero.
+    /// The string is the label of the group.
+    ZeroFrequency(String),
+    /// The driver status frequency or the interval between driver status messages is zero.
+    ZeroStatusInterval,
+    /// The period at which the UDP telemetry host name is resolved again is zero.
+    ZeroResolvePeriod,
+    /// The size at which sensor logs are rotated is zero.
+    ZeroRotateSize,
+    /// Sensor logs are rotated, but are written in the binary format.
+    RotatedBinaryLog,
+    /// A sensor group has a dashboard decimation factor of zero.
+    /// The string is the label of the group.
+    ZeroDecimation(String),
+    /// A sensor's range or display bounds have a minimum which is not less than the maximum.
+    /// The string is the label of the sensor.
+    BadRange(String),
+    /// A virtual sensor's expression could not be parsed.
+    /// The string is the label of the virtual sensor.
+    BadExpression(String, expression::Error),
+    /// A virtual sensor has the same label as another sensor in its group, real or virtual.
+    /// The string is the label of the virtual sensor.
+    DuplicateVirtualLabel(String),
+    /// A driver's precondition refers to a sensor which does not exist, or has a minimum which is
+    /// not less than its maximum.
+    /// The string is the label of the driver.
+    BadPrecondition(String),
+}
+
+impl Sensor {
+    #[must_use]
+    /// Convert the raw reading `reading` of this sensor into the input of its linear calibration:
+    /// the voltage at the ADC input if the sensor has a reference voltage, the scaled value if it
+    /// is read through IIO, or else the raw reading itself.
+    pub fn calibration_input(&self, reading: u16) -> f64 {
+        match (self.vref, self.iio_scale) {
+            (Some(vref), _) => counts_to_volts(reading, vref),
+            (None, Some(scale)) => f64::from(reading) * scale,
+            (None, None) => f64::from(reading),
+        }
+    }
+}
+
+impl VirtualSensor {
+    /// Parse the expression of this virtual sensor, which belongs to `group`.
+    /// Each sensor in the expression is referred to by its ID within `group`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the expression is malformed or names a sensor which
+    /// is not in `group`.
+    pub fn parse(&self, group: &SensorGroup) -> Result<Expression, Error> {
+        Expression::parse(
+            &self.expression,
+            group.sensors.iter().map(|sensor| sensor.label.as_str()),
+        )
+        .map_err(|e| Error::BadExpression(self.label.clone(), e))
+    }
+}
+
+impl Thermistor {
+    #[must_use]
+    /// Compute the temperature of this thermistor, in degrees Celsius, from the raw ADC reading of
+    /// the voltage across it.
+    pub fn temperature(&self, reading: u16) -> f64 {
+        let voltage = counts_to_volts(reading, self.reference_voltage);
+        let resistance = self.series_resistance * voltage / (self.reference_voltage - voltage);
+        let ln_resistance = resistance.ln();
+        let kelvin = 1.0 / (self.a + self.b * ln_resistance + self.c * ln_resistance.powi(3));
+        kelvin - 273.15
+    }
 }
 
 impl Configuration {
@@ -180,73 +843,402 @@ impl Configuration {
     /// # Errors
     ///
     /// This function will return errors in line with the definition of `Error` in this module.
+    /// If the configuration has more than one problem, only the first is returned.
     pub fn parse(source: &mut impl Read) -> Result<Configuration, Error> {
-        // deserialize the configuration
-        let config: Configuration = serde_json::from_reader(source).map_err(Error::Malformed)?;
+        let config = Configuration::parse_unvalidated(source)?;
+        match config.problems().into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(config),
+        }
+    }
+
+    /// Construct a new `Configuration` by parsing some readable source, without checking it for
+    /// logical inconsistencies.
+    /// Use `problems` to find every inconsistency in the result.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err(Error::Malformed)` if the source could not be parsed.
+    pub fn parse_unvalidated(source: &mut impl Read) -> Result<Configuration, Error> {
+        serde_json::from_reader(source).map_err(Error::Malformed)
+    }
 
-        // now validate it
+    #[allow(clippy::too_many_lines)]
+    #[must_use]
+    /// Find every logical inconsistency in this configuration.
+    /// The configuration is valid if and only if the returned list is empty.
+    ///
+    /// This is the validation performed by `parse`, so a configuration which passes it will be
+    /// accepted by the controller.
+    pub fn problems(&self) -> Vec<Error> {
+        let mut problems = Vec::new();
 
         // check that SPI frequency is correct
-        if u64::from(config.spi_frequency_clk) < Mcp3208::<ListenerPin>::SPI_MIN_FREQUENCY {
-            return Err(Error::ClockTooSlow);
+        if u64::from(self.spi_frequency_clk) < Mcp3208::<ListenerPin>::SPI_MIN_FREQUENCY {
+            problems.push(Error::ClockTooSlow);
         }
 
-        // check that each sensor has an ADC associated with it
-        for group in &config.sensor_groups {
+        if self.frequency_status == 0 || self.driver_status_interval_ms == Some(0) {
+            problems.push(Error::ZeroStatusInterval);
+        }
+
+        if self.udp_telemetry_resolve_s == Some(0) {
+            problems.push(Error::ZeroResolvePeriod);
+        }
+
+        if self.log_rotate_size == Some(0) {
+            problems.push(Error::ZeroRotateSize);
+        }
+        if self.log_rotate_size.is_some() && self.log_format == LogFormat::Binary {
+            problems.push(Error::RotatedBinaryLog);
+        }
+
+        // check that each sensor has an ADC associated with it, and no other sensor shares its
+        // channel
+        let mut channels_used = HashSet::new();
+        for group in &self.sensor_groups {
+            if let Some(priority) = group.realtime_priority {
+                if !(MIN_PRIORITY..=MAX_PRIORITY).contains(&priority) {
+                    problems.push(Error::BadPriority(priority));
+                }
+            }
+
+            if group.frequency_standby == 0
+                || group.frequency_ignition == 0
+                || group.frequency_transmission == 0
+            {
+                problems.push(Error::ZeroFrequency(group.label.clone()));
+            }
+
+            if group.dashboard_decimation == Some(0) {
+                problems.push(Error::ZeroDecimation(group.label.clone()));
+            }
+
+            problems.extend(
+                group
+                    .virtual_sensors
+                    .iter()
+                    .filter_map(|virtual_sensor| virtual_sensor.parse(group).err()),
+            );
+
+            // a virtual sensor's log and readings are named by its label, just like a real sensor's
+            let mut labels: HashSet<&str> = group
+                .sensors
+                .iter()
+                .map(|sensor| sensor.label.as_str())
+                .collect();
+            for virtual_sensor in &group.virtual_sensors {
+                if !labels.insert(&virtual_sensor.label) {
+                    problems.push(Error::DuplicateVirtualLabel(virtual_sensor.label.clone()));
+                }
+            }
+
             for sensor in &group.sensors {
-                if usize::from(sensor.adc) >= config.adc_cs.len() {
-                    return Err(Error::NoSuchAdc(sensor.adc));
+                if sensor.iio_channel.is_none() {
+                    if usize::from(sensor.adc) >= self.adc_cs.len() {
+                        problems.push(Error::NoSuchAdc(sensor.adc));
+                    }
+
+                    if sensor.channel >= 8 {
+                        problems.push(Error::BadChannel(sensor.channel));
+                    }
+
+                    if !channels_used.insert((sensor.adc, sensor.channel)) {
+                        problems.push(Error::DuplicateChannel(sensor.adc, sensor.channel));
+                    }
+                } else if sensor.thermistor.is_some() || sensor.vref.is_some() {
+                    problems.push(Error::IioResolution(sensor.label.clone()));
+                }
+
+                if sensor.vref.map_or(false, |vref| vref <= 0.0) {
+                    problems.push(Error::BadVref(sensor.label.clone()));
+                }
+
+                if sensor.oversample == Some(0) {
+                    problems.push(Error::ZeroOversample);
+                }
+
+                for (min, max) in [sensor.range, sensor.display_min.zip(sensor.display_max)]
+                    .into_iter()
+                    .flatten()
+                {
+                    if min.partial_cmp(&max) != Some(Ordering::Less) {
+                        problems.push(Error::BadRange(sensor.label.clone()));
+                    }
+                }
+
+                if let Some(name) = &sensor.abort_action {
+                    if !self.abort_actions.contains_key(name) {
+                        problems.push(Error::NoSuchAbortAction(name.clone()));
+                    }
+                }
+
+                if let Some(thermistor) = &sensor.thermistor {
+                    if thermistor.series_resistance <= 0.0 || thermistor.reference_voltage <= 0.0 {
+                        problems.push(Error::BadThermistor);
+                    }
                 }
 
-                if sensor.channel >= 8 {
-                    return Err(Error::BadChannel(sensor.channel));
+                match sensor.simulation {
+                    Some(Waveform::Sine { period, .. }) if period <= 0.0 => {
+                        problems.push(Error::BadWaveform);
+                    }
+                    Some(Waveform::Ramp { duration, .. }) if duration < 0.0 => {
+                        problems.push(Error::BadWaveform);
+                    }
+                    _ => (),
                 }
             }
         }
 
         // check that actuations correspond to real drivers
-        for procedure in [&config.ignition_sequence, &config.estop_sequence] {
+        for procedure in [
+            &self.ignition_sequence,
+            &self.estop_sequence,
+            &self.purge_sequence,
+        ] {
             for step in procedure {
-                let Action::Actuate { driver_id, value: _ } = step else { continue; };
-                if usize::from(*driver_id) > config.drivers.len() {
-                    return Err(Error::NoSuchDriver(*driver_id));
+                let Action::Actuate {
+                    driver_id,
+                    value: _,
+                } = step
+                else {
+                    continue;
+                };
+                if usize::from(*driver_id) >= self.drivers.len() {
+                    problems.push(Error::NoSuchDriver(*driver_id));
                 }
             }
         }
+        for target in self.abort_actions.values().flatten() {
+            if usize::from(target.driver_id) >= self.drivers.len() {
+                problems.push(Error::NoSuchDriver(target.driver_id));
+            }
+        }
+        for &driver_id in self
+            .interlocks
+            .iter()
+            .flat_map(|interlock| &interlock.drivers)
+        {
+            if usize::from(driver_id) >= self.drivers.len() {
+                problems.push(Error::NoSuchDriver(driver_id));
+            }
+        }
+        for driver in &self.drivers {
+            let Some(precondition) = driver.precondition else {
+                continue;
+            };
+            let sensor_exists = self
+                .sensor_groups
+                .get(usize::from(precondition.group_id))
+                .map_or(false, |group| {
+                    usize::from(precondition.sensor_id) < group.sensors.len()
+                });
+            let empty = matches!(
+                (precondition.min, precondition.max),
+                (Some(min), Some(max)) if min >= max
+            );
+            if !sensor_exists || empty {
+                problems.push(Error::BadPrecondition(driver.label.clone()));
+            }
+        }
+        if let Some(name) = &self.disconnect_action {
+            if !self.abort_actions.contains_key(name) {
+                problems.push(Error::NoSuchAbortAction(name.clone()));
+            }
+        }
+
+        // check that every hazardous driver is made safe during an emergency stop
+        for (driver_id, driver) in self.drivers.iter().enumerate() {
+            #[allow(clippy::cast_possible_truncation)]
+            let driver_id = driver_id as u8;
+            if driver.hazardous
+                && (!self.estop_sequence.contains(&Action::Actuate {
+                    driver_id,
+                    value: false,
+                }) || self.abort_actions.values().any(|targets| {
+                    !targets.contains(&AbortTarget {
+                        driver_id,
+                        value: false,
+                    })
+                }))
+            {
+                problems.push(Error::UnsafeHazard(driver_id));
+            }
+            if driver.hazardous
+                && self.purge_sequence.contains(&Action::Actuate {
+                    driver_id,
+                    value: true,
+                })
+            {
+                problems.push(Error::HazardousPurge(driver_id));
+            }
+            if driver.hazardous && (driver.startup_level || driver.shutdown_level) {
+                problems.push(Error::HazardousRest(driver_id));
+            }
+        }
+
+        // check that log templates are well-formed and stay inside the logs directory
+        let sample_run = RunInfo {
+            timestamp: 0,
+            run_id: Some("run"),
+        };
+        if let Err(e) = self.log_layout.sensor_path("group", "sensor", &sample_run) {
+            problems.push(e);
+        }
+        if let Err(e) = self.log_layout.other_path("name", &sample_run) {
+            problems.push(e);
+        }
 
         // check that no pins are reused in the configuration
         // also, check that no illegal pins (i.e. ones on the Raspberry Pi which are reserved) are
         // used
         let mut pins_used = HashSet::new();
-        for pin in config
+        for pin in self
             .drivers
             .iter()
             .map(|d| d.pin)
-            .chain([config.spi_mosi, config.spi_miso, config.spi_clk])
-            .chain(config.adc_cs.iter().copied())
+            .chain(self.drivers.iter().filter_map(|d| d.pin_sense))
+            .chain([self.spi_mosi, self.spi_miso, self.spi_clk])
+            .chain(self.pin_armed_indicator)
+            .chain(self.adc_cs.iter().copied())
+            .chain(
+                self.sensor_groups
+                    .iter()
+                    .flat_map(|group| &group.digital_inputs)
+                    .map(|input| input.pin),
+            )
         {
             if !is_legal(pin) {
-                return Err(Error::ReservedPin(pin));
+                problems.push(Error::ReservedPin(pin));
             }
-            if pins_used.contains(&pin) {
-                return Err(Error::DuplicatePin(pin));
+            if !pins_used.insert(pin) {
+                problems.push(Error::DuplicatePin(pin));
             }
-            pins_used.insert(pin);
         }
+        // the heartbeat LED must not share its pin with anything else either
+        if !pins_used.insert(self.pin_heartbeat) {
+            problems.push(Error::DuplicatePin(self.pin_heartbeat));
+        }
+
+        problems
+    }
+
+    #[must_use]
+    /// Get every GPIO pin which this configuration uses for something other than a driver.
+    pub fn non_driver_pins(&self) -> Vec<u8> {
+        [
+            self.spi_mosi,
+            self.spi_miso,
+            self.spi_clk,
+            self.pin_heartbeat,
+        ]
+        .into_iter()
+        .chain(self.pin_armed_indicator)
+        .chain(self.drivers.iter().filter_map(|d| d.pin_sense))
+        .chain(self.adc_cs.iter().copied())
+        .chain(
+            self.sensor_groups
+                .iter()
+                .flat_map(|group| &group.digital_inputs)
+                .map(|input| input.pin),
+        )
+        .collect()
+    }
+}
+
+impl Default for LogLayout {
+    fn default() -> Self {
+        LogLayout {
+            sensor: "{group}/{sensor}".into(),
+            other: "{name}".into(),
+        }
+    }
+}
+
+impl LogLayout {
+    /// Construct the path of the log for a sensor, relative to the logs directory.
+    /// The returned path has no extension.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the template is illegal, if it requires a run ID but
+    /// none was given, or if the resulting path would be outside the logs directory.
+    pub fn sensor_path(&self, group: &str, sensor: &str, run: &RunInfo) -> Result<PathBuf, Error> {
+        render_template(&self.sensor, &[("group", group), ("sensor", sensor)], run)
+    }
+
+    /// Construct the path of a log file other than a sensor log, relative to the logs directory.
+    /// `name` is the default name of the file, such as `commands.csv`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the template is illegal, if it requires a run ID but
+    /// none was given, or if the resulting path would be outside the logs directory.
+    pub fn other_path(&self, name: &str, run: &RunInfo) -> Result<PathBuf, Error> {
+        render_template(&self.other, &[("name", name)], run)
+    }
+}
+
+/// Substitute tokens into a log file template.
+/// Tokens are names surrounded by braces, such as `{name}`.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the template contains an unknown or unterminated token,
+/// if it uses `{run_id}` but `run` has no run ID, or if the resulting path is not a relative path
+/// which stays inside the logs directory.
+fn render_template(
+    template: &str,
+    tokens: &[(&str, &str)],
+    run: &RunInfo,
+) -> Result<PathBuf, Error> {
+    let bad_template = || Error::BadTemplate(template.to_string());
+    let timestamp = run.timestamp.to_string();
+
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(bad_template)? + start;
+        let value = match &rest[start + 1..end] {
+            "timestamp" => timestamp.as_str(),
+            "run_id" => run.run_id.ok_or(Error::MissingRunId)?,
+            token => {
+                tokens
+                    .iter()
+                    .find(|&&(name, _)| name == token)
+                    .ok_or_else(bad_template)?
+                    .1
+            }
+        };
+        rendered.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
 
-        // all validation steps passed
-        Ok(config)
+    // only plain path components are allowed, so that the path cannot escape the logs directory
+    let path = PathBuf::from(rendered);
+    if path.as_os_str().is_empty()
+        || !Path::new(&path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(bad_template());
     }
+
+    Ok(path)
 }
 
 /// Determine whether a GPIO pin ID is a legal pin for use in the controller.
-fn is_legal(pin: u8) -> bool {
+pub(crate) fn is_legal(pin: u8) -> bool {
     // There are GPIO pins 0 through 27 (inclusive).
     // However, pins 0 and 1 are reserved for EEPROM.
     1 < pin && pin <= 27
 }
 
 impl Display for Error {
+    #[allow(clippy::too_many_lines)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::Malformed(json_err) => {
@@ -257,6 +1249,25 @@ impl Display for Error {
                 "ADC {a} is referenced but not listed in set of ADC chip select pins"
             ),
             Error::BadChannel(c) => write!(f, "ADC channel {c} referenced (must be in 0..=7)"),
+            Error::DuplicateChannel(a, c) => {
+                write!(f, "Channel {c} of ADC {a} is read by multiple sensors")
+            }
+            Error::ZeroOversample => write!(f, "A sensor's oversample count must be at least 1"),
+            Error::IioResolution(label) => write!(
+                f,
+                "Sensor {label} is read through IIO, so it cannot be a thermistor or have a reference voltage"
+            ),
+            Error::BadVref(label) => {
+                write!(f, "Sensor {label} must have a positive reference voltage")
+            }
+            Error::BadThermistor => write!(
+                f,
+                "A thermistor's series resistance and reference voltage must be positive"
+            ),
+            Error::BadWaveform => write!(
+                f,
+                "A simulated sine wave's period must be positive and a ramp's duration must not be negative"
+            ),
             Error::ClockTooSlow => write!(
                 f,
                 "SPI clock frequency is too slow (must be at least {} Hz)",
@@ -265,13 +1276,77 @@ impl Display for Error {
             Error::NoSuchDriver(d) => write!(f, "A procedure refers to a driver with ID {d}, but no such driver is given in the list of drivers"),
             Error::DuplicatePin(p) => write!(f, "GPIO pin {p} is used for multiple purposes"),
             Error::ReservedPin(p) => write!(f, "GPIO pin {p} is not allowed to be used on the Raspberry Pi"),
+            Error::BadPriority(p) => write!(
+                f,
+                "Real-time priority {p} is out of range (must be in {MIN_PRIORITY}..={MAX_PRIORITY})"
+            ),
+            Error::BadTemplate(t) => write!(
+                f,
+                "Log file template {t:?} has an unknown token or escapes the logs directory"
+            ),
+            Error::NoSuchAbortAction(name) => write!(
+                f,
+                "An abort action named {name:?} is referenced, but no such action is defined"
+            ),
+            Error::MissingRunId => write!(
+                f,
+                "A log file template uses {{run_id}}, but no run ID was given with --run-id"
+            ),
+            Error::ZeroFrequency(label) => write!(
+                f,
+                "Sensor group {label} has a frequency of zero (every frequency must be positive)"
+            ),
+            Error::ZeroStatusInterval => write!(
+                f,
+                "The driver status frequency and interval must both be positive"
+            ),
+            Error::ZeroResolvePeriod => write!(
+                f,
+                "The UDP telemetry resolve period must be positive"
+            ),
+            Error::ZeroRotateSize => write!(f, "The sensor log rotation size must be positive"),
+            Error::RotatedBinaryLog => write!(
+                f,
+                "Sensor logs may only be rotated when they are written as CSV"
+            ),
+            Error::ZeroDecimation(label) => write!(
+                f,
+                "Sensor group {label} has a dashboard decimation of zero (it must be positive)"
+            ),
+            Error::BadRange(label) => write!(
+                f,
+                "Sensor {label} has a range or display bounds whose minimum is not less than the maximum"
+            ),
+            Error::BadExpression(label, e) => {
+                write!(f, "The expression of virtual sensor {label} is invalid: {e}")
+            }
+            Error::DuplicateVirtualLabel(label) => write!(
+                f,
+                "Virtual sensor {label} has the same label as another sensor in its group"
+            ),
+            Error::BadPrecondition(label) => write!(
+                f,
+                "Driver {label} has a precondition on a sensor which does not exist, or whose minimum is not less than its maximum"
+            ),
+            Error::UnsafeHazard(d) => write!(
+                f,
+                "Driver {d} is hazardous, but the emergency stop sequence or an abort action does not deactuate it"
+            ),
+            Error::HazardousPurge(d) => write!(
+                f,
+                "Driver {d} is hazardous, but the purge sequence energizes it"
+            ),
+            Error::HazardousRest(d) => write!(
+                f,
+                "Driver {d} is hazardous, but it is powered when the controller starts or exits"
+            ),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::{fs::File, io::Cursor};
 
     use super::*;
 
@@ -281,6 +1356,7 @@ mod tests {
     fn full_config() {
         let config_str = r##"{
             "frequency_status": 10,
+            "driver_status_interval_ms": 250,
             "log_buffer_size": 256,
             "sensor_groups": [
                 {
@@ -360,6 +1436,7 @@ mod tests {
         }"##;
         let config = Configuration {
             frequency_status: 10,
+            driver_status_interval_ms: Some(250),
             log_buffer_size: 256,
             sensor_groups: vec![SensorGroup {
                 label: "FAST".into(),
@@ -375,6 +1452,19 @@ mod tests {
                         calibration_intercept: 0.34,
                         calibration_slope: 33.2,
                         rolling_average_width: Some(5),
+                        full_scale_min: None,
+                        full_scale_max: None,
+                        display_min: None,
+                        display_max: None,
+                        oversample: None,
+                        discard_first: 0,
+                        log_raw: false,
+                        vref: None,
+                        iio_channel: None,
+                        iio_scale: None,
+                        thermistor: None,
+                        abort_action: None,
+                        simulation: None,
                         adc: 0,
                         channel: 0,
                     },
@@ -386,10 +1476,29 @@ mod tests {
                         calibration_intercept: 92.3,
                         calibration_slope: -302.4,
                         rolling_average_width: None,
+                        full_scale_min: None,
+                        full_scale_max: None,
+                        display_min: None,
+                        display_max: None,
+                        oversample: None,
+                        discard_first: 0,
+                        log_raw: false,
+                        vref: None,
+                        iio_channel: None,
+                        iio_scale: None,
+                        thermistor: None,
+                        abort_action: None,
+                        simulation: None,
                         adc: 0,
                         channel: 1,
                     },
                 ],
+                realtime_priority: None,
+                cpu_core: None,
+                aligned: false,
+                digital_inputs: Vec::new(),
+                dashboard_decimation: None,
+                virtual_sensors: Vec::new(),
             }],
             pre_ignite_time: 500,
             post_ignite_time: 5000,
@@ -399,6 +1508,14 @@ mod tests {
                 label_deactuate: "Close".into(),
                 pin: 21,
                 protected: false,
+                hazardous: false,
+                kind: DriverKind::Binary,
+                min_actuation_interval_ms: None,
+                deadman_interval_ms: None,
+                startup_level: false,
+                shutdown_level: false,
+                precondition: None,
+                pin_sense: None,
             }],
             ignition_sequence: vec![
                 Action::Actuate {
@@ -417,15 +1534,717 @@ mod tests {
                 driver_id: 0,
                 value: false,
             }],
+            purge_sequence: Vec::new(),
+            gpio_chip: None,
             spi_mosi: 26,
             spi_miso: 25,
             spi_clk: 24,
             spi_frequency_clk: 50_000,
             adc_cs: vec![20],
+            cs_settle_us: 0,
             pin_heartbeat: 0,
+            pin_armed_indicator: None,
+            log_preallocate_size: None,
+            log_sync_period: None,
+            log_format: LogFormat::Csv,
+            log_rotate_size: None,
+            log_compress_rotated: false,
+            udp_telemetry_addr: None,
+            udp_telemetry_resolve_s: None,
+            syslog_addr: None,
+            syslog_transport: SyslogTransport::Udp,
+            metrics_port: None,
+            reuse_port_failure: ReusePortFailure::Warn,
+            dashboard_keepalive_s: None,
+            disconnect_grace_ms: None,
+            disconnect_action: None,
+            message_log_queue_size: None,
+            ready_gate: false,
+            log_layout: LogLayout::default(),
+            pretrigger_duration_ms: None,
+            history_size: None,
+            calibration_file: None,
+            stall_timeout_ms: None,
+            stall_estop: false,
+            ack_unsupported_commands: false,
+            jitter_threshold_us: None,
+            pinned_warn_ms: None,
+            stale_reading_ms: None,
+            sensor_value_fields: SensorValueFields::Both,
+            readback_interval_ms: None,
+            sequence_policy: SequencePolicy::Disjoint,
+            command_rate_limit: None,
+            min_protocol_version: None,
+            estop_cooldown_ms: None,
+            abort_actions: BTreeMap::new(),
+            interlocks: Vec::new(),
         };
 
         let mut cursor = Cursor::new(config_str);
         assert_eq!(config, Configuration::parse(&mut cursor).unwrap());
     }
+
+    #[test]
+    /// Test that a sensor group with an out-of-range real-time priority is rejected.
+    fn bad_priority() {
+        let config_str = r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [
+                {
+                    "label": "FAST",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 1000,
+                    "frequency_transmission": 10,
+                    "sensors": [],
+                    "realtime_priority": 100,
+                    "cpu_core": 3
+                }
+            ],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        assert!(matches!(
+            Configuration::parse(&mut Cursor::new(config_str)),
+            Err(Error::BadPriority(100))
+        ));
+    }
+
+    #[test]
+    /// Test that a driver status interval of zero is rejected.
+    fn zero_status_interval() {
+        let config_str = r#"{
+            "frequency_status": 10,
+            "driver_status_interval_ms": 0,
+            "log_buffer_size": 256,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        assert!(matches!(
+            Configuration::parse(&mut Cursor::new(config_str)),
+            Err(Error::ZeroStatusInterval)
+        ));
+    }
+
+    #[test]
+    /// Test that the heartbeat and armed-state indicator pins may not share a pin with anything
+    /// else.
+    fn indicator_pin_conflicts() {
+        let config = Configuration::parse_unvalidated(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 25,
+            "pin_armed_indicator": 24
+        }"#,
+        ))
+        .unwrap();
+
+        let problems = config.problems();
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(problems[0], Error::DuplicatePin(24)));
+        assert!(matches!(problems[1], Error::DuplicatePin(25)));
+    }
+
+    #[test]
+    /// Test that a UDP telemetry resolve period of zero is rejected.
+    fn zero_resolve_period() {
+        let config_str = r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0,
+            "udp_telemetry_addr": "127.0.0.1:2708",
+            "udp_telemetry_resolve_s": 0
+        }"#;
+
+        assert!(matches!(
+            Configuration::parse(&mut Cursor::new(config_str)),
+            Err(Error::ZeroResolvePeriod)
+        ));
+    }
+
+    #[test]
+    /// Test that a virtual sensor may not share its label with a real or virtual sensor in its
+    /// group.
+    fn virtual_sensor_labels() {
+        let config_str = r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [
+                {
+                    "label": "FAST",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 1000,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0
+                        }
+                    ],
+                    "virtual_sensors": [
+                        {
+                            "label": "PT1",
+                            "units": "psi",
+                            "expression": "[PT1] * 2"
+                        },
+                        {
+                            "label": "Doubled",
+                            "units": "psi",
+                            "expression": "[PT1] * 2"
+                        },
+                        {
+                            "label": "Doubled",
+                            "units": "psi",
+                            "expression": "[PT1] * 1e+0 * 2"
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [23],
+            "pin_heartbeat": 0
+        }"#;
+
+        let config: Configuration = serde_json::from_str(config_str).unwrap();
+        let problems = config.problems();
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(&problems[0], Error::DuplicateVirtualLabel(label) if label == "PT1"));
+        assert!(matches!(&problems[1], Error::DuplicateVirtualLabel(label) if label == "Doubled"));
+    }
+
+    #[test]
+    /// Test that rotating binary sensor logs is rejected.
+    fn rotated_binary_log() {
+        let config_str = r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0,
+            "log_format": "Binary",
+            "log_rotate_size": 1048576,
+            "log_compress_rotated": true
+        }"#;
+
+        assert!(matches!(
+            Configuration::parse(&mut Cursor::new(config_str)),
+            Err(Error::RotatedBinaryLog)
+        ));
+    }
+
+    #[test]
+    /// Test that a configuration with only the required fields, as written before any optional
+    /// field existed, still parses and takes the safe default for every optional field.
+    fn legacy_defaults() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [
+                {
+                    "label": "FAST",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 1000,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "LC_MAIN",
+                            "color": "red",
+                            "calibration_intercept": 0.34,
+                            "calibration_slope": 33.2,
+                            "adc": 0,
+                            "channel": 0
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [
+                {
+                    "label": "OXI_FILL",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                }
+            ],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [20],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+
+        assert!(config.purge_sequence.is_empty());
+        assert_eq!(config.pin_armed_indicator, None);
+        assert_eq!(config.log_format, LogFormat::Csv);
+        assert_eq!(config.log_layout, LogLayout::default());
+        assert!(!config.ready_gate);
+        assert!(!config.stall_estop);
+        assert!(!config.ack_unsupported_commands);
+        assert_eq!(config.sequence_policy, SequencePolicy::Disjoint);
+        assert!(config.abort_actions.is_empty());
+        assert!(config.interlocks.is_empty());
+        assert_eq!(config.disconnect_action, None);
+        assert_eq!(config.command_rate_limit, None);
+        assert_eq!(config.min_protocol_version, None);
+        assert_eq!(config.estop_cooldown_ms, None);
+        assert_eq!(config.calibration_file, None);
+
+        let driver = &config.drivers[0];
+        assert!(!driver.hazardous);
+        assert_eq!(driver.kind, DriverKind::Binary);
+        assert_eq!(driver.deadman_interval_ms, None);
+
+        let group = &config.sensor_groups[0];
+        assert!(!group.aligned);
+        assert!(group.digital_inputs.is_empty());
+        assert_eq!(group.dashboard_decimation, None);
+
+        let sensor = &group.sensors[0];
+        assert_eq!(sensor.units, "");
+        assert_eq!(sensor.range, None);
+        assert_eq!(sensor.abort_action, None);
+    }
+
+    #[test]
+    /// Test that a hazardous driver which is not deactuated during an emergency stop is rejected.
+    fn unsafe_hazard() {
+        let config_str = r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [
+                {
+                    "label": "IGNITION",
+                    "label_actuate": "Ignite",
+                    "label_deactuate": "Stop",
+                    "pin": 21,
+                    "protected": true,
+                    "hazardous": true
+                }
+            ],
+            "ignition_sequence": [],
+            "estop_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": true
+                }
+            ],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        assert!(matches!(
+            Configuration::parse(&mut Cursor::new(config_str)),
+            Err(Error::UnsafeHazard(0))
+        ));
+    }
+
+    #[test]
+    /// Test that a purge sequence which energizes a hazardous driver is rejected.
+    fn hazardous_purge() {
+        let config_str = r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [
+                {
+                    "label": "IGNITION",
+                    "label_actuate": "Ignite",
+                    "label_deactuate": "Stop",
+                    "pin": 21,
+                    "protected": true,
+                    "hazardous": true
+                }
+            ],
+            "ignition_sequence": [],
+            "estop_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": false
+                }
+            ],
+            "purge_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": true
+                }
+            ],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        assert!(matches!(
+            Configuration::parse(&mut Cursor::new(config_str)),
+            Err(Error::HazardousPurge(0))
+        ));
+    }
+
+    #[test]
+    /// Test that a hazardous driver which would be powered at startup is rejected.
+    fn hazardous_startup() {
+        let config_str = r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 256,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [
+                {
+                    "label": "IGNITION",
+                    "label_actuate": "Ignite",
+                    "label_deactuate": "Stop",
+                    "pin": 21,
+                    "protected": true,
+                    "hazardous": true,
+                    "startup_level": true
+                }
+            ],
+            "ignition_sequence": [],
+            "estop_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": false
+                }
+            ],
+            "spi_mosi": 26,
+            "spi_miso": 25,
+            "spi_clk": 24,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        assert!(matches!(
+            Configuration::parse(&mut Cursor::new(config_str)),
+            Err(Error::HazardousRest(0))
+        ));
+    }
+
+    #[test]
+    /// Test that log file templates are rendered correctly.
+    fn log_templates() {
+        let layout = LogLayout {
+            sensor: "{run_id}/{group}/{run_id}_{sensor}".into(),
+            other: "{run_id}/{timestamp}_{name}".into(),
+        };
+        let run = RunInfo {
+            timestamp: 1234,
+            run_id: Some("T42"),
+        };
+
+        assert_eq!(
+            layout.sensor_path("FAST", "PT_FEED", &run).unwrap(),
+            PathBuf::from("T42/FAST/T42_PT_FEED")
+        );
+        assert_eq!(
+            layout.other_path("commands.csv", &run).unwrap(),
+            PathBuf::from("T42/1234_commands.csv")
+        );
+        assert!(matches!(
+            layout.other_path(
+                "commands.csv",
+                &RunInfo {
+                    timestamp: 1234,
+                    run_id: None
+                }
+            ),
+            Err(Error::MissingRunId)
+        ));
+    }
+
+    #[test]
+    /// Test that log file templates which are malformed or escape the logs directory are rejected.
+    fn bad_log_templates() {
+        let run = RunInfo {
+            timestamp: 0,
+            run_id: None,
+        };
+        for template in ["../{name}", "/tmp/{name}", "{nmae}", "{name", ""] {
+            let layout = LogLayout {
+                sensor: String::new(),
+                other: template.into(),
+            };
+            assert!(
+                matches!(layout.other_path("x", &run), Err(Error::BadTemplate(_))),
+                "{template:?} should be rejected"
+            );
+        }
+
+        // labels cannot be used to escape the logs directory either
+        assert!(matches!(
+            LogLayout::default().sensor_path("..", "..", &run),
+            Err(Error::BadTemplate(_))
+        ));
+    }
+
+    #[test]
+    /// Test that parsing a configuration from an in-memory source, as is done for standard input,
+    /// yields the same configuration as parsing it from a file.
+    fn parse_from_memory() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config/titan-karca.json");
+        let from_file = Configuration::parse(&mut File::open(path).unwrap()).unwrap();
+        let from_memory =
+            Configuration::parse(&mut Cursor::new(std::fs::read(path).unwrap())).unwrap();
+
+        assert_eq!(from_file, from_memory);
+    }
+
+    #[test]
+    /// Test thermistor linearization against reference points from the resistance-temperature
+    /// table of a 10 kOhm NTC thermistor datasheet.
+    fn thermistor_reference_points() {
+        let thermistor = Thermistor {
+            series_resistance: 10_000.0,
+            reference_voltage: 3.3,
+            a: 1.125_256_672e-3,
+            b: 2.347_204_473e-4,
+            c: 8.563_052_732e-8,
+        };
+
+        // (ADC reading, temperature in Celsius), where each reading is the one produced by the
+        // datasheet resistance at that temperature: 32.65 kOhm, 10 kOhm, and 3.603 kOhm
+        for (reading, temperature) in [(3136, 0.0), (2048, 25.0), (1085, 50.0)] {
+            let computed = thermistor.temperature(reading);
+            assert!(
+                (computed - temperature).abs() < 0.1,
+                "reading {reading} gave {computed} C, expected {temperature} C"
+            );
+        }
+    }
+
+    #[test]
+    /// Test that a sensor reading a channel beyond the ADC's eight channels, or a channel which
+    /// another sensor already reads, is rejected.
+    fn adc_channel_mapping() {
+        let config = Configuration::parse_unvalidated(&mut Cursor::new(
+            r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "FAST",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 1000,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 3
+                        },
+                        {
+                            "label": "PT2",
+                            "color": "blue",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 1,
+                            "channel": 3
+                        },
+                        {
+                            "label": "PT3",
+                            "color": "green",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 8
+                        }
+                    ]
+                },
+                {
+                    "label": "SLOW",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 10,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "LC1",
+                            "color": "black",
+                            "units": "lbf",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 3
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [5, 6],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+
+        let problems = config.problems();
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(problems[0], Error::BadChannel(8)));
+        assert!(matches!(problems[1], Error::DuplicateChannel(0, 3)));
+    }
+
+    #[test]
+    /// Test that every problem with a configuration is reported, rather than only the first.
+    fn every_problem_reported() {
+        let config = Configuration::parse_unvalidated(&mut Cursor::new(
+            r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "FAST",
+                    "frequency_standby": 0,
+                    "frequency_ignition": 1000,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "range": [100, 0],
+                            "adc": 0,
+                            "channel": 0
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [{
+                "label": "OXI_FILL",
+                "label_actuate": "Open",
+                "label_deactuate": "Close",
+                "pin": 11,
+                "protected": false
+            }],
+            "ignition_sequence": [{"type": "Actuate", "driver_id": 1, "value": true}],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [5],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+
+        let problems = config.problems();
+        assert_eq!(problems.len(), 4);
+        assert!(matches!(&problems[0], Error::ZeroFrequency(label) if label == "FAST"));
+        assert!(matches!(&problems[1], Error::BadRange(label) if label == "PT1"));
+        assert!(matches!(problems[2], Error::NoSuchDriver(1)));
+        assert!(matches!(problems[3], Error::DuplicatePin(11)));
+
+        // parsing reports the first of the problems
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(matches!(
+            Configuration::parse(&mut Cursor::new(json)),
+            Err(Error::ZeroFrequency(_))
+        ));
+    }
 }
@@ -16,7 +16,23 @@
   along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use std::{fmt::Display, io::Write, sync::Mutex, time::SystemTime};
+use std::{
+    fmt::Display,
+    io::Write,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc::Sender,
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::syslog::Syslog;
+
+/// A single log entry, as sent to a subscriber.
+pub type LogEntry = (LogLevel, String);
 
 /// A log for data displayed to the user.
 /// The data sent to the user log need not be machine-readable.
@@ -24,11 +40,19 @@ use std::{fmt::Display, io::Write, sync::Mutex, time::SystemTime};
 pub struct UserLog<W: Write> {
     /// The buffer to which user log information will be written.
     log_buffer: Mutex<W>,
+    /// A subscriber to which every log entry at or above a minimum level is also sent, if any.
+    subscriber: Mutex<Option<(LogLevel, Sender<LogEntry>)>>,
+    /// The lowest level of log entry which is displayed, mirrored, and sent to the subscriber, as a
+    /// `LogLevel` cast to a `u8`.
+    /// Every entry is written to the log buffer regardless.
+    verbosity: AtomicU8,
+    /// A remote syslog server to which every recorded entry is also forwarded, if any.
+    syslog: Mutex<Option<Syslog>>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 /// The levels for logging.
-enum LogLevel {
+pub enum LogLevel {
     /// The lowest log level.
     /// Used exclusively for displaying random garbage to help the developer debug their problems.
     Debug = 0,
@@ -58,6 +82,21 @@ impl Display for LogLevel {
     }
 }
 
+impl LogLevel {
+    /// The verbosity used by the controller until it is changed at runtime.
+    pub const DEFAULT: LogLevel = LogLevel::Info;
+
+    /// Convert a `LogLevel` which was cast to a `u8` back into a `LogLevel`.
+    fn from_u8(n: u8) -> LogLevel {
+        match n {
+            0 => LogLevel::Debug,
+            1 => LogLevel::Info,
+            2 => LogLevel::Warn,
+            _ => LogLevel::Critical,
+        }
+    }
+}
+
 impl<W: Write> UserLog<W> {
     /// Construct a new `UserLog`.
     ///
@@ -65,9 +104,68 @@ impl<W: Write> UserLog<W> {
     pub fn new(buf: W) -> UserLog<W> {
         UserLog {
             log_buffer: Mutex::new(buf),
+            subscriber: Mutex::new(None),
+            verbosity: AtomicU8::new(LogLevel::DEFAULT as u8),
+            syslog: Mutex::new(None),
         }
     }
 
+    /// Forward every subsequent recorded entry to `syslog` as well.
+    /// Replaces any previous syslog server.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the internal lock is poisoned.
+    pub fn mirror_to(&self, syslog: Syslog) {
+        *self.syslog.lock().unwrap() = Some(syslog);
+    }
+
+    /// Set the lowest level of log entry which will be displayed, mirrored, or sent to the
+    /// subscriber.
+    /// Entries below `level` are only written to the log buffer.
+    pub fn set_verbosity(&self, level: LogLevel) {
+        self.verbosity.store(level as u8, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    /// Get the lowest level of log entry which will be displayed.
+    pub fn verbosity(&self) -> LogLevel {
+        LogLevel::from_u8(self.verbosity.load(Ordering::Relaxed))
+    }
+
+    #[must_use]
+    #[inline]
+    /// Determine whether log entries of level `level` are currently being displayed.
+    ///
+    /// This is cheap enough to call on every sensor reading, so expensive debug information
+    /// should only be formatted after checking that it will be displayed.
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level as u8 >= self.verbosity.load(Ordering::Relaxed)
+    }
+
+    /// Send every subsequent log entry at or above `min_level` to `subscriber`, along with its
+    /// level.
+    /// Replaces any previous subscriber.
+    ///
+    /// The subscription ends when `unsubscribe` is called or when the receiving end of `subscriber`
+    /// is dropped.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the internal lock is poisoned.
+    pub fn subscribe(&self, min_level: LogLevel, subscriber: Sender<LogEntry>) {
+        *self.subscriber.lock().unwrap() = Some((min_level, subscriber));
+    }
+
+    /// End the current subscription, if any.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the internal lock is poisoned.
+    pub fn unsubscribe(&self) {
+        *self.subscriber.lock().unwrap() = None;
+    }
+
     #[allow(clippy::missing_errors_doc)]
     /// Log some debug information for the user.
     ///
@@ -113,28 +211,183 @@ impl<W: Write> UserLog<W> {
     ///
     /// This function will panic if the current time is before the UNIX epoch.
     fn write(&self, level: LogLevel, string: &str) -> std::io::Result<()> {
+        let log_time = SystemTime::now();
+        let shown = self.enabled(level);
+        self.record(log_time, level, string, shown)?;
+        if !shown {
+            return Ok(());
+        }
+
+        if let Some(syslog) = &*self.syslog.lock().unwrap() {
+            syslog.forward(level, log_time, string);
+            // a failure to forward is only noted locally, since it cannot be forwarded either
+            if let Some(note) = syslog.take_note() {
+                self.record(log_time, LogLevel::Warn, &note, true)?;
+            }
+        }
+
+        let mut subscriber = self.subscriber.lock().unwrap();
+        if let Some((min_level, sender)) = &*subscriber {
+            if level >= *min_level && sender.send((level, string.to_string())).is_err() {
+                // nobody is listening anymore
+                *subscriber = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a log entry of level `level`, logged at `log_time`, to the log buffer, and display it
+    /// as well if `shown` is true.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Error` if we are unable to write to the log buffer.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `log_time` is before the UNIX epoch.
+    fn record(
+        &self,
+        log_time: SystemTime,
+        level: LogLevel,
+        string: &str,
+        shown: bool,
+    ) -> std::io::Result<()> {
         // we trust that this code was run after January 1st, 1970
-        let log_time_nanos = SystemTime::now()
+        let log_time_nanos = log_time
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
 
-        // use terminal text control characters to change colors
-        match level {
-            LogLevel::Critical => print!("\x1b[31m"), // red
-            LogLevel::Warn => print!("\x1b[33m"),     // yellow
-            LogLevel::Info => (),
-            LogLevel::Debug => print!("\x1b[90m"), // faded
-        };
-        println!("[{log_time_nanos}] [{level}] {string}");
-
-        // wipe previous coloring
-        print!("\x1b[0m");
+        if shown {
+            // use terminal text control characters to change colors
+            match level {
+                LogLevel::Critical => print!("\x1b[31m"), // red
+                LogLevel::Warn => print!("\x1b[33m"),     // yellow
+                LogLevel::Info => (),
+                LogLevel::Debug => print!("\x1b[90m"), // faded
+            };
+            println!("[{log_time_nanos}] [{level}] {string}");
+
+            // wipe previous coloring
+            print!("\x1b[0m");
+        }
         writeln!(
             // we trust writing to the log buffer will not cause a panic.
             self.log_buffer.lock().unwrap(),
             "[{log_time_nanos}] [{level}] {string}"
-        )?;
-        Ok(())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read},
+        net::TcpListener,
+        sync::mpsc::channel,
+        thread::sleep,
+        time::Duration,
+    };
+
+    use crate::config::SyslogTransport;
+
+    use super::*;
+
+    #[test]
+    /// Test that entries below the verbosity are not sent to the subscriber but are still written
+    /// to the log buffer, and that the verbosity can be changed.
+    fn verbosity() {
+        let log = UserLog::new(Vec::new());
+        let (sender, receiver) = channel();
+        log.subscribe(LogLevel::Debug, sender);
+        log.debug("hidden").unwrap();
+        log.info("shown").unwrap();
+        log.set_verbosity(LogLevel::Debug);
+        assert_eq!(log.verbosity(), LogLevel::Debug);
+        log.debug("now shown").unwrap();
+        log.set_verbosity(LogLevel::Critical);
+        log.warn("hidden again").unwrap();
+        log.unsubscribe();
+
+        assert_eq!(
+            receiver
+                .iter()
+                .map(|(_, message)| message)
+                .collect::<Vec<_>>(),
+            ["shown", "now shown"]
+        );
+
+        let contents = String::from_utf8(log.log_buffer.into_inner().unwrap()).unwrap();
+        let messages: Vec<&str> = contents
+            .lines()
+            .map(|line| line.split("] ").last().unwrap())
+            .collect();
+        assert_eq!(messages, ["hidden", "shown", "now shown", "hidden again"]);
+    }
+
+    #[test]
+    /// Test that a subscriber only receives entries at or above its minimum level, and receives
+    /// nothing after unsubscribing.
+    fn subscribe_min_level() {
+        let log = UserLog::new(Vec::new());
+        let (sender, receiver) = channel();
+        log.subscribe(LogLevel::Warn, sender);
+
+        log.info("boring").unwrap();
+        log.warn("uh oh").unwrap();
+        log.critical("oh no").unwrap();
+        log.unsubscribe();
+        log.critical("too late").unwrap();
+
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            [
+                (LogLevel::Warn, "uh oh".to_string()),
+                (LogLevel::Critical, "oh no".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that recorded entries are forwarded to a TCP syslog server with the right severities,
+    /// and that entries which cannot be forwarded are noted in the log instead.
+    fn syslog_forwarding() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let log = UserLog::new(Vec::new());
+        log.mirror_to(Syslog::new(listener.local_addr().unwrap(), SyslogTransport::Tcp).unwrap());
+        log.debug("hidden").unwrap();
+        log.info("all good").unwrap();
+        log.critical("oh no").unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut frames = Vec::new();
+        for _ in 0..2 {
+            let mut len = Vec::new();
+            reader.read_until(b' ', &mut len).unwrap();
+            let len: usize = std::str::from_utf8(&len).unwrap().trim().parse().unwrap();
+            let mut frame = vec![0; len];
+            reader.read_exact(&mut frame).unwrap();
+            frames.push(String::from_utf8(frame).unwrap());
+        }
+        assert!(frames[0].starts_with("<14>1 "), "{}", frames[0]);
+        assert!(frames[0].contains(" slonk "));
+        assert!(frames[0].ends_with("- - all good"));
+        assert!(frames[1].starts_with("<10>1 "), "{}", frames[1]);
+        assert!(frames[1].ends_with("- - oh no"));
+
+        // nothing is listening at this address once the listener is dropped
+        let addr = listener.local_addr().unwrap();
+        drop((reader, listener));
+        let log = UserLog::new(Vec::new());
+        log.mirror_to(Syslog::new(addr, SyslogTransport::Tcp).unwrap());
+        log.warn("lost").unwrap();
+        for _ in 0..100 {
+            sleep(Duration::from_millis(10));
+            log.info("still logging").unwrap();
+        }
+        let contents = String::from_utf8(log.log_buffer.into_inner().unwrap()).unwrap();
+        assert!(contents.contains("could not be forwarded to syslog"));
     }
 }
@@ -21,374 +21,4044 @@
 use std::{
     collections::VecDeque,
     io::Write,
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+        Condvar, Mutex, PoisonError,
+    },
     thread::{sleep, Scope},
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
-    config::Configuration,
-    console::UserLog,
-    execution::emergency_stop,
-    hardware::{Adc, GpioPin},
-    outgoing::{DashChannel, Message, SensorReading},
+    binary_log,
+    config::{Configuration, LogFormat, Sensor, SensorGroup},
+    console::{LogLevel, UserLog},
+    execution::{self, abort, emergency_stop, Interlocks, PriorityMutex},
+    hardware::{Adc, Edge, GpioPin, ADC_MAX_READING},
+    outgoing::{DashChannel, DriverState, Message, ReadingList, SensorReading, UdpTelemetry},
+    reload::{SensorTuning, Tunings},
+    rotation::SyncHandle,
     state::{Guard, State},
     ControllerError,
 };
 
-#[allow(dead_code)]
-#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
-/// A function which will continuously listen for new data from sensors.
-/// It will loop indefinitely.
-///
-/// # Inputs
-///
-/// * `thread_scope`: A reference to a scope that this function can use to spawn other threads.
-///     This is required so that the sensor listener thread can emergency-stop, if needed.
-/// * `group_id`: The ID of the sensor group that this thread is responsible for.
-///     This is equal to the index of the sensor group in the configuration object.
-/// * `adcs`: The set of ADCs which can be read from by the sensors.
-/// * `configuration`: The primary configuration of the controller.
-/// * `driver_lines`: The GPIO lines for each driver.
-/// * `log_files`: Handles for log files associated with the sensors in this sensor group.
-///     Each index corresponds exactly to its associated index in the group.
-/// * `state`: The state of the whole system.
-///     If a sensor enters an invalid value during ignition, this thread will automatically update
-///     the state as needed.
-/// * `dashboard_stream`: A stream where messages can be sent to the dashboard.
-///
-/// # Errors
-///
-/// This function will only return an error if the controller state status lock is poisoned.
-///
-/// # Panics
-///
-/// This function will panic in any of the following cases:
+/// A store of synthetic sensor readings which have been requested by the dashboard but not yet
+/// reported by a sensor listener thread.
 ///
-/// * If the value of `group_id` does not correspond to an existing sensor group.
-/// * If the current system time is before the UNIX epoch.
-pub fn sensor_listen<'a>(
-    thread_scope: &'a Scope<'a, '_>,
-    group_id: u8,
-    configuration: &'a Configuration,
-    driver_lines: &'a Mutex<Vec<impl GpioPin + Send + Sync>>,
-    log_files: &mut [impl Write],
-    user_log: &UserLog<impl Write>,
-    adcs: &[Mutex<impl Adc>],
-    state: &'a Guard,
-    dashboard_stream: &'a DashChannel<impl Write, impl Write>,
-) -> Result<(), ControllerError> {
-    assert!(usize::from(group_id) < configuration.sensor_groups.len());
+/// Injection is used to test dashboard and abort logic without physically stressing hardware.
+/// It must never be allowed during a real firing.
+pub struct Injections {
+    /// Whether injecting readings is permitted at all.
+    allowed: bool,
+    /// The pending injected reading for each sensor.
+    /// The outer index is the group ID, and the inner index is the sensor ID.
+    pending: Mutex<Vec<Vec<Option<u16>>>>,
+}
 
-    // more convenient access to our sensor group data
-    let group = &configuration.sensor_groups[usize::from(group_id)];
-    // the last time that we sent a sensor status update
-    let mut last_transmission_time = SystemTime::now();
+impl Injections {
+    #[must_use]
+    /// Construct a new `Injections` with no pending readings, sized to fit every sensor in
+    /// `configuration`.
+    pub fn new(configuration: &Configuration, allowed: bool) -> Injections {
+        Injections {
+            allowed,
+            pending: Mutex::new(
+                configuration
+                    .sensor_groups
+                    .iter()
+                    .map(|group| vec![None; group.sensors.len()])
+                    .collect(),
+            ),
+        }
+    }
 
-    // the most recent reading from each sensor which has *not* already been sent to the dashboard.
-    // each element will be None if the most recent reading was sent to the dashboard.
-    let mut transmission_readings: Vec<Option<(SystemTime, u16)>> = vec![None; group.sensors.len()];
+    /// Request that the next reading on a sensor be replaced with `reading`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if injection is not allowed, if the sensor does not
+    /// exist, or if the internal lock is poisoned.
+    pub fn inject(
+        &self,
+        group_id: u8,
+        sensor_id: u8,
+        reading: u16,
+    ) -> Result<(), execution::Error> {
+        if !self.allowed {
+            return Err(execution::Error::InjectionForbidden);
+        }
 
-    // most recent values read, to be logged.
-    // in each queue, the "back" contains the most recent readings and the "front" contains the
-    // oldest ones.
-    let mut most_recent_readings: Vec<VecDeque<(SystemTime, u16, f64)>> =
-        vec![VecDeque::new(); group.sensors.len()];
+        let mut pending = self.pending.lock()?;
+        let slot = pending
+            .get_mut(usize::from(group_id))
+            .and_then(|group| group.get_mut(usize::from(sensor_id)))
+            .ok_or(execution::Error::SensorOutOfBounds)?;
+        *slot = Some(reading);
 
-    // Rolling average values for sensor readings.
-    let mut rolling_averages: Vec<f64> = group
-        .sensors
-        .iter()
-        .map(|sensor| {
-            // extract the middle value of the range to seed our rolling average so that the sensor
-            // doesn't immediately start in a "bad" spot
-            if let Some((min, max)) = sensor.range {
-                (min + max) / 2.0
-            } else {
-                0.0
-            }
-        })
-        .collect();
+        Ok(())
+    }
 
-    let standby_period = Duration::from_secs(1) / group.frequency_standby;
-    let ignition_period = Duration::from_secs(1) / group.frequency_ignition;
-    let transmission_period = Duration::from_secs(1) / group.frequency_transmission;
+    /// Take the pending injected reading for a sensor, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    fn take(&self, group_id: u8, sensor_id: usize) -> Result<Option<u16>, ControllerError> {
+        Ok(self.pending.lock()?[usize::from(group_id)][sensor_id].take())
+    }
+}
 
-    while state.status()? != State::Quit {
-        // read from each device
-        for (idx, sensor) in group.sensors.iter().enumerate() {
-            let Ok(mut adc_guard) = adcs[usize::from(sensor.adc)].lock() else {
-                #[allow(unused_must_use)]{
-                    user_log.critical(&format!("unable to acquire mutex on sensor ADC for {} due to poisoning", sensor.label));
-                }
-                continue;
-            };
-            let adc_read_result = adc_guard.read(sensor.channel);
-            let Ok(reading) = adc_read_result else {
-                #[allow(unused_must_use)] {
-                    user_log.warn(&format!("unable to read {} due to error: {adc_read_result:?}", sensor.label));
-                }
-                continue;
-            };
-            let read_time = SystemTime::now();
-            let calibrated_value =
-                f64::from(reading) * sensor.calibration_slope + sensor.calibration_intercept;
-            most_recent_readings[idx].push_back((read_time, reading, calibrated_value));
-            transmission_readings[idx] = Some((read_time, reading));
-            // update rolling averages
-            let width = sensor.rolling_average_width.unwrap_or(1);
-            let rolling_avg = (rolling_averages[idx] * (f64::from(width - 1)) + calibrated_value)
-                / f64::from(width);
-            rolling_averages[idx] = rolling_avg;
+/// Requests for every log to be written out and synced to disk, so that the operator can be sure
+/// that no data will be lost if the controller loses power.
+///
+/// Sensor threads hold recent readings in memory until a log buffer fills, so a flush first asks
+/// each sensor thread to write out its readings.
+/// The sensor threads never wait on a flush; they only write out their readings on their next
+/// loop, as they would when a log buffer fills.
+/// The files are then synced by the thread which requested the flush.
+pub struct Flusher {
+    /// The number of flushes which have been requested.
+    requested: Mutex<u64>,
+    /// The number of requested flushes for which each sensor group has written out its readings,
+    /// indexed by group ID.
+    completed: Mutex<Vec<u64>>,
+    /// A condition variable notified whenever a sensor group completes a flush.
+    progress: Condvar,
+    /// Handles to every log file, used only for syncing.
+    /// These may be clones of the handles used for writing, since syncing a clone syncs the whole
+    /// file.
+    files: Vec<SyncHandle>,
+}
 
-            // if rolling average went out of bounds, immediately start emergency stopping
-            if let Some((min, max)) = sensor.range {
-                #[allow(unused_must_use)]
-                if rolling_avg < min || max < rolling_avg {
-                    user_log.warn(&format!(
-                        "Sensor {} was out of bounds with value {rolling_avg}, attempting emergency stop", 
-                        sensor.label
-                    ));
-                    // oh no! a sensor is now in an illegal range!
-                    // spin up another thread to emergency stop.
-                    // this may return an error due to illegal transistion, but that is not our
-                    // problem.
-                    thread_scope.spawn(|| {
-                        emergency_stop(configuration, driver_lines, state);
-                    });
-                }
-            }
+impl Flusher {
+    #[must_use]
+    /// Construct a new `Flusher` for the sensor groups in `configuration`, which syncs `files`.
+    pub fn new(configuration: &Configuration, files: Vec<SyncHandle>) -> Flusher {
+        Flusher {
+            requested: Mutex::new(0),
+            completed: Mutex::new(vec![0; configuration.sensor_groups.len()]),
+            progress: Condvar::new(),
+            files,
         }
+    }
 
-        // transmit data to the dashboard if it's been long enough since our last transmission
-        if SystemTime::now() > last_transmission_time + transmission_period {
-            if dashboard_stream.has_target()? {
-                // send message to dashboard
-                dashboard_stream.send(&Message::SensorValue {
-                    group_id,
-                    readings: &transmission_readings
-                        .iter()
-                        .enumerate()
-                        .filter_map(|(sensor_id, opt)| {
-                            #[allow(clippy::cast_possible_truncation)]
-                            opt.map(|(time, reading)| SensorReading {
-                                sensor_id: sensor_id as u8,
-                                reading,
-                                time,
-                            })
-                        })
-                        .collect::<Vec<_>>(),
+    /// Write out the readings held by every sensor thread, and then sync every log file to disk.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any sensor thread does not write out its readings
+    /// within `timeout`, if syncing a file fails, or if an internal lock is poisoned.
+    pub fn flush(&self, timeout: Duration) -> Result<(), execution::Error> {
+        let target = {
+            let mut requested = self.requested.lock()?;
+            *requested += 1;
+            *requested
+        };
+
+        let (completed, wait) =
+            self.progress
+                .wait_timeout_while(self.completed.lock()?, timeout, |completed| {
+                    completed.iter().any(|&n| n < target)
                 })?;
-            }
+        drop(completed);
+        if wait.timed_out() {
+            return Err(execution::Error::FlushTimeout);
+        }
 
-            last_transmission_time = SystemTime::now();
-            transmission_readings = vec![None; group.sensors.len()];
+        for file in &self.files {
+            file.sync_all().map_err(execution::Error::Sync)?;
         }
 
-        for (sensor_id, reading_queue) in most_recent_readings.iter_mut().enumerate() {
-            if reading_queue.len() >= configuration.log_buffer_size {
-                #[allow(unused_must_use)]
-                if let Err(e) = write_sensor_log(&mut log_files[sensor_id], reading_queue.iter()) {
-                    user_log.warn(&format!(
-                        "unable to write data for sensor {}: {e:?}",
-                        group.sensors[sensor_id].label
-                    ));
-                }
+        Ok(())
+    }
 
-                reading_queue.clear();
-            }
+    /// Determine whether sensor group `group_id` has a flush to complete.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an internal lock is poisoned.
+    fn pending(&self, group_id: u8) -> Result<bool, ControllerError> {
+        let requested = *self.requested.lock()?;
+        Ok(self.completed.lock()?[usize::from(group_id)] < requested)
+    }
+
+    /// Mark that sensor group `group_id` has written out its readings for every flush requested so
+    /// far.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an internal lock is poisoned.
+    fn complete(&self, group_id: u8) -> Result<(), ControllerError> {
+        let requested = *self.requested.lock()?;
+        self.completed.lock()?[usize::from(group_id)] = requested;
+        self.progress.notify_all();
+        Ok(())
+    }
+}
+
+/// A pause on the logging and streaming of every sensor.
+///
+/// While paused, sensor threads keep reading their sensors and checking them against their ranges,
+/// so that an abort is never missed, but their readings are neither logged nor sent to the
+/// dashboard.
+/// Each sensor log records the start and end of a pause with a marker row.
+pub struct Pause {
+    /// Whether logging and streaming are currently paused.
+    paused: AtomicBool,
+}
+
+impl Pause {
+    #[must_use]
+    /// Construct a new `Pause` which is not in place.
+    pub fn new() -> Pause {
+        Pause {
+            paused: AtomicBool::new(false),
         }
+    }
 
-        // use the system state to determine how long to sleep until the next loop.
-        // standby means we are sampling slowly, and anything else means we sample quickly.
-        let sleep_time = match state.status()? {
-            State::Standby => standby_period,
-            _ => ignition_period,
-        };
+    /// Pause or resume logging and streaming.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if logging and streaming are already in the requested
+    /// condition.
+    pub fn set(&self, paused: bool) -> Result<(), execution::Error> {
+        if self.paused.swap(paused, Ordering::Relaxed) == paused {
+            return Err(if paused {
+                execution::Error::AlreadyPaused
+            } else {
+                execution::Error::NotPaused
+            });
+        }
+        Ok(())
+    }
 
-        // now take a nap until we next need to get data
-        sleep(sleep_time);
+    #[must_use]
+    /// Determine whether logging and streaming are currently paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
     }
+}
 
-    // we are now quitting
-    Ok(())
+impl Default for Pause {
+    fn default() -> Self {
+        Pause::new()
+    }
 }
 
-#[allow(dead_code)]
-/// Periodically check in on the status of the drivers, and log that status.
-/// Will also transmit that driver status to the dashboard.
+/// Requests to restart the threads of sensor groups without restarting the whole controller.
 ///
-/// # Inputs
-///
-/// * `configuration`: The configuration for the current mode of the controller.
-/// * `driver_lines`: The driver GPIO pins.
-/// * `log_file`: The file to which logs should be written.
-///     Information will be written to the log file in the following format:
-///     ```text
-///      {time},{driver0_status},{driver1_status},
-///
-///     ```
-///     with one row for every sample.
-///     `{time}` is the number of nanoseconds since the UNIX epoch.
-/// * `state`: The overall system state.
-///     This function will only return after `State` transitions to `State::Quit`.
-/// * `dashboard_stream`: A channel by which messages can be sent to the dashboard.
-///
-/// # Errors
+/// A sensor thread notices a request at the start of its next loop, writes out every reading it
+/// holds, and then starts over with the same log files and ADCs, as though it had just been
+/// spawned.
+/// A sensor thread which fails waits for a request instead, and then starts over in the same way.
+pub struct Restarts {
+    /// Whether each sensor group has been asked to restart and has not yet done so, indexed by
+    /// group ID.
+    requested: Vec<AtomicBool>,
+    /// Whether each sensor group has ever restarted, indexed by group ID.
+    restarted: Vec<AtomicBool>,
+    /// Whether each sensor group has a thread which can act on a request, indexed by group ID.
+    running: Vec<AtomicBool>,
+    /// Whether each sensor group has failed and not yet restarted, indexed by group ID.
+    failed: Vec<AtomicBool>,
+}
+
+/// A mark that the thread of a sensor group is running, which is cleared when it is dropped.
+pub struct Running<'a> {
+    /// The restarts of every sensor group.
+    restarts: &'a Restarts,
+    /// The ID of the group whose thread is running.
+    group_id: u8,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.restarts.running[usize::from(self.group_id)].store(false, Ordering::Relaxed);
+    }
+}
+
+/// The period at which a failed sensor thread checks for a request to restart.
+const RESTART_POLL_PERIOD: Duration = Duration::from_millis(100);
+
+impl Restarts {
+    #[must_use]
+    /// Construct a new `Restarts` for the sensor groups in `configuration`, none of which have
+    /// been asked to restart.
+    pub fn new(configuration: &Configuration) -> Restarts {
+        let flags = || {
+            configuration
+                .sensor_groups
+                .iter()
+                .map(|_| AtomicBool::new(false))
+                .collect()
+        };
+        Restarts {
+            requested: flags(),
+            restarted: flags(),
+            running: flags(),
+            failed: flags(),
+        }
+    }
+
+    #[must_use]
+    /// Mark the thread of sensor group `group_id` as running until the returned mark is dropped.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `group_id` is not the ID of a sensor group.
+    pub fn run(&self, group_id: u8) -> Running {
+        self.running[usize::from(group_id)].store(true, Ordering::Relaxed);
+        Running {
+            restarts: self,
+            group_id,
+        }
+    }
+
+    #[must_use]
+    /// Determine whether sensor group `group_id` has a running thread which can be restarted.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `group_id` is not the ID of a sensor group.
+    pub fn is_running(&self, group_id: u8) -> bool {
+        self.running[usize::from(group_id)].load(Ordering::Relaxed)
+    }
+
+    /// Ask the thread of sensor group `group_id` to restart.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `group_id` is not the ID of a sensor group.
+    pub fn request(&self, group_id: u8) {
+        self.requested[usize::from(group_id)].store(true, Ordering::Relaxed);
+    }
+
+    /// Determine whether sensor group `group_id` has been asked to restart.
+    fn pending(&self, group_id: u8) -> bool {
+        self.requested[usize::from(group_id)].load(Ordering::Relaxed)
+    }
+
+    #[must_use]
+    /// Take the request for sensor group `group_id` to restart, if there is one.
+    /// Returns `true` if the group should restart, in which case it is marked as having
+    /// restarted.
+    pub fn take(&self, group_id: u8) -> bool {
+        let requested = self.requested[usize::from(group_id)].swap(false, Ordering::Relaxed);
+        if requested {
+            self.restarted[usize::from(group_id)].store(true, Ordering::Relaxed);
+            self.failed[usize::from(group_id)].store(false, Ordering::Relaxed);
+        }
+        requested
+    }
+
+    /// Mark sensor group `group_id` as failed until it next restarts.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `group_id` is not the ID of a sensor group.
+    pub fn fail(&self, group_id: u8) {
+        self.failed[usize::from(group_id)].store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    /// Get the IDs of every sensor group which has failed and not yet restarted, in order.
+    pub fn failed(&self) -> Vec<u8> {
+        #[allow(clippy::cast_possible_truncation)]
+        self.failed
+            .iter()
+            .enumerate()
+            .filter(|(_, failed)| failed.load(Ordering::Relaxed))
+            .map(|(group_id, _)| group_id as u8)
+            .collect()
+    }
+
+    /// Wait until sensor group `group_id` is asked to restart or the controller quits.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the state lock is poisoned.
+    pub fn await_request(&self, group_id: u8, state: &Guard) -> Result<(), crate::state::Error> {
+        let mut status = state.status()?;
+        while status != State::Quit && !self.pending(group_id) {
+            status = state.wait_while(status, RESTART_POLL_PERIOD)?;
+        }
+        Ok(())
+    }
+
+    /// Determine whether sensor group `group_id` has ever restarted.
+    fn has_restarted(&self, group_id: u8) -> bool {
+        self.restarted[usize::from(group_id)].load(Ordering::Relaxed)
+    }
+}
+
+/// The calibrated value of every sensor in a snapshot, indexed first by group ID and then by sensor
+/// ID.
+/// A sensor which could not be read has no value.
+pub type SnapshotValues = Vec<Vec<Option<f64>>>;
+
+/// Requests for a snapshot: a single reading of every sensor, recorded together as one row of the
+/// snapshot log.
 ///
-/// This function will return an error if we are unable to acquire a lock or if we are unable to
-/// write to the message log.
+/// As with a flush, a snapshot asks each sensor thread to contribute the readings from its next
+/// loop, so every group is read as soon as it can get the bus.
+/// The sensor threads never wait on a snapshot.
 ///
-/// # Panics
+/// # Types
 ///
-/// This function will panic if the current time is before the UNIX epoch.
-pub fn driver_status_listen(
-    configuration: &Configuration,
-    driver_lines: &Mutex<Vec<impl GpioPin>>,
-    log_file: &mut impl Write,
-    user_log: &UserLog<impl Write>,
-    state: &Guard,
-    dashboard_stream: &DashChannel<impl Write, impl Write>,
-) -> Result<(), ControllerError> {
-    // the time required to sleep
-    let sleep_time = Duration::from_secs(1) / configuration.frequency_status;
-    let mut driver_states = vec![false; driver_lines.lock()?.len()];
-    while state.status()? != State::Quit {
-        // read off the states of the drivers
-        let read_time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap();
-
-        let mut drivers_guard = driver_lines.lock().map_err(|e| {
-            #[allow(unused_must_use)]
-            {
-                user_log.critical("Unable to acquire lock over driver mutex");
-            }
-            e
-        })?;
+/// * `W`: the type of the snapshot log.
+pub struct Snapshots<W: Write> {
+    /// The number of snapshots which have been requested.
+    requested: Mutex<u64>,
+    /// The number of requested snapshots to which each sensor group has contributed, and the values
+    /// it contributed to the latest one, indexed by group ID.
+    completed: Mutex<Vec<(u64, Vec<Option<f64>>)>>,
+    /// A condition variable notified whenever a sensor group contributes to a snapshot.
+    progress: Condvar,
+    /// The header row of the snapshot log, naming the column of each sensor.
+    header: String,
+    /// The snapshot log, and whether its header row has been written.
+    log: Mutex<(W, bool)>,
+}
 
-        for (driver_idx, (driver_line, state_ref)) in
-            drivers_guard.iter_mut().zip(&mut driver_states).enumerate()
+impl<W: Write> Snapshots<W> {
+    #[must_use]
+    /// Construct a new `Snapshots` for the sensor groups in `configuration`, which appends every
+    /// snapshot to `log`.
+    pub fn new(configuration: &Configuration, log: W) -> Snapshots<W> {
+        let mut header = String::from("time");
+        for sensor in configuration
+            .sensor_groups
+            .iter()
+            .flat_map(|group| &group.sensors)
         {
-            match driver_line.read() {
-                Ok(read_value) => *state_ref = read_value,
-                Err(e) => {
-                    user_log.warn(&format!(
-                        "Unable to read state of driver {driver_idx}: {e:?}"
-                    ))?;
-                }
-            }
+            header.push(',');
+            header.push_str(&sensor.label);
+        }
+
+        Snapshots {
+            requested: Mutex::new(0),
+            completed: Mutex::new(
+                configuration
+                    .sensor_groups
+                    .iter()
+                    .map(|group| (0, vec![None; group.sensors.len()]))
+                    .collect(),
+            ),
+            progress: Condvar::new(),
+            header,
+            log: Mutex::new((log, false)),
         }
+    }
 
-        // write driver status information
-        write_driver_log(log_file, read_time, &driver_states)?;
+    /// Take a snapshot of every sensor, append it to the snapshot log, and return it along with
+    /// the time at which it was requested, which is the timestamp shared by the whole snapshot.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any sensor thread does not contribute its readings
+    /// within `timeout`, if writing to the snapshot log fails, or if an internal lock is poisoned.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the current time is before the UNIX epoch.
+    pub fn take(
+        &self,
+        timeout: Duration,
+    ) -> Result<(SystemTime, SnapshotValues), execution::Error> {
+        let time = SystemTime::now();
+        let target = {
+            let mut requested = self.requested.lock()?;
+            *requested += 1;
+            *requested
+        };
 
-        // optionally transmit to dashboard
-        dashboard_stream.send(&Message::DriverValue {
-            values: &driver_states,
-        })?;
+        let (completed, wait) =
+            self.progress
+                .wait_timeout_while(self.completed.lock()?, timeout, |completed| {
+                    completed.iter().any(|&(n, _)| n < target)
+                })?;
+        if wait.timed_out() {
+            return Err(execution::Error::SnapshotTimeout);
+        }
+        let values: SnapshotValues = completed.iter().map(|(_, values)| values.clone()).collect();
+        drop(completed);
+
+        let mut row = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+            .to_string();
+        for value in values.iter().flatten() {
+            row.push(',');
+            if let Some(value) = value {
+                row.push_str(&value.to_string());
+            }
+        }
+        let mut log = self.log.lock()?;
+        let (file, header_written) = &mut *log;
+        if !*header_written {
+            writeln!(file, "{}", self.header).map_err(execution::Error::SnapshotNotLogged)?;
+            *header_written = true;
+        }
+        writeln!(file, "{row}").map_err(execution::Error::SnapshotNotLogged)?;
+        file.flush().map_err(execution::Error::SnapshotNotLogged)?;
 
-        drop(drivers_guard); // don't keep the drivers guard while we sleep!
+        Ok((time, values))
+    }
 
-        // take a nap until we are ready to send another message
-        sleep(sleep_time);
+    /// Get the number of the snapshot to which sensor group `group_id` should contribute its next
+    /// readings, or `None` if it has no snapshot to contribute to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an internal lock is poisoned.
+    fn pending(&self, group_id: u8) -> Result<Option<u64>, ControllerError> {
+        let requested = *self.requested.lock()?;
+        Ok((self.completed.lock()?[usize::from(group_id)].0 < requested).then_some(requested))
     }
 
-    Ok(())
+    /// Contribute the calibrated values `values` of every sensor in group `group_id` to every
+    /// snapshot requested up to and including snapshot number `target`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an internal lock is poisoned.
+    fn complete(
+        &self,
+        group_id: u8,
+        target: u64,
+        values: Vec<Option<f64>>,
+    ) -> Result<(), ControllerError> {
+        self.completed.lock()?[usize::from(group_id)] = (target, values);
+        self.progress.notify_all();
+        Ok(())
+    }
 }
 
-/// Write the status of the drivers to a log file.
+/// Read a channel of an ADC `oversample` times back-to-back and average the reads into a single
+/// reading, rounded to the nearest integer.
 ///
-/// # Inputs
-///
-/// * `log_file`: The file to which log information should be written.
-/// * `read_time`: The time at which the read of driver states occurred.
-/// * `driver_states`: The state of each driver.
+/// Averaging `K` reads reduces uncorrelated noise by a factor of `sqrt(K)`, which is worth about
+/// `log2(K) / 2` effective bits of resolution (for instance, one extra bit for every factor of 4).
+/// However, the sample rate available to each sensor is divided by `K`.
 ///
 /// # Errors
 ///
-/// This function will return an `Err` if writing to the log file fails.
-fn write_driver_log(
-    log_file: &mut impl Write,
-    read_time: Duration,
-    driver_states: &[bool],
-) -> std::io::Result<()> {
-    write!(log_file, "{},", read_time.as_nanos())?;
-    for driver_state in driver_states {
-        write!(log_file, "{driver_state},")?;
+/// This function will return an error if any of the reads fail.
+fn read_oversampled(
+    adc: &mut impl Adc,
+    channel: u8,
+    oversample: u8,
+) -> Result<u16, ControllerError> {
+    let count = u32::from(oversample.max(1));
+    let mut sum = 0;
+    for _ in 0..count {
+        sum += u32::from(adc.read(channel)?);
     }
-    writeln!(log_file)?;
 
-    Ok(())
+    // the average of `u16`s always fits in a `u16`
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(((sum + count / 2) / count) as u16)
 }
 
-/// Write a new log datum to the sensor log file.
+/// Read `sensor` `sensor.discard_first` times, throwing the readings away, so that the settling
+/// transient of its ADC is never reported.
+/// Injected readings are left alone, and failed reads still count as discarded.
 ///
-/// # Inputs
+/// Returns the number of readings discarded, which is zero if the sensor's ADC failed to
+/// initialize.
+fn discard_warmup(
+    sensor: &Sensor,
+    adcs: &[Option<Mutex<impl Adc>>],
+    iio_channel: Option<&mut impl Adc>,
+) -> Result<u8, ControllerError> {
+    if let Some(iio_channel) = iio_channel {
+        for _ in 0..sensor.discard_first {
+            let _ = iio_channel.read(0);
+        }
+    } else {
+        let Some(adc) = &adcs[usize::from(sensor.adc)] else {
+            return Ok(0);
+        };
+        let mut adc = adc.lock()?;
+        for _ in 0..sensor.discard_first {
+            let _ = adc.read(sensor.channel);
+        }
+    }
+
+    Ok(sensor.discard_first)
+}
+
+/// Determine whether the raw ADC reading `reading` is at either end of the ADC's range.
+fn is_railed(reading: u16) -> bool {
+    reading == 0 || reading >= ADC_MAX_READING
+}
+
+/// Determine whether a reading from `sensor` is saturated.
+/// A reading is saturated if the raw reading `reading` is at either end of the ADC's range, or if
+/// the calibrated value `value` is outside the full-scale range of the sensor.
+/// The range of an IIO channel is unknown, so a sensor read through IIO is only checked against
+/// its full-scale range.
 ///
-/// * `log_file`: The file to which the log will be written.
-///     There must be exactly one log file per sensor.
-/// * `adc_readings`: All the most recent sensor readings to be written to the file.
+/// A saturated reading usually means that the transducer railed or that its wiring broke, so its
+/// value should not be trusted.
+fn is_saturated(sensor: &Sensor, reading: u16, value: f64) -> bool {
+    (sensor.iio_channel.is_none() && is_railed(reading))
+        || sensor.full_scale_min.map_or(false, |min| value < min)
+        || sensor.full_scale_max.map_or(false, |max| max < value)
+}
+
+#[allow(dead_code)]
+/// A record of the most recent readings from each sensor in a group, kept so that the lead-up to
+/// an emergency stop can be logged.
 ///
-/// # Results
+/// Readings are kept for a fixed duration, after which they are discarded.
+pub struct PreTrigger {
+    /// The length of time for which readings are kept.
+    duration: Duration,
+    /// The kept readings for each sensor, with the oldest reading at the front of each queue.
+    /// Each element is a tuple of the time of the reading, the raw ADC reading, and the calibrated
+    /// value.
+    readings: Vec<VecDeque<(SystemTime, u16, f64)>>,
+}
+
+impl PreTrigger {
+    #[must_use]
+    /// Construct a new, empty `PreTrigger` for a group of `n_sensors` sensors, which keeps readings
+    /// for `duration`.
+    pub fn new(duration: Duration, n_sensors: usize) -> PreTrigger {
+        PreTrigger {
+            duration,
+            readings: vec![VecDeque::new(); n_sensors],
+        }
+    }
+
+    /// Record a new reading for the sensor with ID `sensor_id`, discarding any of its readings
+    /// which are more than `self.duration` older than the new one.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `sensor_id` is not the ID of a sensor in this group.
+    pub fn push(&mut self, sensor_id: usize, time: SystemTime, reading: u16, value: f64) {
+        let queue = &mut self.readings[sensor_id];
+        queue.push_back((time, reading, value));
+        while let Some(&(oldest, _, _)) = queue.front() {
+            match time.duration_since(oldest) {
+                Ok(age) if age > self.duration => queue.pop_front(),
+                _ => break,
+            };
+        }
+    }
+
+    /// Write out and discard every kept reading.
+    ///
+    /// Each reading is written as one row of the form
+    /// `{group_id},{sensor_id},{time},{reading},{value}`, where `{time}` is the number of
+    /// nanoseconds since the UNIX epoch.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to `log_file` fails.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if a kept reading was taken before the UNIX epoch.
+    pub fn flush(&mut self, group_id: u8, log_file: &mut impl Write) -> std::io::Result<()> {
+        for (sensor_id, queue) in self.readings.iter_mut().enumerate() {
+            for (time, reading, value) in queue.drain(..) {
+                writeln!(
+                    log_file,
+                    "{group_id},{sensor_id},{},{reading},{value}",
+                    time.duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap()
+                        .as_nanos()
+                )?;
+            }
+        }
+
+        log_file.flush()
+    }
+}
+
+/// A filter which reports the level of a bouncing digital input only once it has settled.
+pub struct Debouncer {
+    /// The time for which the input must hold a new level before the level is settled.
+    interval: Duration,
+    /// The most recently settled level, or `None` if the input has never been sampled.
+    settled: Option<bool>,
+    /// The time at which the input was first seen at a level other than the settled one, if it is
+    /// currently at such a level.
+    changed_since: Option<Instant>,
+}
+
+impl Debouncer {
+    #[must_use]
+    /// Construct a new `Debouncer` for an input which has not yet been sampled, which settles on a
+    /// new level once the input has held it for `interval`.
+    pub fn new(interval: Duration) -> Debouncer {
+        Debouncer {
+            interval,
+            settled: None,
+            changed_since: None,
+        }
+    }
+
+    /// Record a sample of the input at level `level`, taken at time `now`, and return the settled
+    /// level.
+    ///
+    /// The first sample is settled immediately.
+    /// Afterward, a new level is only settled once every sample for `interval` has had that level.
+    pub fn update(&mut self, level: bool, now: Instant) -> bool {
+        let Some(settled) = self.settled else {
+            self.settled = Some(level);
+            return level;
+        };
+        if level == settled {
+            self.changed_since = None;
+            return settled;
+        }
+
+        let since = *self.changed_since.get_or_insert(now);
+        if now.duration_since(since) >= self.interval {
+            self.settled = Some(level);
+            self.changed_since = None;
+            return level;
+        }
+        settled
+    }
+}
+
+/// The minimum time between warnings about excessive loop jitter in one sensor group.
+const JITTER_WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A monitor of the jitter of a sensor group's loop, as measured by its `LoopSchedule`.
+pub struct JitterMonitor {
+    /// The jitter above which a loop is considered questionable.
+    threshold: Duration,
+    /// The time of the most recent warning, if any warning has been given.
+    last_warning: Option<Instant>,
+    /// The statistics gathered since the most recent warning.
+    stats: JitterStats,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Statistics on the jitter of a sensor group's loop over some span of loops.
+pub struct JitterStats {
+    /// The number of loops measured.
+    pub n_loops: u32,
+    /// The number of loops whose jitter exceeded the threshold.
+    pub n_exceeded: u32,
+    /// The total jitter of every loop measured.
+    pub total: Duration,
+    /// The largest jitter of any loop measured.
+    pub max: Duration,
+}
+
+impl JitterMonitor {
+    #[must_use]
+    /// Construct a new `JitterMonitor` which considers any jitter above `threshold` excessive.
+    pub fn new(threshold: Duration) -> JitterMonitor {
+        JitterMonitor {
+            threshold,
+            last_warning: None,
+            stats: JitterStats::default(),
+        }
+    }
+
+    /// Record that a loop began at time `now` with a jitter of `jitter`.
+    ///
+    /// Returns the statistics gathered since the last warning if a warning is due: that is, if
+    /// the loop's jitter exceeded the threshold and no warning has been given in the last
+    /// `JITTER_WARN_INTERVAL`.
+    /// The statistics are reset after each warning.
+    pub fn record(&mut self, now: Instant, jitter: Duration) -> Option<JitterStats> {
+        self.stats.n_loops += 1;
+        self.stats.total += jitter;
+        self.stats.max = self.stats.max.max(jitter);
+        if jitter <= self.threshold {
+            return None;
+        }
+        self.stats.n_exceeded += 1;
+
+        if self.last_warning.map_or(false, |warning| {
+            now.duration_since(warning) < JITTER_WARN_INTERVAL
+        }) {
+            return None;
+        }
+        self.last_warning = Some(now);
+        Some(std::mem::take(&mut self.stats))
+    }
+}
+
+/// The wall-clock time and monotonic instant at which the first `MonotonicClock` was created, which
+/// every later clock shares.
+static CLOCK_BASE: Mutex<Option<(SystemTime, Instant)>> = Mutex::new(None);
+
+/// A source of wall-clock times which advances with the monotonic clock.
 ///
-/// Will write the data from the ADC readings in a CSV format to the file.
-/// There will be two "columns" to this CSV data:
-/// 1. The time since the UNIX epoch, in nanoseconds.
-/// 1. The raw ADC value of the sensor at this time.
-/// Will also include a trailing newline after the last row.
-/// At the end of writing all of these lines, the file will be "flushed," meaning that all data will
-/// be immediately saved.
+/// The times it gives never go backwards, and are spaced exactly as far apart as the instants they
+/// were computed from, even if the system clock is adjusted while the controller runs.
+/// Every clock shares one base, so the logs of every sensor group, including a group which has
+/// restarted, are stamped on the same timeline.
+struct MonotonicClock {
+    /// The wall-clock time when the first clock was created.
+    base_time: SystemTime,
+    /// The monotonic instant when the first clock was created.
+    base_instant: Instant,
+}
+
+impl MonotonicClock {
+    /// Construct a new `MonotonicClock` with the shared base, which agrees with the system clock at
+    /// the time the first clock was created.
+    fn new() -> MonotonicClock {
+        // the base is only ever written once, so a panic while holding the lock cannot corrupt it
+        let mut base = CLOCK_BASE.lock().unwrap_or_else(PoisonError::into_inner);
+        let (base_time, base_instant) =
+            *base.get_or_insert_with(|| (SystemTime::now(), Instant::now()));
+        MonotonicClock {
+            base_time,
+            base_instant,
+        }
+    }
+
+    /// Get the wall-clock time corresponding to `instant`, which must not be before the first
+    /// clock was created.
+    fn at(&self, instant: Instant) -> SystemTime {
+        self.base_time + instant.saturating_duration_since(self.base_instant)
+    }
+}
+
+/// The schedule of a sensor group's loop.
 ///
-/// For instance, if a sensor had a reading of 42 at a time of 1 second, 500 nanoseconds after the
-/// UNIX epoch began, and the calibrated reading from the sensor was 1.25, the following text would
-/// be written:
+/// Each loop is due exactly one period after the previous loop was due, no matter how long the
+/// work in each loop takes, so the jitter of a loop is how late it began relative to when it was
+/// due.
+struct LoopSchedule {
+    /// The time at which the current loop was due to begin.
+    due: Instant,
+}
+
+impl LoopSchedule {
+    /// Construct a new `LoopSchedule` whose first loop is due at `start`.
+    fn new(start: Instant) -> LoopSchedule {
+        LoopSchedule { due: start }
+    }
+
+    /// Record that a loop began at time `now`, where loops are meant to be `period` apart, and
+    /// return the loop's jitter.
+    ///
+    /// A loop which began early (because a change of state cut the wait before it short) or which
+    /// is at least a whole period late starts the schedule over from `now`, so that missed loops
+    /// are skipped rather than run in a burst.
+    fn begin(&mut self, now: Instant, period: Duration) -> Duration {
+        let jitter = now.saturating_duration_since(self.due);
+        if now < self.due || jitter >= period {
+            self.due = now;
+        }
+        jitter
+    }
+
+    /// Schedule the next loop `period` after the current loop was due, and return the time at
+    /// which it is due.
+    fn next(&mut self, period: Duration) -> Instant {
+        self.due += period;
+        self.due
+    }
+}
+
+impl JitterStats {
+    #[must_use]
+    /// Get the mean jitter of the loops measured.
+    pub fn mean(&self) -> Duration {
+        self.total.checked_div(self.n_loops).unwrap_or_default()
+    }
+}
+
+/// A fixed schedule of periodic events, such as sending driver status to the dashboard.
 ///
-/// ```text
-/// 1000000500,42,1.24
+/// Events are due at a fixed cadence from the start of the schedule.
+/// If an event is late by more than a whole period, the missed events are skipped rather than
+/// happening in a burst.
+pub struct Cadence {
+    /// The time between events.
+    period: Duration,
+    /// The time at which the next event is due.
+    next: Instant,
+}
+
+impl Cadence {
+    #[must_use]
+    /// Construct a new `Cadence` with a period of `period`, whose first event is due at `start`.
+    pub fn new(period: Duration, start: Instant) -> Cadence {
+        Cadence {
+            period,
+            next: start,
+        }
+    }
+
+    /// Determine whether an event is due at time `now`.
+    /// If one is, it is marked as having happened, and the next event is scheduled.
+    pub fn due(&mut self, now: Instant) -> bool {
+        if now < self.next {
+            return false;
+        }
+        self.next += self.period;
+        if self.next <= now {
+            self.next = now + self.period;
+        }
+        true
+    }
+
+    #[must_use]
+    /// Get the time at which the next event is due.
+    pub fn next(&self) -> Instant {
+        self.next
+    }
+}
+
+/// A monitor of a sensor's raw readings which detects when they stay pinned at either end of the
+/// ADC's range.
 ///
-/// ```
+/// A reading of exactly zero or full scale for a long time is almost never a real measurement: it
+/// usually means that the sensor's input is disconnected or shorted.
+pub struct PinnedMonitor {
+    /// The time for which a reading must stay pinned before it is reported.
+    timeout: Duration,
+    /// The pinned reading and the time at which it was first read, if the latest reading was
+    /// pinned.
+    pinned_since: Option<(u16, Instant)>,
+    /// Whether the current pinned reading has already been reported.
+    reported: bool,
+}
+
+impl PinnedMonitor {
+    #[must_use]
+    /// Construct a new `PinnedMonitor` which reports readings pinned for longer than `timeout`.
+    pub fn new(timeout: Duration) -> PinnedMonitor {
+        PinnedMonitor {
+            timeout,
+            pinned_since: None,
+            reported: false,
+        }
+    }
+
+    /// Record that the raw reading `reading` was taken at time `now`.
+    ///
+    /// Returns `true` exactly once for each span of identical pinned readings, as soon as the span
+    /// has lasted longer than the timeout.
+    pub fn record(&mut self, reading: u16, now: Instant) -> bool {
+        if !is_railed(reading) {
+            self.pinned_since = None;
+            return false;
+        }
+        match self.pinned_since {
+            Some((pinned, since)) if pinned == reading => {
+                if self.reported || now.duration_since(since) <= self.timeout {
+                    return false;
+                }
+                self.reported = true;
+                true
+            }
+            _ => {
+                self.pinned_since = Some((reading, now));
+                self.reported = false;
+                false
+            }
+        }
+    }
+}
+
+/// The most recent readings from every sensor, kept so that a dashboard which connects partway
+/// through a test can fill in its graphs.
+/// The latest reading of every sensor is always kept, even if no history is requested, so that it
+/// can be used as a calibration reference.
+pub struct History {
+    /// The number of readings which may be requested for each sensor.
+    size: usize,
+    /// The kept readings, indexed first by group ID and then by sensor ID, with the oldest reading
+    /// at the front of each queue.
+    readings: Vec<Mutex<Vec<VecDeque<SensorReading>>>>,
+    /// The latest value of every virtual sensor, indexed first by group ID and then by virtual
+    /// sensor ID.
+    /// A virtual sensor which has not been derived yet has no value.
+    virtual_values: Vec<Mutex<Vec<Option<f64>>>>,
+}
+
+impl History {
+    #[must_use]
+    /// Construct a new, empty `History` for the sensor groups in `configuration`, which keeps
+    /// `configuration.history_size` readings for each sensor.
+    pub fn new(configuration: &Configuration) -> History {
+        History {
+            size: configuration.history_size.unwrap_or(0),
+            readings: configuration
+                .sensor_groups
+                .iter()
+                .map(|group| Mutex::new(vec![VecDeque::new(); group.sensors.len()]))
+                .collect(),
+            virtual_values: configuration
+                .sensor_groups
+                .iter()
+                .map(|group| Mutex::new(vec![None; group.virtual_sensors.len()]))
+                .collect(),
+        }
+    }
+
+    /// Record a new reading from a sensor in group `group_id`, discarding its oldest reading if
+    /// too many are kept.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an internal lock is poisoned.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `group_id` or the reading's sensor ID does not refer to an
+    /// existing sensor.
+    pub fn push(&self, group_id: u8, reading: SensorReading) -> Result<(), ControllerError> {
+        let mut group = self.readings[usize::from(group_id)].lock()?;
+        let queue = &mut group[usize::from(reading.sensor_id)];
+        if queue.len() >= self.size.max(1) {
+            queue.pop_front();
+        }
+        queue.push_back(reading);
+        Ok(())
+    }
+
+    /// Get up to `count` of the most recent readings from sensor `sensor_id` in group `group_id`,
+    /// oldest first.
+    /// Fewer readings are returned if fewer are kept.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sensor does not exist or if an internal lock is
+    /// poisoned.
+    pub fn recent(
+        &self,
+        group_id: u8,
+        sensor_id: u8,
+        count: usize,
+    ) -> Result<Vec<SensorReading>, execution::Error> {
+        let count = count.min(self.size);
+        let group = self
+            .readings
+            .get(usize::from(group_id))
+            .ok_or(execution::Error::SensorOutOfBounds)?
+            .lock()?;
+        let queue = group
+            .get(usize::from(sensor_id))
+            .ok_or(execution::Error::SensorOutOfBounds)?;
+        Ok(queue
+            .iter()
+            .skip(queue.len().saturating_sub(count))
+            .copied()
+            .collect())
+    }
+
+    /// Record the latest value of every virtual sensor in group `group_id`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an internal lock is poisoned.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `group_id` does not refer to an existing group.
+    pub fn set_virtual(&self, group_id: u8, values: &[Option<f64>]) -> Result<(), ControllerError> {
+        self.virtual_values[usize::from(group_id)]
+            .lock()?
+            .copy_from_slice(values);
+        Ok(())
+    }
+
+    /// Get the latest value of every virtual sensor in group `group_id`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the group does not exist or if an internal lock is
+    /// poisoned.
+    pub fn latest_virtual(&self, group_id: u8) -> Result<Vec<Option<f64>>, execution::Error> {
+        Ok(self
+            .virtual_values
+            .get(usize::from(group_id))
+            .ok_or(execution::Error::SensorOutOfBounds)?
+            .lock()?
+            .clone())
+    }
+
+    /// Get the most recent reading from sensor `sensor_id` in group `group_id`, or `None` if it
+    /// has not been read yet.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sensor does not exist or if an internal lock is
+    /// poisoned.
+    pub fn latest(
+        &self,
+        group_id: u8,
+        sensor_id: u8,
+    ) -> Result<Option<SensorReading>, execution::Error> {
+        let group = self
+            .readings
+            .get(usize::from(group_id))
+            .ok_or(execution::Error::SensorOutOfBounds)?
+            .lock()?;
+        Ok(group
+            .get(usize::from(sensor_id))
+            .ok_or(execution::Error::SensorOutOfBounds)?
+            .back()
+            .copied())
+    }
+}
+
+/// Whether the controller has finished initializing, so that no hardware is commanded before then.
 ///
-/// If the entire process succeeds, this function will return `Ok(())`.
+/// All GPIO is acquired before any sensor thread starts, so the controller is ready once every
+/// sensor group has completed its first successful read of every enabled sensor.
+pub struct Readiness {
+    /// Whether each sensor group has completed its first read of every sensor, indexed by group ID.
+    groups_ready: Mutex<Vec<bool>>,
+    /// Whether commands which actuate hardware are rejected until the controller is ready.
+    gate: bool,
+}
+
+impl Readiness {
+    #[must_use]
+    /// Construct a new `Readiness` for the sensor groups in `configuration`, none of which have
+    /// completed a read.
+    pub fn new(configuration: &Configuration) -> Readiness {
+        Readiness {
+            groups_ready: Mutex::new(vec![false; configuration.sensor_groups.len()]),
+            gate: configuration.ready_gate,
+        }
+    }
+
+    /// Determine whether every sensor group has completed its first read.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    pub fn is_ready(&self) -> Result<bool, ControllerError> {
+        Ok(self.groups_ready.lock()?.iter().all(|&ready| ready))
+    }
+
+    /// Check that the controller may command hardware.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `Err(execution::Error::NotReady)` if the ready gate is enabled
+    /// and the controller is not yet ready, or an error if the internal lock is poisoned.
+    pub fn check(&self) -> Result<(), execution::Error> {
+        if self.gate && !self.groups_ready.lock()?.iter().all(|&ready| ready) {
+            return Err(execution::Error::NotReady);
+        }
+        Ok(())
+    }
+
+    /// Mark that sensor group `group_id` has completed its first read of every sensor.
+    /// Returns `true` if this made the whole controller ready.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    fn group_ready(&self, group_id: u8) -> Result<bool, ControllerError> {
+        let mut groups_ready = self.groups_ready.lock()?;
+        let was_ready = groups_ready.iter().all(|&ready| ready);
+        groups_ready[usize::from(group_id)] = true;
+        Ok(!was_ready && groups_ready.iter().all(|&ready| ready))
+    }
+}
+
+/// The period at which the liveness of every sensor group is checked.
+const STALL_POLL_PERIOD: Duration = Duration::from_millis(50);
+
+/// A count of the loops completed by each sensor group, used to notice a group whose thread has
+/// stopped making progress.
+/// Alongside the count, each group records its failed reads and the jitter of its latest loop, so
+/// that its health can be reported as metrics.
+pub struct Liveness {
+    /// The number of loops each sensor group has begun, indexed by group ID.
+    loops: Vec<AtomicU64>,
+    /// The number of failed reads of any sensor in each sensor group, indexed by group ID.
+    read_errors: Vec<AtomicU64>,
+    /// The jitter, in nanoseconds, of the most recent loop of each sensor group, indexed by group
+    /// ID.
+    jitter_ns: Vec<AtomicU64>,
+}
+
+impl Liveness {
+    #[must_use]
+    /// Construct a new `Liveness` for the sensor groups in `configuration`, none of which have
+    /// begun a loop.
+    pub fn new(configuration: &Configuration) -> Liveness {
+        let counters = || {
+            configuration
+                .sensor_groups
+                .iter()
+                .map(|_| AtomicU64::new(0))
+                .collect()
+        };
+        Liveness {
+            loops: counters(),
+            read_errors: counters(),
+            jitter_ns: counters(),
+        }
+    }
+
+    /// Record that sensor group `group_id` has begun another loop.
+    fn beat(&self, group_id: u8) {
+        self.loops[usize::from(group_id)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a read of a sensor in sensor group `group_id` failed.
+    fn read_error(&self, group_id: u8) {
+        self.read_errors[usize::from(group_id)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that the most recent loop of sensor group `group_id` had a jitter of `jitter`.
+    fn record_jitter(&self, group_id: u8, jitter: Duration) {
+        self.jitter_ns[usize::from(group_id)].store(
+            u64::try_from(jitter.as_nanos()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    #[must_use]
+    /// Get the number of loops which each sensor group has begun, indexed by group ID.
+    pub fn counts(&self) -> Vec<u64> {
+        load_all(&self.loops)
+    }
+
+    #[must_use]
+    /// Get the number of failed sensor reads in each sensor group, indexed by group ID.
+    pub fn read_errors(&self) -> Vec<u64> {
+        load_all(&self.read_errors)
+    }
+
+    #[must_use]
+    /// Get the jitter of the most recent loop of each sensor group, indexed by group ID.
+    pub fn jitters(&self) -> Vec<Duration> {
+        load_all(&self.jitter_ns)
+            .into_iter()
+            .map(Duration::from_nanos)
+            .collect()
+    }
+}
+
+/// Load the value of every counter in `counters`.
+fn load_all(counters: &[AtomicU64]) -> Vec<u64> {
+    counters
+        .iter()
+        .map(|count| count.load(Ordering::Relaxed))
+        .collect()
+}
+
+/// Watch the liveness of every sensor group, and report any group which stops making progress for
+/// longer than `configuration.stall_timeout_ms`.
+/// If `configuration.stall_estop` is set, a stall also triggers an emergency stop.
+/// Returns immediately if no stall timeout is configured; otherwise, will loop until the controller
+/// quits.
 ///
-/// # Errors
+/// A group is only reported once per stall, and its recovery is logged if it starts making
+/// progress again.
 ///
-/// This function will return an `Err` if writing to the log file fails.
+/// # Inputs
 ///
-/// # Panics
+/// * `configuration`: The configuration for the current mode of the controller.
+/// * `driver_lines`: The driver GPIO pins, used to perform the emergency stop sequence.
+/// * `liveness`: The count of loops completed by each sensor group.
+/// * `user_log`: The log to which stalls and recoveries are reported.
+/// * `state`: The overall system state.
+/// * `dashboard`: A channel by which each stall is reported to the dashboard.
 ///
-/// This function will panic if a time contained in the ADC readings was before the UNIX epoch.
-fn write_sensor_log<'a>(
-    log_file: &mut impl Write,
-    adc_readings: impl IntoIterator<Item = &'a (SystemTime, u16, f64)>,
-) -> std::io::Result<()> {
-    for (sys_time, reading, calib) in adc_readings {
-        let since_epoch_time = sys_time.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+/// # Errors
+///
+/// This function will return an error if a lock is poisoned or if we are unable to write to the
+/// user log or the dashboard.
+pub fn stall_watch(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    liveness: &Liveness,
+    user_log: &UserLog<impl Write>,
+    state: &Guard,
+    dashboard: &DashChannel<impl Write, impl Write>,
+) -> Result<(), ControllerError> {
+    let Some(timeout_ms) = configuration.stall_timeout_ms else {
+        return Ok(());
+    };
+    let timeout = Duration::from_millis(u64::from(timeout_ms));
 
-        writeln!(
-            log_file,
-            "{},{reading},{calib}",
-            since_epoch_time.as_nanos()
-        )?;
+    let mut last_counts = liveness.counts();
+    let mut last_progress = vec![Instant::now(); last_counts.len()];
+    let mut stalled = vec![false; last_counts.len()];
+
+    while state.status()? != State::Quit {
+        sleep(STALL_POLL_PERIOD);
+        let now = Instant::now();
+
+        for (group_id, count) in liveness.counts().into_iter().enumerate() {
+            let label = &configuration.sensor_groups[group_id].label;
+            if count != last_counts[group_id] {
+                last_counts[group_id] = count;
+                last_progress[group_id] = now;
+                if stalled[group_id] {
+                    stalled[group_id] = false;
+                    user_log.info(&format!("Sensor group {label} has recovered from a stall"))?;
+                }
+            } else if !stalled[group_id] && now.duration_since(last_progress[group_id]) >= timeout {
+                stalled[group_id] = true;
+                let message = format!(
+                    "Sensor group {label} has made no progress in {timeout_ms} ms and may be stuck"
+                );
+                user_log.critical(&message)?;
+                dashboard.send(&Message::Error {
+                    level: LogLevel::Critical,
+                    message: &message,
+                })?;
+
+                if configuration.stall_estop {
+                    user_log.critical(&format!(
+                        "Attempting emergency stop due to stall of sensor group {label}"
+                    ))?;
+                    // an emergency stop may already be in progress, which is not our problem
+                    if let Err(e) = emergency_stop(configuration, driver_lines, state, user_log) {
+                        user_log.warn(&format!(
+                            "Unable to emergency stop after stall of sensor group {label}: {e}"
+                        ))?;
+                    }
+                }
+            }
+        }
     }
 
-    log_file.flush()
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use std::{io::Cursor, thread::scope};
+#[allow(clippy::too_many_arguments)]
+/// Take the next reading of a sensor.
+/// A pending injected reading is taken in place of reading the sensor's ADC.
+///
+/// Returns the reading and whether it was injected, or `None` if the sensor could not be read.
+/// Any reason for failing to read the sensor, other than its ADC being disabled, is logged.
+///
+/// # Inputs
+///
+/// * `group_id`: The ID of the group containing the sensor.
+/// * `sensor_id`: The ID of the sensor within its group.
+/// * `sensor`: The configuration of the sensor.
+/// * `adcs`: The set of ADCs which can be read from by the sensors.
+/// * `iio_channel`: The IIO channel which the sensor reads, if it is read through IIO rather than
+///     from one of `adcs`.
+/// * `injections`: Synthetic readings which should be reported in place of real ADC readings.
+/// * `user_log`: The log to which injected readings and read failures are reported.
+/// * `liveness`: The health of every sensor group, to which read failures are counted.
+///
+/// # Errors
+///
+/// This function will return an error if the injections lock is poisoned or if an injected
+/// reading cannot be logged.
+fn acquire_reading(
+    group_id: u8,
+    sensor_id: usize,
+    sensor: &Sensor,
+    adcs: &[Option<Mutex<impl Adc>>],
+    iio_channel: Option<&mut impl Adc>,
+    injections: &Injections,
+    user_log: &UserLog<impl Write>,
+    liveness: &Liveness,
+) -> Result<Option<(u16, bool)>, ControllerError> {
+    if let Some(reading) = injections.take(group_id, sensor_id)? {
+        user_log.warn(&format!(
+            "Reporting injected reading {reading} for sensor {}",
+            sensor.label
+        ))?;
+        return Ok(Some((reading, true)));
+    }
 
-    use serde_json::Value;
+    let adc_read_result = if let Some(iio_channel) = iio_channel {
+        read_oversampled(iio_channel, 0, sensor.oversample.unwrap_or(1))
+    } else {
+        let Some(adc) = &adcs[usize::from(sensor.adc)] else {
+            // this sensor's ADC failed to initialize, so the sensor is disabled
+            return Ok(None);
+        };
+        let Ok(mut adc_guard) = adc.lock() else {
+            #[allow(unused_must_use)]
+            {
+                user_log.critical(&format!(
+                    "unable to acquire mutex on sensor ADC for {} due to poisoning",
+                    sensor.label
+                ));
+            }
+            return Ok(None);
+        };
+        read_oversampled(
+            &mut *adc_guard,
+            sensor.channel,
+            sensor.oversample.unwrap_or(1),
+        )
+    };
+    let Ok(reading) = adc_read_result else {
+        liveness.read_error(group_id);
+        #[allow(unused_must_use)]
+        {
+            user_log.warn(&format!(
+                "unable to read {} due to error: {adc_read_result:?}",
+                sensor.label
+            ));
+        }
+        return Ok(None);
+    };
+    Ok(Some((reading, false)))
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
+/// A function which will continuously listen for new data from sensors.
+/// It will loop indefinitely.
+///
+/// # Inputs
+///
+/// * `thread_scope`: A reference to a scope that this function can use to spawn other threads.
+///     This is required so that the sensor listener thread can emergency-stop, if needed.
+/// * `group_id`: The ID of the sensor group that this thread is responsible for.
+///     This is equal to the index of the sensor group in the configuration object.
+/// * `adcs`: The set of ADCs which can be read from by the sensors.
+///     An ADC is `None` if it failed to initialize, in which case its sensors are disabled and
+///     never read.
+/// * `digital_inputs`: The GPIO lines of the digital inputs in this sensor group.
+///     Each index corresponds exactly to its associated index in the group's `digital_inputs`.
+/// * `iio_channels`: The IIO channels read by the sensors in this sensor group.
+///     Each index corresponds exactly to its associated index in the group, and is `None` if the
+///     sensor is not read through IIO.
+/// * `configuration`: The primary configuration of the controller.
+/// * `driver_lines`: The GPIO lines for each driver.
+/// * `log_files`: Handles for log files associated with the sensors in this sensor group.
+///     Each index corresponds exactly to its associated index in the group.
+/// * `state`: The state of the whole system.
+///     If a sensor enters an invalid value during ignition, this thread will automatically update
+///     the state as needed.
+/// * `dashboard_stream`: A stream where messages can be sent to the dashboard.
+/// * `injections`: Synthetic readings which should be reported in place of real ADC readings.
+/// * `telemetry`: A UDP channel to which every sensor value message is additionally sent, if one
+///     is configured.
+/// * `pretrigger_file`: The log file to which the most recent readings are written whenever an
+///     emergency stop begins, if a pre-trigger duration is configured.
+/// * `tunings`: The current calibration, range, and rolling average width of every sensor.
+///     These may change while this function runs if the configuration is reloaded.
+/// * `flusher`: Requests to write out every reading held in memory.
+/// * `readiness`: The readiness of the controller, which is updated once this group has read every
+///     sensor, at which point the dashboard is told if the whole controller is ready.
+/// * `history`: The most recent readings from every sensor, to which each new reading is added.
+/// * `liveness`: The count of loops completed by each sensor group, which is advanced at the start
+///     of every loop.
+/// * `snapshots`: Requests for a snapshot, to which this group contributes the readings from its
+///     next loop.
+/// * `pause`: The pause on logging and streaming.
+///     While paused, readings are still checked against their ranges, but are neither logged nor
+///     sent to the dashboard.
+/// * `restarts`: Requests to restart this group.
+///     Once a restart is requested, this function writes out every reading it holds and returns.
+///
+/// # Errors
+///
+/// This function will return an error if the controller state status lock is poisoned, or if
+/// we are unable to write the header of a binary log file.
+///
+/// # Panics
+///
+/// This function will panic in any of the following cases:
+///
+/// * If the value of `group_id` does not correspond to an existing sensor group.
+/// * If the current system time is before the UNIX epoch.
+pub fn sensor_listen<'a>(
+    thread_scope: &'a Scope<'a, '_>,
+    group_id: u8,
+    configuration: &'a Configuration,
+    driver_lines: &'a PriorityMutex<Vec<impl GpioPin + Send + Sync>>,
+    log_files: &mut [impl Write],
+    user_log: &'a UserLog<impl Write + Send>,
+    adcs: &[Option<Mutex<impl Adc>>],
+    digital_inputs: &mut [impl GpioPin],
+    iio_channels: &mut [Option<impl Adc>],
+    state: &'a Guard,
+    dashboard_stream: &'a DashChannel<impl Write, impl Write>,
+    injections: &Injections,
+    telemetry: Option<&UdpTelemetry>,
+    pretrigger_file: Option<&Mutex<impl Write>>,
+    tunings: &Tunings,
+    flusher: &Flusher,
+    readiness: &Readiness,
+    history: &History,
+    liveness: &Liveness,
+    snapshots: &Snapshots<impl Write>,
+    pause: &Pause,
+    restarts: &Restarts,
+) -> Result<(), ControllerError> {
+    assert!(usize::from(group_id) < configuration.sensor_groups.len());
+
+    // more convenient access to our sensor group data
+    let group = &configuration.sensor_groups[usize::from(group_id)];
+    // the last time that we sent a sensor status update
+    let mut last_transmission_time = SystemTime::now();
+
+    // the most recent reading from each sensor which has *not* already been sent to the dashboard.
+    // each element will be None if the most recent reading was sent to the dashboard.
+    let mut transmission_readings: Vec<Option<SensorReading>> = vec![None; group.sensors.len()];
+    // the number of readings taken from each sensor since the last one which could be sent to the
+    // dashboard
+    let mut n_readings: Vec<u32> = vec![0; group.sensors.len()];
+    // the most recent reading from each sensor, whether or not it was sent to the dashboard
+    let mut latest_readings: Vec<Option<SensorReading>> = vec![None; group.sensors.len()];
+    let stale_window = configuration
+        .stale_reading_ms
+        .map(|ms| Duration::from_millis(u64::from(ms)));
+    let decimation = group.dashboard_decimation.unwrap_or(1);
+
+    // most recent values read, to be logged.
+    // in each queue, the "back" contains the most recent readings and the "front" contains the
+    // oldest ones.
+    // the final element of each reading marks whether it was injected rather than truly read.
+    let mut most_recent_readings: Vec<VecDeque<(SystemTime, u16, f64, bool)>> =
+        vec![VecDeque::new(); group.sensors.len()];
+
+    // the expression of each virtual sensor, along with the sensors it depends on.
+    // its log follows the logs of the real sensors in `log_files`.
+    let virtual_expressions = group
+        .virtual_sensors
+        .iter()
+        .map(|virtual_sensor| {
+            let expression = virtual_sensor.parse(group)?;
+            let inputs = expression.sensors();
+            Ok((expression, inputs))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ControllerError::Configuration)?;
+    // the most recent value of each virtual sensor, or `None` if it has never been computed
+    let mut virtual_values: Vec<Option<f64>> = vec![None; group.virtual_sensors.len()];
+    // the values of each virtual sensor which have not yet been logged
+    let mut virtual_queues: Vec<VecDeque<(SystemTime, f64)>> =
+        vec![VecDeque::new(); group.virtual_sensors.len()];
+
+    // the tuning last recorded in each binary log, so that a change in calibration is recorded
+    // ahead of the readings it applies to.
+    // a restarted group appends to logs which already have their headers, and records its tunings
+    // afresh in case they changed while it was stopped.
+    let mut logged_tunings: Vec<Option<SensorTuning>> = vec![None; group.sensors.len()];
+    if configuration.log_format == LogFormat::Binary && !restarts.has_restarted(group_id) {
+        let start = SystemTime::now();
+        let tunings_guard = tunings.read()?;
+        for (sensor_id, (log_file, sensor)) in log_files.iter_mut().zip(&group.sensors).enumerate()
+        {
+            let tuning = tunings_guard[usize::from(group_id)][sensor_id];
+            binary_log::write_header(log_file, sensor, &tuning, start)?;
+            logged_tunings[sensor_id] = Some(tuning);
+        }
+    }
+
+    // Rolling average values for sensor readings.
+    let mut rolling_averages: Vec<f64> = group
+        .sensors
+        .iter()
+        .map(|sensor| {
+            // extract the middle value of the range to seed our rolling average so that the sensor
+            // doesn't immediately start in a "bad" spot
+            if let Some((min, max)) = sensor.range {
+                (min + max) / 2.0
+            } else {
+                0.0
+            }
+        })
+        .collect();
+
+    // the most recent readings from each sensor, along with where to write them on an emergency
+    // stop
+    let mut pretrigger = match (configuration.pretrigger_duration_ms, pretrigger_file) {
+        (Some(duration_ms), Some(file)) => Some((
+            PreTrigger::new(
+                Duration::from_millis(u64::from(duration_ms)),
+                group.sensors.len(),
+            ),
+            file,
+        )),
+        _ => None,
+    };
+    // the number of emergency stops which have begun as of the last time we checked
+    let mut estop_count = state.estop_count();
+
+    let standby_period = Duration::from_secs(1) / group.frequency_standby;
+    let ignition_period = Duration::from_secs(1) / group.frequency_ignition;
+    let transmission_period = Duration::from_secs(1) / group.frequency_transmission;
+
+    // whether each sensor has been read yet; sensors whose ADC failed are never read
+    let mut unread: Vec<bool> = group
+        .sensors
+        .iter()
+        .map(|sensor| sensor.iio_channel.is_some() || adcs[usize::from(sensor.adc)].is_some())
+        .collect();
+    let mut ready = false;
+
+    let mut debouncers: Vec<Debouncer> = group
+        .digital_inputs
+        .iter()
+        .map(|input| Debouncer::new(Duration::from_millis(u64::from(input.debounce_ms))))
+        .collect();
+    // the settled level of each digital input, or `None` if it has never been read
+    let mut digital_values: Vec<Option<bool>> = vec![None; group.digital_inputs.len()];
+
+    // throw away the first readings of each sensor while its ADC settles
+    for (idx, sensor) in group.sensors.iter().enumerate() {
+        if sensor.discard_first > 0 {
+            let n_discarded = discard_warmup(
+                sensor,
+                adcs,
+                iio_channels.get_mut(idx).and_then(Option::as_mut),
+            )?;
+            user_log.info(&format!(
+                "Discarded {n_discarded} warm-up readings of sensor {}",
+                sensor.label
+            ))?;
+        }
+    }
+
+    let mut jitter_monitor = configuration
+        .jitter_threshold_us
+        .map(|threshold_us| JitterMonitor::new(Duration::from_micros(u64::from(threshold_us))));
+    // the time which the previous loop was meant to take
+    let mut sleep_time = standby_period;
+    // the range of an IIO channel is unknown, so it cannot be pinned at either end
+    let mut pinned_monitors: Vec<Option<PinnedMonitor>> = group
+        .sensors
+        .iter()
+        .map(|sensor| {
+            configuration
+                .pinned_warn_ms
+                .filter(|_| sensor.iio_channel.is_none())
+                .map(|ms| PinnedMonitor::new(Duration::from_millis(u64::from(ms))))
+        })
+        .collect();
+    // whether logging and streaming were paused as of the last loop
+    let mut paused = false;
+    // every reading is stamped from the monotonic clock, so that the spacing of readings is exact
+    let clock = MonotonicClock::new();
+    let mut schedule = LoopSchedule::new(Instant::now());
+
+    while state.status()? != State::Quit && !restarts.pending(group_id) {
+        liveness.beat(group_id);
+        let loop_start = Instant::now();
+        let jitter = schedule.begin(loop_start, sleep_time);
+        liveness.record_jitter(group_id, jitter);
+
+        // a pause only lasts while the controller is in standby, so that an ignition or emergency
+        // stop is never left out of the logs
+        if pause.is_paused() && state.status()? != State::Standby && pause.set(false).is_ok() {
+            user_log
+                .warn("Sensor logging and streaming resumed, since the controller left standby")?;
+        }
+
+        // mark the start or end of a pause in every log, after every reading taken before it
+        if pause.is_paused() != paused {
+            paused = !paused;
+            let marker_time = SystemTime::now();
+            let marker = if paused { "paused" } else { "resumed" };
+            for (sensor_id, reading_queue) in most_recent_readings.iter_mut().enumerate() {
+                let log_file = &mut log_files[sensor_id];
+                let mut write_result =
+                    write_queue(configuration.log_format, log_file, reading_queue);
+                if write_result.is_ok() {
+                    write_result = match configuration.log_format {
+                        LogFormat::Csv => write_sensor_marker(log_file, marker_time, marker),
+                        LogFormat::Binary => {
+                            binary_log::write_marker(log_file, marker_time, marker)
+                        }
+                    };
+                }
+                #[allow(unused_must_use)]
+                if let Err(e) = write_result {
+                    user_log.warn(&format!(
+                        "unable to write data for sensor {}: {e:?}",
+                        group.sensors[sensor_id].label
+                    ));
+                }
+            }
+        }
+
+        if let Some(stats) = jitter_monitor
+            .as_mut()
+            .and_then(|monitor| monitor.record(loop_start, jitter))
+        {
+            user_log.warn(&format!(
+                "Sensor group {} had loop jitter of up to {:?} (mean {:?}); {} of {} loops exceeded the jitter threshold, and data may be questionable",
+                group.label,
+                stats.max,
+                stats.mean(),
+                stats.n_exceeded,
+                stats.n_loops,
+            ))?;
+        }
+
+        // the calibration, range, and filtering of each sensor, which may be reloaded at any time
+        let tunings_guard = tunings.read()?;
+        let group_tunings = &tunings_guard[usize::from(group_id)];
+        if configuration.log_format == LogFormat::Binary {
+            record_calibrations(
+                group,
+                group_tunings,
+                &mut logged_tunings,
+                log_files,
+                &mut most_recent_readings,
+                user_log,
+            );
+        }
+
+        // if a snapshot was requested, the readings from this loop are contributed to it
+        let snapshot_target = snapshots.pending(group_id)?;
+        let mut snapshot_values = vec![None; group.sensors.len()];
+
+        // an aligned group reads every sensor back-to-back before processing any of them, so that
+        // the readings can share a single timestamp
+        let group_start = Instant::now();
+        let group_time = clock.at(group_start);
+        let mut aligned_readings = Vec::new();
+        if group.aligned {
+            for (idx, sensor) in group.sensors.iter().enumerate() {
+                let reading = acquire_reading(
+                    group_id,
+                    idx,
+                    sensor,
+                    adcs,
+                    iio_channels.get_mut(idx).and_then(Option::as_mut),
+                    injections,
+                    user_log,
+                    liveness,
+                )?;
+                aligned_readings.push(reading.map(|r| (r, group_start.elapsed())));
+            }
+        }
+
+        // process the reading from each device
+        for (idx, sensor) in group.sensors.iter().enumerate() {
+            let ((reading, injected), read_time, offset) = if group.aligned {
+                let Some((acquired, offset)) = aligned_readings[idx] else {
+                    continue;
+                };
+                (acquired, group_time + offset, Some(offset))
+            } else {
+                let read_start = Instant::now();
+                let Some(acquired) = acquire_reading(
+                    group_id,
+                    idx,
+                    sensor,
+                    adcs,
+                    iio_channels.get_mut(idx).and_then(Option::as_mut),
+                    injections,
+                    user_log,
+                    liveness,
+                )?
+                else {
+                    continue;
+                };
+                // a reading is stamped at the middle of its acquisition, which may be long if the
+                // sensor is oversampled
+                let read_duration = Instant::now().duration_since(read_start);
+                (acquired, clock.at(read_start + read_duration / 2), None)
+            };
+            unread[idx] = false;
+            if let Some(monitor) = &mut pinned_monitors[idx] {
+                if monitor.record(reading, Instant::now()) {
+                    user_log.warn(&format!(
+                        "Sensor {} has read {reading} (the end of its ADC's range) for over {} ms; its input may be disconnected or shorted",
+                        sensor.label,
+                        monitor.timeout.as_millis()
+                    ))?;
+                }
+            }
+            let tuning = &group_tunings[idx];
+            let calibrated_value = match &sensor.thermistor {
+                Some(thermistor) => thermistor.temperature(reading),
+                None => {
+                    sensor.calibration_input(reading) * tuning.calibration_slope
+                        + tuning.calibration_intercept
+                }
+            };
+            if !paused {
+                most_recent_readings[idx].push_back((
+                    read_time,
+                    reading,
+                    calibrated_value,
+                    injected,
+                ));
+            }
+            snapshot_values[idx] = Some(calibrated_value);
+            #[allow(clippy::cast_possible_truncation)]
+            let sensor_reading = SensorReading {
+                sensor_id: idx as u8,
+                reading,
+                value: calibrated_value,
+                // an aligned reading is stamped with the group's time, and reports its offset
+                time: offset.map_or(read_time, |_| group_time),
+                offset_ns: offset.map(|offset| offset.as_nanos() as u32),
+                saturated: is_saturated(sensor, reading, calibrated_value),
+                stale: false,
+            };
+            latest_readings[idx] = Some(sensor_reading);
+            // only every Nth reading is sent, but every reading is logged and checked
+            if n_readings[idx] == 0 && !paused {
+                transmission_readings[idx] = Some(sensor_reading);
+            }
+            n_readings[idx] = (n_readings[idx] + 1) % decimation;
+            history.push(group_id, sensor_reading)?;
+            if let Some((pretrigger, _)) = &mut pretrigger {
+                pretrigger.push(idx, read_time, reading, calibrated_value);
+            }
+            // update rolling averages
+            let width = tuning.rolling_average_width.unwrap_or(1);
+            let rolling_avg = (rolling_averages[idx] * (f64::from(width - 1)) + calibrated_value)
+                / f64::from(width);
+            rolling_averages[idx] = rolling_avg;
+
+            // if rolling average went out of bounds, immediately start emergency stopping
+            if let Some((min, max)) = tuning.range {
+                // an emergency stop already under way, or its purge, must not be restarted by
+                // every reading which is still out of range
+                let safing = matches!(state.status()?, State::EStopping | State::Purging);
+                #[allow(unused_must_use)]
+                if !safing && (rolling_avg < min || max < rolling_avg) {
+                    user_log.warn(&format!(
+                        "Sensor {} was out of bounds with value {rolling_avg}, attempting emergency stop", 
+                        sensor.label
+                    ));
+                    // oh no! a sensor is now in an illegal range!
+                    // spin up another thread to emergency stop.
+                    // this may return an error due to illegal transistion, but that is not our
+                    // problem.
+                    thread_scope.spawn(|| match &sensor.abort_action {
+                        Some(action) => abort(configuration, driver_lines, state, action, user_log),
+                        None => emergency_stop(configuration, driver_lines, state, user_log),
+                    });
+                }
+            }
+        }
+
+        drop(tunings_guard);
+
+        // derive the virtual sensors from the latest calibrated values
+        let values: Vec<f64> = latest_readings
+            .iter()
+            .map(|reading| reading.map_or(f64::NAN, |r| r.value))
+            .collect();
+        for (((expression, inputs), value), queue) in virtual_expressions
+            .iter()
+            .zip(&mut virtual_values)
+            .zip(&mut virtual_queues)
+        {
+            // a virtual sensor has no value until every sensor it depends on has been read
+            let Some(time) = inputs
+                .iter()
+                .map(|&idx| latest_readings[idx].map(|r| r.time))
+                .collect::<Option<Vec<_>>>()
+                .map(|times| times.into_iter().max().unwrap_or(group_time))
+            else {
+                continue;
+            };
+            let derived = expression.evaluate(&values);
+            *value = Some(derived);
+            if !paused {
+                queue.push_back((time, derived));
+            }
+        }
+        if !virtual_values.is_empty() {
+            history.set_virtual(group_id, &virtual_values)?;
+        }
+
+        // sample and debounce each digital input
+        for ((line, input), (debouncer, value)) in digital_inputs
+            .iter_mut()
+            .zip(&group.digital_inputs)
+            .zip(debouncers.iter_mut().zip(digital_values.iter_mut()))
+        {
+            let level = match line.read() {
+                Ok(level) => level,
+                Err(e) => {
+                    #[allow(unused_must_use)]
+                    {
+                        user_log.warn(&format!(
+                            "unable to read digital input {} due to error: {e:?}",
+                            input.label
+                        ));
+                    }
+                    continue;
+                }
+            };
+            let settled = debouncer.update(level, Instant::now());
+            if *value != Some(settled) {
+                user_log.info(&format!(
+                    "Digital input {} is now {}",
+                    input.label,
+                    if settled { "high" } else { "low" }
+                ))?;
+                *value = Some(settled);
+            }
+        }
+
+        if !ready && !unread.contains(&true) {
+            ready = true;
+            if readiness.group_ready(group_id)? {
+                user_log.info("Every sensor has been read. The controller is ready")?;
+                dashboard_stream.send(&Message::Ready)?;
+            }
+        }
+
+        // if an emergency stop has begun since we last checked, log its lead-up
+        let new_estop_count = state.estop_count();
+        if new_estop_count != estop_count {
+            estop_count = new_estop_count;
+            if let Some((pretrigger, file)) = &mut pretrigger {
+                user_log.info(&format!(
+                    "Writing pre-trigger readings for sensor group {}",
+                    group.label
+                ))?;
+                if let Err(e) = pretrigger.flush(group_id, &mut *file.lock()?) {
+                    user_log.warn(&format!(
+                        "unable to write pre-trigger readings for sensor group {}: {e}",
+                        group.label
+                    ))?;
+                }
+            }
+        }
+
+        // transmit data to the dashboard if it's been long enough since our last transmission
+        if SystemTime::now() > last_transmission_time + transmission_period {
+            if !paused && (dashboard_stream.has_target()? || telemetry.is_some()) {
+                let readings = readings_to_send(
+                    &transmission_readings,
+                    &latest_readings,
+                    stale_window,
+                    clock.at(Instant::now()),
+                );
+                let skew_ns = group
+                    .aligned
+                    .then(|| readings.iter().filter_map(|r| r.offset_ns).max())
+                    .flatten();
+                // the dashboard may have chosen different values than telemetry receives
+                let sensor_value = |fields| Message::SensorValue {
+                    group_id,
+                    readings: ReadingList {
+                        readings: &readings,
+                        fields,
+                    },
+                    skew_ns,
+                };
+
+                // send message to dashboard
+                dashboard_stream.send(&sensor_value(dashboard_stream.sensor_fields()?))?;
+                if !group.digital_inputs.is_empty() && !digital_values.contains(&None) {
+                    dashboard_stream.send(&Message::DigitalValue {
+                        group_id,
+                        values: &digital_values.iter().flatten().copied().collect::<Vec<_>>(),
+                    })?;
+                }
+                if !virtual_values.is_empty() {
+                    dashboard_stream.send(&Message::VirtualValue {
+                        group_id,
+                        values: &virtual_values,
+                    })?;
+                }
+
+                // UDP telemetry is best-effort, so failing to send is not fatal
+                if let Some(telemetry) = telemetry {
+                    match telemetry.send(&sensor_value(configuration.sensor_value_fields)) {
+                        Ok(()) => {
+                            if telemetry.set_failing(false) {
+                                user_log.info("UDP telemetry recovered")?;
+                            }
+                        }
+                        Err(e) => {
+                            if telemetry.set_failing(true) {
+                                user_log.warn(&format!(
+                                    "Unable to send UDP telemetry: {e}. Further failures will not be logged until it recovers"
+                                ))?;
+                            }
+                        }
+                    }
+                }
+            }
+
+            last_transmission_time = SystemTime::now();
+            transmission_readings = vec![None; group.sensors.len()];
+        }
+
+        if let Some(target) = snapshot_target {
+            snapshots.complete(group_id, target, snapshot_values)?;
+        }
+
+        // if a flush was requested, write out every reading, even if its log buffer is not full
+        let flush_pending = flusher.pending(group_id)?;
+        for (sensor_id, reading_queue) in most_recent_readings.iter_mut().enumerate() {
+            if reading_queue.len() >= configuration.log_buffer_size
+                || (flush_pending && !reading_queue.is_empty())
+            {
+                let write_result = write_queue(
+                    configuration.log_format,
+                    &mut log_files[sensor_id],
+                    reading_queue,
+                );
+                #[allow(unused_must_use)]
+                if let Err(e) = write_result {
+                    user_log.warn(&format!(
+                        "unable to write data for sensor {}: {e:?}",
+                        group.sensors[sensor_id].label
+                    ));
+                }
+            }
+        }
+        for (virtual_id, queue) in virtual_queues.iter_mut().enumerate() {
+            if queue.len() >= configuration.log_buffer_size || (flush_pending && !queue.is_empty())
+            {
+                let write_result = write_virtual_log(
+                    &mut log_files[group.sensors.len() + virtual_id],
+                    queue.drain(..),
+                );
+                #[allow(unused_must_use)]
+                if let Err(e) = write_result {
+                    user_log.warn(&format!(
+                        "unable to write data for virtual sensor {}: {e:?}",
+                        group.virtual_sensors[virtual_id].label
+                    ));
+                }
+            }
+        }
+        if flush_pending {
+            for log_file in log_files.iter_mut() {
+                #[allow(unused_must_use)]
+                if let Err(e) = log_file.flush() {
+                    user_log.warn(&format!("unable to flush sensor log: {e:?}"));
+                }
+            }
+            // the ADCs buffer the raw transactions of any sensors which log them
+            for sensor in group.sensors.iter().filter(|s| s.iio_channel.is_none()) {
+                let Some(adc) = &adcs[usize::from(sensor.adc)] else {
+                    continue;
+                };
+                #[allow(unused_must_use)]
+                if let Err(e) = adc.lock()?.flush_raw_logs() {
+                    user_log.warn(&format!(
+                        "unable to flush raw SPI log for sensor {}: {e:?}",
+                        sensor.label
+                    ));
+                }
+            }
+            flusher.complete(group_id)?;
+        }
+
+        // use the system state to determine how long to sleep until the next loop.
+        // standby means we are sampling slowly, and anything else means we sample quickly.
+        let status = state.status()?;
+        sleep_time = match status {
+            State::Standby => standby_period,
+            _ => ignition_period,
+        };
+
+        // now take a nap until we next need to get data, cutting it short if the state changes so
+        // that the new sampling rate takes effect at once
+        let due = schedule.next(sleep_time);
+        state.wait_while(status, due.saturating_duration_since(Instant::now()))?;
+    }
+
+    // we are now quitting or restarting, so no held reading may be lost
+    for (sensor_id, reading_queue) in most_recent_readings.iter_mut().enumerate() {
+        #[allow(unused_must_use)]
+        if let Err(e) = write_queue(
+            configuration.log_format,
+            &mut log_files[sensor_id],
+            reading_queue,
+        ) {
+            user_log.warn(&format!(
+                "unable to write data for sensor {}: {e:?}",
+                group.sensors[sensor_id].label
+            ));
+        }
+    }
+    for (virtual_id, queue) in virtual_queues.iter_mut().enumerate() {
+        #[allow(unused_must_use)]
+        if let Err(e) = write_virtual_log(
+            &mut log_files[group.sensors.len() + virtual_id],
+            queue.drain(..),
+        ) {
+            user_log.warn(&format!(
+                "unable to write data for virtual sensor {}: {e:?}",
+                group.virtual_sensors[virtual_id].label
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Select the readings of a sensor group to send to the dashboard at time `now`.
+///
+/// Every reading in `fresh` (the readings which have not yet been sent) is sent.
+/// If `stale_window` is given, a sensor with no fresh reading has its reading in `latest` sent again
+/// once that reading is older than the window.
+/// Every sent reading older than the window is flagged as stale.
+fn readings_to_send(
+    fresh: &[Option<SensorReading>],
+    latest: &[Option<SensorReading>],
+    stale_window: Option<Duration>,
+    now: SystemTime,
+) -> Vec<SensorReading> {
+    fresh
+        .iter()
+        .zip(latest)
+        .filter_map(|readings| {
+            let (mut reading, resent) = match readings {
+                (Some(reading), _) => (*reading, false),
+                (None, Some(reading)) => (*reading, true),
+                (None, None) => return None,
+            };
+            reading.stale = stale_window.map_or(false, |window| {
+                now.duration_since(reading.time).unwrap_or_default() > window
+            });
+            (!resent || reading.stale).then_some(reading)
+        })
+        .collect()
+}
+
+/// Record in the binary log of each sensor in `group` any change in its calibration since the
+/// tuning in `logged_tunings`, after writing out every reading taken with the old calibration.
+/// A change which cannot be written is warned about once, and not retried.
+fn record_calibrations(
+    group: &SensorGroup,
+    tunings: &[SensorTuning],
+    logged_tunings: &mut [Option<SensorTuning>],
+    log_files: &mut [impl Write],
+    reading_queues: &mut [VecDeque<(SystemTime, u16, f64, bool)>],
+    user_log: &UserLog<impl Write>,
+) {
+    for (sensor_id, (sensor, tuning)) in group.sensors.iter().zip(tunings).enumerate() {
+        let calibration =
+            |tuning: &SensorTuning| (tuning.calibration_slope, tuning.calibration_intercept);
+        if logged_tunings[sensor_id].map(|logged| calibration(&logged)) == Some(calibration(tuning))
+        {
+            continue;
+        }
+        let log_file = &mut log_files[sensor_id];
+        let write_result = write_queue(LogFormat::Binary, log_file, &mut reading_queues[sensor_id])
+            .and_then(|()| {
+                binary_log::write_calibration(log_file, sensor, tuning, SystemTime::now())
+            });
+        logged_tunings[sensor_id] = Some(*tuning);
+        #[allow(unused_must_use)]
+        if let Err(e) = write_result {
+            user_log.warn(&format!(
+                "unable to record the new calibration of sensor {}, so its binary log will be decoded with the old one: {e:?}",
+                sensor.label
+            ));
+        }
+    }
+}
+
+/// Write every reading in `reading_queue` to `log_file` in the format `log_format`, and then empty
+/// the queue.
+/// The queue is emptied even if writing fails, since the failed readings cannot be retried.
+///
+/// # Errors
+///
+/// This function will return an error if writing to `log_file` fails.
+fn write_queue(
+    log_format: LogFormat,
+    log_file: &mut impl Write,
+    reading_queue: &mut VecDeque<(SystemTime, u16, f64, bool)>,
+) -> std::io::Result<()> {
+    let write_result = match log_format {
+        LogFormat::Csv => write_sensor_log(log_file, reading_queue.iter()),
+        LogFormat::Binary => binary_log::write_records(log_file, reading_queue.iter()),
+    };
+    reading_queue.clear();
+    write_result
+}
+
+/// Get the period at which drivers are sampled and logged under `configuration`, and the period at
+/// which their status is sent to the dashboard.
+/// Status is sent with every sample unless `driver_status_interval_ms` is configured.
+fn driver_status_periods(configuration: &Configuration) -> (Duration, Duration) {
+    let sample_period = Duration::from_secs(1) / configuration.frequency_status;
+    let broadcast_period = configuration
+        .driver_status_interval_ms
+        .map_or(sample_period, |ms| Duration::from_millis(u64::from(ms)));
+    (sample_period, broadcast_period)
+}
+
+#[allow(dead_code, clippy::too_many_arguments)]
+/// Periodically check in on the status of the drivers, and log that status.
+/// Will also transmit that driver status to the dashboard, at its own cadence if
+/// `driver_status_interval_ms` is configured.
+///
+/// # Inputs
+///
+/// * `configuration`: The configuration for the current mode of the controller.
+/// * `driver_lines`: The driver GPIO pins.
+/// * `log_file`: The file to which logs should be written.
+///     Information will be written to the log file in the following format:
+///     ```text
+///      {time},{driver0_status},{driver1_status},
+///
+///     ```
+///     with one row for every sample, and one row for every change in the level of a driver.
+///     `{time}` is the number of nanoseconds since the UNIX epoch.
+/// * `state`: The overall system state.
+///     This function will only return after `State` transitions to `State::Quit`.
+/// * `dashboard_stream`: A channel by which messages can be sent to the dashboard.
+/// * `edges`: The changes in the level of each driver, as they happen.
+///     Between samples, each change is logged and sent to the dashboard as soon as it arrives.
+/// * `interlocks`: The record of overridden interlocks, of which the dashboard is reminded with
+///     every sample.
+///
+/// # Errors
+///
+/// This function will return an error if we are unable to acquire a lock or if we are unable to
+/// write to the message log.
+///
+/// # Panics
+///
+/// This function will panic if the current time is before the UNIX epoch.
+pub fn driver_status_listen(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    log_file: &mut impl Write,
+    user_log: &UserLog<impl Write>,
+    state: &Guard,
+    dashboard_stream: &DashChannel<impl Write, impl Write>,
+    edges: &Receiver<Edge>,
+    interlocks: &Interlocks,
+) -> Result<(), ControllerError> {
+    let (sample_period, broadcast_period) = driver_status_periods(configuration);
+    let start = Instant::now();
+    let mut samples = Cadence::new(sample_period, start);
+    let mut broadcasts = Cadence::new(broadcast_period, start);
+    let mut driver_states = vec![false; driver_lines.lock()?.len()];
+    while state.status()? != State::Quit {
+        let now = Instant::now();
+        if samples.due(now) {
+            // read off the states of the drivers
+            let read_time = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap();
+
+            let mut drivers_guard = driver_lines.lock().map_err(|e| {
+                #[allow(unused_must_use)]
+                {
+                    user_log.critical("Unable to acquire lock over driver mutex");
+                }
+                e
+            })?;
+
+            let mut failures = Vec::new();
+            for (driver_idx, (driver_line, state_ref)) in
+                drivers_guard.iter_mut().zip(&mut driver_states).enumerate()
+            {
+                match driver_line.read() {
+                    Ok(read_value) => *state_ref = read_value,
+                    Err(e) => failures.push((driver_idx, e)),
+                }
+            }
+
+            // don't keep the drivers guard while we log or sleep!
+            drop(drivers_guard);
+
+            for (driver_idx, e) in failures {
+                user_log.warn(&format!(
+                    "Unable to read state of driver {driver_idx}: {e:?}"
+                ))?;
+            }
+
+            // write driver status information
+            write_driver_log(log_file, read_time, &driver_states)?;
+        }
+
+        // optionally transmit to dashboard
+        if broadcasts.due(now) {
+            let values: Vec<DriverState> = configuration
+                .drivers
+                .iter()
+                .zip(&driver_states)
+                .map(|(driver, &level)| DriverState::new(driver.kind, level))
+                .collect();
+            dashboard_stream.send(&Message::DriverValue { values: &values })?;
+            if let Some(cooldown_ms) = configuration.estop_cooldown_ms {
+                let remaining =
+                    state.cooldown_remaining(Duration::from_millis(u64::from(cooldown_ms)))?;
+                if !remaining.is_zero() {
+                    #[allow(clippy::cast_possible_truncation)]
+                    dashboard_stream.send(&Message::Cooldown {
+                        remaining_ms: remaining.as_millis() as u64,
+                    })?;
+                }
+            }
+            let overridden = interlocks
+                .overridden()
+                .map_err(|_| ControllerError::Poison)?;
+            if !overridden.is_empty() {
+                dashboard_stream.send(&Message::InterlockOverride {
+                    interlock_ids: &overridden,
+                })?;
+            }
+        }
+
+        // until we are ready to sample or send again, report each change as it happens
+        let deadline = samples.next().min(broadcasts.next());
+        loop {
+            match edges.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(edge) => {
+                    driver_states[usize::from(edge.driver_id)] = edge.level;
+                    write_driver_log(
+                        log_file,
+                        edge.time.duration_since(SystemTime::UNIX_EPOCH).unwrap(),
+                        &driver_states,
+                    )?;
+                    dashboard_stream.send(&Message::DriverChange {
+                        driver_id: edge.driver_id,
+                        level: edge.level,
+                        time: edge.time,
+                    })?;
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    sleep(deadline.saturating_duration_since(Instant::now()));
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Periodically sync the sensor log files to disk.
+/// Will loop until the controller quits.
+///
+/// # Inputs
+///
+/// * `period`: The time to wait between each sync.
+/// * `log_files`: Handles to every sensor log file.
+///     These may be clones of the handles used for writing, since syncing a clone syncs the whole
+///     file.
+/// * `user_log`: The log to which sync failures will be reported.
+/// * `state`: The overall system state.
+///     This function will only return after `State` transitions to `State::Quit`.
+///
+/// # Errors
+///
+/// This function will return an error if the state lock is poisoned or if we are unable to write
+/// to the user log.
+pub fn log_sync(
+    period: Duration,
+    log_files: &[SyncHandle],
+    user_log: &UserLog<impl Write>,
+    state: &Guard,
+) -> Result<(), ControllerError> {
+    while state.status()? != State::Quit {
+        for log_file in log_files {
+            if let Err(e) = log_file.sync_data() {
+                user_log.warn(&format!("Unable to sync sensor log file: {e:?}"))?;
+            }
+        }
+
+        sleep(period);
+    }
+
+    Ok(())
+}
+
+/// Write the status of the drivers to a log file.
+///
+/// # Inputs
+///
+/// * `log_file`: The file to which log information should be written.
+/// * `read_time`: The time at which the read of driver states occurred.
+/// * `driver_states`: The state of each driver.
+///
+/// # Errors
+///
+/// This function will return an `Err` if writing to the log file fails.
+fn write_driver_log(
+    log_file: &mut impl Write,
+    read_time: Duration,
+    driver_states: &[bool],
+) -> std::io::Result<()> {
+    write!(log_file, "{},", read_time.as_nanos())?;
+    for driver_state in driver_states {
+        write!(log_file, "{driver_state},")?;
+    }
+    writeln!(log_file)?;
+
+    Ok(())
+}
+
+/// Write a new log datum to the sensor log file.
+///
+/// # Inputs
+///
+/// * `log_file`: The file to which the log will be written.
+///     There must be exactly one log file per sensor.
+/// * `adc_readings`: All the most recent sensor readings to be written to the file.
+///
+/// # Results
+///
+/// Will write the data from the ADC readings in a CSV format to the file.
+/// There will be three "columns" to this CSV data:
+/// 1. The time since the UNIX epoch, in nanoseconds.
+/// 1. The raw ADC value of the sensor at this time.
+/// 1. The calibrated value of the sensor at this time.
+/// If a reading was injected by the dashboard rather than read from the ADC, its row will have a
+/// fourth column containing the text `injected`.
+/// Will also include a trailing newline after the last row.
+/// At the end of writing all of these lines, the file will be "flushed," meaning that all data will
+/// be immediately saved.
+///
+/// For instance, if a sensor had a reading of 42 at a time of 1 second, 500 nanoseconds after the
+/// UNIX epoch began, and the calibrated reading from the sensor was 1.25, the following text would
+/// be written:
+///
+/// ```text
+/// 1000000500,42,1.24
+///
+/// ```
+///
+/// If the entire process succeeds, this function will return `Ok(())`.
+///
+/// # Errors
+///
+/// This function will return an `Err` if writing to the log file fails.
+///
+/// # Panics
+///
+/// This function will panic if a time contained in the ADC readings was before the UNIX epoch.
+pub fn write_sensor_log<'a>(
+    log_file: &mut impl Write,
+    adc_readings: impl IntoIterator<Item = &'a (SystemTime, u16, f64, bool)>,
+) -> std::io::Result<()> {
+    for (sys_time, reading, calib, injected) in adc_readings {
+        let since_epoch_time = sys_time.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+
+        write!(
+            log_file,
+            "{},{reading},{calib}",
+            since_epoch_time.as_nanos()
+        )?;
+        if *injected {
+            write!(log_file, ",injected")?;
+        }
+        writeln!(log_file)?;
+    }
+
+    log_file.flush()
+}
+
+/// Write the values of a virtual sensor to its log.
+///
+/// Virtual sensor logs are always CSV, whatever the log format of the real sensors.
+/// Each value is written as a row with two columns: the time of the most recent reading from
+/// which it was derived, in nanoseconds since the UNIX epoch, and the value itself.
+/// For instance, a value of 2.5 at a time of 1 second after the UNIX epoch is written as follows:
+///
+/// ```text
+/// 1000000000,2.5
+///
+/// ```
+///
+/// # Errors
+///
+/// This function will return an `Err` if writing to the log file fails.
+///
+/// # Panics
+///
+/// This function will panic if a time of a value was before the UNIX epoch.
+pub fn write_virtual_log(
+    log_file: &mut impl Write,
+    values: impl IntoIterator<Item = (SystemTime, f64)>,
+) -> std::io::Result<()> {
+    for (time, value) in values {
+        let since_epoch_time = time.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        writeln!(log_file, "{},{value}", since_epoch_time.as_nanos())?;
+    }
+
+    log_file.flush()
+}
+
+/// Write a marker row to a CSV sensor log, recording that `marker` happened at time `time`.
+///
+/// A marker row has the same four columns as a row of readings, but its reading and value are
+/// empty, and its last column contains the marker.
+/// For instance, a pause at a time of 1 second after the UNIX epoch is marked as follows:
+///
+/// ```text
+/// 1000000000,,,paused
+///
+/// ```
+///
+/// # Errors
+///
+/// This function will return an `Err` if writing to the log file fails.
+///
+/// # Panics
+///
+/// This function will panic if `time` is before the UNIX epoch.
+pub fn write_sensor_marker(
+    log_file: &mut impl Write,
+    time: SystemTime,
+    marker: &str,
+) -> std::io::Result<()> {
+    let since_epoch_time = time.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    writeln!(log_file, "{},,,{marker}", since_epoch_time.as_nanos())?;
+
+    log_file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::atomic::AtomicBool, thread::scope};
+
+    use serde_json::Value;
+
+    use crate::{
+        hardware::{ListenerPin, ReturnsNumber},
+        outgoing::Capture,
+    };
+
+    use super::*;
+
+    #[test]
+    /// Test that readings are flagged as saturated at the ends of the ADC's range and outside of the
+    /// sensor's full-scale range.
+    fn saturation() {
+        let mut sensor: Sensor = serde_json::from_str(
+            r##"{
+                "label": "PT1",
+                "color": "#ef3b9e",
+                "units": "psi",
+                "calibration_intercept": 0,
+                "calibration_slope": 1,
+                "adc": 0,
+                "channel": 0
+            }"##,
+        )
+        .unwrap();
+        assert!(is_saturated(&sensor, 0, 0.0));
+        assert!(is_saturated(&sensor, ADC_MAX_READING, 0.0));
+        assert!(!is_saturated(&sensor, 2000, 1e9));
+
+        sensor.full_scale_min = Some(-10.0);
+        sensor.full_scale_max = Some(10.0);
+        assert!(!is_saturated(&sensor, 2000, 10.0));
+        assert!(is_saturated(&sensor, 2000, 10.5));
+        assert!(is_saturated(&sensor, 2000, -10.5));
+
+        sensor.iio_channel = Some("/sys/bus/iio/devices/iio:device0/in_voltage0_raw".into());
+        assert!(!is_saturated(&sensor, 0, 0.0));
+        assert!(!is_saturated(&sensor, 65535, 0.0));
+        assert!(is_saturated(&sensor, 65535, 10.5));
+    }
+
+    #[test]
+    /// Test that the ready gate rejects hardware commands until every sensor group has been read.
+    fn ready_gate() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+                "frequency_status": 1,
+                "log_buffer_size": 1,
+                "ready_gate": true,
+                "sensor_groups": [
+                    {
+                        "label": "FAST",
+                        "frequency_standby": 10,
+                        "frequency_ignition": 10,
+                        "frequency_transmission": 10,
+                        "sensors": []
+                    },
+                    {
+                        "label": "SLOW",
+                        "frequency_standby": 10,
+                        "frequency_ignition": 10,
+                        "frequency_transmission": 10,
+                        "sensors": []
+                    }
+                ],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [],
+                "pin_heartbeat": 0
+            }"#,
+        ))
+        .unwrap();
+        let readiness = Readiness::new(&config);
+
+        assert!(matches!(readiness.check(), Err(execution::Error::NotReady)));
+        assert!(!readiness.group_ready(1).unwrap());
+        assert!(matches!(readiness.check(), Err(execution::Error::NotReady)));
+        assert!(readiness.group_ready(0).unwrap());
+        assert!(readiness.is_ready().unwrap());
+        readiness.check().unwrap();
+
+        // the controller only becomes ready once
+        assert!(!readiness.group_ready(0).unwrap());
+    }
+
+    #[test]
+    /// Test that a flush waits for every sensor group to write out its readings, and gives up if a
+    /// group never does.
+    fn flush_waits_for_groups() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+                "frequency_status": 1,
+                "log_buffer_size": 1,
+                "sensor_groups": [
+                    {
+                        "label": "FAST",
+                        "frequency_standby": 10,
+                        "frequency_ignition": 10,
+                        "frequency_transmission": 10,
+                        "sensors": []
+                    }
+                ],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [],
+                "pin_heartbeat": 0
+            }"#,
+        ))
+        .unwrap();
+        let flusher = Flusher::new(&config, Vec::new());
+
+        assert!(matches!(
+            flusher.flush(Duration::from_millis(50)),
+            Err(execution::Error::FlushTimeout)
+        ));
+
+        scope(|s| {
+            s.spawn(|| {
+                while !flusher.pending(0).unwrap() {
+                    sleep(Duration::from_millis(1));
+                }
+                flusher.complete(0).unwrap();
+            });
+            flusher.flush(Duration::from_secs(5)).unwrap();
+        });
+        assert!(!flusher.pending(0).unwrap());
+    }
+
+    #[test]
+    /// Test that a snapshot collects the values contributed by every sensor group into one row of
+    /// the snapshot log, and gives up if a group never contributes.
+    fn snapshot_collects_groups() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r##"{
+                "frequency_status": 1,
+                "log_buffer_size": 1,
+                "sensor_groups": [
+                    {
+                        "label": "PRESSURE",
+                        "frequency_standby": 10,
+                        "frequency_ignition": 10,
+                        "frequency_transmission": 10,
+                        "sensors": [
+                            {
+                                "label": "PT1",
+                                "color": "#ef3b9e",
+                                "calibration_intercept": 0,
+                                "calibration_slope": 1,
+                                "adc": 0,
+                                "channel": 0
+                            },
+                            {
+                                "label": "PT2",
+                                "color": "#ef3b9e",
+                                "calibration_intercept": 0,
+                                "calibration_slope": 1,
+                                "adc": 0,
+                                "channel": 1
+                            }
+                        ]
+                    },
+                    {
+                        "label": "THERMAL",
+                        "frequency_standby": 10,
+                        "frequency_ignition": 10,
+                        "frequency_transmission": 10,
+                        "sensors": [
+                            {
+                                "label": "TC1",
+                                "color": "#ef3b9e",
+                                "calibration_intercept": 0,
+                                "calibration_slope": 1,
+                                "adc": 0,
+                                "channel": 2
+                            }
+                        ]
+                    }
+                ],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [5],
+                "pin_heartbeat": 0
+            }"##,
+        ))
+        .unwrap();
+        let snapshots = Snapshots::new(&config, Vec::new());
+
+        assert!(matches!(
+            snapshots.take(Duration::from_millis(50)),
+            Err(execution::Error::SnapshotTimeout)
+        ));
+
+        let (time, values) = scope(|s| {
+            s.spawn(|| {
+                let target = loop {
+                    match (snapshots.pending(0).unwrap(), snapshots.pending(1).unwrap()) {
+                        (Some(target), Some(_)) => break target,
+                        _ => sleep(Duration::from_millis(1)),
+                    }
+                };
+                snapshots
+                    .complete(0, target, vec![Some(1.5), None])
+                    .unwrap();
+                snapshots.complete(1, target, vec![Some(20.0)]).unwrap();
+            });
+            snapshots.take(Duration::from_secs(5)).unwrap()
+        });
+        assert_eq!(values, [vec![Some(1.5), None], vec![Some(20.0)]]);
+        assert_eq!(snapshots.pending(0).unwrap(), None);
+
+        let log = String::from_utf8(snapshots.log.into_inner().unwrap().0).unwrap();
+        let nanos = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        assert_eq!(log, format!("time,PT1,PT2,TC1\n{nanos},1.5,,20\n"));
+    }
+
+    #[test]
+    /// Test that a loop's jitter is how late it began relative to when it was due, no matter how
+    /// long the loops before it took, and that warnings are throttled.
+    fn loop_jitter() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let period = Duration::from_millis(10);
+        let mut schedule = LoopSchedule::new(at(0));
+        let mut monitor = JitterMonitor::new(Duration::from_millis(2));
+        let mut begin = |ms| {
+            let jitter = schedule.begin(at(ms), period);
+            schedule.next(period);
+            monitor.record(at(ms), jitter)
+        };
+
+        // loops which begin on time or slightly late are fine
+        assert_eq!(begin(0), None);
+        assert_eq!(begin(11), None);
+        assert_eq!(begin(20), None);
+
+        // a loop which begins 5 ms late is warned about
+        assert_eq!(
+            begin(35),
+            Some(JitterStats {
+                n_loops: 4,
+                n_exceeded: 1,
+                total: Duration::from_millis(6),
+                max: Duration::from_millis(5),
+            })
+        );
+
+        // the schedule does not drift after a late loop, and warnings are throttled
+        assert_eq!(begin(40), None);
+        assert_eq!(begin(55), None);
+        let stats = begin(10_080).unwrap();
+        assert_eq!(stats.n_loops, 3);
+        assert_eq!(stats.n_exceeded, 2);
+        assert_eq!(stats.max, Duration::from_millis(10_020));
+        assert_eq!(stats.mean(), Duration::from_millis(10_025) / 3);
+
+        // that loop was more than a period late, so the schedule started over from it
+        assert_eq!(begin(10_090), None);
+    }
+
+    #[test]
+    /// Test that events on a cadence are due once per period, and that missed events are skipped
+    /// rather than happening in a burst.
+    fn status_cadence() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+                "frequency_status": 100,
+                "driver_status_interval_ms": 250,
+                "log_buffer_size": 1,
+                "sensor_groups": [],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [],
+                "pin_heartbeat": 0
+            }"#,
+        ))
+        .unwrap();
+        let (sample_period, broadcast_period) = driver_status_periods(&config);
+        assert_eq!(sample_period, Duration::from_millis(10));
+        let mut cadence = Cadence::new(broadcast_period, start);
+
+        // checked every sample, the broadcasts follow the configured interval
+        let due: Vec<u64> = (0..=1000)
+            .step_by(10)
+            .filter(|&ms| cadence.due(at(ms)))
+            .collect();
+        assert_eq!(due, [0, 250, 500, 750, 1000]);
+        assert_eq!(cadence.next(), at(1250));
+
+        // a long stall skips the broadcasts it missed
+        assert!(cadence.due(at(2010)));
+        assert!(!cadence.due(at(2250)));
+        assert_eq!(cadence.next(), at(2260));
+    }
+
+    #[test]
+    /// Test that a sensor which stops producing readings has its last reading sent again as stale
+    /// once it is older than the staleness window, and that fresh readings are not flagged.
+    fn stale_readings() {
+        let start = SystemTime::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let reading = |sensor_id, ms| SensorReading {
+            sensor_id,
+            reading: 100,
+            value: 1.0,
+            time: at(ms),
+            offset_ns: None,
+            saturated: false,
+            stale: false,
+        };
+        let window = Some(Duration::from_millis(500));
+        let latest = [Some(reading(0, 1000)), Some(reading(1, 200)), None];
+
+        // sensor 1 has fallen behind, but is only resent once its reading is stale
+        let fresh = [Some(reading(0, 1000)), None, None];
+        let sent = readings_to_send(&fresh, &latest, window, at(600));
+        assert_eq!(sent.len(), 1);
+        assert!(!sent[0].stale);
+
+        let sent = readings_to_send(&fresh, &latest, window, at(1000));
+        assert_eq!(
+            sent.iter()
+                .map(|r| (r.sensor_id, r.stale))
+                .collect::<Vec<_>>(),
+            [(0, false), (1, true)]
+        );
+
+        // a reading which was delayed until after the window is flagged even if it is new
+        let sent = readings_to_send(&fresh, &latest, window, at(1600));
+        assert!(sent.iter().all(|r| r.stale));
+
+        // without a window, nothing is resent or flagged
+        let sent = readings_to_send(&fresh, &latest, None, at(10_000));
+        assert_eq!(sent.len(), 1);
+        assert!(!sent[0].stale);
+    }
+
+    #[test]
+    /// Test that a reading pinned at the end of the ADC's range is reported once it has been pinned
+    /// for the whole timeout, but that a ramp through the ends of the range is not.
+    fn pinned_reading() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut monitor = PinnedMonitor::new(Duration::from_millis(100));
+
+        // a ramp from zero to full scale only touches each end briefly
+        for (i, reading) in (0..=ADC_MAX_READING).step_by(5).enumerate() {
+            assert!(!monitor.record(reading, at(i as u64)));
+        }
+        assert!(!monitor.record(2000, at(1000)));
+
+        // a reading stuck at zero is reported once
+        assert!(!monitor.record(0, at(2000)));
+        assert!(!monitor.record(0, at(2100)));
+        assert!(monitor.record(0, at(2101)));
+        assert!(!monitor.record(0, at(3000)));
+
+        // moving from one end to the other starts a new span
+        assert!(!monitor.record(ADC_MAX_READING, at(3001)));
+        assert!(monitor.record(ADC_MAX_READING, at(3200)));
+    }
+
+    #[test]
+    /// Test that a bouncing input only changes its settled level once it holds a new level for the
+    /// whole debounce interval.
+    fn debounce_bouncing_input() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(Duration::from_millis(10));
+
+        // the first sample is settled immediately
+        assert!(!debouncer.update(false, at(0)));
+
+        // a switch closing, bouncing for a few milliseconds before it settles high
+        let bouncing = [
+            (1, true),
+            (2, false),
+            (3, true),
+            (4, false),
+            (6, true),
+            (9, true),
+            (14, true),
+        ];
+        for (ms, level) in bouncing {
+            assert!(!debouncer.update(level, at(ms)), "settled early at {ms} ms");
+        }
+        assert!(debouncer.update(true, at(16)));
+
+        // a single glitch low is ignored
+        assert!(debouncer.update(false, at(20)));
+        assert!(debouncer.update(true, at(35)));
+        assert!(debouncer.update(true, at(50)));
+
+        // with no interval, every change is settled immediately
+        let mut immediate = Debouncer::new(Duration::ZERO);
+        assert!(!immediate.update(false, at(0)));
+        assert!(immediate.update(true, at(1)));
+    }
+
+    #[test]
+    /// Test that only the most recent readings are kept in the history, and that a request for more
+    /// readings than are kept returns every kept reading.
+    fn history_capped() {
+        let mut config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "FAST",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 10,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        config.history_size = Some(3);
+        let history = History::new(&config);
+
+        for reading in 0..5 {
+            history
+                .push(
+                    0,
+                    SensorReading {
+                        sensor_id: 0,
+                        reading,
+                        value: f64::from(reading),
+                        time: SystemTime::UNIX_EPOCH,
+                        offset_ns: None,
+                        saturated: false,
+                        stale: false,
+                    },
+                )
+                .unwrap();
+        }
+
+        let recent = |count| -> Vec<u16> {
+            history
+                .recent(0, 0, count)
+                .unwrap()
+                .iter()
+                .map(|r| r.reading)
+                .collect()
+        };
+        assert_eq!(recent(2), [3, 4]);
+        assert_eq!(recent(100), [2, 3, 4]);
+        assert_eq!(history.latest(0, 0).unwrap().unwrap().reading, 4);
+        assert!(matches!(
+            history.recent(0, 1, 1),
+            Err(execution::Error::SensorOutOfBounds)
+        ));
+    }
+
+    #[test]
+    /// Test that a pre-trigger buffer only keeps readings from within its duration, and that
+    /// flushing it writes and then discards them.
+    fn pretrigger_window() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+        let mut pretrigger = PreTrigger::new(Duration::from_millis(100), 2);
+
+        pretrigger.push(0, start, 1, 0.5);
+        pretrigger.push(1, start, 7, 3.5);
+        pretrigger.push(0, start + Duration::from_millis(50), 2, 1.0);
+        pretrigger.push(0, start + Duration::from_millis(150), 3, 1.5);
+
+        let mut log = Vec::new();
+        pretrigger.flush(4, &mut log).unwrap();
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "4,0,1050000000,2,1\n4,0,1150000000,3,1.5\n4,1,1000000000,7,3.5\n"
+        );
+
+        let mut log = Vec::new();
+        pretrigger.flush(4, &mut log).unwrap();
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    fn data_written() {
+        // create some dummy configuration for the sensor listener thread to read
+        let config = r##"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "dummy",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 10,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "dummy_sensor0",
+                            "color": "#ef3b9e",
+                            "units": "mops",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 0,
+                            "adc": 0,
+                            "channel": 0
+                        },
+                        {
+                            "label": "dummy_sensor1",
+                            "color": "#ef3b9e",
+                            "units": "mops",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 0,
+                            "adc": 1,
+                            "channel": 0
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 5000,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14, 15],
+            "pin_heartbeat": 0
+        }"##;
+        let adcs: Vec<Option<Mutex<ReturnsNumber>>> =
+            (0..2).map(|n| Some(Mutex::new(ReturnsNumber(n)))).collect();
+        let mut cfg_cursor = Cursor::new(config);
+        let config = Configuration::parse(&mut cfg_cursor).unwrap();
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new()); 2];
+        // log file of outputs
+        let mut output_log = Vec::new();
+        // stream of outgoing messages
+        let mut output_stream_buf = Vec::new();
+        let output_stream = DashChannel::<&mut Vec<u8>, &mut Vec<u8>>::new(&mut output_log);
+        output_stream
+            .set_channel(Some(&mut output_stream_buf))
+            .unwrap();
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+
+        // actual magic happens here
+        let user_log = UserLog::new(Vec::<u8>::new());
+        scope(|s| {
+            // spawn a sensor listener thread and let it do its thing
+            let handle = s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
+                )
+            });
+
+            // give the thread enough time to read exactly one value
+            sleep(Duration::from_millis(150));
+
+            // notify the thread to die
+            // hackery to make a valid state transition sequence
+            state.move_to(State::Quit).unwrap();
+            println!("joining...");
+
+            // collect the thread's return value
+            handle.join().unwrap().unwrap();
+        });
+
+        // the controller became ready after the first read, and then sent the one sensor reading
+        // to our dummy dashboard
+        let mut messages =
+            serde_json::Deserializer::from_slice(&output_stream_buf).into_iter::<Value>();
+        assert_eq!(messages.next().unwrap().unwrap()["type"], "Ready");
+
+        let json_val: Value = messages.next().unwrap().unwrap();
+
+        let json_obj = json_val.as_object().unwrap();
+
+        assert_eq!(
+            json_obj.get("type").unwrap().as_str().unwrap(),
+            "SensorValue"
+        );
+
+        assert_eq!(json_obj.get("group_id").unwrap().as_u64().unwrap(), 0);
+
+        // extract group ids and reading values
+        let readings: Vec<(u64, u64)> = json_obj
+            .get("readings")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|val| val.as_object().unwrap())
+            .map(|obj| {
+                (
+                    obj.get("sensor_id").unwrap().as_u64().unwrap(),
+                    obj.get("reading").unwrap().as_u64().unwrap(),
+                )
+            })
+            .collect();
+
+        assert_eq!(readings, [(0, 0), (1, 1)]);
+
+        // check that log information was generated correctly
+        let logged_strings: Vec<String> = logs
+            .into_iter()
+            .map(|cursor| String::from_utf8(cursor.into_inner()).unwrap())
+            .collect();
+
+        for (idx, logged_string) in logged_strings.iter().enumerate() {
+            let adc_readings = logged_string
+                .split('\n')
+                .filter_map(|l| Some(l.split(',').nth(1)?.parse::<usize>().unwrap()))
+                .collect::<Vec<usize>>();
+
+            for &adc_val in &adc_readings {
+                assert_eq!(adc_val, idx);
+            }
+
+            assert_eq!(adc_readings.len(), 2);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    /// Test that the readings of an aligned group share one timestamp and report their offsets.
+    fn aligned_group() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "aligned",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 10,
+                    "frequency_transmission": 10,
+                    "aligned": true,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0
+                        },
+                        {
+                            "label": "PT2",
+                            "color": "blue",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 1,
+                            "channel": 0
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14, 15],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let adcs: Vec<Option<Mutex<ReturnsNumber>>> =
+            (0..2).map(|n| Some(Mutex::new(ReturnsNumber(n)))).collect();
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new()); 2];
+        let mut output_stream_buf = Vec::new();
+        let output_stream = DashChannel::<&mut Vec<u8>, Vec<u8>>::new(Vec::new());
+        output_stream
+            .set_channel(Some(&mut output_stream_buf))
+            .unwrap();
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+
+        let user_log = UserLog::new(Vec::<u8>::new());
+        scope(|s| {
+            let handle = s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
+                )
+            });
+            sleep(Duration::from_millis(150));
+            state.move_to(State::Quit).unwrap();
+            handle.join().unwrap().unwrap();
+        });
+
+        let message = serde_json::Deserializer::from_slice(&output_stream_buf)
+            .into_iter::<Value>()
+            .map(Result::unwrap)
+            .find(|message| message["type"] == "SensorValue")
+            .unwrap();
+        let readings = message["readings"].as_array().unwrap();
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[0]["time"], readings[1]["time"]);
+
+        let offsets: Vec<u64> = readings
+            .iter()
+            .map(|reading| reading["offset_ns"].as_u64().unwrap())
+            .collect();
+        assert!(offsets[0] <= offsets[1]);
+        assert_eq!(message["skew_ns"].as_u64().unwrap(), offsets[1]);
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    /// Test that each reading sent to the dashboard carries its own capture time, so that the
+    /// readings in a batch are spaced by the time taken to read each sensor, and successive
+    /// readings of a sensor by at least the time taken to read the whole group.
+    fn reading_timestamps() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "batched",
+                    "frequency_standby": 20,
+                    "frequency_ignition": 20,
+                    "frequency_transmission": 1000,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0
+                        },
+                        {
+                            "label": "PT2",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 1
+                        },
+                        {
+                            "label": "PT3",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 2
+                        },
+                        {
+                            "label": "PT4",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 3
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let read_time = Duration::from_millis(2);
+        let adcs = vec![Some(Mutex::new(SlowAdc(read_time)))];
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new()); 4];
+        let output_stream = DashChannel::<Capture, Vec<u8>>::new(Vec::new());
+        let capture = Capture::default();
+        output_stream.set_channel(Some(capture.clone())).unwrap();
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+        let user_log = UserLog::new(Vec::<u8>::new());
+
+        scope(|s| {
+            let handle = s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<SlowAdc>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
+                )
+            });
+            // the group runs until it has sent enough batches, however long that takes
+            while full_batches(&capture).len() < 3 {
+                sleep(Duration::from_millis(10));
+            }
+            state.move_to(State::Quit).unwrap();
+            handle.join().unwrap().unwrap();
+        });
+
+        let batches = full_batches(&capture);
+        assert!(batches.len() >= 3, "only {} full batches", batches.len());
+
+        // each read takes at least the read time, so these bounds hold however the threads are
+        // scheduled
+        for times in &batches {
+            for pair in times.windows(2) {
+                assert!(
+                    pair[1] - pair[0] >= read_time.as_nanos() * 9 / 10,
+                    "readings at {times:?} are not spaced by the read time"
+                );
+            }
+        }
+        for pair in batches.windows(2) {
+            let spacing = pair[1][0] - pair[0][0];
+            assert!(
+                spacing >= read_time.as_nanos() * 4 * 9 / 10,
+                "successive readings were only {spacing} ns apart"
+            );
+        }
+    }
+
+    /// Get the capture time of every reading, in nanoseconds since the epoch, in each message in
+    /// `capture` which has a reading of all four sensors.
+    fn full_batches(capture: &Capture) -> Vec<Vec<u128>> {
+        let nanos = |time: &Value| {
+            u128::from(time["secs_since_epoch"].as_u64().unwrap()) * 1_000_000_000
+                + u128::from(time["nanos_since_epoch"].as_u64().unwrap())
+        };
+        capture
+            .messages()
+            .iter()
+            .filter(|message| message["type"] == "SensorValue")
+            .map(|message| {
+                message["readings"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|reading| nanos(&reading["time"]))
+                    .collect()
+            })
+            .filter(|times: &Vec<u128>| times.len() == 4)
+            .collect()
+    }
+
+    #[test]
+    /// Test that clocks created at different times share one base, so that the readings of
+    /// different groups, or of a group before and after it restarts, share one timeline.
+    fn monotonic_clock_shared() {
+        let first = MonotonicClock::new();
+        sleep(Duration::from_millis(1));
+        let second = MonotonicClock::new();
+        let now = Instant::now();
+
+        assert_eq!(first.at(now), second.at(now));
+    }
+
+    #[test]
+    /// Test that every reading is logged, but only every Nth reading is sent to the dashboard.
+    fn dashboard_decimation() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "decimated",
+                    "frequency_standby": 100,
+                    "frequency_ignition": 100,
+                    "frequency_transmission": 1000,
+                    "dashboard_decimation": 4,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let adcs = vec![Some(Mutex::new(ReturnsNumber(0)))];
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new())];
+        let output_stream = DashChannel::<Capture, Vec<u8>>::new(Vec::new());
+        let capture = Capture::default();
+        output_stream.set_channel(Some(capture.clone())).unwrap();
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+        let user_log = UserLog::new(Vec::<u8>::new());
+
+        scope(|s| {
+            let handle = s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
+                )
+            });
+            sleep(Duration::from_millis(400));
+            state.move_to(State::Quit).unwrap();
+            handle.join().unwrap().unwrap();
+        });
+
+        let n_logged = String::from_utf8(logs[0].get_ref().clone())
+            .unwrap()
+            .lines()
+            .count();
+        let n_sent: usize = capture
+            .messages()
+            .iter()
+            .filter(|message| message["type"] == "SensorValue")
+            .map(|message| message["readings"].as_array().unwrap().len())
+            .sum();
+        assert!(n_logged >= 20, "only {n_logged} readings were logged");
+        assert!(
+            (n_logged / 4..=(n_logged + 3) / 4).contains(&n_sent),
+            "{n_sent} of {n_logged} readings were sent"
+        );
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    /// Test that a virtual sensor giving the difference of two sensors is logged and sent to the
+    /// dashboard.
+    fn virtual_difference() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "injector",
+                    "frequency_standby": 100,
+                    "frequency_ignition": 100,
+                    "frequency_transmission": 20,
+                    "sensors": [
+                        {
+                            "label": "PT3: Injector",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 2,
+                            "adc": 0,
+                            "channel": 0
+                        },
+                        {
+                            "label": "PT1: Combustion chamber",
+                            "color": "blue",
+                            "units": "psi",
+                            "calibration_intercept": -10,
+                            "calibration_slope": 1,
+                            "adc": 1,
+                            "channel": 0
+                        }
+                    ],
+                    "virtual_sensors": [
+                        {
+                            "label": "Injector drop",
+                            "units": "psi",
+                            "expression": "[PT3: Injector] - [PT1: Combustion chamber]"
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14, 15],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let adcs = vec![
+            Some(Mutex::new(ReturnsNumber(100))),
+            Some(Mutex::new(ReturnsNumber(40))),
+        ];
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new()); 3];
+        let output_stream = DashChannel::<Capture, Vec<u8>>::new(Vec::new());
+        let capture = Capture::default();
+        output_stream.set_channel(Some(capture.clone())).unwrap();
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+        let user_log = UserLog::new(Vec::<u8>::new());
+
+        scope(|s| {
+            let handle = s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
+                )
+            });
+            sleep(Duration::from_millis(200));
+            state.move_to(State::Quit).unwrap();
+            handle.join().unwrap().unwrap();
+        });
+
+        // 2 * 100 - (40 - 10)
+        let virtual_log = String::from_utf8(logs[2].get_ref().clone()).unwrap();
+        assert!(virtual_log.lines().count() >= 5);
+        for line in virtual_log.lines() {
+            assert_eq!(line.split(',').nth(1), Some("170"));
+        }
+
+        let virtual_messages: Vec<Value> = capture
+            .messages()
+            .into_iter()
+            .filter(|message| message["type"] == "VirtualValue")
+            .collect();
+        assert!(!virtual_messages.is_empty());
+        for message in virtual_messages {
+            assert_eq!(message["group_id"], 0);
+            assert_eq!(message["values"], serde_json::json!([170.0]));
+        }
+    }
+
+    #[test]
+    /// Test that a sensor thread asked to restart writes out the readings it holds and returns
+    /// without the controller quitting.
+    fn restart_requested() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1000,
+            "sensor_groups": [
+                {
+                    "label": "restarted",
+                    "frequency_standby": 100,
+                    "frequency_ignition": 100,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let adcs = vec![Some(Mutex::new(ReturnsNumber(7)))];
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new())];
+        let output_stream = DashChannel::<Vec<u8>, Vec<u8>>::new(Vec::new());
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+        let user_log = UserLog::new(Vec::<u8>::new());
+        let restarts = Restarts::new(&config);
+
+        scope(|s| {
+            let handle = s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &restarts,
+                )
+            });
+            sleep(Duration::from_millis(100));
+            restarts.request(0);
+            handle.join().unwrap().unwrap();
+        });
+
+        assert_eq!(state.status().unwrap(), State::Standby);
+        assert!(restarts.take(0));
+        // the log buffer never filled, but the held readings were still written out
+        let log = String::from_utf8(logs[0].get_ref().clone()).unwrap();
+        assert!(log.lines().count() >= 5);
+        assert!(log.lines().all(|line| line.ends_with(",7,7")));
+    }
+
+    #[test]
+    /// Test that a failed sensor thread waits for a request to restart, or for the controller to
+    /// quit.
+    fn failed_group_awaits_restart() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "failed",
+                    "frequency_standby": 100,
+                    "frequency_ignition": 100,
+                    "frequency_transmission": 10,
+                    "sensors": []
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let state = Guard::new(State::Standby);
+        let restarts = Restarts::new(&config);
+
+        scope(|s| {
+            let waiting = s.spawn(|| restarts.await_request(0, &state));
+            sleep(Duration::from_millis(50));
+            assert!(!waiting.is_finished());
+            restarts.request(0);
+            waiting.join().unwrap().unwrap();
+        });
+        assert!(restarts.take(0));
+
+        scope(|s| {
+            let waiting = s.spawn(|| restarts.await_request(0, &state));
+            state.move_to(State::Quit).unwrap();
+            waiting.join().unwrap().unwrap();
+        });
+        assert!(!restarts.take(0));
+    }
+
+    #[test]
+    /// Test that a sensor group switches to its ignition sampling rate as soon as an ignition
+    /// begins, rather than after finishing its slow standby nap.
+    fn sampling_rate_follows_state() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "burst",
+                    "frequency_standby": 1,
+                    "frequency_ignition": 200,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let adcs = vec![Some(Mutex::new(ReturnsNumber(0)))];
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new())];
+        let output_stream = DashChannel::<Vec<u8>, Vec<u8>>::new(Vec::new());
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+        let user_log = UserLog::new(Vec::<u8>::new());
+
+        scope(|s| {
+            let handle = s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
+                )
+            });
+            sleep(Duration::from_millis(100));
+            state.move_to(State::PreIgnite).unwrap();
+            sleep(Duration::from_millis(300));
+            state.move_to(State::EStopping).unwrap();
+            state.move_to(State::Standby).unwrap();
+            state.move_to(State::Quit).unwrap();
+            handle.join().unwrap().unwrap();
+        });
+
+        // at the standby rate, the group would still be asleep for the whole ignition
+        let n_logged = String::from_utf8(logs[0].get_ref().clone())
+            .unwrap()
+            .lines()
+            .count();
+        assert!(n_logged >= 30, "only {n_logged} readings were logged");
+    }
+
+    #[test]
+    /// Test that an emergency stop is successfully called.
+    fn estop_called() {
+        // create some dummy configuration for the sensor listener thread to read
+        let config = r##"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "dummy",
+                    "frequency_standby": 1,
+                    "frequency_ignition": 1,
+                    "frequency_transmission": 1,
+                    "sensors": [
+                        {
+                            "label": "dummy_sensor0",
+                            "color": "#ef3b9e",
+                            "units": "mops",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0,
+                            "rolling_average_width": 1,
+                            "range": [-5, 5]
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [ {
+                "type": "Sleep",
+                "duration": {
+                    "secs": 0,
+                    "nanos": 500000000
+                }
+            }],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0
+        }"##;
+
+        let adcs = [Some(Mutex::new(ReturnsNumber(100)))];
+
+        let mut cfg_cursor = Cursor::new(config);
+        let config = Configuration::parse(&mut cfg_cursor).unwrap();
+
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new()); 2];
+        let output_stream = DashChannel::<Vec<u8>, Vec<u8>>::new(Vec::new());
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+
+        // actual magic happens here
+        let user_log = UserLog::new(Vec::<u8>::new());
+        scope(|s| {
+            // spawn a sensor listener thread and let it do its thing
+            s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
+                )
+            });
+
+            // give the thread enough time to read values
+            sleep(Duration::from_millis(200));
 
-    use crate::hardware::{ListenerPin, ReturnsNumber};
+            // check that we are currently e-stopping
+            assert_eq!(state.status().unwrap(), State::EStopping);
 
-    use super::*;
+            // bring the sensor back in range so that no further emergency stop begins, and then
+            // kill the thread
+            adcs[0].as_ref().unwrap().lock().unwrap().0 = 0;
+            while state.move_to(State::Quit).is_err() {
+                state
+                    .wait_while(State::EStopping, Duration::from_millis(100))
+                    .unwrap();
+            }
+        });
+    }
 
     #[test]
-    #[allow(clippy::too_many_lines)]
-    fn data_written() {
-        // create some dummy configuration for the sensor listener thread to read
+    /// Test that an injected reading is reported in place of an ADC reading and is flagged in the
+    /// sensor log.
+    fn injected_reading_flagged() {
         let config = r##"{
             "frequency_status": 10,
             "log_buffer_size": 1,
@@ -404,24 +4074,15 @@ mod tests {
                             "color": "#ef3b9e",
                             "units": "mops",
                             "calibration_intercept": 0,
-                            "calibration_slope": 0,
+                            "calibration_slope": 1,
                             "adc": 0,
                             "channel": 0
-                        },
-                        {
-                            "label": "dummy_sensor1",
-                            "color": "#ef3b9e",
-                            "units": "mops",
-                            "calibration_intercept": 0,
-                            "calibration_slope": 0,
-                            "adc": 1,
-                            "channel": 0
                         }
                     ]
                 }
             ],
-            "pre_ignite_time": 500,
-            "post_ignite_time": 5000,
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
             "drivers": [],
             "ignition_sequence": [],
             "estop_sequence": [],
@@ -429,28 +4090,22 @@ mod tests {
             "spi_miso": 12,
             "spi_clk": 13,
             "spi_frequency_clk": 50000,
-            "adc_cs": [14, 15],
+            "adc_cs": [14],
             "pin_heartbeat": 0
         }"##;
-        let adcs: Vec<Mutex<ReturnsNumber>> =
-            (0..2).map(|n| Mutex::new(ReturnsNumber(n))).collect();
+
         let mut cfg_cursor = Cursor::new(config);
         let config = Configuration::parse(&mut cfg_cursor).unwrap();
         let state = Guard::new(State::Standby);
-        let mut logs = vec![Cursor::new(Vec::new()); 2];
-        // log file of outputs
-        let mut output_log = Vec::new();
-        // stream of outgoing messages
-        let mut output_stream_buf = Vec::new();
-        let output_stream = DashChannel::<&mut Vec<u8>, &mut Vec<u8>>::new(&mut output_log);
-        output_stream
-            .set_channel(Some(&mut output_stream_buf))
-            .unwrap();
-        let driver_lines = Mutex::new(Vec::<ListenerPin>::new());
+        let mut logs = vec![Cursor::new(Vec::new())];
+        let output_stream = DashChannel::<Vec<u8>, Vec<u8>>::new(Vec::new());
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+        let injections = Injections::new(&config, true);
 
-        // actual magic happens here
+        injections.inject(0, 0, 1234).unwrap();
+
+        let user_log = UserLog::new(Vec::<u8>::new());
         scope(|s| {
-            // spawn a sensor listener thread and let it do its thing
             let handle = s.spawn(|| {
                 sensor_listen(
                     s,
@@ -458,100 +4113,244 @@ mod tests {
                     &config,
                     &driver_lines,
                     &mut logs,
-                    &UserLog::new(Vec::<u8>::new()),
-                    &adcs,
+                    &user_log,
+                    &[Some(Mutex::new(ReturnsNumber(7)))],
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
                     &state,
                     &output_stream,
+                    &injections,
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
                 )
             });
 
-            // give the thread enough time to read exactly one value
+            // give the thread enough time to read exactly two values
             sleep(Duration::from_millis(150));
-
-            // notify the thread to die
-            // hackery to make a valid state transition sequence
             state.move_to(State::Quit).unwrap();
-            println!("joining...");
 
-            // collect the thread's return value
             handle.join().unwrap().unwrap();
         });
 
-        // validate the one sensor reading that was sent to our dummy dashboard
+        let logged_string = String::from_utf8(logs.pop().unwrap().into_inner()).unwrap();
+        let rows: Vec<Vec<&str>> = logged_string
+            .lines()
+            .map(|line| line.split(',').skip(1).collect())
+            .collect();
 
-        let json_val: Value = serde_json::from_slice(&output_stream_buf).unwrap();
+        assert_eq!(rows[0], ["1234", "1234", "injected"]);
+        assert_eq!(rows[1], ["7", "7"]);
+    }
 
-        let json_obj = json_val.as_object().unwrap();
+    #[test]
+    /// Test that a pause stops readings from being logged, with a marker row at each end of the
+    /// gap, but that readings are still checked against their range while paused, and that the
+    /// pause ends once the controller leaves standby.
+    fn pause_markers() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r##"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "paused",
+                    "frequency_standby": 100,
+                    "frequency_ignition": 100,
+                    "frequency_transmission": 100,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "#ef3b9e",
+                            "units": "psi",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0,
+                            "range": [-5, 5]
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0
+        }"##,
+        ))
+        .unwrap();
+        let state = Guard::new(State::Standby);
+        let mut logs = vec![Cursor::new(Vec::new())];
+        let output_stream = DashChannel::<Vec<u8>, Vec<u8>>::new(Vec::new());
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+        let injections = Injections::new(&config, true);
+        let pause = Pause::new();
+        let user_log = UserLog::new(Vec::<u8>::new());
+
+        scope(|s| {
+            let handle = s.spawn(|| {
+                sensor_listen(
+                    s,
+                    0,
+                    &config,
+                    &driver_lines,
+                    &mut logs,
+                    &user_log,
+                    &[Some(Mutex::new(ReturnsNumber(0)))],
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &injections,
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &Liveness::new(&config),
+                    &Snapshots::new(&config, Vec::new()),
+                    &pause,
+                    &Restarts::new(&config),
+                )
+            });
+
+            for paused in [true, false, true] {
+                sleep(Duration::from_millis(50));
+                pause.set(paused).unwrap();
+            }
+            assert!(pause.set(true).is_err());
+            sleep(Duration::from_millis(50));
+
+            // an out-of-range reading while paused must still cause an emergency stop
+            injections.inject(0, 0, 100).unwrap();
+            sleep(Duration::from_millis(100));
+            assert_eq!(state.estop_count(), 1);
+            assert!(!pause.is_paused());
+
+            while state.move_to(State::Quit).is_err() {}
+            handle.join().unwrap().unwrap();
+        });
 
+        let logged_string = String::from_utf8(logs.pop().unwrap().into_inner()).unwrap();
+        let mut kinds: Vec<&str> = logged_string
+            .lines()
+            .map(|line| line.split(",,,").nth(1).unwrap_or("reading"))
+            .collect();
+        kinds.dedup();
         assert_eq!(
-            json_obj.get("type").unwrap().as_str().unwrap(),
-            "SensorValue"
+            kinds,
+            ["reading", "paused", "resumed", "reading", "paused", "resumed", "reading"]
         );
+    }
 
-        assert_eq!(json_obj.get("group_id").unwrap().as_u64().unwrap(), 0);
+    #[test]
+    /// Test that readings cannot be injected unless injection is allowed.
+    fn injection_forbidden() {
+        let config = r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
 
-        // extract group ids and reading values
-        let readings: Vec<(u64, u64)> = json_obj
-            .get("readings")
-            .unwrap()
-            .as_array()
-            .unwrap()
-            .iter()
-            .map(|val| val.as_object().unwrap())
-            .map(|obj| {
-                (
-                    obj.get("sensor_id").unwrap().as_u64().unwrap(),
-                    obj.get("reading").unwrap().as_u64().unwrap(),
-                )
-            })
-            .collect();
+        let config = Configuration::parse(&mut Cursor::new(config)).unwrap();
 
-        assert_eq!(readings, [(0, 0), (1, 1)]);
+        assert!(matches!(
+            Injections::new(&config, false).inject(0, 0, 1),
+            Err(execution::Error::InjectionForbidden)
+        ));
+        assert!(matches!(
+            Injections::new(&config, true).inject(0, 0, 1),
+            Err(execution::Error::SensorOutOfBounds)
+        ));
+    }
 
-        // check that log information was generated correctly
-        let logged_strings: Vec<String> = logs
-            .into_iter()
-            .map(|cursor| String::from_utf8(cursor.into_inner()).unwrap())
-            .collect();
+    /// An ADC which returns each value of a sequence in turn, counting the number of reads.
+    struct SequenceAdc {
+        values: Vec<u16>,
+        reads: usize,
+    }
 
-        for (idx, logged_string) in logged_strings.iter().enumerate() {
-            let adc_readings = logged_string
-                .split('\n')
-                .filter_map(|l| Some(l.split(',').nth(1)?.parse::<usize>().unwrap()))
-                .collect::<Vec<usize>>();
+    impl Adc for SequenceAdc {
+        fn read(&mut self, _: u8) -> Result<u16, ControllerError> {
+            let value = self.values[self.reads % self.values.len()];
+            self.reads += 1;
+            Ok(value)
+        }
+    }
 
-            for &adc_val in &adc_readings {
-                assert_eq!(adc_val, idx);
-            }
+    /// An ADC which takes the given time to complete every read, returning the channel read.
+    struct SlowAdc(Duration);
 
-            assert_eq!(adc_readings.len(), 2);
+    impl Adc for SlowAdc {
+        fn read(&mut self, channel: u8) -> Result<u16, ControllerError> {
+            sleep(self.0);
+            Ok(u16::from(channel))
+        }
+    }
+
+    /// An ADC which hangs on every read while `stuck` is set.
+    struct StuckAdc<'a> {
+        stuck: &'a AtomicBool,
+    }
+
+    impl Adc for StuckAdc<'_> {
+        fn read(&mut self, _: u8) -> Result<u16, ControllerError> {
+            while self.stuck.load(Ordering::Relaxed) {
+                sleep(Duration::from_millis(1));
+            }
+            Ok(0)
         }
     }
 
     #[test]
-    /// Test that an emergency stop is successfully called.
-    fn estop_called() {
-        // create some dummy configuration for the sensor listener thread to read
-        let config = r##"{
+    #[allow(clippy::too_many_lines)]
+    /// Test that a sensor group whose read hangs is reported as stalled and triggers an emergency
+    /// stop, but that a healthy group is not.
+    fn stalled_group() {
+        let mut config = Configuration::parse(&mut Cursor::new(
+            r#"{
             "frequency_status": 10,
             "log_buffer_size": 1,
             "sensor_groups": [
                 {
-                    "label": "dummy",
-                    "frequency_standby": 1,
-                    "frequency_ignition": 1,
-                    "frequency_transmission": 1,
+                    "label": "FAST",
+                    "frequency_standby": 100,
+                    "frequency_ignition": 100,
+                    "frequency_transmission": 10,
                     "sensors": [
                         {
-                            "label": "dummy_sensor0",
-                            "color": "#ef3b9e",
-                            "units": "mops",
+                            "label": "PT1",
+                            "color": "red",
                             "calibration_intercept": 0,
                             "calibration_slope": 1,
                             "adc": 0,
-                            "channel": 0,
-                            "rolling_average_width": 2,
-                            "range": [-5, 5]
+                            "channel": 0
                         }
                     ]
                 }
@@ -560,58 +4359,178 @@ mod tests {
             "post_ignite_time": 0,
             "drivers": [],
             "ignition_sequence": [],
-            "estop_sequence": [ {
-                "type": "Sleep",
-                "duration": {
-                    "secs": 1,
-                    "nanos": 0
-                }
-            }],
+            "estop_sequence": [],
             "spi_mosi": 11,
             "spi_miso": 12,
             "spi_clk": 13,
             "spi_frequency_clk": 50000,
             "adc_cs": [14],
             "pin_heartbeat": 0
-        }"##;
-
-        let adc = Mutex::new(ReturnsNumber(100));
-
-        let mut cfg_cursor = Cursor::new(config);
-        let config = Configuration::parse(&mut cfg_cursor).unwrap();
+        }"#,
+        ))
+        .unwrap();
+        config.stall_timeout_ms = Some(100);
+        config.stall_estop = true;
 
+        let stuck = AtomicBool::new(false);
+        let adcs = vec![Some(Mutex::new(StuckAdc { stuck: &stuck }))];
         let state = Guard::new(State::Standby);
-        let mut logs = vec![Cursor::new(Vec::new()); 2];
-        let output_stream = DashChannel::<Vec<u8>, Vec<u8>>::new(Vec::new());
-        let driver_lines = Mutex::new(Vec::<ListenerPin>::new());
+        let mut output_stream_buf = Vec::new();
+        let output_stream = DashChannel::<&mut Vec<u8>, Vec<u8>>::new(Vec::new());
+        output_stream
+            .set_channel(Some(&mut output_stream_buf))
+            .unwrap();
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+        let liveness = Liveness::new(&config);
+        let user_log = UserLog::new(Vec::<u8>::new());
 
-        // actual magic happens here
         scope(|s| {
-            // spawn a sensor listener thread and let it do its thing
-            s.spawn(|| {
+            let listener = s.spawn(|| {
                 sensor_listen(
                     s,
                     0,
                     &config,
                     &driver_lines,
-                    &mut logs,
-                    &UserLog::new(Vec::<u8>::new()),
-                    &[adc],
+                    &mut [Vec::new()],
+                    &user_log,
+                    &adcs,
+                    &mut Vec::<ListenerPin>::new(),
+                    &mut Vec::<Option<ReturnsNumber>>::new(),
+                    &state,
+                    &output_stream,
+                    &Injections::new(&config, false),
+                    None,
+                    None::<&Mutex<Vec<u8>>>,
+                    &Tunings::new(&config),
+                    &Flusher::new(&config, Vec::new()),
+                    &Readiness::new(&config),
+                    &History::new(&config),
+                    &liveness,
+                    &Snapshots::new(&config, Vec::new()),
+                    &Pause::new(),
+                    &Restarts::new(&config),
+                )
+            });
+            let watcher = s.spawn(|| {
+                stall_watch(
+                    &config,
+                    &driver_lines,
+                    &liveness,
+                    &user_log,
                     &state,
                     &output_stream,
                 )
             });
 
-            // give the thread enough time to read values
-            sleep(Duration::from_millis(200));
+            // a healthy group is never reported
+            sleep(Duration::from_millis(250));
+            assert_eq!(state.estop_count(), 0);
 
-            // check that we are currently e-stopping
-            assert_eq!(state.status().unwrap(), State::EStopping);
+            stuck.store(true, Ordering::Relaxed);
+            sleep(Duration::from_millis(300));
+            assert_eq!(state.estop_count(), 1);
 
-            // continually attempt to kill the thread
-            while state.move_to(State::Quit).is_err() {
-                println!("failed: {:?}", state.status().unwrap());
-            }
+            stuck.store(false, Ordering::Relaxed);
+            state.move_to(State::Quit).unwrap();
+            listener.join().unwrap().unwrap();
+            watcher.join().unwrap().unwrap();
         });
+
+        let errors: Vec<Value> = serde_json::Deserializer::from_slice(&output_stream_buf)
+            .into_iter::<Value>()
+            .map(Result::unwrap)
+            .filter(|message| message["type"] == "Error")
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["level"], "Critical");
+    }
+
+    #[test]
+    /// Test that oversampling performs `K` reads and averages them into a single reading.
+    fn oversampling() {
+        let mut adc = SequenceAdc {
+            values: vec![100, 200, 300, 401],
+            reads: 0,
+        };
+
+        assert_eq!(read_oversampled(&mut adc, 0, 4).unwrap(), 250);
+        assert_eq!(adc.reads, 4);
+
+        assert_eq!(read_oversampled(&mut adc, 0, 1).unwrap(), 100);
+        assert_eq!(adc.reads, 5);
+    }
+
+    #[test]
+    /// Test that the configured number of warm-up readings are never reported.
+    fn warmup_discarded() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "FAST",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 10,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "PT1",
+                            "color": "red",
+                            "calibration_intercept": 0,
+                            "calibration_slope": 1,
+                            "adc": 0,
+                            "channel": 0,
+                            "discard_first": 2
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [14],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let sensor = &config.sensor_groups[0].sensors[0];
+        let adcs = vec![Some(Mutex::new(SequenceAdc {
+            values: vec![4095, 4095, 1000],
+            reads: 0,
+        }))];
+        let read = |sensor: &Sensor| {
+            acquire_reading(
+                0,
+                0,
+                sensor,
+                &adcs,
+                None::<&mut SequenceAdc>,
+                &Injections::new(&config, false),
+                &UserLog::new(Vec::new()),
+                &Liveness::new(&config),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            discard_warmup(sensor, &adcs, None::<&mut SequenceAdc>).unwrap(),
+            2
+        );
+        assert_eq!(read(sensor), Some((1000, false)));
+        assert_eq!(adcs[0].as_ref().unwrap().lock().unwrap().reads, 3);
+
+        // a sensor whose ADC failed has nothing to discard
+        let adcs = vec![None::<Mutex<SequenceAdc>>];
+        assert_eq!(
+            discard_warmup(sensor, &adcs, None::<&mut SequenceAdc>).unwrap(),
+            0
+        );
     }
 }
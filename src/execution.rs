@@ -19,17 +19,32 @@
 //! Functions for command execution.
 
 use crate::{
-    config::{Action, Configuration},
+    calibration::{self, Calibrator},
+    config::is_legal,
+    config::{Action, Configuration, SequencePolicy},
     console::UserLog,
-    hardware::GpioPin,
-    incoming::Command,
+    data::{Flusher, History, Injections, Pause, Readiness, Restarts, Snapshots},
+    hardware::{GpioPin, Readback, Rewire},
+    incoming::{Actuation, Command, SensorRef},
+    outgoing::{
+        CalibrationSummary, DashChannel, DriverInfo, DriverState, Message, ReadingList, SensorInfo,
+        SensorReading, SensorStatus, PROTOCOL_VERSION,
+    },
+    reload::Tunings,
     state::{self, Guard, State},
+    ControllerError,
 };
 use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
     io::Write,
-    sync::{Mutex, PoisonError},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Condvar, LockResult, Mutex, MutexGuard, PoisonError,
+    },
     thread::sleep,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 #[derive(Debug)]
@@ -41,277 +56,4216 @@ pub enum Error {
     DriverOutOfBounds,
     /// While executing a procedure, an illegal transition was attempted.
     State(state::Error),
+    /// The dashboard attempted to inject a sensor reading, but the controller was not started with
+    /// injection allowed.
+    InjectionForbidden,
+    /// The command referred to a sensor that doesn't exist.
+    SensorOutOfBounds,
+    /// The command referred to a sensor by a label which no sensor has.
+    UnknownSensor(String),
+    /// The command referred to a sensor by a label which more than one sensor has.
+    AmbiguousSensor(String),
+    /// The command tried to power a hazardous driver without the operator's confirmation.
+    HazardUnconfirmed,
+    /// The command tried to power a hazardous driver outside of an ignition.
+    /// The value inside this variant is the state the controller was in at the time.
+    HazardOutsideIgnition(State),
+    /// The command tried to actuate a driver sooner than its minimum actuation interval allows.
+    /// The value inside this variant is the remaining time until the driver may be actuated again.
+    ActuationTooSoon(Duration),
+    /// The dashboard requested a hold, but no ignition is in progress.
+    NothingToHold,
+    /// A sequence was abandoned because an emergency stop began while it was executing.
+    SequenceAborted,
+    /// A client attempted to authenticate with an operator name which is empty or contains
+    /// characters other than ASCII alphanumerics, `-`, `_`, and `.`.
+    BadOperator,
+    /// A client declared a version of the dashboard protocol which the controller cannot speak.
+    UnsupportedProtocol {
+        /// The version declared by the client.
+        declared: u32,
+        /// The oldest version which the controller accepts.
+        min: u32,
+    },
+    /// A client sent a command without first declaring its version of the dashboard protocol, but
+    /// the configuration requires one.
+    UndeclaredProtocol,
+    /// An abort was requested with an action which is not defined in the configuration.
+    /// The string is the name of the action.
+    NoSuchAbortAction(String),
+    /// A flush was requested, but not every sensor thread wrote out its readings in time.
+    FlushTimeout,
+    /// Sensor logging and streaming were paused, but they were already paused.
+    AlreadyPaused,
+    /// Sensor logging and streaming were resumed, but they were not paused.
+    NotPaused,
+    /// Sensor logging and streaming were paused while the controller was not in standby.
+    /// The value inside this variant is the state that the controller was in.
+    PauseOutsideStandby(State),
+    /// A log file could not be synced to disk.
+    Sync(std::io::Error),
+    /// A snapshot was requested, but not every sensor thread contributed its readings in time.
+    SnapshotTimeout,
+    /// A snapshot could not be written to the snapshot log.
+    SnapshotNotLogged(std::io::Error),
+    /// The command tried to actuate hardware before every sensor had been read.
+    NotReady,
+    /// A keep-alive was sent for a driver which is not energized under a dead man's switch.
+    /// The value inside this variant is the ID of the driver.
+    NotHeld(u8),
+    /// The command requested a test fire longer than the safety ceiling allows.
+    /// The value inside this variant is the requested duration, in milliseconds.
+    TestFireTooLong(u32),
+    /// The command requested a test fire while the controller was not in standby.
+    /// The value inside this variant is the state the controller was in at the time.
+    TestFireOutsideStandby(State),
+    /// The command requested a leak check longer than the ceiling allows.
+    /// The value inside this variant is the requested duration, in seconds.
+    LeakCheckTooLong(u32),
+    /// The command requested a leak check while the controller was not in standby.
+    /// The value inside this variant is the state the controller was in at the time.
+    LeakCheckOutsideStandby(State),
+    /// The sensor watched by a leak check produced no new reading during the check.
+    NoNewReading,
+    /// The command tried to calibrate a sensor while the controller was not in standby.
+    /// The value inside this variant is the state the controller was in at the time.
+    CalibrationOutsideStandby(State),
+    /// The command tried to linearly calibrate a thermistor, whose linear calibration is ignored.
+    CalibrateThermistor,
+    /// The command tried to calibrate a sensor which has not been read yet.
+    NoReading,
+    /// Both reference points of a calibration had the same raw reading, so no slope can be
+    /// computed.
+    /// The value inside this variant is the raw reading.
+    DegenerateCalibration(u16),
+    /// A computed calibration could not be saved to the calibration file, so it was not applied.
+    CalibrationNotSaved(calibration::Error),
+    /// The command tried to change a sensor's range while the controller was not in standby.
+    /// The value inside this variant is the state the controller was in at the time.
+    ThresholdOutsideStandby(State),
+    /// The command tried to move a driver to a new pin while the controller was not in standby.
+    /// The value inside this variant is the state the controller was in at the time.
+    RemapOutsideStandby(State),
+    /// The command tried to move a driver to a pin which is reserved or already in use.
+    /// The value inside this variant is the pin ID.
+    PinInUse(u8),
+    /// The new pin of a driver could not be acquired, so the driver kept its old pin.
+    /// The values inside this variant are the pin ID and the cause of the failure.
+    PinUnavailable(u8, ControllerError),
+    /// The GPIO line of a driver could not be read or written.
+    /// The values inside this variant are the driver ID and the cause of the failure.
+    DriverLine(u8, gpio_cdev::Error),
+    /// The command gave a sensor a range whose minimum is not less than its maximum.
+    /// The values inside this variant are the minimum and maximum.
+    BadThreshold(f64, f64),
+    /// The command tried to begin an ignition before the cooldown after the last emergency stop was
+    /// over.
+    /// The value inside this variant is the remaining time until the cooldown is over.
+    CoolingDown(Duration),
+    /// The command tried to begin an ignition after an emergency stop while a sensor's latest
+    /// reading was still outside its range.
+    /// The values inside this variant are the sensor's label and its latest value.
+    StillOutOfRange(String, f64),
+    /// The command tried to begin a sequence while another was running, and only one sequence may
+    /// run at a time.
+    SequenceRunning,
+    /// The command tried to begin a sequence controlling a driver which another running sequence
+    /// already controls.
+    /// The value inside this variant is the ID of the driver.
+    DriverClaimed(u8),
+    /// The command tried to actuate the same driver more than once in a single group of
+    /// actuations.
+    /// The value inside this variant is the ID of the driver.
+    RepeatedDriver(u8),
+    /// The command referenced an interlock which does not exist.
+    /// The value inside this variant is the ID of the interlock.
+    NoSuchInterlock(u8),
+    /// The command tried to energize a driver while another driver in the same interlock was
+    /// energized.
+    /// The values inside this variant are the ID of the driver being energized and the ID of the
+    /// energized driver blocking it.
+    Interlocked(u8, u8),
+    /// The command tried to override an interlock without the operator's confirmation.
+    OverrideUnconfirmed,
+    /// The command tried to override an interlock while the controller was not in standby.
+    /// The value inside this variant is the state that the controller was in.
+    OverrideOutsideStandby(State),
+    /// The command referenced a sensor group which does not exist.
+    /// The value inside this variant is the ID of the group.
+    NoSuchGroup(u8),
+    /// The command tried to restart a sensor group without the operator's confirmation.
+    RestartUnconfirmed,
+    /// The command tried to restart a sensor group while the controller was not in standby.
+    /// The value inside this variant is the state that the controller was in.
+    RestartOutsideStandby(State),
+    /// The command tried to restart a sensor group which has no running thread.
+    /// The value inside this variant is the ID of the group.
+    GroupNotRunning(u8),
+    /// The command tried to make an annotation with no text.
+    EmptyAnnotation,
+    /// An annotation could not be written to the event log.
+    Annotation(std::io::Error),
+    /// The command tried to energize a driver whose precondition sensor was outside its range.
+    /// The values inside this variant are the ID of the driver and the latest value of the sensor,
+    /// or `None` if the sensor has not been read yet.
+    PreconditionUnmet(u8, Option<f64>),
+    /// The command tried to energize a driver whose precondition sensor's latest reading was stale
+    /// or older than the precondition allows.
+    /// The values inside this variant are the ID of the driver and the age of the reading.
+    PreconditionStale(u8, Duration),
 }
 
-impl<T> From<PoisonError<T>> for Error {
-    fn from(_: PoisonError<T>) -> Self {
-        Error::Poison
-    }
+/// The record of when each driver was last actuated by a command, used to enforce each driver's
+/// minimum actuation interval.
+pub struct Throttle {
+    /// The minimum interval between actuations of each driver, if any.
+    intervals: Vec<Option<Duration>>,
+    /// The time at which each driver was last actuated by a command, if ever.
+    last_actuation: Mutex<Vec<Option<Instant>>>,
 }
 
-impl From<state::Error> for Error {
-    fn from(value: state::Error) -> Self {
-        Error::State(value)
+impl Throttle {
+    #[must_use]
+    /// Construct a new `Throttle` for the drivers in `configuration`, none of which have been
+    /// actuated yet.
+    pub fn new(configuration: &Configuration) -> Throttle {
+        Throttle {
+            intervals: configuration
+                .drivers
+                .iter()
+                .map(|driver| {
+                    driver
+                        .min_actuation_interval_ms
+                        .map(|ms| Duration::from_millis(u64::from(ms)))
+                })
+                .collect(),
+            last_actuation: Mutex::new(vec![None; configuration.drivers.len()]),
+        }
+    }
+
+    /// Record an actuation of the driver with ID `driver_id`, if it is allowed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the driver was last actuated less than its minimum
+    /// actuation interval ago, or if the internal lock is poisoned.
+    pub fn actuate(&self, driver_id: u8) -> Result<(), Error> {
+        self.actuate_all(&[driver_id])
+    }
+
+    /// Record an actuation of every driver in `driver_ids`, if all of them are allowed.
+    /// If any actuation is not allowed, none of them are recorded.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if any driver was last actuated less than its minimum
+    /// actuation interval ago, or if the internal lock is poisoned.
+    pub fn actuate_all(&self, driver_ids: &[u8]) -> Result<(), Error> {
+        let mut last_actuation = self.last_actuation.lock()?;
+        let now = Instant::now();
+        for &driver_id in driver_ids {
+            let idx = usize::from(driver_id);
+            let Some(&last) = last_actuation.get(idx) else {
+                return Err(Error::DriverOutOfBounds);
+            };
+            if let (Some(interval), Some(prev)) = (self.intervals[idx], last) {
+                let remaining = interval.saturating_sub(now.duration_since(prev));
+                if !remaining.is_zero() {
+                    return Err(Error::ActuationTooSoon(remaining));
+                }
+            }
+        }
+
+        for &driver_id in driver_ids {
+            last_actuation[usize::from(driver_id)] = Some(now);
+        }
+        Ok(())
     }
 }
 
-/// Execute a command and log the process of execution.
-///
-/// # Inputs
-///
-/// * `cmd`: The command to be executed.
-/// * `log_file`: Location where log information will be written.
-/// * `configuration`: Configuration object for program execution.
-/// * `driver_lines`: Output lines for the drivers.
-///     Each index in `driver_lines` corresponds one-to-one with the drivers in `configuration`.  
-/// * `state`: The controller for the current system state.
-/// * `dashboard_stream`: The stream to use for writing messages to the dashboard.
-///
-/// # Errors
+/// The period at which a held sequence checks whether an emergency stop has begun.
+const HOLD_POLL_PERIOD: Duration = Duration::from_millis(10);
+
+/// The longest time a flush waits for every sensor thread to write out its readings.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest time a snapshot waits for every sensor thread to contribute its readings.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The period at which dead man's switches are checked for missed keep-alives.
+const DEADMAN_POLL_PERIOD: Duration = Duration::from_millis(5);
+
+/// The longest time, in milliseconds, for which a test fire may energize a driver.
+const TEST_FIRE_CEILING_MS: u32 = 5000;
+
+/// The longest time for which the purge thread waits before checking whether the controller is
+/// quitting.
+const PURGE_POLL_PERIOD: Duration = Duration::from_millis(100);
+
+/// The period at which a test fire checks whether its deadline has passed.
+const TEST_FIRE_POLL_PERIOD: Duration = Duration::from_millis(5);
+
+/// The longest time, in seconds, for which a leak check may watch a sensor.
+const LEAK_CHECK_CEILING_S: u32 = 3600;
+
+/// The period at which a leak check checks whether its hold time has passed.
+const LEAK_CHECK_POLL_PERIOD: Duration = Duration::from_millis(50);
+
+/// A mutex which lets safing actions jump the queue.
 ///
-/// This function will return an error if a lock is poisoned or if we are unable to actuate GPIO.
+/// The driver lines are shared between the command handler, sequences, and the sensor threads.
+/// An abort or emergency stop must not wait behind a queue of ordinary actuations, so it takes
+/// the lock with [`PriorityMutex::lock_urgent`].
+/// While any urgent acquisition is pending, ordinary calls to [`PriorityMutex::lock`] wait for it
+/// to finish before contending for the lock, and one which was already contending gives the lock
+/// straight back if it wins, so a safing action only ever waits for the one holder ahead of it.
+pub struct PriorityMutex<T> {
+    /// The protected value.
+    value: Mutex<T>,
+    /// The number of urgent acquisitions which are pending or in progress.
+    urgent: Mutex<usize>,
+    /// A condition variable which is notified when the last urgent acquisition finishes.
+    cleared: Condvar,
+}
+
+/// A guard for an urgent acquisition of a [`PriorityMutex`].
 ///
-/// # Panics
+/// When dropped, the lock is released and ordinary acquisitions may proceed.
+pub struct UrgentGuard<'a, T> {
+    /// The guard on the protected value.
+    /// This is only `None` while the guard is being dropped.
+    guard: Option<MutexGuard<'a, T>>,
+    /// The mutex from which this guard was acquired.
+    mutex: &'a PriorityMutex<T>,
+}
+
+impl<T> PriorityMutex<T> {
+    #[must_use]
+    /// Construct a new `PriorityMutex` protecting `value`.
+    pub fn new(value: T) -> PriorityMutex<T> {
+        PriorityMutex {
+            value: Mutex::new(value),
+            urgent: Mutex::new(0),
+            cleared: Condvar::new(),
+        }
+    }
+
+    /// Acquire the lock, first waiting for every pending urgent acquisition to finish.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the lock is poisoned.
+    pub fn lock(&self) -> LockResult<MutexGuard<T>> {
+        loop {
+            let urgent = self.urgent.lock().unwrap_or_else(PoisonError::into_inner);
+            // the counter is only ever incremented and decremented, so a poisoned counter is fine
+            drop(
+                self.cleared
+                    .wait_while(urgent, |n| *n > 0)
+                    .unwrap_or_else(PoisonError::into_inner),
+            );
+            let guard = self.value.lock();
+            // an urgent acquisition which began while we were contending goes first
+            if *self.urgent.lock().unwrap_or_else(PoisonError::into_inner) == 0 {
+                return guard;
+            }
+        }
+    }
+
+    /// Acquire the lock ahead of every ordinary acquisition which has not yet started.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the lock is poisoned.
+    pub fn lock_urgent(&self) -> Result<UrgentGuard<T>, PoisonError<UrgentGuard<T>>> {
+        *self.urgent.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        match self.value.lock() {
+            Ok(guard) => Ok(UrgentGuard {
+                guard: Some(guard),
+                mutex: self,
+            }),
+            Err(e) => Err(PoisonError::new(UrgentGuard {
+                guard: Some(e.into_inner()),
+                mutex: self,
+            })),
+        }
+    }
+}
+
+impl<T> std::ops::Deref for UrgentGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T> std::ops::DerefMut for UrgentGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for UrgentGuard<'_, T> {
+    fn drop(&mut self) {
+        // release the value before letting ordinary acquisitions through
+        drop(self.guard.take());
+        let mut urgent = self
+            .mutex
+            .urgent
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *urgent -= 1;
+        if *urgent == 0 {
+            self.mutex.cleared.notify_all();
+        }
+    }
+}
+
+/// A manual hold on the ignition sequence.
 ///
-/// This function will panic if the current system time is before the UNIX epoch.
-pub fn handle_command(
-    cmd: &Command,
-    log_file: &Mutex<impl Write>,
-    user_log: &UserLog<impl Write>,
-    configuration: &Configuration,
-    driver_lines: &Mutex<Vec<impl GpioPin>>,
-    state: &Guard,
-) -> Result<(), Error> {
-    let time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
+/// While a hold is in place, the ignition sequence will not advance to its next step, and every
+/// driver stays at its current level.
+/// An emergency stop overrides a hold, abandoning the held sequence.
+pub struct Hold {
+    /// Whether a hold is currently in place.
+    held: Mutex<bool>,
+    /// A condition variable which is notified when the hold is released.
+    released: Condvar,
+}
 
-    #[allow(unused_must_use)]
-    {
-        user_log.info(&format!("Executing command {cmd:?}"));
+impl Hold {
+    #[must_use]
+    /// Construct a new `Hold` which is not in place.
+    pub fn new() -> Hold {
+        Hold {
+            held: Mutex::new(false),
+            released: Condvar::new(),
+        }
     }
 
-    #[allow(unused_must_use)]
-    if let Err(e) = writeln!(
-        log_file.lock().map_err(|_| Error::Poison)?,
-        "{},request,{cmd}",
-        time.as_nanos()
-    ) {
-        user_log.warn(&format!("Unable to log command {cmd} to log file: {e:?}"));
+    /// Place a hold on the ignition sequence.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if no ignition is in progress, or if a lock is poisoned.
+    pub fn hold(&self, state: &Guard) -> Result<(), Error> {
+        if !matches!(state.status()?, State::PreIgnite | State::Ignite) {
+            return Err(Error::NothingToHold);
+        }
+        *self.held.lock()? = true;
+        Ok(())
     }
 
-    match cmd {
-        Command::Actuate { driver_id, value } => {
-            if usize::from(*driver_id) > configuration.drivers.len() {
-                // we were asked to actuate a non-existent driver
-                return Err(Error::DriverOutOfBounds);
+    /// Move the controller into the `PreIgnite` state to begin an ignition, releasing any hold
+    /// left over from an aborted ignition.
+    ///
+    /// The hold is only released once the transition has succeeded, so that a rejected ignition
+    /// never releases the hold on one which is already running.
+    /// The transition is made under the same lock as placing a hold, so a hold placed on the new
+    /// ignition is never released either.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the controller is not in standby, or if a lock is
+    /// poisoned.
+    pub fn begin_ignition(&self, state: &Guard) -> Result<(), Error> {
+        let mut held = self.held.lock()?;
+        state.move_to(State::PreIgnite)?;
+        *held = false;
+        self.released.notify_all();
+        Ok(())
+    }
+
+    /// Determine whether a hold is currently in place.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the internal lock is poisoned.
+    pub fn is_held(&self) -> Result<bool, Error> {
+        Ok(*self.held.lock()?)
+    }
+
+    /// Release the hold on the ignition sequence, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the internal lock is poisoned.
+    pub fn resume(&self) -> Result<(), Error> {
+        *self.held.lock()? = false;
+        self.released.notify_all();
+        Ok(())
+    }
+
+    /// Block until no hold is in place.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err(Error::SequenceAborted)` if the ignition is ended by an
+    /// emergency stop before the hold is released, or an `Err` if a lock is poisoned.
+    fn wait(&self, state: &Guard) -> Result<(), Error> {
+        let mut held = self.held.lock()?;
+        while *held {
+            // an emergency stop may already have finished and returned to standby, so any state
+            // other than ignition means that this sequence must be abandoned
+            if !matches!(state.status()?, State::PreIgnite | State::Ignite) {
+                return Err(Error::SequenceAborted);
             }
+            held = self.released.wait_timeout(held, HOLD_POLL_PERIOD)?.0;
+        }
+        Ok(())
+    }
+}
 
-            actuate_driver(
-                driver_lines.lock().map_err(|_| Error::Poison)?.as_mut(),
-                *driver_id,
-                *value,
-            )?;
+impl Default for Hold {
+    fn default() -> Self {
+        Hold::new()
+    }
+}
+
+/// A function which acquires the GPIO line of a pin as an output, initially at a logic level.
+type RequestLine<P> = Box<dyn FnMut(u8, bool) -> Result<P, ControllerError> + Send>;
+
+/// The GPIO pin of every driver, along with the means to move a driver to a new pin.
+pub struct Rewiring<P> {
+    /// The GPIO pin ID of each driver, indexed by driver ID.
+    pins: Mutex<Vec<u8>>,
+    /// Acquires the line of a driver's new pin.
+    request: Mutex<RequestLine<P>>,
+}
+
+impl<P> Rewiring<P> {
+    #[must_use]
+    /// Construct a new `Rewiring` for the drivers in `configuration`, each of which is on the pin
+    /// given in the configuration.
+    /// New pins are acquired by calling `request` with the pin ID and initial logic level.
+    pub fn new(
+        configuration: &Configuration,
+        request: impl FnMut(u8, bool) -> Result<P, ControllerError> + Send + 'static,
+    ) -> Rewiring<P> {
+        Rewiring {
+            pins: Mutex::new(configuration.drivers.iter().map(|d| d.pin).collect()),
+            request: Mutex::new(Box::new(request)),
         }
-        Command::Ignition => ignition(configuration, driver_lines, state)?,
-        Command::EmergencyStop => {
-            emergency_stop(configuration, driver_lines, state)?;
+    }
+
+    /// Move driver `driver_id` to GPIO pin `new_pin`, keeping its logic level, and release its old
+    /// pin.
+    /// Returns the ID of the old pin.
+    ///
+    /// Acquiring the new line may be slow, so the driver lines are only locked to read the
+    /// driver's level and then to swap in the new line.
+    /// If the driver is actuated in between, the new line is set to match as it is swapped in, so
+    /// no actuation can be lost in the move.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the driver does not exist, if the new pin is
+    /// reserved or used by anything else, if the new pin could not be acquired, if the driver's
+    /// line could not be read or written, or if a lock was poisoned.
+    /// In every case, the driver keeps its old pin.
+    pub fn remap(
+        &self,
+        configuration: &Configuration,
+        driver_lines: &PriorityMutex<Vec<impl Rewire<P>>>,
+        driver_id: u8,
+        new_pin: u8,
+    ) -> Result<u8, Error> {
+        // the pins stay locked throughout, so that no two moves can claim the same pin
+        let mut pins = self.pins.lock()?;
+        let idx = usize::from(driver_id);
+        if idx >= pins.len() {
+            return Err(Error::DriverOutOfBounds);
+        }
+        if !is_legal(new_pin)
+            || pins.contains(&new_pin)
+            || configuration.non_driver_pins().contains(&new_pin)
+        {
+            return Err(Error::PinInUse(new_pin));
         }
-    };
 
-    let time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
+        let line_error = |e| Error::DriverLine(driver_id, e);
+        let level = driver_lines.lock()?[idx].read().map_err(line_error)?;
+        let new_line = (self.request.lock()?)(new_pin, level)
+            .map_err(|e| Error::PinUnavailable(new_pin, e))?;
 
-    #[allow(unused_must_use)]
-    if let Err(e) = writeln!(
-        log_file.lock().map_err(|_| Error::Poison)?,
-        "{},finish,{cmd}",
-        time.as_nanos()
-    ) {
-        user_log.warn(&format!(
-            "Unable to log completion of command {cmd} to log file: {e:?}"
-        ));
+        let mut lines = driver_lines.lock()?;
+        let current = lines[idx].read().map_err(line_error)?;
+        let old_line = lines[idx].rewire(new_line);
+        if current != level {
+            lines[idx].write(current).map_err(line_error)?;
+        }
+        drop(lines);
+        // the old line is released as soon as it is dropped
+        drop(old_line);
+
+        Ok(std::mem::replace(
+            &mut pins[usize::from(driver_id)],
+            new_pin,
+        ))
     }
-    Ok(())
 }
 
-/// Attempt to perform an emergency stop procedure.
-///
-/// # Errors
-///
-/// This function can return an `Err` in the following cases:
-///
-/// * The user attempted to perform an ignition from a state which was not standby.
-/// * A lock was poisoned.
-/// * We failed to gain control over GPIO.
-pub fn emergency_stop(
-    configuration: &Configuration,
-    driver_lines: &Mutex<Vec<impl GpioPin>>,
-    state: &Guard,
-) -> Result<(), Error> {
-    // transition to EStop, and if it's already in EStopping, don't interfere
-    state.move_to(State::EStopping)?;
+/// The record of which interlocks the operator has overridden.
+pub struct Interlocks {
+    /// Whether each interlock, indexed by ID, is overridden.
+    overridden: Mutex<Vec<bool>>,
+}
+
+impl Interlocks {
+    #[must_use]
+    /// Construct a new `Interlocks` for the interlocks in `configuration`, none of which are
+    /// overridden.
+    pub fn new(configuration: &Configuration) -> Interlocks {
+        Interlocks {
+            overridden: Mutex::new(vec![false; configuration.interlocks.len()]),
+        }
+    }
 
-    perform_actions(driver_lines, &configuration.estop_sequence)?;
+    /// Override the interlock with ID `interlock_id` if `overridden` is `true`, or restore it
+    /// otherwise.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the interlock does not exist or if the internal lock
+    /// is poisoned.
+    pub fn set_overridden(&self, interlock_id: u8, overridden: bool) -> Result<(), Error> {
+        *self
+            .overridden
+            .lock()?
+            .get_mut(usize::from(interlock_id))
+            .ok_or(Error::NoSuchInterlock(interlock_id))? = overridden;
+        Ok(())
+    }
 
-    // done doing the estop sequence, move back to standby
-    state.move_to(State::Standby)?;
+    /// Restore every overridden interlock, returning the IDs of those which were overridden.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    pub fn restore_all(&self) -> Result<Vec<u8>, Error> {
+        let restored = self.overridden()?;
+        self.overridden.lock()?.fill(false);
+        Ok(restored)
+    }
 
-    Ok(())
+    /// Get the IDs of every overridden interlock.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    pub fn overridden(&self) -> Result<Vec<u8>, Error> {
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(self
+            .overridden
+            .lock()?
+            .iter()
+            .enumerate()
+            .filter(|(_, &overridden)| overridden)
+            .map(|(interlock_id, _)| interlock_id as u8)
+            .collect())
+    }
+
+    /// Check that every driver in `energized` may be energized, given that `levels` are the levels
+    /// of every driver once they are.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err(Error::Interlocked)` if a driver in `energized` shares an
+    /// interlock which is not overridden with another energized driver, or an `Err` if the internal
+    /// lock is poisoned.
+    pub fn check(
+        &self,
+        configuration: &Configuration,
+        levels: &[bool],
+        energized: &[u8],
+    ) -> Result<(), Error> {
+        let overridden = self.overridden.lock()?;
+        for (interlock, _) in configuration
+            .interlocks
+            .iter()
+            .zip(overridden.iter())
+            .filter(|(_, &overridden)| !overridden)
+        {
+            for driver_id in energized
+                .iter()
+                .filter(|driver_id| interlock.drivers.contains(driver_id))
+            {
+                if let Some(&blocking_id) = interlock
+                    .drivers
+                    .iter()
+                    .find(|&&other| other != *driver_id && levels[usize::from(other)])
+                {
+                    return Err(Error::Interlocked(*driver_id, blocking_id));
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
-/// Attempt to perform an ignition procedure.
+/// The event log, to which the operator's annotations are written.
 ///
-/// # Errors
+/// # Types
 ///
-/// This function can return an `Err` in the following cases:
+/// * `W`: the type of the event log.
+pub struct Annotations<W: Write> {
+    /// The event log, and whether its header row has been written.
+    log: Mutex<(W, bool)>,
+}
+
+impl<W: Write> Annotations<W> {
+    #[must_use]
+    /// Construct a new `Annotations` which appends every annotation to `log`.
+    pub fn new(log: W) -> Annotations<W> {
+        Annotations {
+            log: Mutex::new((log, false)),
+        }
+    }
+
+    /// Write an annotation `text`, made by `client` at `time`, as a row of the event log.
+    ///
+    /// The first row of the log is a header, `time,operator,peer,text`.
+    /// Each later row holds the time of the annotation in nanoseconds since the UNIX epoch, the
+    /// operator and peer address of the client which made it, and its text, quoted so that it may
+    /// contain commas, quotes, or newlines.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned or if writing to the
+    /// log fails.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `time` is before the UNIX epoch.
+    pub fn record(&self, time: SystemTime, client: &Client, text: &str) -> Result<(), Error> {
+        let mut guard = self.log.lock()?;
+        let (log, header_written) = &mut *guard;
+        let mut write = || -> std::io::Result<()> {
+            if !*header_written {
+                writeln!(log, "time,operator,peer,text")?;
+                *header_written = true;
+            }
+            writeln!(
+                log,
+                "{},{client},\"{}\"",
+                time.duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos(),
+                text.replace('"', "\"\"")
+            )?;
+            log.flush()
+        };
+        write().map_err(Error::Annotation)
+    }
+}
+
+/// The record of every running sequence, and the drivers which each one controls.
 ///
-/// * The user attempted to perform an ignition from a state which was not standby.
-/// * A lock was poisoned.
-/// * We failed to gain control over GPIO.
-fn ignition(
-    configuration: &Configuration,
-    driver_lines: &Mutex<Vec<impl GpioPin>>,
-    state: &Guard,
-) -> Result<(), Error> {
-    state.move_to(State::PreIgnite)?;
-    sleep(Duration::from_millis(u64::from(
-        configuration.pre_ignite_time,
-    )));
+/// A sequence is any command which controls drivers over a period of time: an ignition or a test
+/// fire.
+/// Emergency stops and abort actions are never sequences, so they can always take over a driver.
+pub struct Sequences {
+    /// The policy deciding which sequences may run at the same time.
+    policy: SequencePolicy,
+    /// The drivers controlled by each running sequence, keyed by an identifier unique to the
+    /// sequence.
+    active: Mutex<BTreeMap<u64, Vec<u8>>>,
+    /// The identifier which will be given to the next sequence.
+    next_id: AtomicU64,
+}
 
-    state.move_to(State::Ignite)?;
-    perform_actions(driver_lines, &configuration.ignition_sequence)?;
+/// A claim on the drivers controlled by a running sequence.
+/// The drivers are released when the claim is dropped.
+pub struct SequenceClaim<'a> {
+    /// The record which the claim was made in.
+    sequences: &'a Sequences,
+    /// The identifier of the sequence.
+    id: u64,
+}
 
-    state.move_to(State::PostIgnite)?;
-    sleep(Duration::from_millis(u64::from(
-        configuration.post_ignite_time,
-    )));
+impl Sequences {
+    #[must_use]
+    /// Construct a new `Sequences` with no running sequences, which follows the sequence policy in
+    /// `configuration`.
+    pub fn new(configuration: &Configuration) -> Sequences {
+        Sequences {
+            policy: configuration.sequence_policy,
+            active: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
 
-    // done doing the ignition sequence, move back to standby
-    state.move_to(State::Standby)?;
+    /// Begin a sequence which controls `drivers`, claiming them until the returned claim is
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err(Error::SequenceRunning)` if only one sequence may run at a
+    /// time and another is running, an `Err(Error::DriverClaimed)` if one of `drivers` is
+    /// controlled by another running sequence, or an `Err` if the internal lock is poisoned.
+    pub fn begin(&self, drivers: Vec<u8>) -> Result<SequenceClaim, Error> {
+        let mut active = self.active.lock()?;
+        match self.policy {
+            SequencePolicy::Exclusive => {
+                if !active.is_empty() {
+                    return Err(Error::SequenceRunning);
+                }
+            }
+            SequencePolicy::Disjoint => {
+                if let Some(&driver_id) = drivers
+                    .iter()
+                    .find(|driver_id| active.values().any(|claimed| claimed.contains(driver_id)))
+                {
+                    return Err(Error::DriverClaimed(driver_id));
+                }
+            }
+        }
 
-    Ok(())
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        active.insert(id, drivers);
+        Ok(SequenceClaim {
+            sequences: self,
+            id,
+        })
+    }
+
+    /// Get the drivers controlled by each running sequence, in the order the sequences began.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the internal lock is poisoned.
+    pub fn running(&self) -> Result<Vec<Vec<u8>>, Error> {
+        Ok(self.active.lock()?.values().cloned().collect())
+    }
 }
 
-/// Actuate a given driver to a given value using GPIO cdev to interface with OS.
-///
-/// # Inputs
-///
-/// * `driver_lines`: A table of GPIO lines for all drivers.
-/// * `driver_id`: The ID of the driver to be actuated.
-///     An ID is an index into `configuration.drivers` for the associated driver.
-///     It is also the same index into `driver_lines`.
-/// * `value`: The logic level that the driver should be actuated to.
-///     `value` should be `true` to get a high value on the GPIO pin, and `false` for a low value.
-///
-/// # Errors
-///
-/// This function may return an error if we are unable to gain control over the GPIO pin associated
-/// with the driver.
-fn actuate_driver(
-    driver_lines: &mut [impl GpioPin],
-    driver_id: u8,
-    value: bool,
-) -> Result<(), Error> {
-    driver_lines[driver_id as usize]
-        .write(value)
-        .map_err(|_| Error::Poison)
+impl Drop for SequenceClaim<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.sequences.active.lock() {
+            active.remove(&self.id);
+        }
+    }
 }
 
-/// Perform a sequence of actions, such as for emergency stopping or for
-/// ignition.
-///
-/// # Errors
+/// The length of time for which a command's ID is remembered, so that retransmissions of the command
+/// can be ignored.
+pub const DEDUP_WINDOW: Duration = Duration::from_secs(30);
+
+/// The maximum number of command IDs which are remembered at once.
+pub const DEDUP_CAPACITY: usize = 256;
+
+/// A record of the IDs of recently executed commands and whether they succeeded.
 ///
-/// This function will return an error if we are unable to write to GPIO.
-fn perform_actions(
-    driver_lines: &Mutex<Vec<impl GpioPin>>,
-    actions: &[Action],
-) -> Result<(), Error> {
-    for action in actions {
-        match action {
-            Action::Actuate { driver_id, value } => {
-                driver_lines.lock().map_err(|_| Error::Poison)?[*driver_id as usize]
-                    .write(*value)
-                    .map_err(|_| Error::Poison)?;
-            }
-            Action::Sleep { duration } => sleep(*duration),
-        };
+/// This allows a dashboard which is unsure whether a command arrived to safely resend it: if the
+/// command was already executed, it is acknowledged again instead of being executed twice.
+/// Safing commands (see `Command::is_safing`) are never checked against this record.
+/// A command which is still executing is remembered as such, so that a duplicate of it is not
+/// executed alongside it.
+/// Entries are forgotten once they are older than the window or once the capacity is exceeded, so
+/// memory use is bounded over a long session.
+pub struct RecentCommands {
+    /// How long each entry is remembered.
+    window: Duration,
+    /// The maximum number of entries.
+    capacity: usize,
+    /// The ID, execution time, and outcome of each remembered command, from oldest to newest.
+    entries: VecDeque<(u64, Instant, Outcome)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The outcome of a recently received command.
+pub enum Outcome {
+    /// The command is still executing.
+    Executing,
+    /// The command has finished.
+    /// The value inside this variant is whether it succeeded.
+    Finished(bool),
+}
+
+impl RecentCommands {
+    #[must_use]
+    /// Construct a new, empty `RecentCommands` which remembers up to `capacity` commands for
+    /// `window` each.
+    pub fn new(window: Duration, capacity: usize) -> RecentCommands {
+        RecentCommands {
+            window,
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Determine whether a command with ID `command_id` was recently executed.
+    /// Returns `Some` containing the outcome of the earlier command if it was, and `None`
+    /// otherwise.
+    pub fn lookup(&mut self, command_id: u64, now: Instant) -> Option<Outcome> {
+        self.expire(now);
+        self.entries
+            .iter()
+            .find(|&&(id, _, _)| id == command_id)
+            .map(|&(_, _, outcome)| outcome)
     }
 
-    Ok(())
-}
+    /// Remember that a command with ID `command_id` began executing at time `now`, and has not
+    /// finished yet.
+    pub fn begin(&mut self, command_id: u64, now: Instant) {
+        self.push(command_id, now, Outcome::Executing);
+    }
+
+    /// Remember that a command with ID `command_id` was executed at time `now`.
+    /// If the command was begun earlier, its entry is replaced.
+    pub fn record(&mut self, command_id: u64, success: bool, now: Instant) {
+        self.entries
+            .retain(|&(id, _, outcome)| id != command_id || outcome != Outcome::Executing);
+        self.push(command_id, now, Outcome::Finished(success));
+    }
+
+    /// Add an entry for a command, making room for it if needed.
+    fn push(&mut self, command_id: u64, now: Instant, outcome: Outcome) {
+        self.expire(now);
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((command_id, now, outcome));
+    }
+
+    /// Forget every command which was executed more than one window before `now`.
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(_, time, _)) = self.entries.front() {
+            if now.duration_since(time) <= self.window {
+                break;
+            }
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// The length of the window over which a client's command rate is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// A limit on the rate at which a single client may send commands, protecting the controller from
+/// a dashboard which floods it with commands.
+///
+/// Safing commands (see `Command::is_safing`) and holds make the engine safer, and keep-alives
+/// must arrive on time for a dead man's switch to stay powered, so none of them is ever limited.
+pub struct RateLimit {
+    /// The greatest number of commands accepted in any one window, or `None` if there is no limit.
+    limit: Option<u32>,
+    /// The time at which each command accepted in the last window arrived, from oldest to newest.
+    accepted: VecDeque<Instant>,
+}
+
+impl RateLimit {
+    #[must_use]
+    /// Construct a new `RateLimit` for a client which has not yet sent any commands, using the
+    /// limit in `configuration`.
+    pub fn new(configuration: &Configuration) -> RateLimit {
+        RateLimit {
+            limit: configuration.command_rate_limit,
+            accepted: VecDeque::new(),
+        }
+    }
+
+    /// Determine whether `cmd`, arriving at time `now`, is within the rate limit.
+    /// Returns `true` if the command should be executed, in which case it counts against the limit.
+    pub fn admit(&mut self, cmd: &Command, now: Instant) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+        if cmd.is_safing() || matches!(cmd, Command::Hold | Command::KeepAlive { .. }) {
+            return true;
+        }
+
+        while let Some(&time) = self.accepted.front() {
+            if now.duration_since(time) < RATE_WINDOW {
+                break;
+            }
+            self.accepted.pop_front();
+        }
+        if self.accepted.len() >= usize::try_from(limit).unwrap_or(usize::MAX) {
+            return false;
+        }
+        self.accepted.push_back(now);
+        true
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The identity of a connected dashboard client, recorded alongside every command it sends.
+pub struct Client {
+    /// The name of the operator, if the client has sent an `Auth` command.
+    operator: Option<String>,
+    /// The network address of the client.
+    peer: SocketAddr,
+}
+
+impl Client {
+    #[must_use]
+    /// Construct a new, anonymous client connected from `peer`.
+    pub fn new(peer: SocketAddr) -> Client {
+        Client {
+            operator: None,
+            peer,
+        }
+    }
+
+    /// Associate an operator name with this client.
+    /// All commands executed after this call will be attributed to `operator`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if `operator` is not a legal operator name.
+    /// Operator names must be nonempty and consist only of ASCII alphanumerics, `-`, `_`, and `.`,
+    /// so that they can be safely written into the command log.
+    pub fn authenticate(&mut self, operator: &str) -> Result<(), Error> {
+        if operator.is_empty()
+            || !operator
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            return Err(Error::BadOperator);
+        }
+
+        self.operator = Some(operator.to_string());
+        Ok(())
+    }
+}
+
+/// Check whether a client which declared the dashboard protocol version `declared` may keep
+/// sending commands.
+/// `declared` is `None` if the client has not declared a version.
+///
+/// # Errors
+///
+/// This function will return an `Err(Error::UnsupportedProtocol)` if the declared version is older
+/// than the oldest version allowed by `configuration` or newer than `PROTOCOL_VERSION`, or an
+/// `Err(Error::UndeclaredProtocol)` if no version was declared but `configuration` requires one.
+pub fn check_protocol(configuration: &Configuration, declared: Option<u32>) -> Result<(), Error> {
+    let min = configuration.min_protocol_version;
+    match declared {
+        Some(declared) if declared < min.unwrap_or(0) || PROTOCOL_VERSION < declared => {
+            Err(Error::UnsupportedProtocol {
+                declared,
+                min: min.unwrap_or(0),
+            })
+        }
+        None if min.is_some() => Err(Error::UndeclaredProtocol),
+        _ => Ok(()),
+    }
+}
+
+impl Display for Client {
+    /// Write out the operator and peer address of this client as two CSV columns.
+    /// Clients which have not authenticated are written as `<anonymous>`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.operator {
+            Some(operator) => write!(f, "{operator},{}", self.peer),
+            None => write!(f, "<anonymous>,{}", self.peer),
+        }
+    }
+}
+
+impl Display for Error {
+    #[allow(clippy::too_many_lines)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Poison => write!(f, "a lock was poisoned"),
+            Error::DriverOutOfBounds => write!(f, "no such driver exists"),
+            Error::State(e) => write!(f, "illegal state transition: {e:?}"),
+            Error::InjectionForbidden => write!(
+                f,
+                "reading injection is not allowed (start the controller with --allow-injection)"
+            ),
+            Error::SensorOutOfBounds => write!(f, "no such sensor exists"),
+            Error::UnknownSensor(label) => write!(f, "no sensor is labeled {label:?}"),
+            Error::AmbiguousSensor(label) => {
+                write!(f, "more than one sensor is labeled {label:?}")
+            }
+            Error::HazardUnconfirmed => {
+                write!(f, "powering a hazardous driver requires confirmation")
+            }
+            Error::HazardOutsideIgnition(state) => write!(
+                f,
+                "hazardous drivers may only be powered during an ignition (currently {state:?})"
+            ),
+            Error::ActuationTooSoon(remaining) => write!(
+                f,
+                "driver was actuated too recently (try again in {remaining:?})"
+            ),
+            Error::NothingToHold => write!(f, "no ignition is in progress to hold"),
+            Error::SequenceAborted => write!(f, "sequence aborted by an emergency stop"),
+            Error::BadOperator => write!(f, "illegal operator name"),
+            Error::UnsupportedProtocol { declared, min } => write!(
+                f,
+                "dashboard speaks protocol version {declared}, but the controller only accepts versions {min} through {PROTOCOL_VERSION}"
+            ),
+            Error::UndeclaredProtocol => write!(
+                f,
+                "dashboard must declare its protocol version before sending commands"
+            ),
+            Error::NoSuchAbortAction(name) => write!(f, "no abort action is named {name:?}"),
+            Error::FlushTimeout => {
+                write!(f, "sensor threads did not write out their readings in time")
+            }
+            Error::AlreadyPaused => write!(f, "sensor logging and streaming are already paused"),
+            Error::NotPaused => write!(f, "sensor logging and streaming are not paused"),
+            Error::PauseOutsideStandby(state) => write!(
+                f,
+                "sensor logging and streaming may only be paused in standby, but the controller is in {state:?}"
+            ),
+            Error::Sync(e) => write!(f, "unable to sync log file: {e}"),
+            Error::SnapshotTimeout => write!(f, "sensor threads did not contribute to snapshot in time"),
+            Error::SnapshotNotLogged(e) => write!(f, "unable to write snapshot to log: {e}"),
+            Error::NotReady => write!(
+                f,
+                "the controller is not ready (not every sensor has been read yet)"
+            ),
+            Error::NotHeld(driver_id) => write!(
+                f,
+                "driver {driver_id} is not energized under a dead man's switch"
+            ),
+            Error::TestFireTooLong(max_ms) => write!(
+                f,
+                "test fire of {max_ms} ms exceeds the ceiling of {TEST_FIRE_CEILING_MS} ms"
+            ),
+            Error::TestFireOutsideStandby(state) => write!(
+                f,
+                "drivers may only be test-fired in standby, but the controller is in {state:?}"
+            ),
+            Error::LeakCheckTooLong(hold_seconds) => write!(
+                f,
+                "leak check of {hold_seconds} s exceeds the ceiling of {LEAK_CHECK_CEILING_S} s"
+            ),
+            Error::LeakCheckOutsideStandby(state) => write!(
+                f,
+                "leak checks may only be run in standby, but the controller is in {state:?}"
+            ),
+            Error::NoNewReading => write!(f, "the sensor was not read during the leak check"),
+            Error::CalibrationOutsideStandby(state) => write!(
+                f,
+                "sensors may only be calibrated in standby (currently {state:?})"
+            ),
+            Error::CalibrateThermistor => {
+                write!(f, "thermistors cannot be given a linear calibration")
+            }
+            Error::NoReading => write!(f, "the sensor has not been read yet"),
+            Error::DegenerateCalibration(reading) => write!(
+                f,
+                "both reference points have raw reading {reading} (apply a different reference)"
+            ),
+            Error::CalibrationNotSaved(e) => write!(f, "calibration was not applied: {e}"),
+            Error::ThresholdOutsideStandby(state) => write!(
+                f,
+                "sensor ranges may only be changed in standby (currently {state:?})"
+            ),
+            Error::RemapOutsideStandby(state) => write!(
+                f,
+                "drivers may only be moved to new pins in standby (currently {state:?})"
+            ),
+            Error::PinInUse(pin) => write!(f, "GPIO pin {pin} is reserved or already in use"),
+            Error::PinUnavailable(pin, e) => {
+                write!(f, "unable to acquire GPIO pin {pin}: {e}")
+            }
+            Error::DriverLine(driver_id, e) => {
+                write!(f, "unable to access the GPIO line of driver {driver_id}: {e}")
+            }
+            Error::BadThreshold(min, max) => write!(
+                f,
+                "range minimum {min} must be less than its maximum {max}"
+            ),
+            Error::CoolingDown(remaining) => write!(
+                f,
+                "an ignition may not begin for another {remaining:?} after the last emergency stop"
+            ),
+            Error::StillOutOfRange(label, value) => write!(
+                f,
+                "an ignition may not begin while sensor {label} is still out of range (latest value {value})"
+            ),
+            Error::SequenceRunning => write!(
+                f,
+                "another sequence is running, and only one sequence may run at a time"
+            ),
+            Error::DriverClaimed(driver_id) => write!(
+                f,
+                "driver {driver_id} is already controlled by another running sequence"
+            ),
+            Error::RepeatedDriver(driver_id) => write!(
+                f,
+                "driver {driver_id} was actuated more than once in the same group"
+            ),
+            Error::NoSuchInterlock(interlock_id) => {
+                write!(f, "interlock {interlock_id} does not exist")
+            }
+            Error::Interlocked(driver_id, blocking_id) => write!(
+                f,
+                "driver {driver_id} may not be energized while driver {blocking_id} is, as they share an interlock"
+            ),
+            Error::OverrideUnconfirmed => {
+                write!(f, "interlock overrides must be explicitly confirmed")
+            }
+            Error::OverrideOutsideStandby(state) => write!(
+                f,
+                "interlocks may only be overridden in standby, but the controller is in {state:?}"
+            ),
+            Error::NoSuchGroup(group_id) => write!(f, "no sensor group has ID {group_id}"),
+            Error::RestartUnconfirmed => {
+                write!(f, "sensor group restarts must be explicitly confirmed")
+            }
+            Error::RestartOutsideStandby(state) => write!(
+                f,
+                "sensor groups may only be restarted in standby, but the controller is in {state:?}"
+            ),
+            Error::GroupNotRunning(group_id) => {
+                write!(f, "sensor group {group_id} has no running thread to restart")
+            }
+            Error::EmptyAnnotation => write!(f, "an annotation must have text"),
+            Error::PreconditionUnmet(driver_id, Some(value)) => write!(
+                f,
+                "driver {driver_id} may not be energized while its precondition sensor reads {value}"
+            ),
+            Error::PreconditionUnmet(driver_id, None) => write!(
+                f,
+                "driver {driver_id} may not be energized until its precondition sensor has been read"
+            ),
+            Error::PreconditionStale(driver_id, age) => write!(
+                f,
+                "driver {driver_id} may not be energized while its precondition sensor's latest reading is {age:?} old"
+            ),
+            Error::Annotation(e) => write!(f, "unable to write annotation to the event log: {e}"),
+        }
+    }
+}
+
+impl<T> From<PoisonError<T>> for Error {
+    fn from(_: PoisonError<T>) -> Self {
+        Error::Poison
+    }
+}
+
+impl From<state::Error> for Error {
+    fn from(value: state::Error) -> Self {
+        Error::State(value)
+    }
+}
+
+/// The dead man's switches of every driver which has one.
+///
+/// A driver with a dead man's switch stays energized after an actuation command only for as long
+/// as keep-alive commands for it keep arriving within its interval.
+pub struct Deadman {
+    /// The longest allowed time between keep-alives for each driver, if it has a dead man's switch.
+    intervals: Vec<Option<Duration>>,
+    /// The time by which the next keep-alive must arrive for each driver which is energized under
+    /// its dead man's switch.
+    deadlines: Mutex<Vec<Option<Instant>>>,
+}
+
+impl Deadman {
+    #[must_use]
+    /// Construct a new `Deadman` for the drivers in `configuration`, none of which are energized.
+    pub fn new(configuration: &Configuration) -> Deadman {
+        Deadman {
+            intervals: configuration
+                .drivers
+                .iter()
+                .map(|driver| {
+                    driver
+                        .deadman_interval_ms
+                        .map(|ms| Duration::from_millis(u64::from(ms)))
+                })
+                .collect(),
+            deadlines: Mutex::new(vec![None; configuration.drivers.len()]),
+        }
+    }
+
+    /// Record that the driver with ID `driver_id` was actuated to `value` by a command.
+    /// If the driver has a dead man's switch and was energized, it must now be kept alive.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    pub fn actuate(&self, driver_id: u8, value: bool) -> Result<(), Error> {
+        let idx = usize::from(driver_id);
+        let Some(&interval) = self.intervals.get(idx) else {
+            return Err(Error::DriverOutOfBounds);
+        };
+        self.deadlines.lock()?[idx] = interval
+            .filter(|_| value)
+            .map(|interval| Instant::now() + interval);
+        Ok(())
+    }
+
+    /// Extend the deadline of the driver with ID `driver_id` by another interval.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the driver is not energized under its dead man's
+    /// switch, or if the internal lock is poisoned.
+    pub fn keep_alive(&self, driver_id: u8) -> Result<(), Error> {
+        let idx = usize::from(driver_id);
+        let mut deadlines = self.deadlines.lock()?;
+        match (deadlines.get_mut(idx), self.intervals.get(idx)) {
+            (Some(deadline @ Some(_)), Some(&Some(interval))) => {
+                *deadline = Some(Instant::now() + interval);
+                Ok(())
+            }
+            (Some(_), _) => Err(Error::NotHeld(driver_id)),
+            (None, _) => Err(Error::DriverOutOfBounds),
+        }
+    }
+
+    /// Get the IDs of every driver whose deadline has passed as of `now`.
+    /// Those drivers are no longer considered energized under their dead man's switch.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    fn expire(&self, now: Instant) -> Result<Vec<u8>, Error> {
+        let mut deadlines = self.deadlines.lock()?;
+        #[allow(clippy::cast_possible_truncation)]
+        let expired = deadlines
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, deadline)| deadline.map_or(false, |deadline| deadline <= now))
+            .map(|(driver_id, deadline)| {
+                *deadline = None;
+                driver_id as u8
+            })
+            .collect();
+        Ok(expired)
+    }
+}
+
+/// Watch the dead man's switch of every driver, and drive each driver safe (unpowered) as soon as
+/// its keep-alives stop arriving.
+/// Will loop until the controller quits.
+///
+/// # Errors
+///
+/// This function will return an error if a lock is poisoned, if we are unable to access GPIO, or if
+/// we are unable to write to the user log.
+pub fn deadman_watch(
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    deadman: &Deadman,
+    user_log: &UserLog<impl Write>,
+    state: &Guard,
+) -> Result<(), ControllerError> {
+    while state.status()? != State::Quit {
+        sleep(DEADMAN_POLL_PERIOD);
+
+        for driver_id in deadman
+            .expire(Instant::now())
+            .map_err(|_| ControllerError::Poison)?
+        {
+            let released = {
+                let mut driver_lines = driver_lines.lock()?;
+                let line = &mut driver_lines[usize::from(driver_id)];
+                // the driver may already have been deactuated, such as by an emergency stop
+                let powered = line.read()?;
+                if powered {
+                    line.write(false)?;
+                }
+                powered
+            };
+            if released {
+                user_log.critical(&format!(
+                    "Dead man's switch released: driver {driver_id} was driven safe after keep-alives stopped"
+                ))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Periodically read the sense input of every driver commanded to its safe (unpowered) level while
+/// an ignition is in progress, and emergency stop if any of them is actually powered, such as
+/// because of a welded relay.
+/// `senses` holds the sense input of each driver, if it has one, in the same order as the drivers;
+/// a driver without one is not checked, since reading back its own output line would only show
+/// the level it was commanded to.
+/// Returns immediately if no readback interval is configured; otherwise, will loop until the
+/// controller quits.
+///
+/// # Errors
+///
+/// This function will return an error if a lock is poisoned or if we are unable to write to the
+/// user log.
+pub fn readback_watch(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl Readback>>,
+    senses: &mut [Option<impl GpioPin>],
+    user_log: &UserLog<impl Write>,
+    state: &Guard,
+) -> Result<(), ControllerError> {
+    let Some(interval_ms) = configuration.readback_interval_ms else {
+        return Ok(());
+    };
+    let interval = Duration::from_millis(u64::from(interval_ms));
+
+    while state.status()? != State::Quit {
+        sleep(interval);
+        if !matches!(
+            state.status()?,
+            State::PreIgnite | State::Ignite | State::PostIgnite
+        ) {
+            continue;
+        }
+
+        let commanded: Vec<bool> = driver_lines
+            .lock()?
+            .iter()
+            .map(Readback::commanded)
+            .collect();
+        let mut stuck = Vec::new();
+        for (driver_id, (sense, commanded)) in senses.iter_mut().zip(commanded).enumerate() {
+            let Some(sense) = sense else {
+                continue;
+            };
+            if commanded {
+                continue;
+            }
+            match sense.read() {
+                Ok(true) => stuck.push(driver_id),
+                Ok(false) => (),
+                Err(e) => {
+                    user_log.warn(&format!("Unable to read back driver {driver_id}: {e:?}"))?;
+                }
+            }
+        }
+
+        if !stuck.is_empty() {
+            user_log.critical(&format!(
+                "Drivers {stuck:?} read back as powered while commanded safe. Attempting emergency stop"
+            ))?;
+            // an emergency stop may already be in progress, which is not our problem
+            if let Err(e) = emergency_stop(configuration, driver_lines, state, user_log) {
+                user_log.warn(&format!(
+                    "Unable to emergency stop after failed driver readback: {e}"
+                ))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
+/// Execute a command and log the process of execution.
+///
+/// # Inputs
+///
+/// * `cmd`: The command to be executed.
+/// * `client`: The client which sent the command.
+///     The operator and peer address of the client are recorded next to the command in `log_file`.
+/// * `log_file`: Location where log information will be written.
+/// * `configuration`: Configuration object for program execution.
+/// * `driver_lines`: Output lines for the drivers.
+///     Each index in `driver_lines` corresponds one-to-one with the drivers in `configuration`.  
+/// * `state`: The controller for the current system state.
+/// * `injections`: The store of synthetic sensor readings requested by the dashboard.
+/// * `throttle`: The record of recent driver actuations, used to reject actuations which arrive
+///     too soon after the previous one.
+/// * `deadman`: The dead man's switches of the drivers, which are armed by actuations and kept
+///     alive by keep-alive commands.
+/// * `hold`: The manual hold on the ignition sequence.
+/// * `dashboard`: A channel by which sensor history and new calibrations are sent to the
+///     dashboard.
+/// * `flusher`: Requests to write out and sync every log.
+/// * `readiness`: The readiness of the controller.
+///     Commands which actuate hardware are rejected until the controller is ready, if the ready
+///     gate is enabled.
+/// * `history`: The most recent readings from every sensor, which are sent to the dashboard on
+///     request and used as calibration references.
+/// * `tunings`: The current tuning of every sensor, whose calibration is replaced when a sensor is
+///     calibrated.
+/// * `calibrator`: The first reference point of every calibration in progress.
+/// * `sequences`: The record of running sequences, used to reject a test fire or ignition
+///     which conflicts with one already running.
+/// * `snapshots`: Requests for a snapshot of every sensor, through which a snapshot is taken.
+/// * `rewiring`: The GPIO pin of every driver, through which a driver is moved to a new pin.
+/// * `interlocks`: The record of overridden interlocks, used to reject commands which would
+///     energize two drivers in the same interlock.
+/// * `pause`: The pause on sensor logging and streaming.
+/// * `restarts`: Requests to restart a sensor group, through which a group is restarted.
+/// * `annotations`: The event log, to which annotations are written.
+///
+/// # Errors
+///
+/// This function will return an error if a lock is poisoned or if we are unable to actuate GPIO.
+///
+/// # Panics
+///
+/// This function will panic if the current system time is before the UNIX epoch.
+pub fn handle_command<P>(
+    cmd: &Command,
+    client: &Client,
+    log_file: &Mutex<impl Write>,
+    user_log: &UserLog<impl Write>,
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl Rewire<P>>>,
+    state: &Guard,
+    injections: &Injections,
+    throttle: &Throttle,
+    deadman: &Deadman,
+    hold: &Hold,
+    dashboard: &DashChannel<impl Write, impl Write>,
+    flusher: &Flusher,
+    readiness: &Readiness,
+    history: &History,
+    tunings: &Tunings,
+    calibrator: &Calibrator,
+    sequences: &Sequences,
+    snapshots: &Snapshots<impl Write>,
+    rewiring: &Rewiring<P>,
+    interlocks: &Interlocks,
+    pause: &Pause,
+    restarts: &Restarts,
+    annotations: &Annotations<impl Write>,
+) -> Result<(), Error> {
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    // keep-alives arrive many times a second, and would drown out every other command
+    let logged = !matches!(cmd, Command::KeepAlive { .. });
+
+    #[allow(unused_must_use)]
+    if logged {
+        user_log.info(&format!("Executing command {cmd:?} from {client}"));
+        if let Err(e) = writeln!(
+            log_file.lock().map_err(|_| Error::Poison)?,
+            "{},request,{client},{cmd}",
+            time.as_nanos()
+        ) {
+            user_log.warn(&format!("Unable to log command {cmd} to log file: {e:?}"));
+        }
+    }
+
+    if matches!(
+        cmd,
+        Command::Actuate { .. }
+            | Command::ActuateMany { .. }
+            | Command::TestFire { .. }
+            | Command::Ignition
+    ) {
+        readiness.check()?;
+    }
+
+    if let (Command::Ignition, Some(cooldown_ms)) = (cmd, configuration.estop_cooldown_ms) {
+        let remaining = state.cooldown_remaining(Duration::from_millis(u64::from(cooldown_ms)))?;
+        if !remaining.is_zero() {
+            return Err(Error::CoolingDown(remaining));
+        }
+        if state.estop_count() > 0 {
+            check_back_in_range(configuration, history, tunings)?;
+        }
+    }
+
+    // a sequence keeps its drivers claimed until it finishes
+    let _claim = match cmd {
+        Command::TestFire { driver_id, .. } => Some(sequences.begin(vec![*driver_id])?),
+        Command::Ignition => Some(
+            sequences.begin(
+                configuration
+                    .ignition_sequence
+                    .iter()
+                    .filter_map(|action| match action {
+                        Action::Actuate { driver_id, .. } => Some(*driver_id),
+                        Action::Sleep { .. } => None,
+                    })
+                    .collect(),
+            )?,
+        ),
+        _ => None,
+    };
+
+    match cmd {
+        Command::Actuate {
+            driver_id,
+            value,
+            confirm,
+        } => {
+            let Some(driver) = configuration.drivers.get(usize::from(*driver_id)) else {
+                // we were asked to actuate a non-existent driver
+                return Err(Error::DriverOutOfBounds);
+            };
+            if driver.hazardous {
+                check_hazardous_actuation(*value, *confirm, state.status()?)?;
+            }
+            if *value {
+                check_precondition(configuration, history, *driver_id)?;
+            }
+            // the interlocks are checked under the same lock as the write, so no other actuation
+            // can come between them
+            let mut lines = driver_lines.lock().map_err(|_| Error::Poison)?;
+            if *value {
+                interlocks.check(configuration, &read_levels(&mut lines)?, &[*driver_id])?;
+            }
+            throttle.actuate(*driver_id)?;
+
+            actuate_driver(lines.as_mut(), *driver_id, *value)?;
+            drop(lines);
+            deadman.actuate(*driver_id, *value)?;
+        }
+        Command::ActuateMany { actions, confirm } => {
+            // every actuation is checked before any is performed, so the group applies all or none
+            let mut driver_ids = Vec::with_capacity(actions.len());
+            for &Actuation { driver_id, value } in actions {
+                let Some(driver) = configuration.drivers.get(usize::from(driver_id)) else {
+                    return Err(Error::DriverOutOfBounds);
+                };
+                if driver_ids.contains(&driver_id) {
+                    return Err(Error::RepeatedDriver(driver_id));
+                }
+                if driver.hazardous {
+                    check_hazardous_actuation(value, *confirm, state.status()?)?;
+                }
+                if value {
+                    check_precondition(configuration, history, driver_id)?;
+                }
+                driver_ids.push(driver_id);
+            }
+            let mut lines = driver_lines.lock().map_err(|_| Error::Poison)?;
+            let mut levels = read_levels(&mut lines)?;
+            let mut energized = Vec::new();
+            for &Actuation { driver_id, value } in actions {
+                levels[usize::from(driver_id)] = value;
+                if value {
+                    energized.push(driver_id);
+                }
+            }
+            interlocks.check(configuration, &levels, &energized)?;
+            throttle.actuate_all(&driver_ids)?;
+
+            for &Actuation { driver_id, value } in actions {
+                actuate_driver(lines.as_mut(), driver_id, value)?;
+            }
+            drop(lines);
+
+            for &Actuation { driver_id, value } in actions {
+                deadman.actuate(driver_id, value)?;
+            }
+        }
+        Command::TestFire { driver_id, max_ms } => {
+            let Some(driver) = configuration.drivers.get(usize::from(*driver_id)) else {
+                return Err(Error::DriverOutOfBounds);
+            };
+            if *max_ms > TEST_FIRE_CEILING_MS {
+                return Err(Error::TestFireTooLong(*max_ms));
+            }
+            let status = state.status()?;
+            if status != State::Standby {
+                return Err(Error::TestFireOutsideStandby(status));
+            }
+            if driver.hazardous {
+                // hazardous drivers may only ever be powered during an ignition
+                return Err(Error::HazardOutsideIgnition(status));
+            }
+            check_precondition(configuration, history, *driver_id)?;
+
+            #[allow(unused_must_use)]
+            {
+                user_log.info(&format!(
+                    "Test-firing driver {} for {max_ms} ms",
+                    driver.label
+                ));
+            }
+            let energized = test_fire(
+                configuration,
+                driver_lines,
+                state,
+                interlocks,
+                throttle,
+                *driver_id,
+                Duration::from_millis(u64::from(*max_ms)),
+            )?;
+            #[allow(unused_must_use)]
+            {
+                user_log.info(&format!(
+                    "Test fire of driver {} complete: energized for {energized:?}",
+                    driver.label
+                ));
+            }
+        }
+        Command::Ignition => ignition(configuration, driver_lines, state, hold, history)?,
+        Command::EmergencyStop => {
+            emergency_stop(configuration, driver_lines, state, user_log)?;
+        }
+        Command::InjectReading { sensor, reading } => {
+            let (group_id, sensor_id) = resolve_sensor(configuration, sensor)?;
+            injections.inject(group_id, sensor_id, *reading)?;
+        }
+        Command::LeakCheck {
+            sensor,
+            hold_seconds,
+            max_decay,
+        } => {
+            let (group_id, sensor_id) = resolve_sensor(configuration, sensor)?;
+            let sensor = configuration
+                .sensor_groups
+                .get(usize::from(group_id))
+                .and_then(|group| group.sensors.get(usize::from(sensor_id)))
+                .ok_or(Error::SensorOutOfBounds)?;
+            if *hold_seconds > LEAK_CHECK_CEILING_S {
+                return Err(Error::LeakCheckTooLong(*hold_seconds));
+            }
+            let status = state.status()?;
+            if status != State::Standby {
+                return Err(Error::LeakCheckOutsideStandby(status));
+            }
+
+            #[allow(unused_must_use)]
+            {
+                user_log.info(&format!(
+                    "Leak-checking sensor {} for {hold_seconds} s",
+                    sensor.label
+                ));
+            }
+            let (initial, last) = leak_check(
+                history,
+                state,
+                group_id,
+                sensor_id,
+                Duration::from_secs(u64::from(*hold_seconds)),
+            )?;
+            let (initial_value, final_value) = (initial.value, last.value);
+            let decay = initial_value - final_value;
+            // the decay is measured between the readings, which need not be exactly `hold_seconds`
+            // apart
+            let span = last
+                .time
+                .duration_since(initial.time)
+                .unwrap_or(Duration::ZERO);
+            let passed = decay <= *max_decay;
+            let summary = format!(
+                "Leak check of sensor {} {}: {initial_value} {units} to {final_value} {units}, a decay of {decay} {units} (at most {max_decay} {units} allowed)",
+                sensor.label,
+                if passed { "passed" } else { "failed" },
+                units = sensor.units,
+            );
+            #[allow(unused_must_use)]
+            if passed {
+                user_log.info(&summary);
+            } else {
+                user_log.warn(&summary);
+            }
+            if let Err(e) = dashboard.send(&Message::LeakCheck {
+                group_id,
+                sensor_id,
+                initial_value,
+                final_value,
+                decay_rate: decay / span.as_secs_f64(),
+                passed,
+            }) {
+                #[allow(unused_must_use)]
+                {
+                    user_log.warn(&format!(
+                        "Unable to send leak check result to dashboard: {e:?}"
+                    ));
+                }
+            }
+        }
+        Command::KeepAlive { driver_id } => deadman.keep_alive(*driver_id)?,
+        Command::History {
+            group_id,
+            sensor_id,
+            count,
+        } => {
+            let readings = history.recent(*group_id, *sensor_id, *count)?;
+            let fields = dashboard.sensor_fields().map_err(|_| Error::Poison)?;
+            if let Err(e) = dashboard.send(&Message::SensorValue {
+                group_id: *group_id,
+                readings: ReadingList {
+                    readings: &readings,
+                    fields,
+                },
+                skew_ns: None,
+            }) {
+                #[allow(unused_must_use)]
+                {
+                    user_log.warn(&format!(
+                        "Unable to send sensor history to dashboard: {e:?}"
+                    ));
+                }
+            }
+        }
+        Command::CalibratePoint {
+            group_id,
+            sensor_id,
+            known_value,
+        } => {
+            let sensor = configuration
+                .sensor_groups
+                .get(usize::from(*group_id))
+                .and_then(|group| group.sensors.get(usize::from(*sensor_id)))
+                .ok_or(Error::SensorOutOfBounds)?;
+            if sensor.thermistor.is_some() {
+                return Err(Error::CalibrateThermistor);
+            }
+            let status = state.status()?;
+            if status != State::Standby {
+                return Err(Error::CalibrationOutsideStandby(status));
+            }
+            let reading = history
+                .latest(*group_id, *sensor_id)?
+                .ok_or(Error::NoReading)?
+                .reading;
+
+            if let Some(calibration) =
+                calibrator.add_point(*group_id, *sensor_id, reading, *known_value)?
+            {
+                calibration::save(configuration, *group_id, *sensor_id, calibration)
+                    .map_err(Error::CalibrationNotSaved)?;
+                tunings
+                    .calibrate(*group_id, *sensor_id, calibration)
+                    .map_err(|_| Error::Poison)?;
+                #[allow(unused_must_use)]
+                {
+                    user_log.info(&format!(
+                        "Calibrated sensor {}: {calibration:?}",
+                        sensor.label
+                    ));
+                }
+                if let Err(e) = dashboard.send(&Message::Calibration {
+                    group_id: *group_id,
+                    sensor_id: *sensor_id,
+                    calibration_intercept: calibration.calibration_intercept,
+                    calibration_slope: calibration.calibration_slope,
+                }) {
+                    #[allow(unused_must_use)]
+                    {
+                        user_log.warn(&format!(
+                            "Unable to send new calibration to dashboard: {e:?}"
+                        ));
+                    }
+                }
+            } else {
+                #[allow(unused_must_use)]
+                {
+                    user_log.info(&format!(
+                        "Recorded first calibration point of sensor {}: reading {reading} is {known_value}",
+                        sensor.label
+                    ));
+                }
+            }
+        }
+        Command::SetThreshold {
+            group_id,
+            sensor_id,
+            min,
+            max,
+        } => {
+            let sensor = configuration
+                .sensor_groups
+                .get(usize::from(*group_id))
+                .and_then(|group| group.sensors.get(usize::from(*sensor_id)))
+                .ok_or(Error::SensorOutOfBounds)?;
+            if min.partial_cmp(max) != Some(std::cmp::Ordering::Less) {
+                return Err(Error::BadThreshold(*min, *max));
+            }
+            let status = state.status()?;
+            if status != State::Standby {
+                return Err(Error::ThresholdOutsideStandby(status));
+            }
+
+            tunings
+                .set_range(*group_id, *sensor_id, (*min, *max))
+                .map_err(|_| Error::Poison)?;
+            #[allow(unused_must_use)]
+            {
+                user_log.info(&format!(
+                    "Range of sensor {} set to [{min}, {max}]",
+                    sensor.label
+                ));
+            }
+            if let Err(e) = dashboard.send(&Message::Threshold {
+                group_id: *group_id,
+                sensor_id: *sensor_id,
+                min: *min,
+                max: *max,
+            }) {
+                #[allow(unused_must_use)]
+                {
+                    user_log.warn(&format!("Unable to send new range to dashboard: {e:?}"));
+                }
+            }
+        }
+        Command::RemapDriver { driver_id, new_pin } => {
+            let status = state.status()?;
+            if status != State::Standby {
+                return Err(Error::RemapOutsideStandby(status));
+            }
+            let old_pin = rewiring.remap(configuration, driver_lines, *driver_id, *new_pin)?;
+            #[allow(unused_must_use)]
+            {
+                user_log.critical(&format!(
+                    "Driver {} moved from GPIO pin {old_pin} to GPIO pin {new_pin}; the configuration still names pin {old_pin}",
+                    configuration.drivers[usize::from(*driver_id)].label
+                ));
+            }
+        }
+        Command::OverrideInterlock {
+            interlock_id,
+            confirm,
+        } => {
+            let Some(interlock) = configuration.interlocks.get(usize::from(*interlock_id)) else {
+                return Err(Error::NoSuchInterlock(*interlock_id));
+            };
+            if !*confirm {
+                return Err(Error::OverrideUnconfirmed);
+            }
+            let status = state.status()?;
+            if status != State::Standby {
+                return Err(Error::OverrideOutsideStandby(status));
+            }
+            interlocks.set_overridden(*interlock_id, true)?;
+            #[allow(unused_must_use)]
+            {
+                user_log.critical(&format!(
+                    "Interlock {} overridden by {client}; its drivers may now be energized together",
+                    interlock.label
+                ));
+            }
+        }
+        Command::RestoreInterlock { interlock_id } => {
+            interlocks.set_overridden(*interlock_id, false)?;
+            #[allow(unused_must_use)]
+            {
+                user_log.info(&format!(
+                    "Interlock {} restored by {client}",
+                    configuration.interlocks[usize::from(*interlock_id)].label
+                ));
+            }
+        }
+        Command::RestartGroup { group_id, confirm } => {
+            let Some(group) = configuration.sensor_groups.get(usize::from(*group_id)) else {
+                return Err(Error::NoSuchGroup(*group_id));
+            };
+            if !*confirm {
+                return Err(Error::RestartUnconfirmed);
+            }
+            let status = state.status()?;
+            if status != State::Standby {
+                return Err(Error::RestartOutsideStandby(status));
+            }
+            if !restarts.is_running(*group_id) {
+                return Err(Error::GroupNotRunning(*group_id));
+            }
+            restarts.request(*group_id);
+            #[allow(unused_must_use)]
+            {
+                user_log.warn(&format!(
+                    "Restart of sensor group {} requested by {client}",
+                    group.label
+                ));
+            }
+        }
+        Command::Hold => hold.hold(state)?,
+        Command::Flush => flusher.flush(FLUSH_TIMEOUT)?,
+        Command::Snapshot => {
+            let (time, values) = snapshots.take(SNAPSHOT_TIMEOUT)?;
+            if let Err(e) = dashboard.send(&Message::Snapshot {
+                time,
+                values: &values,
+            }) {
+                #[allow(unused_must_use)]
+                {
+                    user_log.warn(&format!("Unable to send snapshot to dashboard: {e:?}"));
+                }
+            }
+        }
+        Command::Annotate { text } => {
+            if text.trim().is_empty() {
+                return Err(Error::EmptyAnnotation);
+            }
+            let time = SystemTime::now();
+            annotations.record(time, client, text)?;
+            #[allow(unused_must_use)]
+            {
+                user_log.info(&format!("Annotation by {client}: {text}"));
+                if let Err(e) = dashboard.send(&Message::Annotation { time, text }) {
+                    user_log.warn(&format!("Unable to send annotation to dashboard: {e:?}"));
+                }
+            }
+        }
+        Command::Resume => hold.resume()?,
+        Command::Describe => {
+            let levels = read_levels(&mut driver_lines.lock().map_err(|_| Error::Poison)?)?;
+            #[allow(clippy::cast_possible_truncation)]
+            let drivers: Vec<DriverInfo> = configuration
+                .drivers
+                .iter()
+                .zip(levels)
+                .enumerate()
+                .map(|(driver_id, (driver, level))| DriverInfo {
+                    driver_id: driver_id as u8,
+                    label: &driver.label,
+                    kind: driver.kind,
+                    hazardous: driver.hazardous,
+                    state: DriverState::new(driver.kind, level),
+                })
+                .collect();
+
+            // the calibrations may have been changed since the controller started
+            let tunings = tunings.read().map_err(|_| Error::Poison)?;
+            let mut sensors = Vec::new();
+            for (group_id, (group, group_tunings)) in configuration
+                .sensor_groups
+                .iter()
+                .zip(tunings.iter())
+                .enumerate()
+            {
+                for (sensor_id, (sensor, tuning)) in
+                    group.sensors.iter().zip(group_tunings).enumerate()
+                {
+                    #[allow(clippy::cast_possible_truncation)]
+                    sensors.push(SensorInfo {
+                        group_id: group_id as u8,
+                        sensor_id: sensor_id as u8,
+                        label: &sensor.label,
+                        units: &sensor.units,
+                        calibration: match sensor.thermistor {
+                            Some(_) => CalibrationSummary::Thermistor,
+                            None => CalibrationSummary::Linear {
+                                slope: tuning.calibration_slope,
+                                intercept: tuning.calibration_intercept,
+                                vref: sensor.vref,
+                            },
+                        },
+                    });
+                }
+            }
+            drop(tunings);
+
+            if let Err(e) = dashboard.send(&Message::Inventory {
+                drivers: &drivers,
+                sensors: &sensors,
+            }) {
+                #[allow(unused_must_use)]
+                {
+                    user_log.warn(&format!("Unable to send inventory to dashboard: {e:?}"));
+                }
+            }
+        }
+        Command::Status => {
+            let status = state.status()?;
+            let levels = read_levels(&mut driver_lines.lock().map_err(|_| Error::Poison)?)?;
+            let drivers: Vec<DriverState> = configuration
+                .drivers
+                .iter()
+                .zip(levels)
+                .map(|(driver, level)| DriverState::new(driver.kind, level))
+                .collect();
+            // a reading is stale by the same window as when it is sent to the dashboard
+            let time = SystemTime::now();
+            let stale_window = configuration
+                .stale_reading_ms
+                .map(|ms| Duration::from_millis(u64::from(ms)));
+            let mut sensors = Vec::with_capacity(configuration.sensor_groups.len());
+            let mut virtual_sensors = Vec::with_capacity(configuration.sensor_groups.len());
+            for (group_id, group) in configuration.sensor_groups.iter().enumerate() {
+                #[allow(clippy::cast_possible_truncation)]
+                let values = (0..group.sensors.len())
+                    .map(|sensor_id| {
+                        Ok(history
+                            .latest(group_id as u8, sensor_id as u8)?
+                            .map(|reading| SensorStatus {
+                                value: reading.value,
+                                saturated: reading.saturated,
+                                stale: stale_window.map_or(false, |window| {
+                                    time.duration_since(reading.time).unwrap_or_default() > window
+                                }),
+                            }))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                sensors.push(values);
+                #[allow(clippy::cast_possible_truncation)]
+                virtual_sensors.push(history.latest_virtual(group_id as u8)?);
+            }
+
+            #[allow(clippy::cast_possible_truncation)]
+            if let Err(e) = dashboard.send(&Message::StatusReport {
+                state: status,
+                armed: status.armed(),
+                uptime_ms: state.uptime().as_millis() as u64,
+                drivers: &drivers,
+                time,
+                sensors: &sensors,
+                virtual_sensors: &virtual_sensors,
+                failed_groups: &restarts.failed(),
+                sequences: &sequences.running()?,
+                held: hold.is_held()?,
+                paused: pause.is_paused(),
+                ready: readiness.is_ready().map_err(|_| Error::Poison)?,
+                estop_count: state.estop_count(),
+                interlock_overrides: &interlocks.overridden()?,
+            }) {
+                #[allow(unused_must_use)]
+                {
+                    user_log.warn(&format!("Unable to send status report to dashboard: {e:?}"));
+                }
+            }
+        }
+        Command::PauseAll | Command::ResumeAll => {
+            let paused = *cmd == Command::PauseAll;
+            let status = state.status()?;
+            if paused && status != State::Standby {
+                return Err(Error::PauseOutsideStandby(status));
+            }
+            pause.set(paused)?;
+            #[allow(unused_must_use)]
+            {
+                user_log.info(&format!(
+                    "Sensor logging and streaming {} by {client}",
+                    if paused { "paused" } else { "resumed" }
+                ));
+            }
+        }
+        Command::SetLogLevel { level } => user_log.set_verbosity(*level),
+        Command::SetSensorFields { fields } => dashboard
+            .set_sensor_fields(*fields)
+            .map_err(|_| Error::Poison)?,
+        // the client's identity was already updated when the command was received, and console
+        // streaming is set up by the client handler once the command is logged
+        Command::Auth { .. } | Command::StreamConsole { .. } => (),
+    };
+
+    if !logged {
+        return Ok(());
+    }
+
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    #[allow(unused_must_use)]
+    if let Err(e) = writeln!(
+        log_file.lock().map_err(|_| Error::Poison)?,
+        "{},finish,{client},{cmd}",
+        time.as_nanos()
+    ) {
+        user_log.warn(&format!(
+            "Unable to log completion of command {cmd} to log file: {e:?}"
+        ));
+    }
+    Ok(())
+}
+
+/// Energize a driver for `duration`, and then return it to its safe (unpowered) level.
+///
+/// The driver is released at the deadline no matter what happens to the dashboard which requested
+/// the test fire.
+/// Returns the length of time for which the driver was energized.
+///
+/// # Errors
+///
+/// This function will return an error without energizing the driver if doing so would violate an
+/// interlock or the driver's minimum actuation interval.
+/// It will return an `Err(Error::SequenceAborted)` if an emergency stop begins during the test fire,
+/// after which the driver is never written to again, since the emergency stop sequence must have
+/// the last word on every driver.
+/// It will also return an error if a lock is poisoned or if we are unable to access GPIO.
+fn test_fire(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    interlocks: &Interlocks,
+    throttle: &Throttle,
+    driver_id: u8,
+    duration: Duration,
+) -> Result<Duration, Error> {
+    // the interlocks are checked under the same lock as the write, so no other actuation can come
+    // between them
+    let mut lines = driver_lines.lock()?;
+    interlocks.check(configuration, &read_levels(&mut lines)?, &[driver_id])?;
+    throttle.actuate(driver_id)?;
+    let estop_count = state.estop_count();
+    let start = Instant::now();
+    actuate_driver(&mut lines, driver_id, true)?;
+    drop(lines);
+
+    loop {
+        let elapsed = start.elapsed();
+        {
+            let mut lines = driver_lines.lock()?;
+            if state.estop_count() != estop_count {
+                return Err(Error::SequenceAborted);
+            }
+            if elapsed >= duration {
+                actuate_driver(&mut lines, driver_id, false)?;
+                return Ok(start.elapsed());
+            }
+        }
+        sleep(TEST_FIRE_POLL_PERIOD.min(duration.saturating_sub(elapsed)));
+    }
+}
+
+/// Watch a sensor for `hold`, returning its latest reading at the start and at the end.
+///
+/// Sensors are still checked against their ranges and abort thresholds by their sensor threads
+/// throughout; the check is abandoned if an emergency stop begins.
+///
+/// # Errors
+///
+/// This function will return an `Err(Error::SequenceAborted)` if an emergency stop begins during the
+/// check.
+/// It will return `Err(Error::NoReading)` if the sensor has not been read before the check, and
+/// `Err(Error::NoNewReading)` if it is not read again during the check.
+/// It will also return an error if a lock is poisoned.
+fn leak_check(
+    history: &History,
+    state: &Guard,
+    group_id: u8,
+    sensor_id: u8,
+    hold: Duration,
+) -> Result<(SensorReading, SensorReading), Error> {
+    let estop_count = state.estop_count();
+    let initial = history
+        .latest(group_id, sensor_id)?
+        .ok_or(Error::NoReading)?;
+    let start = Instant::now();
+
+    loop {
+        if state.estop_count() != estop_count {
+            return Err(Error::SequenceAborted);
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= hold {
+            break;
+        }
+        sleep(LEAK_CHECK_POLL_PERIOD.min(hold.saturating_sub(elapsed)));
+    }
+
+    let last = history
+        .latest(group_id, sensor_id)?
+        .ok_or(Error::NoReading)?;
+    if last.time <= initial.time {
+        return Err(Error::NoNewReading);
+    }
+    Ok((initial, last))
+}
+
+/// Find the group ID and sensor ID of the sensor referred to by `sensor`.
+///
+/// References by ID are returned as-is; it is up to the caller to check that they are in bounds.
+///
+/// # Errors
+///
+/// This function will return an `Err` if `sensor` is a label which is held by no sensor, or by more
+/// than one sensor.
+fn resolve_sensor(configuration: &Configuration, sensor: &SensorRef) -> Result<(u8, u8), Error> {
+    let label = match sensor {
+        SensorRef::Id {
+            group_id,
+            sensor_id,
+        } => return Ok((*group_id, *sensor_id)),
+        SensorRef::Label { sensor } => sensor,
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    let mut matches =
+        configuration
+            .sensor_groups
+            .iter()
+            .enumerate()
+            .flat_map(|(group_id, group)| {
+                group
+                    .sensors
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| &s.label == label)
+                    .map(move |(sensor_id, _)| (group_id as u8, sensor_id as u8))
+            });
+
+    match (matches.next(), matches.next()) {
+        (Some(ids), None) => Ok(ids),
+        (None, _) => Err(Error::UnknownSensor(label.clone())),
+        (Some(_), Some(_)) => Err(Error::AmbiguousSensor(label.clone())),
+    }
+}
+
+/// Determine whether a command may energize the driver with ID `driver_id`, given the latest
+/// readings in `history`.
+///
+/// A reading is only trusted while it is neither stale (older than the configured staleness
+/// window) nor older than the precondition's maximum age.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the driver has a precondition and the latest value of its
+/// sensor is outside the precondition's range, if the sensor has not been read yet, or if its latest
+/// reading is too old.
+fn check_precondition(
+    configuration: &Configuration,
+    history: &History,
+    driver_id: u8,
+) -> Result<(), Error> {
+    let Some(precondition) = configuration.drivers[usize::from(driver_id)].precondition else {
+        return Ok(());
+    };
+    let Some(latest) = history.latest(precondition.group_id, precondition.sensor_id)? else {
+        return Err(Error::PreconditionUnmet(driver_id, None));
+    };
+    let age = SystemTime::now()
+        .duration_since(latest.time)
+        .unwrap_or_default();
+    let max_age = [configuration.stale_reading_ms, precondition.max_age_ms]
+        .into_iter()
+        .flatten()
+        .min();
+    if max_age.map_or(false, |max_age| {
+        age > Duration::from_millis(u64::from(max_age))
+    }) {
+        return Err(Error::PreconditionStale(driver_id, age));
+    }
+    if !precondition.admits(latest.value) {
+        return Err(Error::PreconditionUnmet(driver_id, Some(latest.value)));
+    }
+    Ok(())
+}
+
+/// Determine whether every sensor which has been read is back inside its range, so that whatever
+/// caused the last emergency stop has cleared.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the latest reading of any sensor is outside its range.
+fn check_back_in_range(
+    configuration: &Configuration,
+    history: &History,
+    tunings: &Tunings,
+) -> Result<(), Error> {
+    let tunings = tunings.read().map_err(|_| Error::Poison)?;
+    for ((group_id, group), group_tunings) in
+        (0u8..).zip(&configuration.sensor_groups).zip(&*tunings)
+    {
+        for ((sensor_id, sensor), tuning) in (0u8..).zip(&group.sensors).zip(group_tunings) {
+            let Some((min, max)) = tuning.range else {
+                continue;
+            };
+            if let Some(latest) = history.latest(group_id, sensor_id)? {
+                if latest.value < min || max < latest.value {
+                    return Err(Error::StillOutOfRange(sensor.label.clone(), latest.value));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Determine whether a command may actuate a hazardous driver to `value`.
+///
+/// A hazardous driver may only be powered while an ignition is in progress, and only with the
+/// operator's confirmation.
+/// Returning it to its safe (unpowered) level needs no confirmation and is allowed at any time, so
+/// that nothing stands in the way of making the system safe.
+///
+/// # Errors
+///
+/// This function will return an `Err` if the actuation would power the driver without
+/// confirmation or outside of an ignition.
+fn check_hazardous_actuation(value: bool, confirm: bool, state: State) -> Result<(), Error> {
+    if !value {
+        return Ok(());
+    }
+    if !confirm {
+        return Err(Error::HazardUnconfirmed);
+    }
+    if !matches!(state, State::PreIgnite | State::Ignite | State::PostIgnite) {
+        return Err(Error::HazardOutsideIgnition(state));
+    }
+    Ok(())
+}
+
+/// Attempt to perform an emergency stop procedure.
+/// If there is a purge sequence, the controller is left in the `Purging` state for `purge_watch`
+/// to run it; otherwise, it returns to standby.
+///
+/// # Errors
+///
+/// This function can return an `Err` in the following cases:
+///
+/// * The user attempted to perform an ignition from a state which was not standby.
+/// * A lock was poisoned.
+/// * We failed to gain control over GPIO.
+pub fn emergency_stop(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), Error> {
+    // transition to EStop, and if it's already in EStopping, don't interfere
+    state.move_to(State::EStopping)?;
+
+    let start = Instant::now();
+    perform_actions(driver_lines, &configuration.estop_sequence, None, true)?;
+    #[allow(unused_must_use)]
+    {
+        user_log.info(&format!(
+            "Emergency stop sequence done in {:?}",
+            start.elapsed()
+        ));
+    }
+
+    // done doing the estop sequence, leave the purge to `purge_watch`
+    end_safing(configuration, state)
+}
+
+/// Attempt to perform the abort action named `action`, in place of the emergency stop sequence.
+///
+/// Every driver targeted by the action is set to its level while holding the lock on the driver
+/// lines, so no other actuation can be interleaved with the action.
+/// Like an emergency stop, the controller is in the `EStopping` state while the action is applied,
+/// and is then left to purge in the same way.
+///
+/// # Errors
+///
+/// This function can return an `Err` in the following cases:
+///
+/// * No abort action is named `action`.
+/// * The controller was already emergency stopping.
+/// * A lock was poisoned.
+/// * We failed to gain control over GPIO.
+pub fn abort(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    action: &str,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), Error> {
+    let targets = configuration
+        .abort_actions
+        .get(action)
+        .ok_or_else(|| Error::NoSuchAbortAction(action.to_string()))?;
+
+    state.move_to(State::EStopping)?;
+
+    let start = Instant::now();
+    {
+        let mut driver_lines = driver_lines.lock_urgent()?;
+        for target in targets {
+            driver_lines
+                .get_mut(usize::from(target.driver_id))
+                .ok_or(Error::DriverOutOfBounds)?
+                .write(target.value)
+                .map_err(|_| Error::Poison)?;
+        }
+    }
+    #[allow(unused_must_use)]
+    {
+        user_log.info(&format!(
+            "Abort action {action} done in {:?}",
+            start.elapsed()
+        ));
+    }
+
+    end_safing(configuration, state)
+}
+
+/// Leave the `EStopping` state once an emergency stop or abort action has made the drivers safe.
+/// If there is a purge sequence, the controller moves to `Purging` for `purge_watch` to run it;
+/// otherwise, it returns straight to standby.
+///
+/// # Errors
+///
+/// This function will return an error if the transition is illegal or a lock is poisoned.
+fn end_safing(configuration: &Configuration, state: &Guard) -> Result<(), Error> {
+    if configuration.purge_sequence.is_empty() {
+        state.move_to(State::Standby)?;
+    } else {
+        state.move_to(State::Purging)?;
+    }
+    Ok(())
+}
+
+/// Run the purge sequence whenever the controller enters the `Purging` state, and then return to
+/// standby.
+/// Will loop until the controller quits.
+///
+/// Every purge runs on this one thread, so an emergency stop during a purge never leaves two purges
+/// running at once: the interrupted purge ends, and the purge starts again once the new emergency
+/// stop has made the drivers safe.
+/// If a purge fails, it is not retried until another emergency stop begins.
+///
+/// # Errors
+///
+/// This function will return an error if a lock is poisoned or if we are unable to write to the
+/// user log.
+pub fn purge_watch(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    dashboard_stream: &DashChannel<impl Write, impl Write>,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    loop {
+        match state.status()? {
+            State::Quit => return Ok(()),
+            State::Purging => {
+                let estop_count = state.estop_count();
+                if let Err(e) = purge(
+                    configuration,
+                    driver_lines,
+                    state,
+                    dashboard_stream,
+                    user_log,
+                ) {
+                    user_log.critical(&format!(
+                        "Purge failed: {e}; send an emergency stop to purge again"
+                    ))?;
+                    while state.estop_count() == estop_count
+                        && state.wait_while(State::Purging, PURGE_POLL_PERIOD)? == State::Purging
+                    {
+                    }
+                }
+            }
+            current => {
+                state.wait_while(current, PURGE_POLL_PERIOD)?;
+            }
+        }
+    }
+}
+
+/// Run the purge sequence once, and then return to standby.
+/// Progress through the purge is reported on `user_log` and to the dashboard.
+///
+/// The purge is abandoned as soon as another emergency stop begins, even in the middle of a sleep.
+/// Each actuation is checked for an interruption while holding the lock on the driver lines, so no
+/// purge actuation can follow the actions of the new emergency stop.
+///
+/// # Errors
+///
+/// This function will return an error if a lock was poisoned or if we are unable to write to GPIO.
+fn purge(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    dashboard_stream: &DashChannel<impl Write, impl Write>,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), Error> {
+    let actions = &configuration.purge_sequence;
+    let estop_count = state.estop_count();
+    let interrupted = |step: usize| {
+        let interrupted = state.estop_count() != estop_count;
+        if interrupted {
+            #[allow(unused_must_use)]
+            {
+                user_log.warn(&format!(
+                    "Purge interrupted after {step} of {} steps",
+                    actions.len()
+                ));
+            }
+        }
+        interrupted
+    };
+    let report = |completed: usize| {
+        #[allow(unused_must_use)]
+        {
+            dashboard_stream.send(&Message::PurgeProgress {
+                completed,
+                total: actions.len(),
+            });
+        }
+    };
+
+    #[allow(unused_must_use)]
+    {
+        user_log.info(&format!("Purging: {} steps", actions.len()));
+    }
+    report(0);
+
+    for (step, action) in actions.iter().enumerate() {
+        match action {
+            Action::Actuate { driver_id, value } => {
+                let mut lines = driver_lines.lock()?;
+                // checked under the lock, so that an emergency stop always has the last word
+                if state.estop_count() != estop_count {
+                    drop(lines);
+                    interrupted(step);
+                    return Ok(());
+                }
+                actuate_driver(&mut lines, *driver_id, *value)?;
+            }
+            Action::Sleep { duration } => {
+                // a new emergency stop moves out of `Purging`, which ends the wait early
+                let deadline = Instant::now() + *duration;
+                let mut remaining = *duration;
+                while !remaining.is_zero()
+                    && state.wait_while(State::Purging, remaining)? == State::Purging
+                    && state.estop_count() == estop_count
+                {
+                    remaining = deadline.saturating_duration_since(Instant::now());
+                }
+                if interrupted(step) {
+                    return Ok(());
+                }
+            }
+        }
+        #[allow(unused_must_use)]
+        {
+            user_log.info(&format!(
+                "Purge step {} of {} done",
+                step + 1,
+                actions.len()
+            ));
+        }
+        report(step + 1);
+    }
+
+    // an emergency stop during the final sleep has already taken over from us
+    match state.move_from(State::Purging, State::Standby) {
+        Err(state::Error::IllegalTransition { .. }) => return Ok(()),
+        result => result?,
+    }
+    #[allow(unused_must_use)]
+    {
+        user_log.info("Purge complete");
+    }
+
+    Ok(())
+}
+
+/// Attempt to perform an ignition procedure.
+///
+/// # Errors
+///
+/// This function can return an `Err` in the following cases:
+///
+/// * The user attempted to perform an ignition from a state which was not standby.
+/// * A lock was poisoned.
+/// * We failed to gain control over GPIO.
+/// * An emergency stop began while the ignition sequence was held.
+/// * The precondition of a driver energized by the ignition sequence was not met before the
+///   ignition began.
+fn ignition(
+    configuration: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    hold: &Hold,
+    history: &History,
+) -> Result<(), Error> {
+    for action in &configuration.ignition_sequence {
+        if let Action::Actuate {
+            driver_id,
+            value: true,
+        } = action
+        {
+            check_precondition(configuration, history, *driver_id)?;
+        }
+    }
+    hold.begin_ignition(state)?;
+    sleep(Duration::from_millis(u64::from(
+        configuration.pre_ignite_time,
+    )));
+
+    state.move_to(State::Ignite)?;
+    perform_actions(
+        driver_lines,
+        &configuration.ignition_sequence,
+        Some((hold, state)),
+        false,
+    )?;
+
+    state.move_to(State::PostIgnite)?;
+    sleep(Duration::from_millis(u64::from(
+        configuration.post_ignite_time,
+    )));
+
+    // done doing the ignition sequence, move back to standby
+    state.move_to(State::Standby)?;
+
+    Ok(())
+}
+
+/// Actuate a given driver to a given value using GPIO cdev to interface with OS.
+///
+/// # Inputs
+///
+/// * `driver_lines`: A table of GPIO lines for all drivers.
+/// * `driver_id`: The ID of the driver to be actuated.
+///     An ID is an index into `configuration.drivers` for the associated driver.
+///     It is also the same index into `driver_lines`.
+/// * `value`: The logic level that the driver should be actuated to.
+///     `value` should be `true` to get a high value on the GPIO pin, and `false` for a low value.
+///
+/// # Errors
+///
+/// This function may return an error if we are unable to gain control over the GPIO pin associated
+/// with the driver.
+fn actuate_driver(
+    driver_lines: &mut [impl GpioPin],
+    driver_id: u8,
+    value: bool,
+) -> Result<(), Error> {
+    driver_lines[driver_id as usize]
+        .write(value)
+        .map_err(|_| Error::Poison)
+}
+
+/// Read the level of every driver in `driver_lines`.
+///
+/// # Errors
+///
+/// This function may return an error if we are unable to read a GPIO pin.
+fn read_levels(driver_lines: &mut [impl GpioPin]) -> Result<Vec<bool>, Error> {
+    driver_lines
+        .iter_mut()
+        .map(|line| line.read().map_err(|_| Error::Poison))
+        .collect()
+}
+
+/// Perform a sequence of actions, such as for emergency stopping or for
+/// ignition.
+///
+/// If `hold` is given, the sequence will wait for any hold to be released before each action.
+/// If `urgent` is set, each run of consecutive actuations takes the lock on the driver lines ahead
+/// of any ordinary actuation and keeps it until the run ends, as is needed for safing.
+///
+/// # Errors
+///
+/// This function will return an error if we are unable to write to GPIO, or if an emergency stop
+/// begins while the sequence is held.
+fn perform_actions(
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    actions: &[Action],
+    hold: Option<(&Hold, &Guard)>,
+    urgent: bool,
+) -> Result<(), Error> {
+    // an urgent lock is only released to sleep, so no ordinary actuation can come between two
+    // consecutive safing actuations
+    let mut urgent_lines = None;
+    for action in actions {
+        if let Some((hold, state)) = hold {
+            hold.wait(state)?;
+        }
+        match action {
+            Action::Actuate { driver_id, value } if urgent => {
+                let lines = match urgent_lines {
+                    Some(ref mut lines) => lines,
+                    None => urgent_lines.insert(driver_lines.lock_urgent()?),
+                };
+                actuate_driver(lines, *driver_id, *value)?;
+            }
+            Action::Actuate { driver_id, value } => {
+                actuate_driver(
+                    &mut driver_lines.lock().map_err(|_| Error::Poison)?,
+                    *driver_id,
+                    *value,
+                )?;
+            }
+            Action::Sleep { duration } => {
+                drop(urgent_lines.take());
+                sleep(*duration);
+            }
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::atomic::AtomicBool, thread::scope};
+
+    use serde_json::{json, Value};
+
+    use crate::{console::UserLog, hardware::ListenerPin, outgoing::Capture};
+
+    use super::*;
+
+    /// A builder of configurations for tests, which starts from a configuration with no sensors,
+    /// drivers, or sequences.
+    struct ConfigBuilder(Value);
+
+    impl ConfigBuilder {
+        /// Construct a new `ConfigBuilder` for the smallest valid configuration.
+        fn new() -> ConfigBuilder {
+            ConfigBuilder(json!({
+                "frequency_status": 1,
+                "log_buffer_size": 1,
+                "sensor_groups": [],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [14],
+                "pin_heartbeat": 0
+            }))
+        }
+
+        /// Set the key `key` of the configuration to `value`.
+        fn with(mut self, key: &str, value: Value) -> ConfigBuilder {
+            self.0[key] = value;
+            self
+        }
+
+        /// Add a sensor group labelled `label`, read at 10 Hz in every state, containing
+        /// `sensors`.
+        fn group(mut self, label: &str, sensors: &[Value]) -> ConfigBuilder {
+            self.0["sensor_groups"].as_array_mut().unwrap().push(json!({
+                "label": label,
+                "frequency_standby": 10,
+                "frequency_ignition": 10,
+                "frequency_transmission": 10,
+                "sensors": sensors
+            }));
+            self
+        }
+
+        /// Parse the configuration.
+        ///
+        /// # Panics
+        ///
+        /// This function will panic if the configuration is invalid.
+        fn build(&self) -> Configuration {
+            Configuration::parse(&mut Cursor::new(self.0.to_string())).unwrap()
+        }
+    }
+
+    /// Describe an unprotected driver labelled `label` on GPIO pin `pin`.
+    fn driver(label: &str, pin: u8) -> Value {
+        json!({
+            "label": label,
+            "label_actuate": "Open",
+            "label_deactuate": "Close",
+            "pin": pin,
+            "protected": false
+        })
+    }
+
+    /// Describe a sensor labelled `label` on channel 0 of ADC 0, whose value is its raw reading.
+    fn sensor(label: &str) -> Value {
+        json!({
+            "label": label,
+            "color": "red",
+            "units": "psi",
+            "calibration_intercept": 0,
+            "calibration_slope": 1,
+            "adc": 0,
+            "channel": 0
+        })
+    }
+
+    /// Everything needed by `handle_command`, constructed from one configuration.
+    /// Every driver is on a `ListenerPin` which starts off, and every message sent to the
+    /// dashboard is captured.
+    struct Harness {
+        config: Configuration,
+        client: Client,
+        log_file: Mutex<Vec<u8>>,
+        user_log: UserLog<Vec<u8>>,
+        driver_lines: PriorityMutex<Vec<ListenerPin>>,
+        state: Guard,
+        injections: Injections,
+        throttle: Throttle,
+        deadman: Deadman,
+        hold: Hold,
+        dashboard: DashChannel<Capture, Vec<u8>>,
+        capture: Capture,
+        flusher: Flusher,
+        readiness: Readiness,
+        history: History,
+        tunings: Tunings,
+        calibrator: Calibrator,
+        sequences: Sequences,
+        snapshots: Snapshots<Vec<u8>>,
+        rewiring: Rewiring<ListenerPin>,
+        interlocks: Interlocks,
+        pause: Pause,
+        restarts: Restarts,
+        annotations: Annotations<Vec<u8>>,
+    }
+
+    impl Harness {
+        /// Construct a new `Harness` for `config`, in standby.
+        fn new(config: Configuration) -> Harness {
+            let capture = Capture::default();
+            let dashboard = DashChannel::new(Vec::new());
+            dashboard.set_channel(Some(capture.clone())).unwrap();
+            Harness {
+                client: Client::new("127.0.0.1:5000".parse().unwrap()),
+                log_file: Mutex::new(Vec::new()),
+                user_log: UserLog::new(Vec::new()),
+                driver_lines: PriorityMutex::new(
+                    config
+                        .drivers
+                        .iter()
+                        .map(|_| ListenerPin::new(false))
+                        .collect(),
+                ),
+                state: Guard::new(State::Standby),
+                injections: Injections::new(&config, false),
+                throttle: Throttle::new(&config),
+                deadman: Deadman::new(&config),
+                hold: Hold::new(),
+                dashboard,
+                capture,
+                flusher: Flusher::new(&config, Vec::new()),
+                readiness: Readiness::new(&config),
+                history: History::new(&config),
+                tunings: Tunings::new(&config),
+                calibrator: Calibrator::new(&config),
+                sequences: Sequences::new(&config),
+                snapshots: Snapshots::new(&config, Vec::new()),
+                rewiring: Rewiring::new(&config, |_, level| Ok(ListenerPin::new(level))),
+                interlocks: Interlocks::new(&config),
+                pause: Pause::new(),
+                restarts: Restarts::new(&config),
+                annotations: Annotations::new(Vec::new()),
+                config,
+            }
+        }
+
+        /// Handle `cmd` as though it were sent by the harness's client.
+        fn run(&self, cmd: &Command) -> Result<(), Error> {
+            handle_command(
+                cmd,
+                &self.client,
+                &self.log_file,
+                &self.user_log,
+                &self.config,
+                &self.driver_lines,
+                &self.state,
+                &self.injections,
+                &self.throttle,
+                &self.deadman,
+                &self.hold,
+                &self.dashboard,
+                &self.flusher,
+                &self.readiness,
+                &self.history,
+                &self.tunings,
+                &self.calibrator,
+                &self.sequences,
+                &self.snapshots,
+                &self.rewiring,
+                &self.interlocks,
+                &self.pause,
+                &self.restarts,
+                &self.annotations,
+            )
+        }
+
+        /// Record a reading of `value` from sensor `sensor_id` of group `group_id`, taken at
+        /// `time`.
+        fn read(&self, group_id: u8, sensor_id: u8, value: f64, time: SystemTime) {
+            self.history
+                .push(
+                    group_id,
+                    SensorReading {
+                        sensor_id,
+                        reading: 0,
+                        value,
+                        time,
+                        offset_ns: None,
+                        saturated: false,
+                        stale: false,
+                    },
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    /// Test that state transitions are performed correctly during ignition.
+    fn ignition_state_transitions() {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 500,
+            "drivers": [],
+            "ignition_sequence": [
+                {
+                    "type": "Sleep",
+                    "duration": {
+                        "secs": 0,
+                        "nanos": 500000000
+                    }
+                }
+            ],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 13,
+            "spi_clk": 14,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        let mut cfg_cursor = Cursor::new(config);
+        let config = Configuration::parse(&mut cfg_cursor).unwrap();
+
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+
+        let state = Guard::new(State::Standby);
+        let state_ref = &state;
+
+        scope(|s| {
+            s.spawn(move || {
+                ignition(
+                    &config,
+                    &driver_lines,
+                    state_ref,
+                    &Hold::new(),
+                    &History::new(&config),
+                )
+                .unwrap();
+            });
+
+            sleep(Duration::from_millis(250));
+            assert_eq!(state.status().unwrap(), State::PreIgnite);
+
+            sleep(Duration::from_millis(500));
+            assert_eq!(state.status().unwrap(), State::Ignite);
+
+            sleep(Duration::from_millis(500));
+            assert_eq!(state.status().unwrap(), State::PostIgnite);
+
+            sleep(Duration::from_millis(500));
+            assert_eq!(state.status().unwrap(), State::Standby);
+        });
+    }
+
+    #[test]
+    /// Test that valve actuations are performed correctly during ignition.
+    fn ignition_actuation() {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [{
+                "label": "OXI_FILL",
+                "label_actuate": "Open",
+                "label_deactuate": "Close",
+                "pin": 21,
+                "protected": false
+            }],
+            "ignition_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": true
+                },
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": false
+                }
+            ],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        let mut cfg_cursor = Cursor::new(config);
+        let config = Configuration::parse(&mut cfg_cursor).unwrap();
+        let driver_lines = PriorityMutex::new(vec![ListenerPin::new(false)]);
+        let state = Guard::new(State::Standby);
+
+        ignition(
+            &config,
+            &driver_lines,
+            &state,
+            &Hold::new(),
+            &History::new(&config),
+        )
+        .unwrap();
+
+        assert_eq!(
+            driver_lines.lock().unwrap()[0].history().as_slice(),
+            [false, true, false]
+        );
+    }
+
+    #[test]
+    /// Test that the correct sequence of state transistions are performed during an emergency stop.
+    fn estop_state_transitions() {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 500,
+            "post_ignite_time": 500,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [
+                {
+                    "type": "Sleep",
+                    "duration": {
+                        "secs": 0,
+                        "nanos": 500000000
+                    }
+                }
+            ],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        let mut cfg_cursor = Cursor::new(config);
+        let config = Configuration::parse(&mut cfg_cursor).unwrap();
+
+        let driver_lines = PriorityMutex::new(Vec::<ListenerPin>::new());
+
+        let state = Guard::new(State::Standby);
+        let state_ref = &state;
+
+        scope(|s| {
+            s.spawn(move || {
+                emergency_stop(&config, &driver_lines, state_ref, &UserLog::new(Vec::new()))
+                    .unwrap();
+            });
+
+            sleep(Duration::from_millis(250));
+            assert_eq!(state.status().unwrap(), State::EStopping);
+
+            sleep(Duration::from_millis(500));
+            assert_eq!(state.status().unwrap(), State::Standby);
+        });
+    }
+
+    #[test]
+    /// Test that distinct abort actions each set only their own drivers, and that an undefined
+    /// action is rejected.
+    fn abort_actions() {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [
+                {
+                    "label": "MAIN",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 20,
+                    "protected": false,
+                    "hazardous": true
+                },
+                {
+                    "label": "VENT",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                },
+                {
+                    "label": "PURGE",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 22,
+                    "protected": false
+                }
+            ],
+            "ignition_sequence": [],
+            "estop_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": false
+                }
+            ],
+            "abort_actions": {
+                "overpressure": [
+                    { "driver_id": 0, "value": false },
+                    { "driver_id": 1, "value": true }
+                ],
+                "comms_loss": [
+                    { "driver_id": 0, "value": false },
+                    { "driver_id": 1, "value": false },
+                    { "driver_id": 2, "value": false }
+                ]
+            },
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+        let config = Configuration::parse(&mut Cursor::new(config)).unwrap();
+        let state = Guard::new(State::Standby);
+        let user_log = UserLog::new(Vec::new());
+
+        let driver_lines =
+            PriorityMutex::new((0..3).map(|_| ListenerPin::new(true)).collect::<Vec<_>>());
+        abort(&config, &driver_lines, &state, "overpressure", &user_log).unwrap();
+        assert_eq!(
+            driver_lines
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|line| line.history().clone())
+                .collect::<Vec<_>>(),
+            [vec![true, false], vec![true, true], vec![true]]
+        );
+        assert_eq!(state.status().unwrap(), State::Standby);
+
+        let driver_lines =
+            PriorityMutex::new((0..3).map(|_| ListenerPin::new(true)).collect::<Vec<_>>());
+        abort(&config, &driver_lines, &state, "comms_loss", &user_log).unwrap();
+        assert!(driver_lines
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|line| line.history().as_slice() == [true, false]));
+
+        assert!(matches!(
+            abort(&config, &driver_lines, &state, "meteor_strike", &user_log),
+            Err(Error::NoSuchAbortAction(_))
+        ));
+        assert_eq!(state.status().unwrap(), State::Standby);
+    }
+
+    #[test]
+    /// Test that an urgent acquisition of a `PriorityMutex` is admitted ahead of every ordinary
+    /// acquisition which begins while it is pending.
+    fn urgent_acquisition_first() {
+        const CONTENDERS: usize = 4;
+        let mutex = PriorityMutex::new(());
+        let order = Mutex::new(Vec::new());
+
+        scope(|s| {
+            let held = mutex.lock().unwrap();
+            s.spawn(|| {
+                let _guard = mutex.lock_urgent().unwrap();
+                order.lock().unwrap().push("urgent");
+            });
+            while *mutex.urgent.lock().unwrap() == 0 {
+                std::thread::yield_now();
+            }
+            for _ in 0..CONTENDERS {
+                s.spawn(|| {
+                    let _guard = mutex.lock().unwrap();
+                    order.lock().unwrap().push("ordinary");
+                });
+            }
+            drop(held);
+        });
+
+        let order = order.into_inner().unwrap();
+        assert_eq!(order.len(), CONTENDERS + 1);
+        // no ordinary acquisition was admitted ahead of the urgent one
+        assert_eq!(order.iter().position(|&who| who == "urgent"), Some(0));
+    }
+
+    #[test]
+    /// Test that an abort makes the drivers safe within a fixed bound, even while many threads
+    /// are contending for the driver lines.
+    fn abort_latency_under_contention() {
+        const CONTENDERS: usize = 8;
+        const TRIALS: usize = 10;
+        /// How long each contender holds the driver lines at a time, like a slow actuation.
+        const HOLD: Duration = Duration::from_millis(20);
+        /// The longest an abort may take: it waits out at most the one holder ahead of it, plus
+        /// some slack for scheduling.
+        const ABORT_LATENCY_BOUND: Duration = Duration::from_millis(60);
+
+        let h = Harness::new(
+            ConfigBuilder::new()
+                .with("drivers", json!([driver("MAIN", 20)]))
+                .with(
+                    "abort_actions",
+                    json!({ "overpressure": [{ "driver_id": 0, "value": false }] }),
+                )
+                .build(),
+        );
+        h.driver_lines.lock().unwrap()[0].write(true).unwrap();
+        let done = AtomicBool::new(false);
+
+        let worst = scope(|s| {
+            for _ in 0..CONTENDERS {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let lines = h.driver_lines.lock().unwrap();
+                        sleep(HOLD);
+                        drop(lines);
+                    }
+                });
+            }
+
+            let mut worst = Duration::ZERO;
+            for _ in 0..TRIALS {
+                // land each abort at a different point in the contenders' holds
+                sleep(HOLD / 3);
+                let start = Instant::now();
+                abort(
+                    &h.config,
+                    &h.driver_lines,
+                    &h.state,
+                    "overpressure",
+                    &h.user_log,
+                )
+                .unwrap();
+                worst = worst.max(start.elapsed());
+            }
+            done.store(true, Ordering::Relaxed);
+            worst
+        });
+
+        assert!(!h.driver_lines.lock().unwrap()[0].read().unwrap());
+        assert_eq!(h.state.status().unwrap(), State::Standby);
+        assert!(
+            worst < ABORT_LATENCY_BOUND,
+            "worst-case abort latency was {worst:?}"
+        );
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    /// Test that the purge sequence runs after an emergency stop, and that a second emergency stop
+    /// interrupts it.
+    fn purge_after_estop() {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [
+                {
+                    "label": "MAIN",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 20,
+                    "protected": false,
+                    "hazardous": true
+                },
+                {
+                    "label": "PURGE",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                }
+            ],
+            "ignition_sequence": [],
+            "estop_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": false
+                }
+            ],
+            "purge_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 1,
+                    "value": true
+                },
+                {
+                    "type": "Sleep",
+                    "duration": {
+                        "secs": 0,
+                        "nanos": 300000000
+                    }
+                },
+                {
+                    "type": "Actuate",
+                    "driver_id": 1,
+                    "value": false
+                }
+            ],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+        let config = Configuration::parse(&mut Cursor::new(config)).unwrap();
+        let state = Guard::new(State::Standby);
+        let user_log = UserLog::new(Vec::new());
+
+        let capture = Capture::default();
+        let dashboard = DashChannel::<Capture, Vec<u8>>::new(Vec::new());
+        dashboard.set_channel(Some(capture.clone())).unwrap();
+        let wait_for_standby = || {
+            while state.status().unwrap() != State::Standby {
+                state
+                    .wait_while(State::Purging, Duration::from_millis(10))
+                    .unwrap();
+            }
+        };
+
+        let driver_lines =
+            PriorityMutex::new(vec![ListenerPin::new(true), ListenerPin::new(false)]);
+        scope(|s| {
+            let watcher =
+                s.spawn(|| purge_watch(&config, &driver_lines, &state, &dashboard, &user_log));
+
+            emergency_stop(&config, &driver_lines, &state, &user_log).unwrap();
+            wait_for_standby();
+            assert_eq!(driver_lines.lock().unwrap()[0].history(), &[true, false]);
+            assert_eq!(
+                driver_lines.lock().unwrap()[1].history(),
+                &[false, true, false]
+            );
+            let progress: Vec<u64> = capture
+                .messages()
+                .iter()
+                .filter(|message| message["type"] == "PurgeProgress")
+                .map(|message| message["completed"].as_u64().unwrap())
+                .collect();
+            assert_eq!(progress, [0, 1, 2, 3]);
+
+            // an emergency stop during the sleep ends the first purge at once, and the purge is
+            // restarted rather than run twice at the same time
+            emergency_stop(&config, &driver_lines, &state, &user_log).unwrap();
+            sleep(Duration::from_millis(100));
+            assert_eq!(state.status().unwrap(), State::Purging);
+            let start = Instant::now();
+            emergency_stop(&config, &driver_lines, &state, &user_log).unwrap();
+            wait_for_standby();
+            assert!(start.elapsed() < Duration::from_millis(600));
+            assert_eq!(
+                driver_lines.lock().unwrap()[1].history(),
+                &[false, true, false, true, true, false]
+            );
+
+            state.move_to(State::Quit).unwrap();
+            watcher.join().unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    /// Test that driver actuations are performed correctly during emergency stop.
+    fn estop_actuation() {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [{
+                "label": "OXI_FILL",
+                "label_actuate": "Open",
+                "label_deactuate": "Close",
+                "pin": 21,
+                "protected": false
+            }],
+            "ignition_sequence": [],
+            "estop_sequence": [
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": true
+                },
+                {
+                    "type": "Actuate",
+                    "driver_id": 0,
+                    "value": false
+                }
+            ],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        let mut cfg_cursor = Cursor::new(config);
+        let config = Configuration::parse(&mut cfg_cursor).unwrap();
+        let driver_lines = PriorityMutex::new(vec![ListenerPin::new(false)]);
+        let state = Guard::new(State::Standby);
+
+        emergency_stop(&config, &driver_lines, &state, &UserLog::new(Vec::new())).unwrap();
+
+        assert_eq!(
+            driver_lines.lock().unwrap()[0].history().as_slice(),
+            [false, true, false]
+        );
+    }
+
+    #[test]
+    /// Test that each command in the command log is attributed to the client which sent it.
+    fn command_attribution() {
+        let mut h = Harness::new(ConfigBuilder::new().build());
+
+        h.run(&Command::EmergencyStop).unwrap();
+        assert!(h.client.authenticate("bad,name").is_err());
+        h.client.authenticate("jdoe").unwrap();
+        h.run(&Command::Auth {
+            operator: "jdoe".into(),
+            protocol_version: None,
+        })
+        .unwrap();
+        h.run(&Command::EmergencyStop).unwrap();
+
+        let log = String::from_utf8(h.log_file.into_inner().unwrap()).unwrap();
+        let rows: Vec<&str> = log
+            .lines()
+            .map(|line| line.split_once(',').unwrap().1)
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "request,<anonymous>,127.0.0.1:5000,estop",
+                "finish,<anonymous>,127.0.0.1:5000,estop",
+                "request,jdoe,127.0.0.1:5000,auth jdoe",
+                "finish,jdoe,127.0.0.1:5000,auth jdoe",
+                "request,jdoe,127.0.0.1:5000,estop",
+                "finish,jdoe,127.0.0.1:5000,estop",
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that rapid actuations of a driver are throttled, but that an emergency stop still goes
+    /// through.
+    fn actuation_throttle() {
+        let mut oxi_fill = driver("OXI_FILL", 21);
+        oxi_fill["min_actuation_interval_ms"] = json!(10000);
+        let h = Harness::new(
+            ConfigBuilder::new()
+                .with("drivers", json!([oxi_fill]))
+                .with(
+                    "estop_sequence",
+                    json!([{ "type": "Actuate", "driver_id": 0, "value": false }]),
+                )
+                .build(),
+        );
+
+        let open = Command::Actuate {
+            driver_id: 0,
+            value: true,
+            confirm: false,
+        };
+        h.run(&open).unwrap();
+        assert!(matches!(h.run(&open), Err(Error::ActuationTooSoon(_))));
+        h.run(&Command::EmergencyStop).unwrap();
+
+        assert_eq!(
+            h.driver_lines.lock().unwrap()[0].history().as_slice(),
+            [false, true, false]
+        );
+    }
+
+    #[test]
+    /// Test that a group of actuations is rejected as a whole if any one of them is invalid, and
+    /// applied as a whole otherwise.
+    fn actuate_many() {
+        let h = Harness::new(
+            ConfigBuilder::new()
+                .with(
+                    "drivers",
+                    json!([driver("OXI_FILL", 21), driver("OXI_VENT", 22)]),
+                )
+                .build(),
+        );
+        let run = |actions: &[(u8, bool)]| {
+            h.run(&Command::ActuateMany {
+                actions: actions
+                    .iter()
+                    .map(|&(driver_id, value)| Actuation { driver_id, value })
+                    .collect(),
+                confirm: false,
+            })
+        };
+
+        assert!(matches!(
+            run(&[(0, true), (2, true)]),
+            Err(Error::DriverOutOfBounds)
+        ));
+        assert!(matches!(
+            run(&[(1, true), (1, false)]),
+            Err(Error::RepeatedDriver(1))
+        ));
+        for line in h.driver_lines.lock().unwrap().iter() {
+            assert_eq!(line.history().as_slice(), [false]);
+        }
+
+        run(&[(0, true), (1, true)]).unwrap();
+        for line in h.driver_lines.lock().unwrap().iter() {
+            assert_eq!(line.history().as_slice(), [false, true]);
+        }
+    }
+
+    #[test]
+    /// Test that a driver with a dead man's switch stays energized while keep-alives arrive, and is
+    /// driven safe once they stop.
+    fn deadman_switch() {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [{
+                "label": "IGNITER",
+                "label_actuate": "Fire",
+                "label_deactuate": "Safe",
+                "pin": 21,
+                "protected": false,
+                "deadman_interval_ms": 50
+            }],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#;
+
+        let config = Configuration::parse(&mut Cursor::new(config)).unwrap();
+        let driver_lines = PriorityMutex::new(vec![ListenerPin::new(false)]);
+        let state = Guard::new(State::Standby);
+        let deadman = Deadman::new(&config);
+        let user_log = UserLog::new(Vec::new());
+
+        // nothing is energized yet, so there is nothing to keep alive
+        assert!(matches!(deadman.keep_alive(0), Err(Error::NotHeld(0))));
+
+        scope(|s| {
+            s.spawn(|| deadman_watch(&driver_lines, &deadman, &user_log, &state));
+
+            driver_lines.lock().unwrap()[0].write(true).unwrap();
+            deadman.actuate(0, true).unwrap();
+            for _ in 0..5 {
+                sleep(Duration::from_millis(20));
+                deadman.keep_alive(0).unwrap();
+            }
+            assert_eq!(driver_lines.lock().unwrap()[0].history(), &[false, true]);
+
+            sleep(Duration::from_millis(150));
+            assert_eq!(
+                driver_lines.lock().unwrap()[0].history(),
+                &[false, true, false]
+            );
+            assert!(matches!(deadman.keep_alive(0), Err(Error::NotHeld(0))));
+
+            state.move_to(State::Quit).unwrap();
+        });
+    }
+
+    #[test]
+    /// Test that keep-alives are handled without being recorded in the command log.
+    fn keep_alive_not_logged() {
+        let mut igniter = driver("IGNITER", 21);
+        igniter["deadman_interval_ms"] = json!(60000);
+        let h = Harness::new(
+            ConfigBuilder::new()
+                .with("drivers", json!([igniter]))
+                .build(),
+        );
+
+        h.run(&Command::Actuate {
+            driver_id: 0,
+            value: true,
+            confirm: false,
+        })
+        .unwrap();
+        h.run(&Command::KeepAlive { driver_id: 0 }).unwrap();
+        h.run(&Command::KeepAlive { driver_id: 0 }).unwrap();
+
+        let log = String::from_utf8(h.log_file.into_inner().unwrap()).unwrap();
+        let rows: Vec<&str> = log
+            .lines()
+            .map(|line| line.split_once(',').unwrap().1)
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "request,<anonymous>,127.0.0.1:5000,actuate 0 true",
+                "finish,<anonymous>,127.0.0.1:5000,actuate 0 true",
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that a snapshot command sends the snapshot to the dashboard, stamped with the time at
+    /// which it was requested.
+    fn snapshot_sent() {
+        let h = Harness::new(ConfigBuilder::new().build());
+        let before = SystemTime::now();
+        h.run(&Command::Snapshot).unwrap();
+
+        let message = &h.capture.messages()[0];
+        assert_eq!(message["type"], "Snapshot");
+        assert_eq!(message["values"], json!([]));
+        assert!(
+            message["time"]["secs_since_epoch"].as_u64().unwrap()
+                >= before
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+        );
+    }
+
+    #[test]
+    /// Test that an annotation is timestamped, written to the event log, and sent to the dashboard.
+    fn annotation_logged() {
+        let mut h = Harness::new(ConfigBuilder::new().build());
+        h.state = Guard::new(State::Ignite);
+        let annotate = |text: &str| h.run(&Command::Annotate { text: text.into() });
+
+        assert!(matches!(annotate("  "), Err(Error::EmptyAnnotation)));
+        let before = SystemTime::now();
+        annotate("main valve \"open\", igniter confirmed").unwrap();
+        let after = SystemTime::now();
+
+        let log = String::from_utf8(h.annotations.log.lock().unwrap().0.clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "time,operator,peer,text");
+        let (nanos, rest) = lines[1].split_once(',').unwrap();
+        assert_eq!(
+            rest,
+            "<anonymous>,127.0.0.1:5000,\"main valve \"\"open\"\", igniter confirmed\""
+        );
+        let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos.parse().unwrap());
+        assert!(before <= time && time <= after);
+
+        let messages = h.capture.messages();
+        let message = messages
+            .iter()
+            .find(|message| message["type"] == "Annotation")
+            .unwrap();
+        assert_eq!(message["text"], "main valve \"open\", igniter confirmed");
+        assert_eq!(
+            message["time"]["secs_since_epoch"].as_u64().unwrap(),
+            time.duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        );
+    }
+
+    #[test]
+    /// Test that sensor logging and streaming may only be paused in standby, but may be resumed in
+    /// any state.
+    fn pause_only_in_standby() {
+        let mut h = Harness::new(ConfigBuilder::new().build());
+
+        h.state = Guard::new(State::Ignite);
+        assert!(matches!(
+            h.run(&Command::PauseAll),
+            Err(Error::PauseOutsideStandby(State::Ignite))
+        ));
+        assert!(!h.pause.is_paused());
+
+        h.state = Guard::new(State::Standby);
+        h.run(&Command::PauseAll).unwrap();
+        assert!(h.pause.is_paused());
+        h.state = Guard::new(State::PostIgnite);
+        h.run(&Command::ResumeAll).unwrap();
+        assert!(!h.pause.is_paused());
+    }
+
+    #[test]
+    /// Test that a sensor group may only be restarted in standby, with confirmation, and only while
+    /// its thread is running.
+    fn restart_group() {
+        let mut h = Harness::new(ConfigBuilder::new().group("FAST", &[]).build());
+        let restart =
+            |h: &Harness, group_id, confirm| h.run(&Command::RestartGroup { group_id, confirm });
+
+        assert!(matches!(restart(&h, 1, true), Err(Error::NoSuchGroup(1))));
+        assert!(matches!(
+            restart(&h, 0, false),
+            Err(Error::RestartUnconfirmed)
+        ));
+        h.state = Guard::new(State::Ignite);
+        assert!(matches!(
+            restart(&h, 0, true),
+            Err(Error::RestartOutsideStandby(State::Ignite))
+        ));
+        h.state = Guard::new(State::Standby);
+        assert!(matches!(
+            restart(&h, 0, true),
+            Err(Error::GroupNotRunning(0))
+        ));
+        assert!(!h.restarts.take(0));
+
+        let running = h.restarts.run(0);
+        restart(&h, 0, true).unwrap();
+        assert!(h.restarts.take(0));
+        assert!(!h.restarts.take(0));
+
+        drop(running);
+        assert!(matches!(
+            restart(&h, 0, true),
+            Err(Error::GroupNotRunning(0))
+        ));
+    }
+
+    #[test]
+    /// Test that an interlock blocks energizing two of its drivers together until it is overridden,
+    /// and does so again once it is restored.
+    fn interlock_override() {
+        let h = Harness::new(
+            ConfigBuilder::new()
+                .with(
+                    "drivers",
+                    json!([driver("OXI_FILL", 21), driver("OXI_VENT", 22)]),
+                )
+                .with(
+                    "interlocks",
+                    json!([{ "label": "oxidizer tank", "drivers": [0, 1] }]),
+                )
+                .build(),
+        );
+        let actuate = |driver_id, value| {
+            h.run(&Command::Actuate {
+                driver_id,
+                value,
+                confirm: false,
+            })
+        };
+
+        actuate(0, true).unwrap();
+        assert!(matches!(actuate(1, true), Err(Error::Interlocked(1, 0))));
+
+        assert!(matches!(
+            h.run(&Command::OverrideInterlock {
+                interlock_id: 0,
+                confirm: false
+            }),
+            Err(Error::OverrideUnconfirmed)
+        ));
+        h.run(&Command::OverrideInterlock {
+            interlock_id: 0,
+            confirm: true,
+        })
+        .unwrap();
+        assert_eq!(h.interlocks.overridden().unwrap(), [0]);
+        actuate(1, true).unwrap();
+
+        h.run(&Command::RestoreInterlock { interlock_id: 0 })
+            .unwrap();
+        assert!(h.interlocks.overridden().unwrap().is_empty());
+        // deactuating is never blocked, but energizing is once again
+        actuate(0, false).unwrap();
+        assert!(matches!(actuate(0, true), Err(Error::Interlocked(0, 1))));
+        // and so is test-firing
+        assert!(matches!(
+            h.run(&Command::TestFire {
+                driver_id: 0,
+                max_ms: 10
+            }),
+            Err(Error::Interlocked(0, 1))
+        ));
+
+        assert_eq!(
+            h.driver_lines.lock().unwrap()[0].history(),
+            &[false, true, false]
+        );
+        assert_eq!(h.driver_lines.lock().unwrap()[1].history(), &[false, true]);
+    }
+
+    #[test]
+    /// Test that a driver with a precondition can only be energized while its sensor's latest
+    /// reading is in range, and can always be deactuated.
+    fn precondition_blocks_actuation() {
+        let mut main = driver("MAIN", 21);
+        main["precondition"] = json!({
+            "group_id": 0,
+            "sensor_id": 0,
+            "min": 300.0,
+            "max": null,
+            "max_age_ms": 1000
+        });
+        let h = Harness::new(
+            ConfigBuilder::new()
+                .group("TANK", &[sensor("PT_TANK")])
+                .with("drivers", json!([main]))
+                .with(
+                    "ignition_sequence",
+                    json!([
+                        { "type": "Actuate", "driver_id": 0, "value": true },
+                        { "type": "Actuate", "driver_id": 0, "value": false }
+                    ]),
+                )
+                .build(),
+        );
+        let actuate = |value| {
+            h.run(&Command::Actuate {
+                driver_id: 0,
+                value,
+                confirm: false,
+            })
+        };
+        let inject = |value, age| h.read(0, 0, value, SystemTime::now() - age);
+
+        // the tank has not been read yet
+        assert!(matches!(
+            actuate(true),
+            Err(Error::PreconditionUnmet(0, None))
+        ));
+        inject(120.0, Duration::ZERO);
+        assert!(matches!(
+            actuate(true),
+            Err(Error::PreconditionUnmet(0, Some(v))) if (v - 120.0).abs() < 1e-9
+        ));
+        // an in-range reading is not trusted once it is too old
+        inject(450.0, Duration::from_secs(5));
+        assert!(matches!(actuate(true), Err(Error::PreconditionStale(0, _))));
+        inject(450.0, Duration::ZERO);
+        actuate(true).unwrap();
+
+        // closing the valve is never blocked
+        inject(50.0, Duration::ZERO);
+        actuate(false).unwrap();
+
+        // nor is an ignition which would energize the valve
+        let ignite = || ignition(&h.config, &h.driver_lines, &h.state, &h.hold, &h.history);
+        assert!(matches!(
+            ignite(),
+            Err(Error::PreconditionUnmet(0, Some(_)))
+        ));
+        assert_eq!(h.state.status().unwrap(), State::Standby);
+        inject(450.0, Duration::ZERO);
+        ignite().unwrap();
+
+        assert_eq!(
+            h.driver_lines.lock().unwrap()[0].history(),
+            &[false, true, false, true, false]
+        );
+    }
+
+    #[test]
+    /// Test that an ignition whose preconditions held when it began runs to completion, leaving
+    /// every driver safe, even if a precondition sensor leaves its range partway through.
+    fn precondition_lost_during_ignition() {
+        let mut main = driver("MAIN", 21);
+        main["precondition"] = json!({
+            "group_id": 0,
+            "sensor_id": 0,
+            "min": 300.0,
+            "max": null
+        });
+        let h = Harness::new(
+            ConfigBuilder::new()
+                .group("TANK", &[sensor("PT_TANK")])
+                .with("drivers", json!([driver("IGNITER", 20), main]))
+                .with(
+                    "ignition_sequence",
+                    json!([
+                        { "type": "Actuate", "driver_id": 0, "value": true },
+                        { "type": "Sleep", "duration": { "secs": 0, "nanos": 200_000_000 } },
+                        { "type": "Actuate", "driver_id": 1, "value": true },
+                        { "type": "Actuate", "driver_id": 1, "value": false },
+                        { "type": "Actuate", "driver_id": 0, "value": false }
+                    ]),
+                )
+                .build(),
+        );
+        h.read(0, 0, 450.0, SystemTime::now());
+
+        scope(|s| {
+            let handle =
+                s.spawn(|| ignition(&h.config, &h.driver_lines, &h.state, &h.hold, &h.history));
 
-#[cfg(test)]
-mod tests {
-    use std::{io::Cursor, thread::scope};
+            // the tank loses pressure while the igniter is lit
+            sleep(Duration::from_millis(100));
+            h.read(0, 0, 50.0, SystemTime::now());
 
-    use crate::hardware::ListenerPin;
+            handle.join().unwrap().unwrap();
+        });
 
-    use super::*;
+        assert_eq!(h.state.status().unwrap(), State::Standby);
+        let levels = read_levels(&mut h.driver_lines.lock().unwrap()).unwrap();
+        assert_eq!(levels, [false, false]);
+    }
+
+    /// The sense input of a driver, which reads as powered while `powered` is set.
+    struct SensePin<'a> {
+        /// Whether the driver's load is actually powered.
+        powered: &'a AtomicBool,
+    }
+
+    impl GpioPin for SensePin<'_> {
+        fn read(&mut self) -> Result<bool, gpio_cdev::Error> {
+            Ok(self.powered.load(Ordering::Relaxed))
+        }
+
+        fn write(&mut self, _: bool) -> Result<(), gpio_cdev::Error> {
+            unreachable!("sense pins are inputs")
+        }
+    }
 
     #[test]
-    /// Test that state transitions are performed correctly during ignition.
-    fn ignition_state_transitions() {
+    /// Test that a driver whose sense input reads as powered while it is commanded safe during an
+    /// ignition triggers an emergency stop.
+    fn stuck_driver_readback() {
         let config = r#"{
             "frequency_status": 1,
             "log_buffer_size": 1,
             "sensor_groups": [],
-            "pre_ignite_time": 500,
-            "post_ignite_time": 500,
-            "drivers": [],
-            "ignition_sequence": [
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [
                 {
-                    "type": "Sleep",
-                    "duration": {
-                        "secs": 0,
-                        "nanos": 500000000
-                    }
+                    "label": "OXI_FILL",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                },
+                {
+                    "label": "OXI_VENT",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 22,
+                    "protected": false
                 }
             ],
+            "ignition_sequence": [],
             "estop_sequence": [],
             "spi_mosi": 11,
-            "spi_miso": 13,
-            "spi_clk": 14,
+            "spi_miso": 12,
+            "spi_clk": 13,
             "spi_frequency_clk": 50000,
             "adc_cs": [],
+            "pin_heartbeat": 0,
+            "readback_interval_ms": 10
+        }"#;
+
+        let config = Configuration::parse(&mut Cursor::new(config)).unwrap();
+        let driver_lines =
+            PriorityMutex::new(vec![ListenerPin::new(true), ListenerPin::new(false)]);
+        let powered = [AtomicBool::new(true), AtomicBool::new(false)];
+        let mut senses = powered
+            .iter()
+            .map(|powered| Some(SensePin { powered }))
+            .collect::<Vec<_>>();
+        let state = Guard::new(State::Ignite);
+        let user_log = UserLog::new(Vec::new());
+
+        scope(|s| {
+            s.spawn(|| readback_watch(&config, &driver_lines, &mut senses, &user_log, &state));
+
+            // an energized driver reads back as powered, which is expected
+            sleep(Duration::from_millis(50));
+            assert_eq!(state.estop_count(), 0);
+
+            // the output line of the vent still reads as commanded, but its relay has welded shut
+            powered[1].store(true, Ordering::Relaxed);
+            sleep(Duration::from_millis(50));
+            assert_eq!(state.estop_count(), 1);
+            assert_eq!(state.status().unwrap(), State::Standby);
+
+            // outside of an ignition, drivers are not read back
+            sleep(Duration::from_millis(50));
+            assert_eq!(state.estop_count(), 1);
+
+            state.move_to(State::Quit).unwrap();
+        });
+    }
+
+    #[test]
+    /// Test that powering a hazardous driver must be confirmed and may only happen during an
+    /// ignition, while returning it to its safe level is always allowed.
+    fn hazardous_actuation() {
+        assert!(matches!(
+            check_hazardous_actuation(true, false, State::Ignite),
+            Err(Error::HazardUnconfirmed)
+        ));
+        assert!(check_hazardous_actuation(false, false, State::Standby).is_ok());
+        assert!(matches!(
+            check_hazardous_actuation(true, true, State::Standby),
+            Err(Error::HazardOutsideIgnition(State::Standby))
+        ));
+        assert!(check_hazardous_actuation(true, true, State::Ignite).is_ok());
+        assert!(check_hazardous_actuation(false, true, State::Standby).is_ok());
+    }
+
+    #[test]
+    /// Test that sensors referred to by label are resolved to their IDs.
+    fn resolve_sensor_label() {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "FAST",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 1000,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {
+                            "label": "PT_FEED",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0.0,
+                            "calibration_slope": 1.0,
+                            "adc": 0,
+                            "channel": 0
+                        },
+                        {
+                            "label": "LC_MAIN",
+                            "color": "blue",
+                            "units": "lb",
+                            "calibration_intercept": 0.0,
+                            "calibration_slope": 1.0,
+                            "adc": 0,
+                            "channel": 1
+                        },
+                        {
+                            "label": "TC",
+                            "color": "green",
+                            "units": "K",
+                            "calibration_intercept": 0.0,
+                            "calibration_slope": 1.0,
+                            "adc": 0,
+                            "channel": 2
+                        },
+                        {
+                            "label": "TC",
+                            "color": "green",
+                            "units": "K",
+                            "calibration_intercept": 0.0,
+                            "calibration_slope": 1.0,
+                            "adc": 0,
+                            "channel": 3
+                        }
+                    ]
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [5],
             "pin_heartbeat": 0
         }"#;
 
         let mut cfg_cursor = Cursor::new(config);
         let config = Configuration::parse(&mut cfg_cursor).unwrap();
+        let label = |s: &str| SensorRef::Label { sensor: s.into() };
 
-        let driver_lines: Mutex<Vec<ListenerPin>> = Mutex::new(Vec::new());
+        assert_eq!(resolve_sensor(&config, &label("LC_MAIN")).unwrap(), (0, 1));
+        assert!(matches!(
+            resolve_sensor(&config, &label("LC_NONE")),
+            Err(Error::UnknownSensor(_))
+        ));
+        assert!(matches!(
+            resolve_sensor(&config, &label("TC")),
+            Err(Error::AmbiguousSensor(_))
+        ));
+    }
 
-        let state = Guard::new(State::Standby);
-        let state_ref = &state;
+    #[test]
+    /// Test that recently executed commands are remembered, and that old or excess commands are
+    /// forgotten.
+    fn recent_commands() {
+        let start = Instant::now();
+        let mut recent = RecentCommands::new(Duration::from_secs(10), 2);
 
-        scope(|s| {
-            s.spawn(move || ignition(&config, &driver_lines, state_ref).unwrap());
+        assert_eq!(recent.lookup(1, start), None);
+        recent.record(1, true, start);
+        recent.begin(2, start);
+        assert_eq!(recent.lookup(1, start), Some(Outcome::Finished(true)));
+        assert_eq!(recent.lookup(2, start), Some(Outcome::Executing));
+        recent.record(2, false, start);
+        assert_eq!(recent.lookup(2, start), Some(Outcome::Finished(false)));
 
-            sleep(Duration::from_millis(250));
-            assert_eq!(state.status().unwrap(), State::PreIgnite);
+        // exceeding the capacity forgets the oldest command
+        recent.record(3, true, start);
+        assert_eq!(recent.lookup(1, start), None);
+        assert_eq!(recent.lookup(3, start), Some(Outcome::Finished(true)));
 
-            sleep(Duration::from_millis(500));
-            assert_eq!(state.status().unwrap(), State::Ignite);
+        // commands are forgotten after the window
+        let later = start + Duration::from_secs(11);
+        assert_eq!(recent.lookup(3, later), None);
+    }
 
-            sleep(Duration::from_millis(500));
-            assert_eq!(state.status().unwrap(), State::PostIgnite);
+    #[test]
+    /// Test that a client's commands beyond the rate limit are dropped until the window passes, and
+    /// that safing commands, holds, and keep-alives are never dropped.
+    fn rate_limit() {
+        let mut config = hold_config();
+        let start = Instant::now();
+        let test_fire = Command::TestFire {
+            driver_id: 0,
+            max_ms: 100,
+        };
 
-            sleep(Duration::from_millis(500));
-            assert_eq!(state.status().unwrap(), State::Standby);
-        });
+        let mut unlimited = RateLimit::new(&config);
+        assert!((0..1000).all(|_| unlimited.admit(&test_fire, start)));
+
+        config.command_rate_limit = Some(2);
+        let mut limit = RateLimit::new(&config);
+        assert!(limit.admit(&test_fire, start));
+        assert!(limit.admit(&Command::Resume, start));
+        assert!(!limit.admit(&test_fire, start + Duration::from_millis(500)));
+        assert!(limit.admit(&Command::EmergencyStop, start));
+        assert!(limit.admit(&Command::Hold, start));
+        assert!(limit.admit(&Command::KeepAlive { driver_id: 0 }, start));
+        let actuate = |value| Command::Actuate {
+            driver_id: 0,
+            value,
+            confirm: false,
+        };
+        assert!(limit.admit(&actuate(false), start));
+        assert!(!limit.admit(&actuate(true), start));
+
+        let later = start + RATE_WINDOW;
+        assert!(limit.admit(&test_fire, later));
+        assert!(limit.admit(&test_fire, later));
+        assert!(!limit.admit(&test_fire, later));
     }
 
     #[test]
-    /// Test that valve actuations are performed correctly during ignition.
-    fn ignition_actuation() {
+    /// Test that a dashboard's declared protocol version is checked against the range the
+    /// controller accepts, and that a version must be declared only if a minimum is configured.
+    fn protocol_version() {
+        let mut config = hold_config();
+        check_protocol(&config, None).unwrap();
+        check_protocol(&config, Some(PROTOCOL_VERSION)).unwrap();
+        assert!(matches!(
+            check_protocol(&config, Some(PROTOCOL_VERSION + 1)),
+            Err(Error::UnsupportedProtocol { .. })
+        ));
+
+        config.min_protocol_version = Some(PROTOCOL_VERSION);
+        check_protocol(&config, Some(PROTOCOL_VERSION)).unwrap();
+        assert!(matches!(
+            check_protocol(&config, None),
+            Err(Error::UndeclaredProtocol)
+        ));
+        assert!(matches!(
+            check_protocol(&config, Some(PROTOCOL_VERSION - 1)),
+            Err(Error::UnsupportedProtocol {
+                min: PROTOCOL_VERSION,
+                ..
+            })
+        ));
+    }
+
+    /// Construct a configuration whose ignition sequence opens and then closes driver 0, with a
+    /// short sleep between them.
+    fn hold_config() -> Configuration {
         let config = r#"{
             "frequency_status": 1,
             "log_buffer_size": 1,
             "sensor_groups": [],
-            "pre_ignite_time": 0,
+            "pre_ignite_time": 100,
             "post_ignite_time": 0,
             "drivers": [{
                 "label": "OXI_FILL",
@@ -326,6 +4280,13 @@ mod tests {
                     "driver_id": 0,
                     "value": true
                 },
+                {
+                    "type": "Sleep",
+                    "duration": {
+                        "secs": 0,
+                        "nanos": 100000000
+                    }
+                },
                 {
                     "type": "Actuate",
                     "driver_id": 0,
@@ -341,107 +4302,478 @@ mod tests {
             "pin_heartbeat": 0
         }"#;
 
-        let mut cfg_cursor = Cursor::new(config);
-        let config = Configuration::parse(&mut cfg_cursor).unwrap();
-        let driver_lines = Mutex::new(vec![ListenerPin::new(false)]);
+        Configuration::parse(&mut Cursor::new(config)).unwrap()
+    }
+
+    #[test]
+    /// Test that a held ignition sequence does not advance until it is resumed.
+    fn hold_and_resume() {
+        let config = hold_config();
+        let driver_lines = PriorityMutex::new(vec![ListenerPin::new(false)]);
         let state = Guard::new(State::Standby);
+        let hold = Hold::new();
+        assert!(matches!(hold.hold(&state), Err(Error::NothingToHold)));
+
+        scope(|s| {
+            let handle = s.spawn(|| {
+                ignition(
+                    &config,
+                    &driver_lines,
+                    &state,
+                    &hold,
+                    &History::new(&config),
+                )
+            });
 
-        ignition(&config, &driver_lines, &state).unwrap();
+            // place the hold during pre-ignition, before the sequence starts
+            sleep(Duration::from_millis(50));
+            hold.hold(&state).unwrap();
+
+            sleep(Duration::from_millis(300));
+            assert_eq!(state.status().unwrap(), State::Ignite);
+            assert_eq!(driver_lines.lock().unwrap()[0].history(), &[false]);
+
+            hold.resume().unwrap();
+            handle.join().unwrap().unwrap();
+        });
 
         assert_eq!(
-            driver_lines.lock().unwrap()[0].history().as_slice(),
-            [false, true, false]
+            driver_lines.lock().unwrap()[0].history(),
+            &[false, true, false]
         );
+        assert_eq!(state.status().unwrap(), State::Standby);
     }
 
     #[test]
-    /// Test that the correct sequence of state transistions are performed during an emergency stop.
-    fn estop_state_transitions() {
-        let config = r#"{
-            "frequency_status": 1,
-            "log_buffer_size": 1,
-            "sensor_groups": [],
-            "pre_ignite_time": 500,
-            "post_ignite_time": 500,
-            "drivers": [],
-            "ignition_sequence": [],
-            "estop_sequence": [
-                {
-                    "type": "Sleep",
-                    "duration": {
-                        "secs": 0,
-                        "nanos": 500000000
-                    }
-                }
-            ],
-            "spi_mosi": 11,
-            "spi_miso": 12,
-            "spi_clk": 13,
-            "spi_frequency_clk": 50000,
-            "adc_cs": [],
-            "pin_heartbeat": 0
-        }"#;
+    /// Test that an emergency stop overrides a hold, abandoning the held sequence.
+    fn estop_overrides_hold() {
+        let config = hold_config();
+        let driver_lines = PriorityMutex::new(vec![ListenerPin::new(false)]);
+        let state = Guard::new(State::Standby);
+        let hold = Hold::new();
 
-        let mut cfg_cursor = Cursor::new(config);
-        let config = Configuration::parse(&mut cfg_cursor).unwrap();
+        scope(|s| {
+            let handle = s.spawn(|| {
+                ignition(
+                    &config,
+                    &driver_lines,
+                    &state,
+                    &hold,
+                    &History::new(&config),
+                )
+            });
 
-        let driver_lines = Mutex::new(Vec::<ListenerPin>::new());
+            sleep(Duration::from_millis(50));
+            hold.hold(&state).unwrap();
+            sleep(Duration::from_millis(100));
+
+            emergency_stop(&config, &driver_lines, &state, &UserLog::new(Vec::new())).unwrap();
+            assert!(matches!(
+                handle.join().unwrap(),
+                Err(Error::SequenceAborted)
+            ));
+        });
+
+        assert_eq!(driver_lines.lock().unwrap()[0].history(), &[false]);
+    }
 
+    #[test]
+    /// Test that beginning an ignition releases a hold left over from an aborted one, but that an
+    /// ignition which is rejected leaves the hold on the running ignition in place.
+    fn begin_ignition_releases_hold() {
         let state = Guard::new(State::Standby);
-        let state_ref = &state;
+        let hold = Hold::new();
+        hold.begin_ignition(&state).unwrap();
+        hold.hold(&state).unwrap();
 
-        scope(|s| {
-            s.spawn(move || emergency_stop(&config, &driver_lines, state_ref).unwrap());
+        assert!(matches!(
+            hold.begin_ignition(&state),
+            Err(Error::State(state::Error::IllegalTransition { .. }))
+        ));
+        assert!(hold.is_held().unwrap());
 
-            sleep(Duration::from_millis(250));
-            assert_eq!(state.status().unwrap(), State::EStopping);
+        // the ignition is aborted while held
+        state.move_to(State::EStopping).unwrap();
+        state.move_to(State::Standby).unwrap();
+        hold.begin_ignition(&state).unwrap();
+        assert!(!hold.is_held().unwrap());
+    }
 
-            sleep(Duration::from_millis(500));
-            assert_eq!(state.status().unwrap(), State::Standby);
+    #[test]
+    /// Test that a test fire energizes its driver and always releases it by the deadline, and that
+    /// test fires which are too long or outside of standby are rejected.
+    fn test_fire_released() {
+        let mut h = Harness::new(
+            ConfigBuilder::new()
+                .with("drivers", json!([driver("OXI_FILL", 21)]))
+                .build(),
+        );
+        let test_fire = |h: &Harness, max_ms| {
+            h.run(&Command::TestFire {
+                driver_id: 0,
+                max_ms,
+            })
+        };
+
+        assert!(matches!(
+            test_fire(&h, TEST_FIRE_CEILING_MS + 1),
+            Err(Error::TestFireTooLong(_))
+        ));
+        h.state = Guard::new(State::Ignite);
+        assert!(matches!(
+            test_fire(&h, 50),
+            Err(Error::TestFireOutsideStandby(State::Ignite))
+        ));
+        assert_eq!(h.driver_lines.lock().unwrap()[0].history(), &[false]);
+
+        h.state = Guard::new(State::Standby);
+        for _ in 0..3 {
+            let start = Instant::now();
+            scope(|s| {
+                s.spawn(|| test_fire(&h, 50).unwrap());
+                sleep(Duration::from_millis(25));
+                assert!(h.driver_lines.lock().unwrap()[0].read().unwrap());
+            });
+            let elapsed = start.elapsed();
+            assert!(!h.driver_lines.lock().unwrap()[0].read().unwrap());
+            assert!(elapsed >= Duration::from_millis(50));
+            assert!(elapsed < Duration::from_millis(50) + TEST_FIRE_POLL_PERIOD * 10);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    /// Test that a leak check compares how far its sensor decays over the hold time against the
+    /// limit, and reports the result to the dashboard.
+    fn leak_check_result() {
+        let mut h = Harness::new(ConfigBuilder::new().group("FAST", &[sensor("PT1")]).build());
+        // a simulated pressure transducer, read at `millis` after the epoch
+        let read = |h: &Harness, value, millis| {
+            h.read(
+                0,
+                0,
+                value,
+                SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+            );
+        };
+        let check = |h: &Harness, max_decay| {
+            h.run(&Command::LeakCheck {
+                sensor: SensorRef::Label {
+                    sensor: "PT1".into(),
+                },
+                hold_seconds: 1,
+                max_decay,
+            })
+        };
+
+        assert!(matches!(check(&h, 10.0), Err(Error::NoReading)));
+        read(&h, 100.0, 0);
+        h.state = Guard::new(State::Ignite);
+        assert!(matches!(
+            check(&h, 10.0),
+            Err(Error::LeakCheckOutsideStandby(State::Ignite))
+        ));
+        h.state = Guard::new(State::Standby);
+
+        // the pressure falls by 5 psi over 2 seconds, within the limit
+        scope(|s| {
+            s.spawn(|| {
+                sleep(Duration::from_millis(200));
+                read(&h, 95.0, 2000);
+            });
+            check(&h, 10.0).unwrap();
+        });
+        // then by 15 psi over the next 2 seconds, beyond it
+        scope(|s| {
+            s.spawn(|| {
+                sleep(Duration::from_millis(200));
+                read(&h, 80.0, 4000);
+            });
+            check(&h, 10.0).unwrap();
         });
+
+        let results = h.capture.messages();
+        assert_eq!(h.capture.types(), ["LeakCheck", "LeakCheck"]);
+        assert_eq!(results[0]["initial_value"], 100.0);
+        assert_eq!(results[0]["final_value"], 95.0);
+        assert_eq!(results[0]["decay_rate"], 2.5);
+        assert_eq!(results[0]["passed"], true);
+        assert_eq!(results[1]["decay_rate"], 7.5);
+        assert_eq!(results[1]["passed"], false);
     }
 
     #[test]
-    /// Test that driver actuations are performed correctly during emergency stop.
-    fn estop_actuation() {
-        let config = r#"{
+    #[allow(clippy::float_cmp)]
+    /// Test that two reference points calibrate a sensor, and that the calibration is applied, sent
+    /// to the dashboard, and saved so that it is restored when the configuration is next loaded.
+    fn calibrate_point() {
+        let path = std::env::temp_dir().join(format!("slonk_calibration_{}", std::process::id()));
+        let mut h = Harness::new(
+            ConfigBuilder::new()
+                .group("FAST", &[sensor("PT1")])
+                .with("calibration_file", json!(path))
+                .build(),
+        );
+        let read = |h: &Harness, reading| {
+            h.history
+                .push(
+                    0,
+                    SensorReading {
+                        sensor_id: 0,
+                        reading,
+                        value: f64::from(reading),
+                        time: SystemTime::UNIX_EPOCH,
+                        offset_ns: None,
+                        saturated: false,
+                        stale: false,
+                    },
+                )
+                .unwrap();
+        };
+        let calibrate = |h: &Harness, known_value| {
+            h.run(&Command::CalibratePoint {
+                group_id: 0,
+                sensor_id: 0,
+                known_value,
+            })
+        };
+
+        assert!(matches!(calibrate(&h, 0.0), Err(Error::NoReading)));
+        read(&h, 1000);
+        h.state = Guard::new(State::Ignite);
+        assert!(matches!(
+            calibrate(&h, 0.0),
+            Err(Error::CalibrationOutsideStandby(State::Ignite))
+        ));
+        h.state = Guard::new(State::Standby);
+        calibrate(&h, 0.0).unwrap();
+        assert!(matches!(
+            calibrate(&h, 100.0),
+            Err(Error::DegenerateCalibration(1000))
+        ));
+        read(&h, 3000);
+        calibrate(&h, 100.0).unwrap();
+
+        let tuning = h.tunings.read().unwrap()[0][0];
+        assert_eq!(tuning.calibration_slope, 0.05);
+        assert_eq!(tuning.calibration_intercept, -50.0);
+        let message = &h.capture.messages()[0];
+        assert_eq!(message["type"], "Calibration");
+        assert_eq!(message["calibration_slope"], 0.05);
+
+        let mut reloaded =
+            Configuration::parse(&mut Cursor::new(serde_json::to_string(&h.config).unwrap()))
+                .unwrap();
+        assert_eq!(calibration::apply_saved(&mut reloaded).unwrap(), 1);
+        assert_eq!(reloaded.sensor_groups[0].sensors[0].calibration_slope, 0.05);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    /// Test that a sensor's range can be changed in standby, and that empty ranges are rejected.
+    fn set_threshold() {
+        let mut pt1 = sensor("PT1");
+        pt1["range"] = json!([0, 500]);
+        let mut h = Harness::new(ConfigBuilder::new().group("FAST", &[pt1]).build());
+        let set_threshold = |h: &Harness, sensor_id, min, max| {
+            h.run(&Command::SetThreshold {
+                group_id: 0,
+                sensor_id,
+                min,
+                max,
+            })
+        };
+
+        assert!(matches!(
+            set_threshold(&h, 0, 100.0, 100.0),
+            Err(Error::BadThreshold(..))
+        ));
+        assert!(matches!(
+            set_threshold(&h, 0, 0.0, f64::NAN),
+            Err(Error::BadThreshold(..))
+        ));
+        assert!(matches!(
+            set_threshold(&h, 1, 0.0, 800.0),
+            Err(Error::SensorOutOfBounds)
+        ));
+        h.state = Guard::new(State::Ignite);
+        assert!(matches!(
+            set_threshold(&h, 0, 0.0, 800.0),
+            Err(Error::ThresholdOutsideStandby(State::Ignite))
+        ));
+        assert_eq!(h.tunings.read().unwrap()[0][0].range, Some((0.0, 500.0)));
+
+        h.state = Guard::new(State::Standby);
+        set_threshold(&h, 0, -10.0, 800.0).unwrap();
+        assert_eq!(h.tunings.read().unwrap()[0][0].range, Some((-10.0, 800.0)));
+        let message = &h.capture.messages()[0];
+        assert_eq!(message["type"], "Threshold");
+        assert_eq!(message["max"], 800.0);
+    }
+
+    #[test]
+    /// Test that an ignition is rejected during the cooldown after an emergency stop, and while a
+    /// sensor is still out of range once the cooldown is over.
+    fn ignition_cooldown() {
+        let mut pt_tank = sensor("PT_TANK");
+        pt_tank["range"] = json!([0, 500]);
+        let mut h = Harness::new(
+            ConfigBuilder::new()
+                .group("TANK", &[pt_tank])
+                .with("estop_cooldown_ms", json!(60000))
+                .build(),
+        );
+        h.state.move_to(State::EStopping).unwrap();
+        h.state.move_to(State::Standby).unwrap();
+        h.read(0, 0, 650.0, SystemTime::now());
+
+        assert!(matches!(
+            h.run(&Command::Ignition),
+            Err(Error::CoolingDown(_))
+        ));
+
+        // the cooldown is over, but the sensor which tripped the emergency stop is still high
+        h.config.estop_cooldown_ms = Some(0);
+        assert!(matches!(
+            h.run(&Command::Ignition),
+            Err(Error::StillOutOfRange(label, value)) if label == "PT_TANK" && value > 500.0
+        ));
+        assert_eq!(h.state.status().unwrap(), State::Standby);
+    }
+
+    #[test]
+    /// Test that a driver can be moved to a free pin, and that a move to a pin which is in use or
+    /// cannot be acquired leaves the driver on its old pin.
+    fn remap_driver() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
             "frequency_status": 1,
             "log_buffer_size": 1,
             "sensor_groups": [],
             "pre_ignite_time": 0,
             "post_ignite_time": 0,
-            "drivers": [],
-            "ignition_sequence": [],
-            "estop_sequence": [
+            "drivers": [
                 {
-                    "type": "Actuate",
-                    "driver_id": 0,
-                    "value": true
+                    "label": "OXI_FILL",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 20,
+                    "protected": false
                 },
                 {
-                    "type": "Actuate",
-                    "driver_id": 0,
-                    "value": false
+                    "label": "VENT",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
                 }
             ],
+            "ignition_sequence": [],
+            "estop_sequence": [],
             "spi_mosi": 11,
             "spi_miso": 12,
             "spi_clk": 13,
             "spi_frequency_clk": 50000,
             "adc_cs": [],
-            "pin_heartbeat": 0
-        }"#;
+            "pin_heartbeat": 4
+        }"#,
+        ))
+        .unwrap();
+        let driver_lines =
+            PriorityMutex::new(vec![ListenerPin::new(true), ListenerPin::new(false)]);
+        driver_lines.lock().unwrap()[0].write(false).unwrap();
 
-        let mut cfg_cursor = Cursor::new(config);
-        let config = Configuration::parse(&mut cfg_cursor).unwrap();
-        let driver_lines = Mutex::new(vec![ListenerPin::new(false)]);
-        let state = Guard::new(State::Standby);
+        let failing = Rewiring::<ListenerPin>::new(&config, |_, _| {
+            Err(ControllerError::Hardware("line busy"))
+        });
+        assert!(matches!(
+            failing.remap(&config, &driver_lines, 0, 25),
+            Err(Error::PinUnavailable(25, _))
+        ));
 
-        emergency_stop(&config, &driver_lines, &state).unwrap();
+        let rewiring = Rewiring::new(&config, |_, level| Ok(ListenerPin::new(level)));
+        for pin in [1, 4, 11, 21] {
+            assert!(matches!(
+                rewiring.remap(&config, &driver_lines, 0, pin),
+                Err(Error::PinInUse(p)) if p == pin
+            ));
+        }
+        assert!(matches!(
+            rewiring.remap(&config, &driver_lines, 2, 25),
+            Err(Error::DriverOutOfBounds)
+        ));
+        assert_eq!(driver_lines.lock().unwrap()[0].history(), &[true, false]);
 
-        assert_eq!(
-            driver_lines.lock().unwrap()[0].history().as_slice(),
-            [false, true, false]
+        // the driver keeps its level on the new pin, and its old pin is free again
+        assert_eq!(rewiring.remap(&config, &driver_lines, 0, 25).unwrap(), 20);
+        assert_eq!(driver_lines.lock().unwrap()[0].history(), &[false]);
+        assert_eq!(rewiring.remap(&config, &driver_lines, 1, 20).unwrap(), 21);
+
+        // a driver whose line cannot be read is not moved
+        let dead_lines = PriorityMutex::new(vec![DeadPin, DeadPin]);
+        let rewiring = Rewiring::new(&config, |_, _| Ok(DeadPin));
+        assert!(matches!(
+            rewiring.remap(&config, &dead_lines, 1, 25),
+            Err(Error::DriverLine(1, _))
+        ));
+        assert_eq!(rewiring.pins.lock().unwrap()[1], 21);
+    }
+
+    /// A driver pin whose line fails every read and write.
+    struct DeadPin;
+
+    impl GpioPin for DeadPin {
+        fn read(&mut self) -> Result<bool, gpio_cdev::Error> {
+            Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe).into())
+        }
+
+        fn write(&mut self, _: bool) -> Result<(), gpio_cdev::Error> {
+            Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe).into())
+        }
+    }
+
+    impl Rewire<DeadPin> for DeadPin {
+        fn rewire(&mut self, line: DeadPin) -> DeadPin {
+            std::mem::replace(self, line)
+        }
+    }
+
+    #[test]
+    /// Test that a sequence is rejected while another running sequence controls one of its
+    /// drivers, and that sequences on disjoint drivers may run at the same time unless the policy
+    /// is exclusive.
+    fn concurrent_sequences() {
+        let mut h = Harness::new(
+            ConfigBuilder::new()
+                .with(
+                    "drivers",
+                    json!([driver("OXI_FILL", 21), driver("OXI_VENT", 22)]),
+                )
+                .build(),
         );
+        assert_eq!(h.config.sequence_policy, SequencePolicy::Disjoint);
+        let test_fire = |h: &Harness, driver_id| {
+            h.run(&Command::TestFire {
+                driver_id,
+                max_ms: 50,
+            })
+        };
+
+        let claim = h.sequences.begin(vec![0]).unwrap();
+        assert!(matches!(test_fire(&h, 0), Err(Error::DriverClaimed(0))));
+        test_fire(&h, 1).unwrap();
+        drop(claim);
+        test_fire(&h, 0).unwrap();
+
+        h.config.sequence_policy = SequencePolicy::Exclusive;
+        h.sequences = Sequences::new(&h.config);
+        let claim = h.sequences.begin(vec![0]).unwrap();
+        assert!(matches!(
+            h.sequences.begin(vec![1]),
+            Err(Error::SequenceRunning)
+        ));
+        assert!(matches!(test_fire(&h, 1), Err(Error::SequenceRunning)));
+        drop(claim);
+        test_fire(&h, 1).unwrap();
     }
 }
@@ -0,0 +1,302 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Arithmetic expressions over sensor readings, used to define virtual sensors.
+//!
+//! An expression combines numbers and the calibrated values of sensors with `+`, `-`, `*`, `/`,
+//! unary minus, and parentheses, with the usual precedence.
+//! A sensor is named by its label in square brackets, so that labels may contain spaces and
+//! punctuation: for instance, `[PT3: Injector] - [PT1: Combustion chamber]`.
+
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+#[derive(Clone, Debug, PartialEq)]
+/// A parsed expression, ready to be evaluated against a set of sensor values.
+pub enum Expression {
+    /// A constant.
+    Number(f64),
+    /// The value of a sensor.
+    /// The value inside this variant is the index of the sensor in the list of names the
+    /// expression was parsed against.
+    Sensor(usize),
+    /// The negation of an expression.
+    Negate(Box<Expression>),
+    /// A binary operation on two expressions.
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A binary arithmetic operator.
+pub enum Operator {
+    /// Addition.
+    Add,
+    /// Subtraction.
+    Subtract,
+    /// Multiplication.
+    Multiply,
+    /// Division.
+    Divide,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+/// The ways in which an expression can fail to parse.
+pub enum Error {
+    /// A character which cannot begin any part of an expression was found.
+    /// The values inside this variant are the character and its byte offset.
+    Unexpected(char, usize),
+    /// The expression ended where a number, sensor, or parenthesis was expected.
+    UnexpectedEnd,
+    /// A sensor's label was opened with `[` but never closed.
+    UnclosedLabel,
+    /// A parenthesis was opened but never closed.
+    UnclosedParenthesis,
+    /// A number could not be parsed.
+    /// The string is the offending text.
+    BadNumber(String),
+    /// A sensor was named which is not in the list of known sensors.
+    /// The string is the label of the sensor.
+    NoSuchSensor(String),
+}
+
+impl Expression {
+    /// Parse `source` into an expression.
+    /// Each sensor named in `source` must be one of `names`, and is referred to by its index in
+    /// `names`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `source` is not a well-formed expression or names an
+    /// unknown sensor.
+    pub fn parse<'a>(
+        source: &str,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Expression, Error> {
+        let mut parser = Parser {
+            source,
+            chars: source.char_indices().peekable(),
+            names: names.into_iter().collect(),
+        };
+        let expression = parser.sum()?;
+        match parser.next_token() {
+            None => Ok(expression),
+            Some((idx, c)) => Err(Error::Unexpected(c, idx)),
+        }
+    }
+
+    #[must_use]
+    /// Evaluate this expression, given that the value of the sensor at index `i` of the names it
+    /// was parsed against is `values[i]`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `values` is too short to contain every sensor named by the
+    /// expression.
+    pub fn evaluate(&self, values: &[f64]) -> f64 {
+        match self {
+            Expression::Number(n) => *n,
+            Expression::Sensor(idx) => values[*idx],
+            Expression::Negate(inner) => -inner.evaluate(values),
+            Expression::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(values), rhs.evaluate(values));
+                match op {
+                    Operator::Add => lhs + rhs,
+                    Operator::Subtract => lhs - rhs,
+                    Operator::Multiply => lhs * rhs,
+                    Operator::Divide => lhs / rhs,
+                }
+            }
+        }
+    }
+
+    #[must_use]
+    /// Get the indices of every sensor named by this expression, in no particular order.
+    pub fn sensors(&self) -> Vec<usize> {
+        match self {
+            Expression::Number(_) => Vec::new(),
+            Expression::Sensor(idx) => vec![*idx],
+            Expression::Negate(inner) => inner.sensors(),
+            Expression::Binary(_, lhs, rhs) => {
+                let mut sensors = lhs.sensors();
+                sensors.extend(rhs.sensors());
+                sensors
+            }
+        }
+    }
+}
+
+/// A recursive-descent parser for expressions.
+struct Parser<'a, 'b> {
+    /// The full text being parsed.
+    source: &'a str,
+    /// The characters which have not yet been consumed.
+    chars: Peekable<CharIndices<'a>>,
+    /// The labels of the sensors which may be named.
+    names: Vec<&'b str>,
+}
+
+impl Parser<'_, '_> {
+    /// Skip any whitespace, then peek at the next character.
+    fn next_token(&mut self) -> Option<(usize, char)> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    /// Parse a sequence of terms joined by `+` or `-`.
+    fn sum(&mut self) -> Result<Expression, Error> {
+        let mut lhs = self.product()?;
+        loop {
+            let op = match self.next_token() {
+                Some((_, '+')) => Operator::Add,
+                Some((_, '-')) => Operator::Subtract,
+                _ => return Ok(lhs),
+            };
+            self.chars.next();
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+    }
+
+    /// Parse a sequence of factors joined by `*` or `/`.
+    fn product(&mut self) -> Result<Expression, Error> {
+        let mut lhs = self.factor()?;
+        loop {
+            let op = match self.next_token() {
+                Some((_, '*')) => Operator::Multiply,
+                Some((_, '/')) => Operator::Divide,
+                _ => return Ok(lhs),
+            };
+            self.chars.next();
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(self.factor()?));
+        }
+    }
+
+    /// Parse a number, a sensor, a parenthesized expression, or the negation of any of these.
+    fn factor(&mut self) -> Result<Expression, Error> {
+        let (start, c) = self.next_token().ok_or(Error::UnexpectedEnd)?;
+        match c {
+            '-' => {
+                self.chars.next();
+                Ok(Expression::Negate(Box::new(self.factor()?)))
+            }
+            '(' => {
+                self.chars.next();
+                let inner = self.sum()?;
+                match self.next_token() {
+                    Some((_, ')')) => {
+                        self.chars.next();
+                        Ok(inner)
+                    }
+                    _ => Err(Error::UnclosedParenthesis),
+                }
+            }
+            '[' => {
+                self.chars.next();
+                let label_start = start + 1;
+                loop {
+                    match self.chars.next() {
+                        Some((end, ']')) => {
+                            let label = &self.source[label_start..end];
+                            return self
+                                .names
+                                .iter()
+                                .position(|&name| name == label)
+                                .map(Expression::Sensor)
+                                .ok_or_else(|| Error::NoSuchSensor(label.to_string()));
+                        }
+                        Some(_) => (),
+                        None => return Err(Error::UnclosedLabel),
+                    }
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = start;
+                // an exponent may be signed, as in `1e-3`
+                let mut previous = c;
+                while let Some((idx, c)) = self.chars.next_if(|&(_, c)| {
+                    c.is_ascii_digit()
+                        || matches!(c, '.' | 'e' | 'E')
+                        || (matches!(c, '+' | '-') && matches!(previous, 'e' | 'E'))
+                }) {
+                    end = idx + c.len_utf8();
+                    previous = c;
+                }
+                let text = &self.source[start..end];
+                text.parse()
+                    .map(Expression::Number)
+                    .map_err(|_| Error::BadNumber(text.to_string()))
+            }
+            c => Err(Error::Unexpected(c, start)),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unexpected(c, idx) => write!(f, "unexpected character '{c}' at offset {idx}"),
+            Error::UnexpectedEnd => write!(f, "the expression ended unexpectedly"),
+            Error::UnclosedLabel => write!(f, "a sensor label was not closed with ']'"),
+            Error::UnclosedParenthesis => write!(f, "a parenthesis was not closed"),
+            Error::BadNumber(text) => write!(f, "{text} is not a number"),
+            Error::NoSuchSensor(label) => write!(f, "there is no sensor labelled {label}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that operators bind with the usual precedence.
+    fn precedence() {
+        let expression = Expression::parse("1 + 2 * -3 - (4 - 6) / 4", []).unwrap();
+        assert!((expression.evaluate(&[]) - -4.5).abs() < 1e-12);
+    }
+
+    #[test]
+    /// Test that a number's exponent may be signed, and that a sign after a number without an
+    /// exponent is still an operator.
+    fn exponents() {
+        let expression = Expression::parse("1e-3 + 2E+2 - 3e1-1", []).unwrap();
+        assert!((expression.evaluate(&[]) - 169.001).abs() < 1e-9);
+    }
+
+    #[test]
+    /// Test that malformed expressions and unknown sensors are rejected.
+    fn parse_errors() {
+        let names = ["a", "b"];
+        assert_eq!(Expression::parse("[a] +", names), Err(Error::UnexpectedEnd));
+        assert_eq!(
+            Expression::parse("([a]", names),
+            Err(Error::UnclosedParenthesis)
+        );
+        assert_eq!(Expression::parse("[a", names), Err(Error::UnclosedLabel));
+        assert_eq!(
+            Expression::parse("[c]", names),
+            Err(Error::NoSuchSensor("c".into()))
+        );
+        assert_eq!(
+            Expression::parse("[a] [b]", names),
+            Err(Error::Unexpected('[', 4))
+        );
+        assert_eq!(
+            Expression::parse("1.2.3", names),
+            Err(Error::BadNumber("1.2.3".into()))
+        );
+    }
+}
@@ -0,0 +1,163 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Reading sensors through the Linux Industrial I/O (IIO) subsystem.
+//!
+//! The kernel exposes each channel of an IIO-supported ADC as a set of attribute files under
+//! `/sys/bus/iio/devices`.
+//! Reading a channel's raw attribute, such as `in_voltage0_raw`, performs a conversion and returns
+//! its result as decimal text.
+//! The channel's scale is read once, from its `_scale` attribute (such as `in_voltage0_scale`) or
+//! else from the scale shared by its type (such as `in_voltage_scale`), and applied before the
+//! sensor's linear calibration.
+
+use std::{
+    fs::{read_to_string, File},
+    io::{ErrorKind, Read, Seek},
+    path::Path,
+};
+
+use crate::ControllerError;
+
+use super::Adc;
+
+/// A single channel of an IIO ADC.
+pub struct IioChannel {
+    /// The channel's raw attribute file.
+    file: File,
+    /// A buffer for the text of the most recent reading, kept to avoid allocating on every read.
+    buffer: String,
+    /// The value of one raw count, or 1 if the channel has no scale.
+    scale: f64,
+}
+
+impl IioChannel {
+    /// Open the IIO channel whose raw attribute file is at `path`, and read its scale.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the attribute file cannot be opened, or if the
+    /// channel has a scale which cannot be read.
+    pub fn open(path: &Path) -> Result<IioChannel, ControllerError> {
+        Ok(IioChannel {
+            file: File::open(path)?,
+            buffer: String::new(),
+            scale: read_scale(path)?,
+        })
+    }
+}
+
+/// Read the scale of the IIO channel whose raw attribute file is at `path`.
+/// Returns 1 if the channel has no scale attribute.
+///
+/// # Errors
+///
+/// This function will return an error if a scale attribute exists but cannot be read or parsed.
+fn read_scale(path: &Path) -> Result<f64, ControllerError> {
+    let Some(prefix) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix("_raw"))
+    else {
+        return Ok(1.0);
+    };
+    // a channel's own scale takes precedence over the scale shared by every channel of its type
+    let shared_prefix = prefix.trim_end_matches(|c: char| c.is_ascii_digit());
+    for candidate in [prefix, shared_prefix] {
+        match read_to_string(path.with_file_name(format!("{candidate}_scale"))) {
+            Ok(text) => {
+                return text
+                    .trim()
+                    .parse()
+                    .map_err(|_| ControllerError::Hardware("IIO channel has a malformed scale"));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(1.0)
+}
+
+impl Adc for IioChannel {
+    /// Read the channel.
+    /// An IIO channel is identified entirely by its attribute file, so `channel` is ignored.
+    fn read(&mut self, _: u8) -> Result<u16, ControllerError> {
+        // each new value of an attribute can only be read from the start of its file
+        self.file.rewind()?;
+        self.buffer.clear();
+        self.file.read_to_string(&mut self.buffer)?;
+        self.buffer
+            .trim()
+            .parse()
+            .map_err(|_| ControllerError::Hardware("IIO channel returned a malformed raw reading"))
+    }
+
+    fn scale(&self) -> f64 {
+        self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, remove_file, write};
+
+    use super::*;
+
+    #[test]
+    /// Test that each read of an IIO channel returns the current value of its attribute, and that
+    /// a value which is not a raw reading is rejected.
+    fn read_attribute() {
+        let path = std::env::temp_dir().join("slonk_read_attribute_in_voltage0_raw");
+        write(&path, "1234\n").unwrap();
+        let mut channel = IioChannel::open(&path).unwrap();
+        assert_eq!(channel.read(0).unwrap(), 1234);
+
+        write(&path, "3071\n").unwrap();
+        assert_eq!(channel.read(0).unwrap(), 3071);
+
+        write(&path, "-5\n").unwrap();
+        assert!(matches!(channel.read(0), Err(ControllerError::Hardware(_))));
+
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    /// Test that a channel's own scale is preferred over the scale shared by its type, and that a
+    /// channel with neither has a scale of 1.
+    fn read_channel_scale() {
+        let dir = std::env::temp_dir().join("slonk_read_channel_scale");
+        create_dir_all(&dir).unwrap();
+        let raw = dir.join("in_voltage1_raw");
+        write(&raw, "100\n").unwrap();
+        assert!((IioChannel::open(&raw).unwrap().scale() - 1.0).abs() < f64::EPSILON);
+
+        write(dir.join("in_voltage_scale"), "0.5\n").unwrap();
+        assert!((IioChannel::open(&raw).unwrap().scale() - 0.5).abs() < f64::EPSILON);
+
+        write(dir.join("in_voltage1_scale"), "0.125\n").unwrap();
+        assert!((IioChannel::open(&raw).unwrap().scale() - 0.125).abs() < f64::EPSILON);
+
+        write(dir.join("in_voltage1_scale"), "fast\n").unwrap();
+        assert!(matches!(
+            IioChannel::open(&raw),
+            Err(ControllerError::Hardware(_))
+        ));
+
+        remove_dir_all(dir).unwrap();
+    }
+}
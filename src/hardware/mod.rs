@@ -21,13 +21,21 @@
 //! The goal of this module is to abstract away some of the details of exactly how our hardware
 //! works so that we can focus on the business logic elsewhere.
 
+pub mod iio;
 pub mod spi;
 
-use std::time::Duration;
+use std::{
+    io::{Sink, Write},
+    sync::mpsc::Sender,
+    time::{Duration, SystemTime},
+};
 
-use gpio_cdev::LineHandle;
+use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
 
-use crate::ControllerError;
+use crate::{
+    console::{LogLevel, UserLog},
+    ControllerError,
+};
 
 /// A trait for GPIO pins.
 pub trait GpioPin {
@@ -47,6 +55,53 @@ pub trait GpioPin {
     fn write(&mut self, value: bool) -> Result<(), gpio_cdev::Error>;
 }
 
+/// A trait for GPIO chips, from which individual GPIO lines can be requested.
+///
+/// This is primarily used so that hardware acquisition logic can be tested without a real chip.
+pub trait GpioChip {
+    /// The type of pin that this chip hands out.
+    type Pin: GpioPin;
+
+    /// Request exclusive use of GPIO line `line` as an output, initially at logic level `value`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the line does not exist or is already in use.
+    fn request_output(&mut self, line: u8, value: bool) -> Result<Self::Pin, ControllerError>;
+
+    /// Request exclusive use of GPIO line `line` as an input.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the line does not exist or is already in use.
+    fn request_input(&mut self, line: u8) -> Result<Self::Pin, ControllerError>;
+}
+
+/// A trait for driver pins whose underlying GPIO line can be replaced while the controller runs,
+/// such as when a driver is rewired to a spare pin.
+pub trait Rewire<P>: GpioPin {
+    /// Replace the underlying line of this pin with `line`, returning the old line.
+    fn rewire(&mut self, line: P) -> P;
+}
+
+/// A trait for driver pins which remember the logic level they were last commanded to, so that it
+/// can be compared against the level read back from the line.
+pub trait Readback: GpioPin {
+    /// Get the logic level which this pin was last written to.
+    fn commanded(&self) -> bool;
+}
+
+/// The largest raw reading an ADC can return.
+/// All of our ADCs have 12 bits of resolution.
+pub const ADC_MAX_READING: u16 = 0xFFF;
+
+#[must_use]
+/// Convert the raw reading `reading` of an ADC with reference voltage `vref` into the voltage at
+/// the ADC input, in the same units as `vref`.
+pub fn counts_to_volts(reading: u16, vref: f64) -> f64 {
+    vref * f64::from(reading) / f64::from(ADC_MAX_READING + 1)
+}
+
 /// A generic trait for an ADC (Analog-to-Digital Converter).
 ///
 /// This is primarily used for dependency injection testing in other parts of the engine controller.
@@ -60,6 +115,24 @@ pub trait Adc {
     ///
     /// This function will return an error if we are unable to read the ADC value.
     fn read(&mut self, channel: u8) -> Result<u16, ControllerError>;
+
+    /// Get the value of one raw count of this ADC, in the units which its sensors are calibrated
+    /// against.
+    /// Unless an ADC reports its own scale, each count is worth exactly 1.
+    fn scale(&self) -> f64 {
+        1.0
+    }
+
+    /// Write out any raw transactions which this ADC has recorded but not yet written to their
+    /// files.
+    /// An ADC which records nothing has nothing to write out.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to a file fails.
+    fn flush_raw_logs(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 /// A structure for interfacing with the MCP3208 ADC.
@@ -69,9 +142,23 @@ pub trait Adc {
 /// It is the primary ADC used in Rice Eclipse's engine controllers.
 /// For more information, refer to the
 /// [datasheet](https://pdf1.alldatasheet.com/datasheet-pdf/view/74937/MICROCHIP/MCP3208.html).
-pub struct Mcp3208<'a, P: GpioPin> {
+///
+/// # Types
+///
+/// * `P`: the type of the GPIO pins used by the SPI device.
+/// * `W`: the type of the buffer of the log to which each transaction is written.
+/// * `R`: the type of the files to which the raw transactions on each channel are written.
+pub struct Mcp3208<'a, P: GpioPin, W: Write = Sink, R: Write = Sink> {
     /// The SPI device associated with this ADC.
     device: spi::Device<'a, P>,
+    /// The log to which the raw contents of every transaction are written at the debug level, if
+    /// any.
+    log: Option<&'a UserLog<W>>,
+    /// The file to which the raw contents of every transaction on each channel are written, if
+    /// any, indexed by channel.
+    raw_logs: [Option<R>; 8],
+    /// Whether the most recent write to a raw log failed, so that each outage is only logged once.
+    raw_log_failing: bool,
 }
 
 /// Dummy ADC structure for testing.
@@ -125,6 +212,31 @@ pub struct ReturnsNumber(pub u16);
 /// ```
 pub struct ListenerPin(Vec<bool>);
 
+/// A GPIO pin for a driver which reports every change in its logic level as it is written.
+///
+/// Writes which leave the level unchanged are not reported.
+pub struct RecordingPin<P> {
+    /// The underlying pin.
+    pin: P,
+    /// The identifier (i.e. index) of the driver controlled by this pin.
+    driver_id: u8,
+    /// The last known logic level of the pin.
+    level: bool,
+    /// The channel on which changes are reported.
+    changes: Sender<Edge>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A change in the logic level of a driver, as reported by a `RecordingPin`.
+pub struct Edge {
+    /// The identifier (i.e. index) of the driver which changed.
+    pub driver_id: u8,
+    /// The logic level of the driver after the change.
+    pub level: bool,
+    /// The time at which the new level was written.
+    pub time: SystemTime,
+}
+
 impl<'a, P: GpioPin> Mcp3208<'a, P> {
     /// The minimum frequency at which the SPI clock can operate for the MCP3208 to work correctly.
     pub const SPI_MIN_FREQUENCY: u64 = 10_000;
@@ -144,7 +256,56 @@ impl<'a, P: GpioPin> Mcp3208<'a, P> {
             device.clock_period()
                 < Duration::from_micros(1_000_000 / Mcp3208::<P>::SPI_MIN_FREQUENCY)
         );
-        Mcp3208 { device }
+        Mcp3208 {
+            device,
+            log: None,
+            raw_logs: Default::default(),
+            raw_log_failing: false,
+        }
+    }
+}
+
+impl<'a, P: GpioPin, W: Write> Mcp3208<'a, P, W> {
+    #[must_use]
+    /// Construct a new `Mcp3208` which writes the raw contents of every transaction to `log`
+    /// whenever `log` is recording debug information.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic under the same conditions as `Mcp3208::new`.
+    pub fn with_log(device: spi::Device<'a, P>, log: &'a UserLog<W>) -> Mcp3208<'a, P, W> {
+        // reuse the sanity checks performed by `new`
+        let Mcp3208 { device, .. } = Mcp3208::new(device);
+        Mcp3208 {
+            device,
+            log: Some(log),
+            raw_logs: Default::default(),
+            raw_log_failing: false,
+        }
+    }
+
+    #[must_use]
+    /// Write the raw contents of every transaction on each channel to its file in `raw_logs`,
+    /// indexed by channel.
+    /// Channels with no file are not recorded.
+    ///
+    /// Each transaction is written as a CSV row with four columns: the time of the transaction in
+    /// nanoseconds since the UNIX epoch, the bytes sent and the bytes received, each in
+    /// hexadecimal, and the decoded reading.
+    /// The reading is left empty if the transaction was rejected for lacking a null bit.
+    /// For instance, a reading of 2706 on channel 0 at a time of 1 second after the UNIX epoch is
+    /// written as follows:
+    ///
+    /// ```text
+    /// 1000000000,060000,ffea92,2706
+    /// ```
+    pub fn with_raw_logs<R: Write>(self, raw_logs: [Option<R>; 8]) -> Mcp3208<'a, P, W, R> {
+        Mcp3208 {
+            device: self.device,
+            log: self.log,
+            raw_logs,
+            raw_log_failing: false,
+        }
     }
 }
 
@@ -162,7 +323,28 @@ impl ListenerPin {
     }
 }
 
-impl<P: GpioPin> Adc for Mcp3208<'_, P> {
+impl<P: GpioPin> RecordingPin<P> {
+    /// Construct a new `RecordingPin` for the driver with ID `driver_id`, which reports changes in
+    /// the level of `pin` on `changes`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the current level of `pin` cannot be read.
+    pub fn new(
+        mut pin: P,
+        driver_id: u8,
+        changes: Sender<Edge>,
+    ) -> Result<RecordingPin<P>, gpio_cdev::Error> {
+        Ok(RecordingPin {
+            level: pin.read()?,
+            pin,
+            driver_id,
+            changes,
+        })
+    }
+}
+
+impl<P: GpioPin, W: Write, R: Write> Adc for Mcp3208<'_, P, W, R> {
     /// Perform an ADC read on channel `channel`.
     /// Returns the raw 12-bit ADC reading of the channel on the device.
     ///
@@ -201,7 +383,19 @@ impl<P: GpioPin> Adc for Mcp3208<'_, P> {
         assert_eq!(outgoing.len(), incoming.len());
 
         // perform an SPI transfer
+        let transfer_time = SystemTime::now();
         self.device.transfer(&outgoing, &mut incoming)?;
+        let received = incoming;
+
+        // only format the transaction if someone will read it, since this is on the fast path
+        if let Some(log) = self.log.filter(|log| log.enabled(LogLevel::Debug)) {
+            #[allow(unused_must_use)]
+            {
+                log.debug(&format!(
+                    "MCP3208 read on channel {channel}: sent {outgoing:02x?}, received word {incoming:02x?}"
+                ));
+            }
+        }
 
         // First byte received:
         // 8 high-Z values
@@ -215,18 +409,88 @@ impl<P: GpioPin> Adc for Mcp3208<'_, P> {
         // B7..=B0 (low 8 bits of ADC reading)
 
         // Verify that we receieved a null bit (implies the ADC is actually any good)
-        if incoming[1] & 0x10 != 0 {
+        let null_bit = incoming[1] & 0x10 == 0;
+
+        // Mask out high-Z data in incoming bytes
+        incoming[1] &= 0x0F;
+
+        // the back two bytes of `incoming` now have our data in big endian representation.
+        let reading = u16::from_be_bytes([incoming[1], incoming[2]]);
+
+        if let Some(raw_log) = &mut self.raw_logs[usize::from(channel)] {
+            // a failure to record a transaction must not prevent the reading from being used
+            let result = write_raw_transaction(
+                raw_log,
+                transfer_time,
+                &outgoing,
+                &received,
+                null_bit.then_some(reading),
+            );
+            let failing = result.is_err();
+            if failing != self.raw_log_failing {
+                self.raw_log_failing = failing;
+                #[allow(unused_must_use)]
+                if let Some(log) = self.log {
+                    match result {
+                        Ok(()) => log.info("Raw SPI logging recovered"),
+                        Err(e) => log.warn(&format!(
+                            "Unable to log raw SPI transaction on channel {channel}: {e}. Further failures will not be logged until it recovers"
+                        )),
+                    };
+                }
+            }
+        }
+
+        if !null_bit {
             return Err(ControllerError::Hardware(
                 "no null bit received from ADC - is it connected?",
             ));
         }
+        Ok(reading)
+    }
 
-        // Mask out high-Z data in incoming bytes
-        incoming[1] &= 0x0F;
+    fn flush_raw_logs(&mut self) -> std::io::Result<()> {
+        for raw_log in self.raw_logs.iter_mut().flatten() {
+            raw_log.flush()?;
+        }
+        Ok(())
+    }
+}
 
-        // the back two bytes of `incoming` now have our data in big endian representation.
-        Ok(u16::from_be_bytes([incoming[1], incoming[2]]))
+/// Write a single raw SPI transaction to `raw_log` as a CSV row, in the format described by
+/// `Mcp3208::with_raw_logs`.
+///
+/// The row is written with a single call, so that it is never split by a partial write.
+///
+/// # Errors
+///
+/// This function will return an error if writing to `raw_log` fails.
+fn write_raw_transaction(
+    raw_log: &mut impl Write,
+    time: SystemTime,
+    sent: &[u8],
+    received: &[u8],
+    reading: Option<u16>,
+) -> std::io::Result<()> {
+    use std::fmt::Write as _;
+
+    let mut row = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string();
+    for bytes in [sent, received] {
+        row.push(',');
+        for byte in bytes {
+            let _ = write!(row, "{byte:02x}");
+        }
     }
+    row.push(',');
+    if let Some(reading) = reading {
+        let _ = write!(row, "{reading}");
+    }
+    row.push('\n');
+    raw_log.write_all(row.as_bytes())
 }
 
 impl Adc for ReturnsNumber {
@@ -257,6 +521,51 @@ impl GpioPin for ListenerPin {
     }
 }
 
+impl<P: GpioPin> GpioPin for RecordingPin<P> {
+    fn read(&mut self) -> Result<bool, gpio_cdev::Error> {
+        self.pin.read()
+    }
+
+    fn write(&mut self, value: bool) -> Result<(), gpio_cdev::Error> {
+        self.pin.write(value)?;
+        if value != self.level {
+            self.level = value;
+            // nobody may be listening for changes, in which case there is no one to tell
+            let _ = self.changes.send(Edge {
+                driver_id: self.driver_id,
+                level: value,
+                time: SystemTime::now(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Rewire<ListenerPin> for ListenerPin {
+    fn rewire(&mut self, line: ListenerPin) -> ListenerPin {
+        std::mem::replace(self, line)
+    }
+}
+
+impl Readback for ListenerPin {
+    fn commanded(&self) -> bool {
+        *self.0.last().unwrap()
+    }
+}
+
+impl<P: GpioPin> Readback for RecordingPin<P> {
+    fn commanded(&self) -> bool {
+        self.level
+    }
+}
+
+impl<P: GpioPin> Rewire<P> for RecordingPin<P> {
+    fn rewire(&mut self, line: P) -> P {
+        std::mem::replace(&mut self.pin, line)
+    }
+}
+
 impl GpioPin for LineHandle {
     fn read(&mut self) -> Result<bool, gpio_cdev::Error> {
         Ok(1 == self.get_value()?)
@@ -270,19 +579,33 @@ impl GpioPin for LineHandle {
     }
 }
 
+impl GpioChip for Chip {
+    type Pin = LineHandle;
+
+    fn request_output(&mut self, line: u8, value: bool) -> Result<LineHandle, ControllerError> {
+        self.get_line(u32::from(line))
+            .and_then(|handle| handle.request(LineRequestFlags::OUTPUT, u8::from(value), "slonk"))
+            .map_err(|e| ControllerError::GpioLine(line, e, None))
+    }
+
+    fn request_input(&mut self, line: u8) -> Result<LineHandle, ControllerError> {
+        self.get_line(u32::from(line))
+            .and_then(|handle| handle.request(LineRequestFlags::INPUT, 0, "slonk"))
+            .map_err(|e| ControllerError::GpioLine(line, e, None))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::sync::Mutex;
-
     use super::{
-        spi::{Bus, Device},
+        spi::{Bus, Device, FairMutex},
         *,
     };
 
     /// A GPIO spoof pin which reads off a vector of values, and which cannot be written to.
-    struct VectorPin {
-        values: Vec<bool>,
-        index: usize,
+    pub(super) struct VectorPin {
+        pub(super) values: Vec<bool>,
+        pub(super) index: usize,
     }
 
     impl GpioPin for VectorPin {
@@ -300,8 +623,7 @@ mod tests {
     #[test]
     /// Test a successful MCP3208 ADC read with spoofed gpio pins.
     fn mcp3208_read() {
-        let bus = Mutex::new(Bus::<Box<dyn GpioPin>> {
-            period: Duration::from_micros(1),
+        let bus = FairMutex::new(Bus::<Box<dyn GpioPin>> {
             pin_mosi: Box::new(ListenerPin::new(false)),
             pin_miso: Box::new(VectorPin {
                 values: vec![
@@ -334,7 +656,11 @@ mod tests {
             }),
             pin_clk: Box::new(ListenerPin::new(false)),
         });
-        let dev = Device::new(&bus, Box::new(ListenerPin::new(true)));
+        let dev = Device::new(
+            &bus,
+            Box::new(ListenerPin::new(true)),
+            Duration::from_micros(1),
+        );
         let mut adc = Mcp3208::new(dev);
 
         assert_eq!(adc.read(0).unwrap(), 2706);
@@ -343,8 +669,7 @@ mod tests {
     #[test]
     /// Test that reading the ADC fails if the null bit is bad.
     fn mcp3208_bad_null_bit() {
-        let bus = Mutex::new(Bus::<Box<dyn GpioPin>> {
-            period: Duration::from_micros(1),
+        let bus = FairMutex::new(Bus::<Box<dyn GpioPin>> {
             pin_mosi: Box::new(ListenerPin::new(false)),
             pin_miso: Box::new(VectorPin {
                 values: vec![
@@ -377,9 +702,127 @@ mod tests {
             }),
             pin_clk: Box::new(ListenerPin::new(false)),
         });
-        let dev = Device::new(&bus, Box::new(ListenerPin::new(true)));
+        let dev = Device::new(
+            &bus,
+            Box::new(ListenerPin::new(true)),
+            Duration::from_micros(1),
+        );
         let mut adc = Mcp3208::new(dev);
 
         assert!(adc.read(0).is_err());
     }
+
+    #[test]
+    /// Test that a raw log which cannot be written does not prevent reading, and that its failure
+    /// is only logged once.
+    fn mcp3208_raw_log_failure() {
+        let received: [u8; 3] = [0xff, 0xea, 0x92];
+        let bus = FairMutex::new(Bus::<Box<dyn GpioPin>> {
+            pin_mosi: Box::new(ListenerPin::new(false)),
+            pin_miso: Box::new(VectorPin {
+                values: received
+                    .iter()
+                    .flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 1 == 1))
+                    .collect(),
+                index: 0,
+            }),
+            pin_clk: Box::new(ListenerPin::new(false)),
+        });
+        let dev = Device::new(
+            &bus,
+            Box::new(ListenerPin::new(true)),
+            Duration::from_micros(1),
+        );
+        let log = UserLog::new(Sink::default());
+        let (sender, warnings) = std::sync::mpsc::channel();
+        log.subscribe(LogLevel::Warn, sender);
+        // an empty slice refuses every write
+        let mut raw_logs: [Option<&mut [u8]>; 8] = Default::default();
+        raw_logs[0] = Some(&mut []);
+        let mut adc = Mcp3208::with_log(dev, &log).with_raw_logs(raw_logs);
+
+        for _ in 0..3 {
+            assert_eq!(adc.read(0).unwrap(), 2706);
+        }
+        drop(adc);
+        log.unsubscribe();
+
+        assert_eq!(warnings.iter().count(), 1);
+    }
+
+    #[test]
+    /// Test that the raw bytes of each transaction are written to the raw log of its channel, along
+    /// with the decoded reading, even when the transaction is rejected.
+    fn mcp3208_raw_log() {
+        // a good transaction reading 2706, followed by one with a bad null bit
+        let received: [u8; 6] = [0xff, 0xea, 0x92, 0xff, 0xfa, 0x92];
+        let bus = FairMutex::new(Bus::<Box<dyn GpioPin>> {
+            pin_mosi: Box::new(ListenerPin::new(false)),
+            pin_miso: Box::new(VectorPin {
+                values: received
+                    .iter()
+                    .flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 1 == 1))
+                    .collect(),
+                index: 0,
+            }),
+            pin_clk: Box::new(ListenerPin::new(false)),
+        });
+        let dev = Device::new(
+            &bus,
+            Box::new(ListenerPin::new(true)),
+            Duration::from_micros(1),
+        );
+        let mut channel_0 = Vec::new();
+        let mut channel_1 = Vec::new();
+        let mut raw_logs: [Option<&mut Vec<u8>>; 8] = Default::default();
+        raw_logs[0] = Some(&mut channel_0);
+        raw_logs[1] = Some(&mut channel_1);
+        let mut adc = Mcp3208::new(dev).with_raw_logs(raw_logs);
+
+        assert_eq!(adc.read(0).unwrap(), 2706);
+        assert!(adc.read(0).is_err());
+        drop(adc);
+
+        let rows: Vec<Vec<&str>> = std::str::from_utf8(&channel_0)
+            .unwrap()
+            .lines()
+            .map(|row| row.split(',').collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0][0].parse::<u128>().unwrap() > 0);
+        assert_eq!(rows[0][1..], ["060000", "ffea92", "2706"]);
+        assert_eq!(rows[1][1..], ["060000", "fffa92", ""]);
+        assert!(channel_1.is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    /// Test that raw readings are converted to voltages in proportion to the reference voltage.
+    fn volts_from_counts() {
+        assert_eq!(counts_to_volts(0, 3.3), 0.0);
+        assert_eq!(counts_to_volts(2048, 3.3), 1.65);
+        assert_eq!(counts_to_volts(1024, 5.0), 1.25);
+        assert!(counts_to_volts(ADC_MAX_READING, 5.0) < 5.0);
+    }
+
+    #[test]
+    /// Test that a recording pin reports each change in its level, but not writes which leave the
+    /// level unchanged.
+    fn recording_pin_edges() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut pin = RecordingPin::new(ListenerPin::new(false), 3, sender).unwrap();
+
+        pin.write(false).unwrap();
+        pin.write(true).unwrap();
+        pin.write(true).unwrap();
+        pin.write(false).unwrap();
+        assert!(!pin.read().unwrap());
+        drop(pin);
+
+        let edges: Vec<(u8, bool)> = receiver
+            .iter()
+            .map(|edge| (edge.driver_id, edge.level))
+            .collect();
+        assert_eq!(edges, [(3, true), (3, false)]);
+    }
 }
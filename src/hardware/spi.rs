@@ -18,7 +18,12 @@
 
 //! Structures and tools for interfacing via Serial Peripheral Interface (SPI).
 
-use std::{sync::Mutex, thread::sleep, time::Duration};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Condvar, Mutex, MutexGuard},
+    thread::sleep,
+    time::Duration,
+};
 
 use crate::ControllerError;
 
@@ -26,14 +31,12 @@ use super::GpioPin;
 
 /// An SPI bus.
 /// This structure contains enough information to talk on SPI, but contains no device data.
+/// The speed of the clock is chosen by each device, so that a slow device on the bus does not
+/// slow down transfers with every other device.
 pub struct Bus<P: GpioPin> {
-    /// The clock period.
-    /// The clock period is the time between two rising edges on the clock.
-    /// Therefore the length of a pulse (the time between a rising and falling edge) is half this
-    /// period.
-    pub period: Duration,
     /// The clock pin.
-    /// This pin will be actuated on a regular timescale determined by `duration` during a transfer.
+    /// This pin will be actuated on a regular timescale determined by the clock period of each
+    /// device during a transfer.
     pub pin_clk: P,
     /// The Master Output - Slave Input pin.
     /// This pin is used to send messages to slave devices.
@@ -43,40 +46,184 @@ pub struct Bus<P: GpioPin> {
     pub pin_miso: P,
 }
 
+/// A mutual exclusion lock which is granted in the order in which it was requested.
+///
+/// A `Mutex` makes no guarantee of fairness: a thread which releases a `Mutex` and immediately
+/// requests it again may reacquire it ahead of threads which were already waiting.
+/// Every ADC shares a single SPI bus, so reads from different sensor groups cannot overlap.
+/// If the bus were protected by a `Mutex`, a sensor group sampling at a high rate could starve the
+/// others of access to the bus.
+/// A `FairMutex` instead serves every thread in turn.
+///
+/// Devices on separate buses never contend with each other, and can be read truly in parallel.
+pub struct FairMutex<T> {
+    /// The protected value.
+    value: Mutex<T>,
+    /// The next ticket to be handed out, and the ticket whose holder currently has its turn.
+    tickets: Mutex<(u64, u64)>,
+    /// A condition variable notified whenever the turn advances.
+    turn: Condvar,
+}
+
+/// A guard granting access to the value inside a `FairMutex`.
+/// When the guard is dropped, the next thread waiting on the lock is given its turn.
+pub struct FairMutexGuard<'a, T> {
+    /// The lock which this guard came from.
+    lock: &'a FairMutex<T>,
+    /// The guard on the protected value.
+    guard: MutexGuard<'a, T>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// The order in which the bits of each byte are transferred over SPI.
+pub enum BitOrder {
+    /// The most significant bit of each byte is transferred first, as is done by the MCP3208.
+    #[default]
+    MsbFirst,
+    /// The least significant bit of each byte is transferred first.
+    LsbFirst,
+}
+
 /// An SPI device.
 /// This structure is actually a wrapper for a single chip-selection pin for SPI communication.
 pub struct Device<'a, P: GpioPin> {
     /// A reference to the bus that this device lives "inside" of.
-    bus: &'a Mutex<Bus<P>>,
+    bus: &'a FairMutex<Bus<P>>,
     /// The chip selection pin.
     pin_cs: P,
+    /// The clock period used when talking to this device.
+    /// The clock period is the time between two rising edges on the clock.
+    /// Therefore the length of a pulse (the time between a rising and falling edge) is half this
+    /// period.
+    /// Devices with different clock periods may share the same bus.
+    period: Duration,
+    /// The order in which this device transfers the bits of each byte.
+    /// Devices with different bit orders may share the same bus.
+    bit_order: BitOrder,
+    /// The time to wait after asserting chip select before clocking the first bit, giving the
+    /// device's input time to settle.
+    cs_settle: Duration,
+    /// The function used to wait during a transfer.
+    /// This is always `sleep`, except in tests.
+    wait: fn(Duration),
+}
+
+impl<T> FairMutex<T> {
+    #[must_use]
+    /// Construct a new `FairMutex` protecting `value`.
+    pub fn new(value: T) -> FairMutex<T> {
+        FairMutex {
+            value: Mutex::new(value),
+            tickets: Mutex::new((0, 0)),
+            turn: Condvar::new(),
+        }
+    }
+
+    /// Acquire the lock, blocking until every thread which requested it earlier has released it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an internal lock is poisoned.
+    pub fn lock(&self) -> Result<FairMutexGuard<T>, ControllerError> {
+        let mut tickets = self.tickets.lock()?;
+        let ticket = tickets.0;
+        tickets.0 += 1;
+        drop(
+            self.turn
+                .wait_while(tickets, |(_, serving)| *serving != ticket)?,
+        );
+
+        match self.value.lock() {
+            Ok(guard) => Ok(FairMutexGuard { lock: self, guard }),
+            Err(e) => {
+                // give up our turn, so that the threads behind us are not stuck forever
+                self.advance();
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Give the next ticket its turn.
+    fn advance(&self) {
+        if let Ok(mut tickets) = self.tickets.lock() {
+            tickets.1 += 1;
+        }
+        self.turn.notify_all();
+    }
+}
+
+impl<T> Deref for FairMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for FairMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for FairMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // the next thread may briefly block on the value until `guard` is dropped after this
+        self.lock.advance();
+    }
 }
 
 impl<'a, P: GpioPin> Device<'a, P> {
     /// Construct a new device, registering its line with the OS.
+    /// The device is clocked with period `period`, and transfers the most significant bit of each
+    /// byte first.
     ///
     /// # Errors
     ///
     /// This function may return an error if we are unable to acquire the line from the OS.
-    pub fn new(bus: &'a Mutex<Bus<P>>, pin_cs: P) -> Device<'a, P> {
-        Device { bus, pin_cs }
+    pub fn new(bus: &'a FairMutex<Bus<P>>, pin_cs: P, period: Duration) -> Device<'a, P> {
+        Device::with_bit_order(bus, pin_cs, period, BitOrder::MsbFirst)
+    }
+
+    #[must_use]
+    /// Construct a new device which is clocked with period `period` and transfers the bits of each
+    /// byte in the order `bit_order`.
+    pub fn with_bit_order(
+        bus: &'a FairMutex<Bus<P>>,
+        pin_cs: P,
+        period: Duration,
+        bit_order: BitOrder,
+    ) -> Device<'a, P> {
+        Device {
+            bus,
+            pin_cs,
+            period,
+            bit_order,
+            cs_settle: Duration::ZERO,
+            wait: sleep,
+        }
+    }
+
+    #[must_use]
+    /// Make this device wait for `cs_settle` after asserting chip select and before clocking the
+    /// first bit of every transfer.
+    pub fn with_cs_settle(self, cs_settle: Duration) -> Device<'a, P> {
+        Device { cs_settle, ..self }
     }
 
     #[must_use]
     /// Get the clock period of this device.
-    ///
-    /// # Panics
-    ///
-    /// This function may panic if the internal mutex is poisoned.
     pub fn clock_period(&self) -> Duration {
-        self.bus.lock().unwrap().period
+        self.period
     }
 
     /// Perform an SPI transfer operation on this device.
     ///
-    /// This transfer is big-endian, that is, the most significant bit of each byte will be
-    /// transferred first, and the least significant bit of each byte will be transferred last in
-    /// the transmission of the byte.
+    /// The bytes are transferred in the order they appear in the buffers, and the bits of each byte
+    /// are transferred in the bit order of this device.
+    /// For instance, if the bit order is `BitOrder::MsbFirst`, the most significant bit of each byte
+    /// will be transferred first, and the least significant bit of each byte will be transferred
+    /// last in the transmission of the byte.
     ///
     /// # Inputs
     ///
@@ -99,17 +246,24 @@ impl<'a, P: GpioPin> Device<'a, P> {
     ) -> Result<(), ControllerError> {
         assert_eq!(outgoing.len(), incoming.len());
         let mut bus_handle = self.bus.lock()?;
-        let half_period = bus_handle.period / 2;
+        let half_period = self.period / 2;
 
         // pull chip select down to begin talking
         self.pin_cs.write(false)?;
+        if !self.cs_settle.is_zero() {
+            (self.wait)(self.cs_settle);
+        }
 
         for (byte_out, byte_in) in outgoing.iter().zip(incoming.iter_mut()) {
-            // Iterate in reverse because we are performing a big endian transfer
-            for bit_idx in (0..8).rev() {
+            for bit_num in 0..8 {
+                // the index of the bit being transferred, where 0 is the least significant bit
+                let bit_idx = match self.bit_order {
+                    BitOrder::MsbFirst => 7 - bit_num,
+                    BitOrder::LsbFirst => bit_num,
+                };
                 bus_handle.pin_mosi.write((1 << bit_idx & byte_out) != 0)?;
                 // perform half a clock wait
-                sleep(half_period);
+                (self.wait)(half_period);
                 // rising edge on the clock corresponds to read from device
                 bus_handle.pin_clk.write(true)?;
                 // read the incoming bit
@@ -117,7 +271,7 @@ impl<'a, P: GpioPin> Device<'a, P> {
                 *byte_in |= bit_in << bit_idx;
 
                 // perform half a clock wait
-                sleep(half_period);
+                (self.wait)(half_period);
                 // falling edge on the clock corresponds to write to device
                 bus_handle.pin_clk.write(false)?;
             }
@@ -132,19 +286,25 @@ impl<'a, P: GpioPin> Device<'a, P> {
 
 #[cfg(test)]
 mod tests {
-    use crate::hardware::ListenerPin;
+    use std::{
+        cell::RefCell,
+        sync::{Arc, Condvar, Mutex},
+        thread::scope,
+        time::Duration,
+    };
+
+    use crate::hardware::{tests::VectorPin, ListenerPin};
 
     use super::*;
 
     #[test]
     fn transfer_byte_zeros() {
-        let bus = Mutex::new(Bus {
-            period: Duration::from_micros(1),
+        let bus = FairMutex::new(Bus {
             pin_mosi: ListenerPin::new(false),
             pin_miso: ListenerPin::new(true),
             pin_clk: ListenerPin::new(false),
         });
-        let mut dev = Device::new(&bus, ListenerPin::new(true));
+        let mut dev = Device::new(&bus, ListenerPin::new(true), Duration::from_micros(1));
         let mut incoming = [0; 1];
 
         dev.transfer(&[0xAC], &mut incoming).unwrap();
@@ -158,4 +318,253 @@ mod tests {
             &[false, true, false, true, false, true, true, false, false]
         );
     }
+
+    #[test]
+    /// Test that the same stream of bits on the wire is decoded differently under each bit order.
+    fn transfer_bit_order() {
+        let bus = FairMutex::new(Bus::<Box<dyn GpioPin>> {
+            pin_mosi: Box::new(ListenerPin::new(false)),
+            pin_miso: Box::new(VectorPin {
+                values: vec![true, false, true, true, false, false, false, false],
+                index: 0,
+            }),
+            pin_clk: Box::new(ListenerPin::new(false)),
+        });
+
+        let mut incoming = [0; 1];
+        Device::with_bit_order(
+            &bus,
+            Box::new(ListenerPin::new(true)),
+            Duration::from_micros(1),
+            BitOrder::MsbFirst,
+        )
+        .transfer(&[0xAC], &mut incoming)
+        .unwrap();
+        assert_eq!(incoming, [0xB0]);
+
+        let mut incoming = [0; 1];
+        Device::with_bit_order(
+            &bus,
+            Box::new(ListenerPin::new(true)),
+            Duration::from_micros(1),
+            BitOrder::LsbFirst,
+        )
+        .transfer(&[0xAC], &mut incoming)
+        .unwrap();
+        assert_eq!(incoming, [0x0D]);
+    }
+
+    #[test]
+    /// Test that a least-significant-bit-first device sends the low bit of each byte first.
+    fn transfer_lsb_first() {
+        let bus = FairMutex::new(Bus {
+            pin_mosi: ListenerPin::new(false),
+            pin_miso: ListenerPin::new(true),
+            pin_clk: ListenerPin::new(false),
+        });
+        let mut dev = Device::with_bit_order(
+            &bus,
+            ListenerPin::new(true),
+            Duration::from_micros(1),
+            BitOrder::LsbFirst,
+        );
+        let mut incoming = [0; 1];
+
+        dev.transfer(&[0xAC], &mut incoming).unwrap();
+
+        let bus_handle = bus.lock().unwrap();
+        let readout: &[bool] = bus_handle.pin_mosi.history().as_ref();
+        assert_eq!(
+            readout,
+            &[false, false, false, true, true, false, true, false, true]
+        );
+    }
+
+    thread_local! {
+        /// The pin writes and waits performed on this thread, in order.
+        static EVENTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// A pin which records each write to `EVENTS`.
+    struct TracePin(&'static str);
+
+    impl GpioPin for TracePin {
+        fn read(&mut self) -> Result<bool, gpio_cdev::Error> {
+            Ok(false)
+        }
+
+        fn write(&mut self, value: bool) -> Result<(), gpio_cdev::Error> {
+            EVENTS.with(|events| events.borrow_mut().push(format!("{}={value}", self.0)));
+            Ok(())
+        }
+    }
+
+    /// A timer which records each wait to `EVENTS` instead of sleeping.
+    fn trace_wait(duration: Duration) {
+        EVENTS.with(|events| events.borrow_mut().push(format!("wait {duration:?}")));
+    }
+
+    #[test]
+    /// Test that the chip-select settle time is waited out after asserting chip select and before
+    /// the first bit is clocked, and only then.
+    fn cs_settle_delay() {
+        let bus = FairMutex::new(Bus {
+            pin_mosi: TracePin("mosi"),
+            pin_miso: TracePin("miso"),
+            pin_clk: TracePin("clk"),
+        });
+        let period = Duration::from_micros(2);
+        let mut dev =
+            Device::new(&bus, TracePin("cs"), period).with_cs_settle(Duration::from_micros(5));
+        dev.wait = trace_wait;
+
+        dev.transfer(&[0x80], &mut [0]).unwrap();
+
+        let events = EVENTS.with(RefCell::take);
+        assert_eq!(
+            events[..6],
+            [
+                "cs=false",
+                "wait 5µs",
+                "mosi=true",
+                "wait 1µs",
+                "clk=true",
+                "wait 1µs"
+            ]
+        );
+        assert_eq!(events.last().unwrap(), "cs=true");
+        assert_eq!(events.iter().filter(|e| *e == "wait 5µs").count(), 1);
+
+        // without a settle time, the first bit is clocked immediately
+        let mut dev = Device::new(&bus, TracePin("cs"), period);
+        dev.wait = trace_wait;
+        dev.transfer(&[0x80], &mut [0]).unwrap();
+        let events = EVENTS.with(RefCell::take);
+        assert_eq!(events[..2], ["cs=false", "mosi=true"]);
+    }
+
+    #[test]
+    /// Test that a thread which releases a `FairMutex` and immediately requests it again does not
+    /// cut ahead of a thread which was already waiting.
+    fn fair_mutex_order() {
+        let lock = FairMutex::new(());
+        let order = Mutex::new(Vec::new());
+
+        scope(|s| {
+            let guard = lock.lock().unwrap();
+            s.spawn(|| {
+                let _guard = lock.lock().unwrap();
+                order.lock().unwrap().push("waiter");
+            });
+            // give the waiter time to request the lock
+            sleep(Duration::from_millis(50));
+            drop(guard);
+            let _guard = lock.lock().unwrap();
+            order.lock().unwrap().push("releaser");
+        });
+
+        assert_eq!(*order.lock().unwrap(), ["waiter", "releaser"]);
+    }
+
+    /// A count of the devices currently selected, shared between several `OverlapPin`s.
+    #[derive(Default)]
+    struct Overlap {
+        /// The number of devices currently selected, and the most ever selected at once.
+        selected: Mutex<(usize, usize)>,
+        /// A condition variable notified whenever a device is selected.
+        changed: Condvar,
+    }
+
+    /// A pin which, when used for chip select, counts its device as selected in an `Overlap`.
+    /// Pins without an `Overlap` do nothing.
+    struct OverlapPin(Option<Arc<Overlap>>);
+
+    impl GpioPin for OverlapPin {
+        fn read(&mut self) -> Result<bool, gpio_cdev::Error> {
+            Ok(false)
+        }
+
+        fn write(&mut self, value: bool) -> Result<(), gpio_cdev::Error> {
+            if let Some(overlap) = &self.0 {
+                let mut selected = overlap.selected.lock().unwrap();
+                if value {
+                    selected.0 -= 1;
+                } else {
+                    selected.0 += 1;
+                    selected.1 = selected.1.max(selected.0);
+                    overlap.changed.notify_all();
+                    // hold the device selected until the other device is selected too, if it can be
+                    drop(
+                        overlap
+                            .changed
+                            .wait_timeout_while(selected, Duration::from_millis(500), |s| s.0 < 2)
+                            .unwrap(),
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Perform a transfer on each of two devices in parallel, where each device is on the bus at
+    /// the corresponding index of `buses`, and return the most devices which were ever selected at
+    /// once.
+    fn most_selected(buses: [&FairMutex<Bus<OverlapPin>>; 2]) -> usize {
+        let overlap = Arc::new(Overlap::default());
+        scope(|s| {
+            for bus in buses {
+                let overlap = Arc::clone(&overlap);
+                s.spawn(move || {
+                    Device::new(bus, OverlapPin(Some(overlap)), Duration::from_micros(1))
+                        .transfer(&[0xAC], &mut [0])
+                        .unwrap();
+                });
+            }
+        });
+        let most = overlap.selected.lock().unwrap().1;
+        most
+    }
+
+    #[test]
+    /// Test that devices on separate buses transfer at the same time, while devices on a shared
+    /// bus take turns.
+    fn separate_bus_overlap() {
+        let new_bus = || {
+            FairMutex::new(Bus {
+                pin_mosi: OverlapPin(None),
+                pin_miso: OverlapPin(None),
+                pin_clk: OverlapPin(None),
+            })
+        };
+        let (bus_a, bus_b) = (new_bus(), new_bus());
+
+        assert_eq!(most_selected([&bus_a, &bus_b]), 2);
+        assert_eq!(most_selected([&bus_a, &bus_a]), 1);
+    }
+
+    #[test]
+    /// Test that two devices on the same bus are each clocked with their own period.
+    fn per_device_clock() {
+        let bus = FairMutex::new(Bus {
+            pin_mosi: TracePin("mosi"),
+            pin_miso: TracePin("miso"),
+            pin_clk: TracePin("clk"),
+        });
+        let slow_period = Duration::from_millis(2);
+        let mut fast = Device::new(&bus, TracePin("cs"), Duration::from_micros(1));
+        let mut slow = Device::new(&bus, TracePin("cs"), slow_period);
+        fast.wait = trace_wait;
+        slow.wait = trace_wait;
+        assert_eq!(fast.clock_period(), Duration::from_micros(1));
+        assert_eq!(slow.clock_period(), slow_period);
+
+        // every bit takes a full clock period, waited out in two halves, on the shared clock line
+        for (dev, half_period) in [(&mut slow, "wait 1ms"), (&mut fast, "wait 500ns")] {
+            dev.transfer(&[0xAC], &mut [0]).unwrap();
+            let events = EVENTS.with(RefCell::take);
+            let waits: Vec<&String> = events.iter().filter(|e| e.starts_with("wait")).collect();
+            assert_eq!(waits, [half_period; 16]);
+            assert_eq!(events.iter().filter(|e| e.starts_with("clk=")).count(), 16);
+        }
+    }
 }
@@ -21,8 +21,10 @@
 use serde::Deserialize;
 use std::{fmt::Display, io::Read};
 
+use crate::{config::SensorValueFields, console::LogLevel};
+
 #[non_exhaustive]
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Deserialize)]
 #[serde(tag = "type")]
 /// A parsed command received from the controller, which is now ready to be executed.
 pub enum Command {
@@ -35,11 +37,236 @@ pub enum Command {
         /// `true` corresponds to powered (i.e. connected to 12V), while `false` corresponds to
         /// unpowered (high-Z connection or grounding; hardware-decided).
         value: bool,
+        #[serde(default)]
+        /// Whether the operator explicitly confirmed this actuation.
+        /// Actuations of hazardous drivers are rejected unless they are confirmed.
+        confirm: bool,
+    },
+    /// The dashboard requested that several drivers be actuated together.
+    /// Every actuation is checked before any is performed, so either all of them take effect or
+    /// none do.
+    ActuateMany {
+        /// The actuations to perform, in order.
+        /// Each driver may appear at most once.
+        actions: Vec<Actuation>,
+        #[serde(default)]
+        /// Whether the operator explicitly confirmed these actuations.
+        /// Actuations of hazardous drivers are rejected unless they are confirmed.
+        confirm: bool,
+    },
+    /// The operator is still holding the dead man's switch of a driver, which must stay energized.
+    KeepAlive {
+        /// The ID of the driver.
+        driver_id: u8,
+    },
+    /// The dashboard requested that a driver be energized briefly to bench-test it.
+    /// The driver is returned to its safe level after `max_ms`, even if the dashboard disconnects.
+    TestFire {
+        /// The ID of the driver to be test-fired.
+        driver_id: u8,
+        /// The time for which the driver is energized, in milliseconds.
+        max_ms: u32,
+    },
+    /// The dashboard requested the most recent readings the controller holds for a sensor, so that
+    /// it can fill in its graph.
+    History {
+        /// The ID of the group containing the sensor.
+        group_id: u8,
+        /// The ID of the sensor within its group.
+        sensor_id: u8,
+        /// The greatest number of readings to send.
+        count: usize,
+    },
+    /// The operator applied a known reference to a sensor, such as a calibrated pressure source.
+    /// Once two references have been applied to a sensor, its linear calibration is computed from
+    /// them and applied.
+    CalibratePoint {
+        /// The ID of the group containing the sensor.
+        group_id: u8,
+        /// The ID of the sensor within its group.
+        sensor_id: u8,
+        /// The calibrated value which the sensor's latest reading corresponds to.
+        known_value: f64,
+    },
+    /// The operator changed the range of calibrated values outside of which a sensor triggers an
+    /// emergency stop (or its abort action).
+    SetThreshold {
+        /// The ID of the group containing the sensor.
+        group_id: u8,
+        /// The ID of the sensor within its group.
+        sensor_id: u8,
+        /// The lowest calibrated value which does not trigger an emergency stop.
+        min: f64,
+        /// The highest calibrated value which does not trigger an emergency stop.
+        max: f64,
+    },
+    /// The operator moved a driver to a different GPIO pin, such as after rewiring a failed relay
+    /// channel to a spare.
+    RemapDriver {
+        /// The ID of the driver to be moved.
+        driver_id: u8,
+        /// The GPIO pin ID which the driver now uses.
+        new_pin: u8,
+    },
+    /// The operator overrode an interlock, allowing the drivers in it to be energized together
+    /// until it is restored.
+    OverrideInterlock {
+        /// The ID of the interlock to be overridden.
+        interlock_id: u8,
+        #[serde(default)]
+        /// Whether the operator explicitly confirmed this override.
+        /// Overrides are rejected unless they are confirmed.
+        confirm: bool,
+    },
+    /// The operator requested that the thread reading a sensor group be restarted, reusing the
+    /// group's log files and ADCs, without restarting the rest of the controller.
+    RestartGroup {
+        /// The ID of the sensor group to be restarted.
+        group_id: u8,
+        #[serde(default)]
+        /// Whether the operator explicitly confirmed this restart.
+        /// Restarts are rejected unless they are confirmed.
+        confirm: bool,
+    },
+    /// The operator restored an overridden interlock, so that it is enforced again.
+    RestoreInterlock {
+        /// The ID of the interlock to be restored.
+        interlock_id: u8,
     },
     /// The dashboard requested to begin an ignition procedure immediately.
     Ignition,
     /// The dashboard requested to begin an emergency stop immediately.
     EmergencyStop,
+    /// The dashboard requested that the ignition sequence be held at its current step.
+    /// Every driver stays at its current level until the sequence is resumed.
+    Hold,
+    /// The dashboard requested that a held ignition sequence be resumed.
+    Resume,
+    /// The dashboard requested that a synthetic reading be reported for a sensor, as if it had
+    /// come from the ADC.
+    /// This is only permitted if the controller was started with `--allow-injection`.
+    InjectReading {
+        #[serde(flatten)]
+        /// The sensor whose reading will be replaced.
+        sensor: SensorRef,
+        /// The raw ADC value to be reported.
+        reading: u16,
+    },
+    /// The operator requested a leak check: the sensor's value is recorded, and after
+    /// `hold_seconds` the check passes if the value has decayed by no more than `max_decay`.
+    LeakCheck {
+        #[serde(flatten)]
+        /// The sensor (usually a pressure transducer) to be watched.
+        sensor: SensorRef,
+        /// The time, in seconds, for which the sensor is watched.
+        hold_seconds: u32,
+        /// The largest drop in the sensor's calibrated value for which the check passes.
+        max_decay: f64,
+    },
+    /// The dashboard identified the operator using it.
+    /// All subsequent commands on this connection will be attributed to the operator in the
+    /// command log.
+    Auth {
+        /// The name of the operator.
+        operator: String,
+        #[serde(default)]
+        /// The version of the protocol spoken by the dashboard.
+        /// If the controller cannot speak this version, it closes the connection.
+        protocol_version: Option<u32>,
+    },
+    /// The dashboard requested that new console log entries be forwarded to it.
+    /// Entries are forwarded until the dashboard disconnects.
+    StreamConsole {
+        /// The lowest level of entry to be forwarded.
+        min_level: LogLevel,
+    },
+    /// The operator requested that every log be written out and synced to disk.
+    /// The command is only acknowledged once every log is on disk.
+    Flush,
+    /// The operator requested a single reading of every sensor, recorded together as one row of
+    /// the snapshot log.
+    Snapshot,
+    /// The operator called out an event during a test, to be recorded in the event log and marked
+    /// on the dashboard's graphs.
+    Annotate {
+        /// The text of the annotation.
+        text: String,
+    },
+    /// The dashboard requested an inventory of the drivers and sensors on the controller.
+    Describe,
+    /// The dashboard requested a report of the complete current status of the controller, such as
+    /// for test automation.
+    Status,
+    /// The operator requested that sensor readings stop being logged and sent to the dashboard
+    /// until `ResumeAll`.
+    /// Sensors are still read and checked against their ranges while paused.
+    PauseAll,
+    /// The operator requested that paused sensor logging and streaming be resumed.
+    ResumeAll,
+    /// The operator requested that the controller change which levels of console log entry it
+    /// displays.
+    /// Every entry is still written to the console log file.
+    SetLogLevel {
+        /// The lowest level of entry to be displayed.
+        level: LogLevel,
+    },
+    /// The dashboard requested that only some values of each sensor reading be sent to it.
+    /// The choice lasts until the dashboard disconnects.
+    SetSensorFields {
+        /// The values of each reading to be sent.
+        fields: SensorValueFields,
+    },
+}
+
+#[derive(Deserialize)]
+/// A command, along with the identifier that the dashboard may have attached to it.
+struct Envelope {
+    /// The identifier of the command, used to detect retransmissions.
+    command_id: Option<u64>,
+    #[serde(flatten)]
+    /// The command itself.
+    command: Command,
+}
+
+#[derive(Deserialize)]
+/// The parts of a well-formed command which can be read without knowing its type.
+struct Probe {
+    #[serde(rename = "type")]
+    /// The type of the command.
+    command_type: String,
+    /// The identifier of the command, used to detect retransmissions.
+    command_id: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+/// A single actuation of a driver, as part of an `ActuateMany` command.
+pub struct Actuation {
+    /// The ID of the driver to be actuated.
+    pub driver_id: u8,
+    /// The logic level that the driver must be actuated to.
+    pub value: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+/// A reference to a single sensor in the configuration.
+///
+/// Sensors may be referred to either by their position in the configuration or by their label.
+/// Referring to sensors by label means that the dashboard does not depend on the exact order of
+/// sensors in the configuration.
+pub enum SensorRef {
+    /// A sensor referred to by its position in the configuration.
+    Id {
+        /// The ID of the sensor group containing the sensor.
+        group_id: u8,
+        /// The ID of the sensor within its group.
+        sensor_id: u8,
+    },
+    /// A sensor referred to by its label.
+    Label {
+        /// The label of the sensor.
+        sensor: String,
+    },
 }
 
 #[non_exhaustive]
@@ -47,9 +274,17 @@ pub enum Command {
 /// The ways in which parsing an incoming command can fail.
 pub enum Error {
     /// The message was malformed or illegal JSON.
-    /// The value inside this variant is the sequence of bytes which contained the malformed
-    /// message.
-    Malformed(Vec<u8>),
+    /// The values inside this variant are the sequence of bytes which contained the malformed
+    /// message and a description of what was wrong with it, such as the name of a missing field.
+    Malformed(Vec<u8>, String),
+    /// The message was a well-formed command, but of a type which the controller does not
+    /// support, such as a command added in a newer version of the dashboard.
+    Unsupported {
+        /// The type of the command.
+        command_type: String,
+        /// The identifier which the dashboard attached to the command, if any.
+        command_id: Option<u64>,
+    },
     /// There was an I/O error in parsing the message.
     Io(std::io::Error),
 }
@@ -64,8 +299,8 @@ impl From<std::io::Error> for Error {
 
 impl Command {
     /// Parse an incoming stream and extract the next command.
-    /// In the `Ok()` case, this will return a pair containing the command and the instant that the
-    /// command was sent.
+    /// In the `Ok()` case, this will return a pair containing the command and the `command_id` that
+    /// the dashboard attached to it, if any.
     ///
     /// # Errors
     ///
@@ -74,7 +309,7 @@ impl Command {
     /// # Panics
     ///
     /// This function will only panic in case of an internal logic error.
-    pub fn parse(src: &mut dyn Read) -> Result<Command, Error> {
+    pub fn parse(src: &mut dyn Read) -> Result<(Command, Option<u64>), Error> {
         let mut buffer = Vec::new();
         let mut bytes = src.bytes();
         let mut depth = 0;
@@ -101,7 +336,10 @@ impl Command {
                         if depth == 0 {
                             // prevent underflow in the case of a message starting with closing
                             // brace
-                            return Err(Error::Malformed(buffer));
+                            return Err(Error::Malformed(
+                                buffer,
+                                "unbalanced closing brace".into(),
+                            ));
                         }
                         depth -= 1;
                         // check if this is the end of the outermost object
@@ -117,19 +355,165 @@ impl Command {
             escaped = c == b'\\' && !escaped;
         }
 
-        let result = serde_json::from_slice(&buffer);
-        let cmd = result.map_err(|_| Error::Malformed(buffer))?;
+        match serde_json::from_slice::<Envelope>(&buffer) {
+            Ok(envelope) => Ok((envelope.command, envelope.command_id)),
+            Err(e) => Err(match serde_json::from_slice::<Probe>(&buffer) {
+                Ok(probe) if !is_known_type(&probe.command_type) => Error::Unsupported {
+                    command_type: probe.command_type,
+                    command_id: probe.command_id,
+                },
+                _ => Error::Malformed(buffer, e.to_string()),
+            }),
+        }
+    }
 
-        Ok(cmd)
+    #[must_use]
+    /// Determine whether this command can only make the engine safer: an emergency stop, or an
+    /// actuation which only de-energizes drivers.
+    /// Safing commands must always be executed, even if they look like a duplicate.
+    pub fn is_safing(&self) -> bool {
+        match self {
+            Command::EmergencyStop => true,
+            Command::Actuate { value, .. } => !value,
+            Command::ActuateMany { actions, .. } => actions.iter().all(|action| !action.value),
+            _ => false,
+        }
     }
 }
 
+/// The `type` of every variant of `Command`, as it appears in a message.
+/// Every new variant of `Command` must be added here.
+const COMMAND_TYPES: [&str; 28] = [
+    "Actuate",
+    "ActuateMany",
+    "KeepAlive",
+    "TestFire",
+    "History",
+    "CalibratePoint",
+    "SetThreshold",
+    "RemapDriver",
+    "OverrideInterlock",
+    "RestartGroup",
+    "RestoreInterlock",
+    "Ignition",
+    "EmergencyStop",
+    "Hold",
+    "Resume",
+    "InjectReading",
+    "LeakCheck",
+    "Auth",
+    "StreamConsole",
+    "Flush",
+    "Snapshot",
+    "Annotate",
+    "Describe",
+    "Status",
+    "PauseAll",
+    "ResumeAll",
+    "SetLogLevel",
+    "SetSensorFields",
+];
+
+/// Determine whether `command_type` names a type of command which the controller supports.
+fn is_known_type(command_type: &str) -> bool {
+    COMMAND_TYPES.contains(&command_type)
+}
+
 impl Display for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Command::Actuate { driver_id, value } => write!(f, "actuate {driver_id} {value}"),
+            Command::Actuate {
+                driver_id,
+                value,
+                confirm: false,
+            } => write!(f, "actuate {driver_id} {value}"),
+            Command::Actuate {
+                driver_id,
+                value,
+                confirm: true,
+            } => write!(f, "actuate {driver_id} {value} confirmed"),
+            Command::ActuateMany { actions, confirm } => {
+                write!(f, "actuate_many")?;
+                for Actuation { driver_id, value } in actions {
+                    write!(f, " {driver_id}:{value}")?;
+                }
+                if *confirm {
+                    write!(f, " confirmed")?;
+                }
+                Ok(())
+            }
             Command::Ignition => write!(f, "ignition"),
             Command::EmergencyStop => write!(f, "estop"),
+            Command::KeepAlive { driver_id } => write!(f, "keep_alive {driver_id}"),
+            Command::TestFire { driver_id, max_ms } => write!(f, "test_fire {driver_id} {max_ms}"),
+            Command::Hold => write!(f, "hold"),
+            Command::Resume => write!(f, "resume"),
+            Command::InjectReading { sensor, reading } => write!(f, "inject {sensor} {reading}"),
+            Command::LeakCheck {
+                sensor,
+                hold_seconds,
+                max_decay,
+            } => write!(f, "leak_check {sensor} {hold_seconds} {max_decay}"),
+            Command::Auth { operator, .. } => write!(f, "auth {operator}"),
+            Command::StreamConsole { min_level } => write!(f, "stream_console {min_level}"),
+            Command::SetLogLevel { level } => write!(f, "set_log_level {level}"),
+            Command::SetSensorFields { fields } => write!(f, "set_sensor_fields {fields:?}"),
+            Command::Flush => write!(f, "flush"),
+            Command::Snapshot => write!(f, "snapshot"),
+            Command::Annotate { text } => write!(f, "annotate {text:?}"),
+            Command::Describe => write!(f, "describe"),
+            Command::Status => write!(f, "status"),
+            Command::PauseAll => write!(f, "pause_all"),
+            Command::ResumeAll => write!(f, "resume_all"),
+            Command::History {
+                group_id,
+                sensor_id,
+                count,
+            } => write!(f, "history {group_id} {sensor_id} {count}"),
+            Command::CalibratePoint {
+                group_id,
+                sensor_id,
+                known_value,
+            } => write!(f, "calibrate_point {group_id} {sensor_id} {known_value}"),
+            Command::SetThreshold {
+                group_id,
+                sensor_id,
+                min,
+                max,
+            } => write!(f, "set_threshold {group_id} {sensor_id} {min} {max}"),
+            Command::RemapDriver { driver_id, new_pin } => {
+                write!(f, "remap_driver {driver_id} {new_pin}")
+            }
+            Command::OverrideInterlock {
+                interlock_id,
+                confirm: false,
+            } => write!(f, "override_interlock {interlock_id}"),
+            Command::OverrideInterlock {
+                interlock_id,
+                confirm: true,
+            } => write!(f, "override_interlock {interlock_id} confirmed"),
+            Command::RestoreInterlock { interlock_id } => {
+                write!(f, "restore_interlock {interlock_id}")
+            }
+            Command::RestartGroup { group_id, confirm } => {
+                write!(f, "restart_group {group_id}")?;
+                if *confirm {
+                    write!(f, " confirmed")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for SensorRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SensorRef::Id {
+                group_id,
+                sensor_id,
+            } => write!(f, "{group_id} {sensor_id}"),
+            SensorRef::Label { sensor } => write!(f, "{sensor}"),
         }
     }
 }
@@ -141,25 +525,82 @@ mod tests {
 
     /// Helper function to construct cursors and save some boilerplate on other tests.
     /// Creates a cursor of `message` and uses it to call `Command::parse`.
-    /// Ignores the extracted time from the parser.
+    /// Ignores the extracted command ID from the parser.
     fn parse_helper(message: &str) -> Result<Command, Error> {
         let mut cursor = Cursor::new(message);
-        Command::parse(&mut cursor)
+        Command::parse(&mut cursor).map(|(cmd, _)| cmd)
     }
 
     #[test]
-    /// Test that a command with a bad identifier cannot be parsed.
+    /// Test that a malformed command cannot be parsed.
     fn bad_command() {
         let message = r#"{
-            "type": "GARBAGE"
+            "type": "Actuate",
+            "driver_id": "GARBAGE"
         }"#;
 
-        let Err(Error::Malformed(s)) = parse_helper(message) else {panic!()};
+        let Err(Error::Malformed(s, _)) = parse_helper(message) else {
+            panic!()
+        };
         let slice: &[u8] = message.as_ref();
 
         assert_eq!(&s, slice);
     }
 
+    #[test]
+    /// Test that a well-formed command of an unknown type is distinguished from a malformed one.
+    fn unsupported_command() {
+        let message = r#"{
+            "type": "GARBAGE",
+            "command_id": 12,
+            "driver_id": 0
+        }"#;
+
+        let Err(Error::Unsupported {
+            command_type,
+            command_id,
+        }) = Command::parse(&mut Cursor::new(message))
+        else {
+            panic!()
+        };
+        assert_eq!(command_type, "GARBAGE");
+        assert_eq!(command_id, Some(12));
+
+        // a command with no type at all is malformed
+        assert!(matches!(
+            parse_helper(r#"{"driver_id": 0}"#),
+            Err(Error::Malformed(..))
+        ));
+
+        // a command of a known type which is missing its fields is malformed, not unsupported
+        assert!(matches!(
+            parse_helper(r#"{"type": "Actuate"}"#),
+            Err(Error::Malformed(..))
+        ));
+    }
+
+    #[test]
+    /// Test that every type in `COMMAND_TYPES` is a variant of `Command`, and that no variant is
+    /// missing from it.
+    fn command_types() {
+        for command_type in COMMAND_TYPES {
+            // a known type with none of its fields fails on a missing field, not an unknown variant
+            let e = serde_json::from_value::<Command>(serde_json::json!({ "type": command_type }))
+                .map_or_else(|e| e.to_string(), |_| String::new());
+            assert!(
+                !e.starts_with("unknown variant"),
+                "{command_type} is not a command type"
+            );
+        }
+
+        // serde lists every variant when it meets an unknown one
+        let e = serde_json::from_value::<Command>(serde_json::json!({ "type": "GARBAGE" }))
+            .unwrap_err()
+            .to_string();
+        let variants = e.matches('`').count() / 2 - 1;
+        assert_eq!(variants, COMMAND_TYPES.len());
+    }
+
     #[test]
     /// Test that an `actuate` command is parsed correctly.
     fn actuate() {
@@ -172,7 +613,67 @@ mod tests {
             parse_helper(message).unwrap(),
             Command::Actuate {
                 driver_id: 0,
-                value: true
+                value: true,
+                confirm: false
+            }
+        );
+    }
+
+    #[test]
+    /// Test that an `Actuate` command missing a field is rejected with a diagnostic naming the
+    /// field, and that unrecognized extra fields are ignored.
+    fn actuate_fields() {
+        let Err(Error::Malformed(_, reason)) =
+            parse_helper(r#"{"type": "Actuate", "value": true}"#)
+        else {
+            panic!()
+        };
+        assert!(reason.contains("missing field `driver_id`"), "{reason}");
+
+        let Err(Error::Malformed(_, reason)) =
+            parse_helper(r#"{"type": "Actuate", "driver_id": 3}"#)
+        else {
+            panic!()
+        };
+        assert!(reason.contains("missing field `value`"), "{reason}");
+
+        assert_eq!(
+            parse_helper(
+                r#"{"type": "Actuate", "driver_id": 3, "value": false, "label": "OXI_FILL"}"#
+            )
+            .unwrap(),
+            Command::Actuate {
+                driver_id: 3,
+                value: false,
+                confirm: false
+            }
+        );
+    }
+
+    #[test]
+    /// Test that an `ActuateMany` command is parsed correctly.
+    fn actuate_many() {
+        let message = r#"{
+            "type": "ActuateMany",
+            "actions": [
+                { "driver_id": 0, "value": true },
+                { "driver_id": 2, "value": false }
+            ]
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::ActuateMany {
+                actions: vec![
+                    Actuation {
+                        driver_id: 0,
+                        value: true
+                    },
+                    Actuation {
+                        driver_id: 2,
+                        value: false
+                    }
+                ],
+                confirm: false
             }
         );
     }
@@ -194,4 +695,324 @@ mod tests {
         }"#;
         assert_eq!(parse_helper(message).unwrap(), Command::EmergencyStop);
     }
+
+    #[test]
+    /// Test that history and calibration commands are parsed correctly.
+    fn history() {
+        assert_eq!(
+            parse_helper(r#"{"type": "History", "group_id": 1, "sensor_id": 3, "count": 500}"#)
+                .unwrap(),
+            Command::History {
+                group_id: 1,
+                sensor_id: 3,
+                count: 500
+            }
+        );
+        assert_eq!(
+            parse_helper(
+                r#"{"type": "CalibratePoint", "group_id": 0, "sensor_id": 2, "known_value": 100.0}"#
+            )
+            .unwrap(),
+            Command::CalibratePoint {
+                group_id: 0,
+                sensor_id: 2,
+                known_value: 100.0
+            }
+        );
+        assert_eq!(
+            parse_helper(
+                r#"{"type": "SetThreshold", "group_id": 1, "sensor_id": 0, "min": -5.0, "max": 750.0}"#
+            )
+            .unwrap(),
+            Command::SetThreshold {
+                group_id: 1,
+                sensor_id: 0,
+                min: -5.0,
+                max: 750.0
+            }
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "RemapDriver", "driver_id": 2, "new_pin": 17}"#).unwrap(),
+            Command::RemapDriver {
+                driver_id: 2,
+                new_pin: 17
+            }
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "OverrideInterlock", "interlock_id": 1, "confirm": true}"#)
+                .unwrap(),
+            Command::OverrideInterlock {
+                interlock_id: 1,
+                confirm: true
+            }
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "RestoreInterlock", "interlock_id": 1}"#).unwrap(),
+            Command::RestoreInterlock { interlock_id: 1 }
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "Annotate", "text": "igniter confirmed"}"#).unwrap(),
+            Command::Annotate {
+                text: "igniter confirmed".into()
+            }
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "RestartGroup", "group_id": 2}"#).unwrap(),
+            Command::RestartGroup {
+                group_id: 2,
+                confirm: false
+            }
+        );
+    }
+
+    #[test]
+    /// Test that hold and resume commands are parsed correctly.
+    fn hold_resume() {
+        assert_eq!(parse_helper(r#"{"type": "Hold"}"#).unwrap(), Command::Hold);
+        assert_eq!(
+            parse_helper(r#"{"type": "KeepAlive", "driver_id": 2}"#).unwrap(),
+            Command::KeepAlive { driver_id: 2 }
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "TestFire", "driver_id": 1, "max_ms": 250}"#).unwrap(),
+            Command::TestFire {
+                driver_id: 1,
+                max_ms: 250
+            }
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "Resume"}"#).unwrap(),
+            Command::Resume
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "Status"}"#).unwrap(),
+            Command::Status
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "Describe"}"#).unwrap(),
+            Command::Describe
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "PauseAll"}"#).unwrap(),
+            Command::PauseAll
+        );
+        assert_eq!(
+            parse_helper(r#"{"type": "ResumeAll"}"#).unwrap(),
+            Command::ResumeAll
+        );
+    }
+
+    #[test]
+    /// Test that a reading injection command is parsed correctly.
+    fn inject_reading() {
+        let message = r#"{
+            "type": "InjectReading",
+            "group_id": 1,
+            "sensor_id": 2,
+            "reading": 4095
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::InjectReading {
+                sensor: SensorRef::Id {
+                    group_id: 1,
+                    sensor_id: 2
+                },
+                reading: 4095
+            }
+        );
+    }
+
+    #[test]
+    /// Test that a leak check command is parsed correctly.
+    fn leak_check() {
+        let message = r#"{
+            "type": "LeakCheck",
+            "sensor": "PT_OX",
+            "hold_seconds": 60,
+            "max_decay": 2.5
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::LeakCheck {
+                sensor: SensorRef::Label {
+                    sensor: "PT_OX".into()
+                },
+                hold_seconds: 60,
+                max_decay: 2.5
+            }
+        );
+    }
+
+    #[test]
+    /// Test that an `InjectReading` command which refers to its sensor by label is parsed correctly.
+    fn inject_reading_by_label() {
+        let message = r#"{
+            "type": "InjectReading",
+            "sensor": "LC_MAIN",
+            "reading": 4095
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::InjectReading {
+                sensor: SensorRef::Label {
+                    sensor: "LC_MAIN".into()
+                },
+                reading: 4095
+            }
+        );
+    }
+
+    #[test]
+    /// Test that an `Auth` command is parsed correctly, with or without a protocol version.
+    fn auth() {
+        let message = r#"{
+            "type": "Auth",
+            "operator": "jdoe"
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::Auth {
+                operator: "jdoe".into(),
+                protocol_version: None,
+            }
+        );
+
+        let message = r#"{
+            "type": "Auth",
+            "operator": "jdoe",
+            "protocol_version": 1
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::Auth {
+                operator: "jdoe".into(),
+                protocol_version: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    /// Test that the `command_id` of a command is extracted alongside it.
+    fn command_id() {
+        let message = r#"{
+            "type": "InjectReading",
+            "command_id": 17,
+            "sensor": "LC_MAIN",
+            "reading": 4095
+        }"#;
+        assert_eq!(
+            Command::parse(&mut Cursor::new(message)).unwrap(),
+            (
+                Command::InjectReading {
+                    sensor: SensorRef::Label {
+                        sensor: "LC_MAIN".into()
+                    },
+                    reading: 4095
+                },
+                Some(17)
+            )
+        );
+
+        let message = r#"{
+            "type": "EmergencyStop"
+        }"#;
+        assert_eq!(
+            Command::parse(&mut Cursor::new(message)).unwrap(),
+            (Command::EmergencyStop, None)
+        );
+    }
+
+    #[test]
+    /// Test that a console streaming command is parsed.
+    fn stream_console() {
+        let message = r#"{
+            "type": "StreamConsole",
+            "min_level": "Warn"
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::StreamConsole {
+                min_level: LogLevel::Warn
+            }
+        );
+    }
+
+    #[test]
+    /// Test that a log level command is parsed.
+    fn set_log_level() {
+        let message = r#"{
+            "type": "SetLogLevel",
+            "level": "Debug"
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::SetLogLevel {
+                level: LogLevel::Debug
+            }
+        );
+    }
+
+    #[test]
+    /// Test that emergency stops and de-energizing actuations are recognized as safing commands.
+    fn safing_commands() {
+        assert!(Command::EmergencyStop.is_safing());
+        assert!(Command::Actuate {
+            driver_id: 0,
+            value: false,
+            confirm: false
+        }
+        .is_safing());
+        assert!(!Command::Actuate {
+            driver_id: 0,
+            value: true,
+            confirm: false
+        }
+        .is_safing());
+        assert!(Command::ActuateMany {
+            actions: vec![
+                Actuation {
+                    driver_id: 0,
+                    value: false
+                },
+                Actuation {
+                    driver_id: 1,
+                    value: false
+                }
+            ],
+            confirm: false
+        }
+        .is_safing());
+        assert!(!Command::ActuateMany {
+            actions: vec![
+                Actuation {
+                    driver_id: 0,
+                    value: false
+                },
+                Actuation {
+                    driver_id: 1,
+                    value: true
+                }
+            ],
+            confirm: false
+        }
+        .is_safing());
+        assert!(!Command::Ignition.is_safing());
+        assert!(!Command::KeepAlive { driver_id: 0 }.is_safing());
+    }
+
+    #[test]
+    /// Test that a sensor value fields command is parsed.
+    fn set_sensor_fields() {
+        let message = r#"{
+            "type": "SetSensorFields",
+            "fields": "Calibrated"
+        }"#;
+        assert_eq!(
+            parse_helper(message).unwrap(),
+            Command::SetSensorFields {
+                fields: SensorValueFields::Calibrated
+            }
+        );
+    }
 }
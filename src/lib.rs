@@ -18,18 +18,27 @@
 
 #![warn(clippy::pedantic)]
 
-use std::sync::PoisonError;
+use std::{fmt::Display, sync::PoisonError};
 
+mod binary_log;
+mod calibration;
 mod config;
 mod console;
 mod data;
 mod execution;
+mod expression;
 pub mod hardware;
 mod heartbeat;
 mod incoming;
+mod metrics;
 mod outgoing;
+mod reload;
+mod rotation;
+mod scheduling;
 pub mod server;
+mod simulation;
 pub mod state;
+mod syslog;
 
 #[non_exhaustive]
 #[derive(Debug)]
@@ -43,14 +52,37 @@ pub enum ControllerError {
     Outgoing(outgoing::Error),
     /// There was an error while attempting to perform some GPIO action.
     Gpio(gpio_cdev::Error),
+    /// A GPIO line could not be acquired.
+    /// The values are the number of the line, the cause of the failure, and a description of the
+    /// other chips which are available, if the line may belong to one of them.
+    GpioLine(u8, gpio_cdev::Error, Option<String>),
+    /// The GPIO chip could not be opened.
+    /// The values are the path of the chip, the cause of the failure, and a description of the
+    /// chips which are available instead.
+    GpioChip(std::path::PathBuf, gpio_cdev::Error, String),
     /// Something went wrong with the hardware.
     Hardware(&'static str),
+    /// Some ADCs failed to initialize, and the controller was not allowed to run without them.
+    /// The value is the number of ADCs which failed.
+    AdcsFailed(usize),
     /// The configuration was incorrectly formed.
     Configuration(config::Error),
-    /// The user gave the wrong input arguments to the main executable.
-    Args(&'static str),
+    /// No path to a configuration JSON was given to the main executable, and none could be found
+    /// in the environment.
+    MissingConfigPath,
+    /// No path to a logs directory was given to the main executable.
+    MissingLogsPath,
+    /// No path to a binary log was given to the main executable in decode mode.
+    MissingBinaryLogPath,
     /// An error ocurred while working with a state guard.
     State(state::Error),
+    /// A binary log could not be decoded.
+    Decode(binary_log::Error),
+    /// The saved sensor calibrations could not be loaded.
+    Calibration(calibration::Error),
+    /// A log file, or a directory containing one, could not be created.
+    /// The values are the path which could not be created and the cause of the failure.
+    LogFile(std::path::PathBuf, std::io::Error),
 }
 
 impl<T> From<PoisonError<T>> for ControllerError {
@@ -92,3 +124,56 @@ impl From<outgoing::Error> for ControllerError {
         ControllerError::Outgoing(err)
     }
 }
+
+impl From<binary_log::Error> for ControllerError {
+    fn from(err: binary_log::Error) -> Self {
+        ControllerError::Decode(err)
+    }
+}
+
+impl From<calibration::Error> for ControllerError {
+    fn from(err: calibration::Error) -> Self {
+        ControllerError::Calibration(err)
+    }
+}
+
+impl Display for ControllerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControllerError::Poison => write!(f, "a lock was poisoned by a panicked thread"),
+            ControllerError::Console(e) => write!(f, "unable to write to log file: {e}"),
+            ControllerError::Outgoing(e) => write!(f, "unable to send message to dashboard: {e:?}"),
+            ControllerError::Gpio(e) => write!(f, "GPIO error: {e}"),
+            ControllerError::GpioLine(line, e, None) => {
+                write!(f, "unable to acquire GPIO line {line}: {e}")
+            }
+            ControllerError::GpioLine(line, e, Some(available)) => write!(
+                f,
+                "unable to acquire GPIO line {line}: {e}. It may belong to a different chip. {available}"
+            ),
+            ControllerError::GpioChip(path, e, available) => write!(
+                f,
+                "unable to open GPIO chip {}: {e}. {available}",
+                path.display()
+            ),
+            ControllerError::Hardware(msg) => write!(f, "hardware error: {msg}"),
+            ControllerError::AdcsFailed(n_failed) => {
+                write!(f, "{n_failed} ADC(s) failed to initialize")
+            }
+            ControllerError::Configuration(e) => write!(f, "invalid configuration: {e}"),
+            ControllerError::MissingConfigPath => write!(
+                f,
+                "no configuration JSON path given, and {} is not set",
+                server::CONFIG_ENV_VAR
+            ),
+            ControllerError::MissingLogsPath => write!(f, "no logs path given"),
+            ControllerError::MissingBinaryLogPath => write!(f, "no binary log path given"),
+            ControllerError::State(e) => write!(f, "illegal state transition: {e:?}"),
+            ControllerError::Decode(e) => write!(f, "unable to decode binary log: {e}"),
+            ControllerError::Calibration(e) => write!(f, "{e}"),
+            ControllerError::LogFile(path, e) => {
+                write!(f, "unable to create log file {}: {e}", path.display())
+            }
+        }
+    }
+}
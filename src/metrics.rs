@@ -0,0 +1,418 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! A read-only HTTP endpoint exposing controller metrics for scraping by Prometheus.
+//!
+//! Metrics are rendered in the Prometheus text exposition format at `/metrics`.
+//! The endpoint is independent of the dashboard protocol: it accepts no commands and changes
+//! nothing on the controller.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread::sleep,
+    time::Duration,
+};
+
+use crate::{
+    config::Configuration,
+    console::UserLog,
+    data::Liveness,
+    execution::PriorityMutex,
+    hardware::GpioPin,
+    state::{Guard, State},
+    ControllerError,
+};
+
+/// The period at which the metrics listener checks for new connections and for the controller
+/// quitting.
+const ACCEPT_POLL_PERIOD: Duration = Duration::from_millis(50);
+
+/// The time allowed for a scraper to send its request or receive its response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The largest request which is read before it is answered.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Serve metrics to every connection on `listener` until the controller quits.
+///
+/// Each connection is answered in turn, so a slow scraper can only delay other scrapers, never the
+/// rest of the controller.
+///
+/// # Inputs
+///
+/// * `listener`: The listener on which scrapers connect.
+/// * `configuration`: The configuration of the controller, giving the labels of each metric.
+/// * `state`: The overall system state.
+/// * `liveness`: The loop counts, read errors, and jitter of every sensor group.
+/// * `driver_lines`: The driver GPIO pins, whose levels are reported.
+/// * `user_log`: The log to which failures to answer a scraper are reported.
+///
+/// # Errors
+///
+/// This function will return an error if the listener fails or if the state lock is poisoned.
+pub fn serve_metrics(
+    listener: &TcpListener,
+    configuration: &Configuration,
+    state: &Guard,
+    liveness: &Liveness,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    listener.set_nonblocking(true)?;
+    while state.status()? != State::Quit {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                match answer(stream, configuration, state, liveness, driver_lines) {
+                    Ok(()) => (),
+                    // scrapers come and go, so a broken connection is not worth a warning
+                    Err(ControllerError::Console(e)) => {
+                        user_log.debug(&format!("Unable to answer metrics request: {e}"))?;
+                    }
+                    Err(e) => user_log.warn(&format!("Unable to collect metrics: {e}"))?,
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => sleep(ACCEPT_POLL_PERIOD),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a single HTTP request from `stream` and answer it, rendering the metrics if the request
+/// was for `/metrics`.
+/// If the metrics cannot be collected, the request is answered with an internal server error.
+///
+/// # Errors
+///
+/// This function will return an error if reading the request or writing the response fails, or
+/// if the metrics could not be collected because the state or driver lock is poisoned.
+fn answer(
+    mut stream: TcpStream,
+    configuration: &Configuration,
+    state: &Guard,
+    liveness: &Liveness,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+) -> Result<(), ControllerError> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    // only the request line matters, but the headers are read so the scraper is not reset
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n")
+        && request.len() < MAX_REQUEST_SIZE
+    {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.split_whitespace();
+
+    let mut failure = None;
+    let (status, body) = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            match collect(configuration, state, liveness, driver_lines) {
+                Ok(body) => ("200 OK", body),
+                Err(e) => {
+                    let body = format!("unable to collect metrics: {e}\n");
+                    failure = Some(e);
+                    ("500 Internal Server Error", body)
+                }
+            }
+        }
+        (Some("GET"), Some(_)) => ("404 Not Found", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "only GET is allowed\n".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    failure.map_or(Ok(()), Err)
+}
+
+/// Collect the current metrics of the controller and render them.
+///
+/// # Errors
+///
+/// This function will return an error if the state or driver lock is poisoned.
+fn collect(
+    configuration: &Configuration,
+    state: &Guard,
+    liveness: &Liveness,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+) -> Result<String, ControllerError> {
+    let levels: Vec<Option<bool>> = driver_lines
+        .lock()?
+        .iter_mut()
+        .map(|line| line.read().ok())
+        .collect();
+    Ok(render(configuration, state.status()?, liveness, &levels))
+}
+
+#[must_use]
+/// Render the metrics of the controller in the Prometheus text exposition format.
+///
+/// # Inputs
+///
+/// * `configuration`: The configuration of the controller, giving the labels of each metric.
+/// * `state`: The current state of the controller.
+/// * `liveness`: The loop counts, read errors, and jitter of every sensor group.
+/// * `levels`: The level of each driver, or `None` if it could not be read.
+pub fn render(
+    configuration: &Configuration,
+    state: State,
+    liveness: &Liveness,
+    levels: &[Option<bool>],
+) -> String {
+    let mut out = String::new();
+    let groups = &configuration.sensor_groups;
+
+    header(
+        &mut out,
+        "slonk_state",
+        "gauge",
+        "The current state of the controller.",
+    );
+    let _ = writeln!(out, "slonk_state{{state=\"{state:?}\"}} 1");
+
+    header(
+        &mut out,
+        "slonk_sensor_group_loops_total",
+        "counter",
+        "The number of loops begun by each sensor group.",
+    );
+    for (group, count) in groups.iter().zip(liveness.counts()) {
+        let _ = writeln!(
+            out,
+            "slonk_sensor_group_loops_total{{group=\"{}\"}} {count}",
+            escape(&group.label)
+        );
+    }
+
+    header(
+        &mut out,
+        "slonk_sensor_group_sample_rate_hz",
+        "gauge",
+        "The rate at which each sensor group is meant to sample in the current state.",
+    );
+    for group in groups {
+        let rate = if state == State::Standby {
+            group.frequency_standby
+        } else {
+            group.frequency_ignition
+        };
+        let _ = writeln!(
+            out,
+            "slonk_sensor_group_sample_rate_hz{{group=\"{}\"}} {rate}",
+            escape(&group.label)
+        );
+    }
+
+    header(
+        &mut out,
+        "slonk_sensor_group_read_errors_total",
+        "counter",
+        "The number of failed sensor reads in each sensor group.",
+    );
+    for (group, count) in groups.iter().zip(liveness.read_errors()) {
+        let _ = writeln!(
+            out,
+            "slonk_sensor_group_read_errors_total{{group=\"{}\"}} {count}",
+            escape(&group.label)
+        );
+    }
+
+    header(
+        &mut out,
+        "slonk_sensor_group_loop_jitter_seconds",
+        "gauge",
+        "The jitter of the most recent loop of each sensor group.",
+    );
+    for (group, jitter) in groups.iter().zip(liveness.jitters()) {
+        let _ = writeln!(
+            out,
+            "slonk_sensor_group_loop_jitter_seconds{{group=\"{}\"}} {}",
+            escape(&group.label),
+            jitter.as_secs_f64()
+        );
+    }
+
+    header(
+        &mut out,
+        "slonk_driver_level",
+        "gauge",
+        "The logic level of each driver which could be read.",
+    );
+    for (driver, level) in configuration.drivers.iter().zip(levels) {
+        if let Some(level) = level {
+            let _ = writeln!(
+                out,
+                "slonk_driver_level{{driver=\"{}\"}} {}",
+                escape(&driver.label),
+                u8::from(*level)
+            );
+        }
+    }
+
+    out
+}
+
+/// Write the `HELP` and `TYPE` lines introducing the metric `name` to `out`.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Escape `label` for use as a label value.
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, thread::scope};
+
+    use crate::hardware::ListenerPin;
+
+    use super::*;
+
+    #[test]
+    /// Test that scraping the endpoint returns every metric in the Prometheus exposition format,
+    /// and that a scrape which cannot collect the metrics is answered with a server error.
+    fn scrape() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+            "frequency_status": 10,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {
+                    "label": "Pressure \"fast\"",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 100,
+                    "frequency_transmission": 10,
+                    "sensors": []
+                }
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [
+                {
+                    "label": "OXI_FILL",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                }
+            ],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [],
+            "pin_heartbeat": 0
+        }"#,
+        ))
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = Guard::new(State::Standby);
+        let liveness = Liveness::new(&config);
+        let driver_lines = PriorityMutex::new(vec![ListenerPin::new(true)]);
+        let user_log = UserLog::new(Vec::new());
+
+        let request = || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream
+                .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let (response, failed_response) = scope(|s| {
+            let serving = s.spawn(|| {
+                serve_metrics(
+                    &listener,
+                    &config,
+                    &state,
+                    &liveness,
+                    &driver_lines,
+                    &user_log,
+                )
+            });
+
+            let response = request();
+            // a poisoned lock fails the request, but not the server
+            let _ = s
+                .spawn(|| {
+                    let _lines = driver_lines.lock();
+                    panic!("poisoning the driver lines");
+                })
+                .join();
+            let failed_response = request();
+
+            state.move_to(State::Quit).unwrap();
+            serving.join().unwrap().unwrap();
+            (response, failed_response)
+        });
+        assert!(failed_response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4"));
+
+        let samples: Vec<&str> = body.lines().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(
+            samples,
+            [
+                "slonk_state{state=\"Standby\"} 1",
+                "slonk_sensor_group_loops_total{group=\"Pressure \\\"fast\\\"\"} 0",
+                "slonk_sensor_group_sample_rate_hz{group=\"Pressure \\\"fast\\\"\"} 10",
+                "slonk_sensor_group_read_errors_total{group=\"Pressure \\\"fast\\\"\"} 0",
+                "slonk_sensor_group_loop_jitter_seconds{group=\"Pressure \\\"fast\\\"\"} 0",
+                "slonk_driver_level{driver=\"OXI_FILL\"} 1",
+            ]
+        );
+        // every metric is introduced by its help and type
+        for name in samples
+            .iter()
+            .map(|sample| sample.split('{').next().unwrap())
+        {
+            assert!(body.contains(&format!("# HELP {name} ")));
+            assert!(body.contains(&format!("# TYPE {name} ")));
+        }
+    }
+}
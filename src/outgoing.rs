@@ -20,14 +20,44 @@
 //! to dashboard.
 
 use std::{
-    io::Write,
-    sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    time::SystemTime,
+    io::{self, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+use crate::{
+    config::{Configuration, DriverKind, SensorValueFields},
+    console::{LogLevel, UserLog},
+    reload::RetunedSensor,
+    state::{Guard, State},
+    ControllerError,
 };
 
-use serde::Serialize;
+/// The period at which the message log writer checks whether the controller is quitting.
+const LOG_QUEUE_POLL_PERIOD: Duration = Duration::from_millis(100);
+
+/// The longest time for which UDP telemetry waits before resolving its host name again after a
+/// datagram fails to send, or after the host name could not be resolved.
+const RESOLVE_POLL_PERIOD: Duration = Duration::from_secs(1);
+
+/// The version of the protocol spoken between the controller and the dashboard.
+/// This is incremented whenever a message or command changes in a way which an older dashboard or
+/// controller would misunderstand.
+pub const PROTOCOL_VERSION: u32 = 1;
 
-use crate::config::Configuration;
+/// The version of this controller build: the crate version, followed by the git commit it was
+/// built from.
+/// The commit is `unknown` if the controller was not built from a git checkout, and is suffixed
+/// with `-dirty` if the checkout had uncommitted changes.
+pub const CONTROLLER_VERSION: &str =
+    concat!(env!("CARGO_PKG_VERSION"), "+", env!("SLONK_GIT_COMMIT"));
 
 #[derive(Serialize)]
 #[serde(tag = "type")]
@@ -37,6 +67,12 @@ pub enum Message<'a> {
     Config {
         /// A reference to the entire configuration object for this controller.
         config: &'a Configuration,
+        /// The version of the protocol spoken by the controller.
+        /// This is always `PROTOCOL_VERSION`.
+        protocol_version: u32,
+        /// The version of the controller build.
+        /// This is always `CONTROLLER_VERSION`.
+        controller_version: &'static str,
     },
     /// A sensor valuation message.
     /// Each key in the map corresponds to a sensor.
@@ -45,30 +81,406 @@ pub enum Message<'a> {
     SensorValue {
         /// The group which generated the readings.
         group_id: u8,
-        /// The readings which were created.
-        readings: &'a [SensorReading],
+        /// The readings which were created, along with which of their values are sent.
+        readings: ReadingList<'a>,
+        /// The largest offset, in nanoseconds, of any of the readings from their group timestamp.
+        /// This is the residual skew between the readings.
+        /// `None` if the group is not aligned.
+        skew_ns: Option<u32>,
+    },
+    /// A digital input values message.
+    /// Describes the debounced levels of the digital inputs in a sensor group.
+    DigitalValue {
+        /// The group containing the inputs.
+        group_id: u8,
+        /// The debounced level of each input.
+        /// Each index corresponds to the input at the same index in the group's `digital_inputs`.
+        values: &'a [bool],
+    },
+    /// A virtual sensor values message.
+    /// Describes the most recent values of the virtual sensors in a sensor group.
+    VirtualValue {
+        /// The group containing the virtual sensors.
+        group_id: u8,
+        /// The most recent value of each virtual sensor, or `None` if it has not yet been
+        /// computed.
+        /// Each index corresponds to the virtual sensor at the same index in the group's
+        /// `virtual_sensors`.
+        values: &'a [Option<f64>],
     },
     /// A driver values message.
     /// Describes the logic levels of the drivers on the controller.
     DriverValue {
-        /// The logic level of each driver.
+        /// The state of each driver.
         /// Each index corresponds to the driver at the same index in the
         /// original configuration object.
-        values: &'a [bool],
+        values: &'a [DriverState],
+    },
+    /// A driver change message, sent as soon as a driver is written to a new logic level.
+    DriverChange {
+        /// The identifier (i.e. index) of the driver which changed.
+        driver_id: u8,
+        /// The logic level of the driver after the change.
+        level: bool,
+        /// The time at which the driver was written.
+        time: SystemTime,
+    },
+    /// A hold status message, sent whenever a hold on the ignition sequence is placed or released.
+    HoldStatus {
+        /// Whether the ignition sequence is now held.
+        held: bool,
+    },
+    /// An acknowledgement of a command which carried a `command_id`.
+    Ack {
+        /// The identifier of the command being acknowledged.
+        command_id: u64,
+        /// Whether the command was successfully executed (or, for long-running procedures,
+        /// successfully started).
+        success: bool,
+    },
+    /// A notice that a command was ignored because the controller does not support its type.
+    Unsupported {
+        /// The type of the ignored command.
+        command_type: &'a str,
+    },
+    /// A summary of a reload of the configuration file.
+    ConfigReload {
+        /// The new tuning of every sensor whose tuning was changed by the reload.
+        sensors: &'a [RetunedSensor],
+        /// The top-level configuration fields which were changed in the file, but which were not
+        /// applied because they require a restart.
+        restart_required: &'a [String],
+    },
+    /// A console log entry of level `Debug` or `Info`, forwarded because the dashboard requested
+    /// that the console be streamed to it.
+    Display {
+        /// The level of the entry.
+        level: LogLevel,
+        /// The text of the entry.
+        message: &'a str,
+    },
+    /// A console log entry of level `Warn` or `Critical`, forwarded because the dashboard requested
+    /// that the console be streamed to it.
+    /// Stalled sensor groups and commands dropped by the rate limit are also reported with this
+    /// message, whether or not the console is streamed.
+    Error {
+        /// The level of the entry.
+        level: LogLevel,
+        /// The text of the entry.
+        message: &'a str,
+    },
+    /// A notice that a sensor's linear calibration was computed from two reference points, and is
+    /// now in use.
+    Calibration {
+        /// The ID of the group containing the sensor.
+        group_id: u8,
+        /// The ID of the sensor within its group.
+        sensor_id: u8,
+        /// The intercept of the new calibration function.
+        calibration_intercept: f64,
+        /// The slope of the new calibration function.
+        calibration_slope: f64,
+    },
+    /// A notice that a sensor's range was changed by the operator, and is now in use.
+    Threshold {
+        /// The ID of the group containing the sensor.
+        group_id: u8,
+        /// The ID of the sensor within its group.
+        sensor_id: u8,
+        /// The lowest calibrated value which does not trigger an emergency stop.
+        min: f64,
+        /// The highest calibrated value which does not trigger an emergency stop.
+        max: f64,
+    },
+    /// The result of a leak check requested by the operator.
+    LeakCheck {
+        /// The ID of the group containing the sensor.
+        group_id: u8,
+        /// The ID of the sensor within its group.
+        sensor_id: u8,
+        /// The calibrated value of the sensor when the check began.
+        initial_value: f64,
+        /// The calibrated value of the sensor when the check ended.
+        final_value: f64,
+        /// The average rate at which the value fell during the check, in units of the sensor per
+        /// second.
+        decay_rate: f64,
+        /// Whether the value fell by no more than the largest decay allowed.
+        passed: bool,
+    },
+    /// A snapshot of every sensor, taken because the operator requested one.
+    Snapshot {
+        /// The time at which the snapshot was requested, which is shared by every reading in it.
+        time: SystemTime,
+        /// The calibrated value of every sensor, indexed first by group ID and then by sensor ID.
+        /// A sensor which could not be read has no value.
+        values: &'a [Vec<Option<f64>>],
+    },
+    /// An annotation made by the operator, to be marked on the dashboard's graphs.
+    Annotation {
+        /// The time at which the annotation was made.
+        time: SystemTime,
+        /// The text of the annotation.
+        text: &'a str,
+    },
+    /// An inventory of the drivers and sensors on the controller, sent in response to a `Describe`
+    /// command.
+    Inventory {
+        /// A description of every driver, in order of driver ID.
+        drivers: &'a [DriverInfo<'a>],
+        /// A description of every sensor, in order of group ID and then of sensor ID.
+        sensors: &'a [SensorInfo<'a>],
+    },
+    /// A report of the complete current status of the controller, sent in response to a `Status`
+    /// command.
+    StatusReport {
+        /// The current state of the controller.
+        state: State,
+        /// Whether the controller is armed, so that the armed-state indicator is active.
+        armed: bool,
+        /// The time since the controller started, in milliseconds.
+        uptime_ms: u64,
+        /// The state of each driver, in order of driver ID.
+        drivers: &'a [DriverState],
+        /// The time at which the report was made.
+        time: SystemTime,
+        /// The latest reading of every sensor, indexed first by group ID and then by sensor ID.
+        /// A sensor which has not been read yet has no reading.
+        sensors: &'a [Vec<Option<SensorStatus>>],
+        /// The latest value of every virtual sensor, indexed first by group ID and then by virtual
+        /// sensor ID.
+        /// A virtual sensor which has not been derived yet has no value.
+        virtual_sensors: &'a [Vec<Option<f64>>],
+        /// The IDs of the sensor groups which have failed and are waiting to be restarted.
+        failed_groups: &'a [u8],
+        /// The drivers controlled by each running sequence.
+        sequences: &'a [Vec<u8>],
+        /// Whether a hold is in place on the ignition sequence.
+        held: bool,
+        /// Whether sensor logging and streaming are paused.
+        paused: bool,
+        /// Whether the controller has finished initializing.
+        ready: bool,
+        /// The number of emergency stops since the controller started.
+        estop_count: u32,
+        /// The IDs of the overridden interlocks.
+        interlock_overrides: &'a [u8],
+    },
+    /// A reminder that interlocks are overridden, sent with every driver status update until every
+    /// interlock is restored.
+    InterlockOverride {
+        /// The IDs of the overridden interlocks.
+        interlock_ids: &'a [u8],
+    },
+    /// A notice that an ignition may not yet begin, because the cooldown after the last emergency
+    /// stop is not over.
+    Cooldown {
+        /// The time remaining until the cooldown is over, in milliseconds.
+        remaining_ms: u64,
+    },
+    /// The progress of the purge sequence after an emergency stop or abort action.
+    PurgeProgress {
+        /// The number of steps of the purge sequence which are done.
+        completed: usize,
+        /// The total number of steps in the purge sequence.
+        total: usize,
+    },
+    /// A notice that the controller has finished initializing, and will accept commands which
+    /// actuate hardware.
+    Ready,
+    /// A notice that the controller is about to close its connection to the dashboard, either
+    /// because it is exiting normally or because it encountered a fatal error.
+    Shutdown {
+        /// A human-readable description of why the controller is shutting down.
+        reason: &'a str,
     },
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The state of a single driver, as reported to the dashboard.
+///
+/// Binary states are serialized as a bare boolean, so configurations containing only binary
+/// drivers produce the same messages as older versions of the controller.
+/// All other states are serialized as an object with a single key naming the variant, such as
+/// `{"Duty": 0.5}`.
+pub enum DriverState {
+    /// The logic level of an on/off driver.
+    Binary(bool),
+    /// The duty cycle of a PWM driver, from 0.0 (off) to 1.0 (fully on).
+    Duty(f32),
+    /// The position of a servo, from 0.0 (deactuated) to 1.0 (actuated).
+    Position(f32),
+}
+
+impl DriverState {
+    #[must_use]
+    /// Construct the state of a driver of kind `kind` whose output is at logic level `level`.
+    pub fn new(kind: DriverKind, level: bool) -> DriverState {
+        let fraction = if level { 1.0 } else { 0.0 };
+        match kind {
+            DriverKind::Binary => DriverState::Binary(level),
+            DriverKind::Pwm => DriverState::Duty(fraction),
+            DriverKind::Servo => DriverState::Position(fraction),
+        }
+    }
+}
+
+impl Serialize for DriverState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            DriverState::Binary(level) => serializer.serialize_bool(level),
+            DriverState::Duty(duty) => {
+                serializer.serialize_newtype_variant("DriverState", 1, "Duty", &duty)
+            }
+            DriverState::Position(position) => {
+                serializer.serialize_newtype_variant("DriverState", 2, "Position", &position)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
 /// An individual reading on a sensor.
 pub struct SensorReading {
     /// The ID of the sensor withing the group that created this reading.
     pub sensor_id: u8,
     /// The value read on the sensor.
     pub reading: u16,
+    /// The calibrated value of the reading, in the units of the sensor.
+    pub value: f64,
     /// The time at which the sensor reading was created.
+    /// If the group is aligned, this is instead the timestamp shared by the whole group.
     pub time: SystemTime,
+    /// The time, in nanoseconds, between the group timestamp and the moment this sensor was
+    /// actually read.
+    /// `None` if the group is not aligned.
+    pub offset_ns: Option<u32>,
+    /// Whether the reading was saturated, meaning that the sensor (or the ADC reading it) was at
+    /// the edge of its range and the true value may lie beyond the reading.
+    pub saturated: bool,
+    /// Whether the reading was older than the staleness window when it was sent, meaning that the
+    /// sensor has not produced a newer reading in time.
+    /// Always `false` if no staleness window is configured.
+    pub stale: bool,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq)]
+/// The latest reading of a single sensor, as given in a status report.
+pub struct SensorStatus {
+    /// The calibrated value of the reading, in the units of the sensor.
+    pub value: f64,
+    /// Whether the reading was saturated.
+    pub saturated: bool,
+    /// Whether the reading is older than the staleness window.
+    /// Always `false` if no staleness window is configured.
+    pub stale: bool,
+}
+
+#[derive(Clone, Copy)]
+/// A list of sensor readings, serialized with only the values selected by `fields`.
+pub struct ReadingList<'a> {
+    /// The readings in the list.
+    pub readings: &'a [SensorReading],
+    /// Which of the raw and calibrated values of each reading are serialized.
+    pub fields: SensorValueFields,
+}
+
+/// A sensor reading as it is serialized in a `ReadingList`, with any unselected value omitted.
+struct SelectedReading<'a> {
+    /// The reading to be serialized.
+    reading: &'a SensorReading,
+    /// Which of the raw and calibrated values of the reading are serialized.
+    fields: SensorValueFields,
+}
+
+impl Serialize for SelectedReading<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let raw = self.fields != SensorValueFields::Calibrated;
+        let calibrated = self.fields != SensorValueFields::Raw;
+        let reading = self.reading;
+        // the fields are the same, and in the same order, as those of a whole `SensorReading`
+        let mut state = serializer.serialize_struct(
+            "SensorReading",
+            5 + usize::from(raw) + usize::from(calibrated),
+        )?;
+        state.serialize_field("sensor_id", &reading.sensor_id)?;
+        if raw {
+            state.serialize_field("reading", &reading.reading)?;
+        } else {
+            state.skip_field("reading")?;
+        }
+        if calibrated {
+            state.serialize_field("value", &reading.value)?;
+        } else {
+            state.skip_field("value")?;
+        }
+        state.serialize_field("time", &reading.time)?;
+        state.serialize_field("offset_ns", &reading.offset_ns)?;
+        state.serialize_field("saturated", &reading.saturated)?;
+        state.serialize_field("stale", &reading.stale)?;
+        state.end()
+    }
 }
 
+impl Serialize for ReadingList<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.readings.iter().map(|reading| SelectedReading {
+            reading,
+            fields: self.fields,
+        }))
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq)]
+/// The description of a single driver in an inventory.
+pub struct DriverInfo<'a> {
+    /// The ID of the driver, which is its index in the configuration.
+    pub driver_id: u8,
+    /// The human-readable name of the driver.
+    pub label: &'a str,
+    /// The kind of load attached to the driver.
+    pub kind: DriverKind,
+    /// Whether the driver controls something hazardous.
+    pub hazardous: bool,
+    /// The current state of the driver.
+    pub state: DriverState,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq)]
+/// The description of a single sensor in an inventory.
+pub struct SensorInfo<'a> {
+    /// The ID of the group containing the sensor.
+    pub group_id: u8,
+    /// The ID of the sensor within its group.
+    pub sensor_id: u8,
+    /// The human-readable name of the sensor.
+    pub label: &'a str,
+    /// The units of the sensor's calibrated values.
+    pub units: &'a str,
+    /// How the sensor's raw readings are converted into calibrated values.
+    pub calibration: CalibrationSummary,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq)]
+#[serde(tag = "type")]
+/// A summary of how a sensor's raw readings are converted into calibrated values.
+pub enum CalibrationSummary {
+    /// A linear calibration, `value = slope * input + intercept`.
+    Linear {
+        /// The slope of the calibration function.
+        slope: f64,
+        /// The intercept of the calibration function.
+        intercept: f64,
+        /// The reference voltage of the sensor's ADC.
+        /// If present, the input of the calibration is the voltage at the ADC; otherwise, it is
+        /// the raw reading.
+        vref: Option<f64>,
+    },
+    /// A thermistor, whose readings are converted into degrees Celsius.
+    Thermistor,
+}
+
+#[allow(clippy::struct_field_names)]
 /// A channel which can write to the dashboard.
 /// It contains a writer for a channel to the dashboard and to a message log.
 ///
@@ -79,10 +491,200 @@ pub struct SensorReading {
 pub struct DashChannel<C: Write, M: Write> {
     /// A channel for the dashboard.
     /// If writing to this channel fails, it will be immediately overwritten with `None`.
+    /// The failure is reported once the client handler's next read from the dashboard also fails,
+    /// after which the controller waits for the dashboard to reconnect.
     /// When `dash_channel` is `None`, nothing will be written.
     pub dash_channel: Arc<RwLock<Option<C>>>,
     /// The log file for all messages that are sent.
     message_log: Mutex<M>,
+    /// The file to which the exact bytes sent to the dashboard are captured, if any.
+    ///
+    /// Each message sent is recorded as a line containing the time it was sent (in nanoseconds
+    /// since the UNIX epoch) and the number of bytes sent, separated by a comma, followed by the
+    /// bytes themselves and then a newline.
+    raw_capture: Option<Mutex<M>>,
+    /// The queue of sent messages waiting to be written to the message log, if message log writes
+    /// are made in the background.
+    /// If `None`, each message is logged as it is sent.
+    log_queue: Option<LogQueue>,
+    /// Which values of each sensor reading are sent to the current dashboard.
+    sensor_fields: Mutex<SensorValueFields>,
+}
+
+/// A bounded queue of sent messages waiting to be written to the message log.
+struct LogQueue {
+    /// The sending half of the queue.
+    /// Each entry is the time at which a message was sent (in nanoseconds since the UNIX epoch) and
+    /// the bytes which were sent.
+    sender: SyncSender<(u128, Vec<u8>)>,
+    /// The receiving half of the queue.
+    receiver: Mutex<Receiver<(u128, Vec<u8>)>>,
+    /// The number of entries dropped because the queue was full, since this was last reported.
+    dropped: AtomicU64,
+}
+
+/// A function which resolves a host name and port to a socket address.
+type Resolver = dyn Fn(&str) -> io::Result<SocketAddr> + Send + Sync;
+
+/// A best-effort channel which streams messages over UDP to an address given by host name.
+/// Each message is sent as a single datagram containing its JSON serialization.
+///
+/// The host name is never resolved while sending, since a lookup may block for seconds.
+/// Instead, `resolve_watch` resolves it on its own thread, periodically and after any datagram
+/// fails to send, so that telemetry follows a receiver which restarts at a new address.
+pub struct UdpTelemetry {
+    /// The host name (or IP address) and port to which datagrams are sent.
+    host: String,
+    /// The period after which the host name is resolved again.
+    resolve_period: Duration,
+    /// The function which resolves `host`.
+    resolver: Box<Resolver>,
+    /// The socket and address currently in use, or `None` if the host name has never been
+    /// resolved.
+    /// Each new route is published whole, so a datagram is never sent with a half-updated route.
+    route: RwLock<Option<Arc<Route>>>,
+    /// Whether the host name must be resolved again as soon as possible, because a datagram failed
+    /// to send.
+    stale_route: AtomicBool,
+    /// Whether the most recent datagram failed to send.
+    failing: AtomicBool,
+}
+
+/// Convert a poisoned lock on the route of a `UdpTelemetry` into an I/O error.
+fn route_poisoned<T>(_: PoisonError<T>) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "UDP telemetry lock was poisoned")
+}
+
+/// The socket and resolved address used by a `UdpTelemetry`.
+struct Route {
+    /// The socket from which datagrams are sent, bound to the address family of `target`.
+    socket: UdpSocket,
+    /// The most recently resolved address to which datagrams are sent.
+    target: SocketAddr,
+}
+
+impl UdpTelemetry {
+    #[must_use]
+    /// Construct a new `UdpTelemetry` which sends datagrams to `host`, a host name or IP address
+    /// followed by a port, resolving it again every `resolve_period`.
+    pub fn new(host: String, resolve_period: Duration) -> UdpTelemetry {
+        UdpTelemetry::with_resolver(host, resolve_period, |host| {
+            host.to_socket_addrs()?.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{host} resolved to no addresses"),
+                )
+            })
+        })
+    }
+
+    #[must_use]
+    /// Construct a new `UdpTelemetry` which resolves `host` using `resolver`.
+    pub fn with_resolver(
+        host: String,
+        resolve_period: Duration,
+        resolver: impl Fn(&str) -> io::Result<SocketAddr> + Send + Sync + 'static,
+    ) -> UdpTelemetry {
+        UdpTelemetry {
+            host,
+            resolve_period,
+            resolver: Box::new(resolver),
+            route: RwLock::new(None),
+            stale_route: AtomicBool::new(false),
+            failing: AtomicBool::new(false),
+        }
+    }
+
+    /// Send a message as a single datagram to the address the host name last resolved to.
+    /// There is no guarantee that the message will be received.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the datagram could not be sent, for instance because
+    /// the message is too large or the network is unreachable, or if the host name has never been
+    /// resolved.
+    pub fn send(&self, message: &Message) -> io::Result<()> {
+        let datagram = serde_json::to_vec(message)?;
+        let route = self
+            .route
+            .read()
+            .map_err(route_poisoned)?
+            .clone()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} has not been resolved yet", self.host),
+                )
+            })?;
+        if let Err(e) = route.socket.send_to(&datagram, route.target) {
+            // the receiver may have moved, so look it up again before waiting out the period
+            self.stale_route.store(true, Ordering::Relaxed);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Resolve the host name, and publish the new route for `send` to use.
+    /// A new socket is only bound if the address family has changed.
+    ///
+    /// If the host name cannot be resolved, the last route is kept, since a stale address is
+    /// better than none and may well still be right.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if the host name cannot be resolved or a socket cannot be
+    /// bound.
+    pub fn resolve(&self) -> io::Result<()> {
+        let target = (self.resolver)(&self.host)?;
+        let current = self.route.read().map_err(route_poisoned)?.clone();
+        let socket = match current {
+            Some(route) if route.target.is_ipv4() == target.is_ipv4() => {
+                route.socket.try_clone()?
+            }
+            _ => UdpSocket::bind(match target {
+                SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            })?,
+        };
+        *self.route.write().map_err(route_poisoned)? = Some(Arc::new(Route { socket, target }));
+        Ok(())
+    }
+
+    /// Resolve the host name every resolve period, and whenever a datagram fails to send, until
+    /// the controller quits.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the state lock is poisoned.
+    pub fn resolve_watch(&self, state: &Guard) -> Result<(), crate::state::Error> {
+        loop {
+            self.stale_route.store(false, Ordering::Relaxed);
+            // a host which cannot be resolved is tried again soon, rather than after a whole period
+            let wait = match self.resolve() {
+                Ok(()) => self.resolve_period,
+                Err(_) => RESOLVE_POLL_PERIOD,
+            };
+            let deadline = Instant::now() + wait;
+            loop {
+                let status = state.status()?;
+                if status == State::Quit {
+                    return Ok(());
+                }
+                let now = Instant::now();
+                if now >= deadline || self.stale_route.load(Ordering::Relaxed) {
+                    break;
+                }
+                state.wait_while(status, RESOLVE_POLL_PERIOD.min(deadline - now))?;
+            }
+        }
+    }
+
+    /// Record whether the most recent datagram failed to send.
+    /// Returns `true` if telemetry has just started or stopped failing, so that each outage can be
+    /// reported once rather than for every datagram.
+    pub fn set_failing(&self, failing: bool) -> bool {
+        self.failing.swap(failing, Ordering::Relaxed) != failing
+    }
 }
 
 #[derive(Debug)]
@@ -100,55 +702,157 @@ impl<C: Write, M: Write> DashChannel<C, M> {
         DashChannel {
             dash_channel: Arc::new(RwLock::new(None)),
             message_log: Mutex::new(message_log),
+            raw_capture: None,
+            log_queue: None,
+            sensor_fields: Mutex::new(SensorValueFields::default()),
+        }
+    }
+
+    /// Construct a new `DashChannel` with no outgoing channel, which additionally captures the
+    /// exact bytes of every message sent to `raw_capture`.
+    pub fn with_capture(message_log: M, raw_capture: M) -> DashChannel<C, M> {
+        DashChannel {
+            raw_capture: Some(Mutex::new(raw_capture)),
+            ..DashChannel::new(message_log)
+        }
+    }
+
+    #[must_use]
+    /// Make this channel write its message log in the background, so that a slow disk never
+    /// delays messages to the dashboard.
+    /// Up to `capacity` sent messages may wait to be logged; if any more are sent while the queue
+    /// is full, they are sent but not logged.
+    ///
+    /// The queue is only emptied while `write_queued_log` is running.
+    pub fn with_log_queue(self, capacity: usize) -> DashChannel<C, M> {
+        let (sender, receiver) = sync_channel(capacity);
+        DashChannel {
+            log_queue: Some(LogQueue {
+                sender,
+                receiver: Mutex::new(receiver),
+                dropped: AtomicU64::new(0),
+            }),
+            ..self
         }
     }
 
     /// Write a message to the dashboard.
-    /// After writing the message, log that the message was written.
+    /// After writing the message, log that the message was written, or queue it to be logged if
+    /// this channel has a log queue.
     ///
     /// If writing the message to the dashboard
     ///     
     /// # Errors
     ///
-    /// This function will return an `Err` if we are unable to write to the message log.
+    /// This function will return an `Err` if we are unable to write to the message log or the raw
+    /// capture.
     ///
     /// # Panics
     ///
-    /// This function will panic if the current time is before the UNIX epoch.
+    /// This function will panic if the current time is before the UNIX epoch, or if the message
+    /// cannot be serialized (which would be a critical logic error).
     pub fn send(&self, message: &Message) -> Result<(), Error> {
         let mut channel_guard = self.dash_channel.write().map_err(|_| Error::Poison)?;
-        let mut message_log_guard = self.message_log.lock().map_err(|_| Error::Poison)?;
         if let Some(ref mut writer) = *channel_guard {
-            match serde_json::to_writer(&mut *writer, message) {
-                Ok(()) => {
-                    // log that we sent this message to the dashboard
-                    // first, mark the time
-                    write!(
-                        message_log_guard,
-                        "{},",
-                        SystemTime::now()
-                            .duration_since(SystemTime::UNIX_EPOCH)
-                            .unwrap()
-                            .as_nanos()
-                    )
-                    .map_err(Error::LogFile)?;
-                    // then, the message
-                    serde_json::to_writer(&mut *message_log_guard, message)
-                        .map_err(|e| Error::LogFile(std::io::Error::from(e)))?;
-                    // then a trailing newline
-                    writeln!(message_log_guard).map_err(Error::LogFile)?;
-                }
-                Err(e) => {
-                    // we should expect that the only thing preventing us from interfacing with the
-                    // dashboard is I/O.
-                    // Anything else is a sign of a critical logic error.
-                    assert_eq!(e.classify(), serde_json::error::Category::Io);
-                    // the connection was closed
-                    *channel_guard = None;
+            // serialize once, so that the bytes logged are exactly the bytes sent
+            let bytes = serde_json::to_vec(message).expect("outgoing messages must serialize");
+            if writer.write_all(&bytes).is_ok() {
+                let time = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos();
+                match &self.log_queue {
+                    Some(queue) => {
+                        // never wait for the log; a full queue means the disk can't keep up
+                        if let Err(TrySendError::Full(_)) = queue.sender.try_send((time, bytes)) {
+                            queue.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    None => self.log(time, &bytes)?,
                 }
+            } else {
+                // the only thing preventing us from writing to the dashboard is I/O, meaning that
+                // the connection was closed
+                *channel_guard = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record that a message was sent in the message log and the raw capture, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if we are unable to write to the message log or the raw
+    /// capture, or if an internal lock is poisoned.
+    fn log(&self, time: u128, bytes: &[u8]) -> Result<(), Error> {
+        let mut message_log_guard = self.message_log.lock().map_err(|_| Error::Poison)?;
+        // first, mark the time
+        write!(message_log_guard, "{time},").map_err(Error::LogFile)?;
+        // then, the message
+        message_log_guard.write_all(bytes).map_err(Error::LogFile)?;
+        // then a trailing newline
+        writeln!(message_log_guard).map_err(Error::LogFile)?;
+
+        if let Some(raw_capture) = &self.raw_capture {
+            let mut raw_capture_guard = raw_capture.lock().map_err(|_| Error::Poison)?;
+            writeln!(raw_capture_guard, "{time},{}", bytes.len()).map_err(Error::LogFile)?;
+            raw_capture_guard.write_all(bytes).map_err(Error::LogFile)?;
+            writeln!(raw_capture_guard).map_err(Error::LogFile)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write every message waiting in the log queue to the message log, waiting up to `timeout`
+    /// for the first one to arrive.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if we are unable to write to the message log or if an
+    /// internal lock is poisoned.
+    fn drain_log_queue(&self, timeout: Duration) -> Result<(), Error> {
+        let Some(queue) = &self.log_queue else {
+            return Ok(());
+        };
+        let receiver = queue.receiver.lock().map_err(|_| Error::Poison)?;
+        if let Ok((time, bytes)) = receiver.recv_timeout(timeout) {
+            self.log(time, &bytes)?;
+            while let Ok((time, bytes)) = receiver.try_recv() {
+                self.log(time, &bytes)?;
             }
         }
+        Ok(())
+    }
 
+    /// Write queued messages to the message log in the background until the controller quits.
+    /// Does nothing if this channel has no log queue.
+    ///
+    /// Whenever messages had to be dropped from the log because the queue was full, a warning is
+    /// written to the user log.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if we are unable to write to the message log or the user
+    /// log, or if a lock is poisoned.
+    pub fn write_queued_log(
+        &self,
+        user_log: &UserLog<impl Write>,
+        state: &Guard,
+    ) -> Result<(), ControllerError> {
+        let Some(queue) = &self.log_queue else {
+            return Ok(());
+        };
+        while state.status()? != State::Quit {
+            self.drain_log_queue(LOG_QUEUE_POLL_PERIOD)?;
+            let dropped = queue.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                user_log.warn(&format!(
+                    "Message log could not keep up; {dropped} sent message(s) were not logged"
+                ))?;
+            }
+        }
         Ok(())
     }
 
@@ -161,6 +865,47 @@ impl<C: Write, M: Write> DashChannel<C, M> {
         Ok(self.dash_channel.read()?.is_some())
     }
 
+    /// Get which values of each sensor reading should be sent to the current dashboard.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if an internal lock is poisoned.
+    pub fn sensor_fields(&self) -> Result<SensorValueFields, Error> {
+        Ok(*self.sensor_fields.lock().map_err(|_| Error::Poison)?)
+    }
+
+    /// Set which values of each sensor reading should be sent to the current dashboard.
+    /// The choice lasts until it is set again, which the client handler does for every new
+    /// connection.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if an internal lock is poisoned.
+    pub fn set_sensor_fields(&self, fields: SensorValueFields) -> Result<(), Error> {
+        *self.sensor_fields.lock().map_err(|_| Error::Poison)? = fields;
+        Ok(())
+    }
+
+    /// Tell the dashboard that the controller is shutting down, and then close the outgoing channel.
+    /// The shutdown message is flushed before the channel is closed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Err` if we are unable to write to the message log or if an
+    /// internal lock is poisoned.
+    pub fn close(&self, reason: &str) -> Result<(), Error> {
+        self.send(&Message::Shutdown { reason })?;
+        // the background log writer may already have stopped, so log anything it left behind
+        self.drain_log_queue(Duration::ZERO)?;
+        let mut channel_guard = self.dash_channel.write().map_err(|_| Error::Poison)?;
+        if let Some(ref mut writer) = *channel_guard {
+            // the dashboard may already be gone, in which case there is nobody left to tell
+            let _ = writer.flush();
+        }
+        *channel_guard = None;
+        Ok(())
+    }
+
     /// Set the outgoing channel for this stream to be `channel`.
     ///
     /// # Errors
@@ -175,6 +920,52 @@ impl<C: Write, M: Write> DashChannel<C, M> {
     }
 }
 
+#[cfg(test)]
+#[derive(Clone, Default)]
+/// A writer which captures every message sent through a `DashChannel`, so that tests can assert
+/// the exact sequence of messages the controller sends to the dashboard.
+///
+/// Clones share the same buffer, so a test can keep one clone while the channel writes to another.
+pub struct Capture(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Capture {
+    /// Decode every message captured so far, in the order in which they were sent.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the captured bytes are not a sequence of JSON values.
+    pub fn messages(&self) -> Vec<serde_json::Value> {
+        serde_json::Deserializer::from_slice(&self.0.lock().unwrap())
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    /// Get the type of every message captured so far, in the order in which they were sent.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if a captured message has no type.
+    pub fn types(&self) -> Vec<String> {
+        self.messages()
+            .iter()
+            .map(|message| message["type"].as_str().unwrap().to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -192,6 +983,30 @@ mod tests {
         assert_eq!(message_value, expected_value);
     }
 
+    #[test]
+    /// Test that a capture decodes the messages sent through a channel back in order, including
+    /// ones sent while a clone of it is being read.
+    fn capture_sequence() {
+        let capture = Capture::default();
+        let channel = DashChannel::<Capture, Vec<u8>>::new(Vec::new());
+        channel.set_channel(Some(capture.clone())).unwrap();
+
+        channel.send(&Message::Ready).unwrap();
+        assert_eq!(capture.types(), ["Ready"]);
+        channel
+            .send(&Message::Ack {
+                command_id: 4,
+                success: true,
+            })
+            .unwrap();
+        channel.send(&Message::HoldStatus { held: false }).unwrap();
+
+        let messages = capture.messages();
+        assert_eq!(capture.types(), ["Ready", "Ack", "HoldStatus"]);
+        assert_eq!(messages[1]["command_id"], 4);
+        assert_eq!(messages[2]["held"], false);
+    }
+
     #[test]
     /// Test that a sensor value message is serialized correctly.
     fn serialize_sensor_value() {
@@ -203,24 +1018,154 @@ mod tests {
                     {
                         "sensor_id": 0,
                         "reading": 3456,
+                        "value": 345.6,
                         "time": {
                             "secs_since_epoch": 1651355351,
                             "nanos_since_epoch": 534000000
-                        } 
+                        },
+                        "offset_ns": null,
+                        "saturated": false,
+                        "stale": false
+                    },
+                    {
+                        "sensor_id": 1,
+                        "reading": 4095,
+                        "value": 409.5,
+                        "time": {
+                            "secs_since_epoch": 1651355351,
+                            "nanos_since_epoch": 535000000
+                        },
+                        "offset_ns": null,
+                        "saturated": true,
+                        "stale": true
                     }
-                ]
+                ],
+                "skew_ns": null
             }"#,
             &Message::SensorValue {
                 group_id: 0,
-                readings: &[SensorReading {
-                    sensor_id: 0,
-                    reading: 3456,
-                    time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_651_355_351_534),
-                }],
+                readings: ReadingList {
+                    readings: &[
+                        SensorReading {
+                            sensor_id: 0,
+                            reading: 3456,
+                            value: 345.6,
+                            time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_651_355_351_534),
+                            offset_ns: None,
+                            saturated: false,
+                            stale: false,
+                        },
+                        SensorReading {
+                            sensor_id: 1,
+                            reading: 4095,
+                            value: 409.5,
+                            time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_651_355_351_535),
+                            offset_ns: None,
+                            saturated: true,
+                            stale: true,
+                        },
+                    ],
+                    fields: SensorValueFields::Both,
+                },
+                skew_ns: None,
             },
         );
     }
 
+    /// A single sensor reading, for testing which of its values are serialized.
+    const SELECTED_READING: SensorReading = SensorReading {
+        sensor_id: 2,
+        reading: 1234,
+        value: 12.34,
+        time: SystemTime::UNIX_EPOCH,
+        offset_ns: Some(50),
+        saturated: false,
+        stale: false,
+    };
+
+    #[test]
+    /// Test that only the raw value of each reading is serialized when only raw values are
+    /// selected.
+    fn serialize_raw_sensor_value() {
+        serialize_helper(
+            r#"{
+                "type": "SensorValue",
+                "group_id": 1,
+                "readings": [
+                    {
+                        "sensor_id": 2,
+                        "reading": 1234,
+                        "time": {
+                            "secs_since_epoch": 0,
+                            "nanos_since_epoch": 0
+                        },
+                        "offset_ns": 50,
+                        "saturated": false,
+                        "stale": false
+                    }
+                ],
+                "skew_ns": 50
+            }"#,
+            &Message::SensorValue {
+                group_id: 1,
+                readings: ReadingList {
+                    readings: &[SELECTED_READING],
+                    fields: SensorValueFields::Raw,
+                },
+                skew_ns: Some(50),
+            },
+        );
+    }
+
+    #[test]
+    /// Test that only the calibrated value of each reading is serialized when only calibrated
+    /// values are selected.
+    fn serialize_calibrated_sensor_value() {
+        serialize_helper(
+            r#"{
+                "type": "SensorValue",
+                "group_id": 1,
+                "readings": [
+                    {
+                        "sensor_id": 2,
+                        "value": 12.34,
+                        "time": {
+                            "secs_since_epoch": 0,
+                            "nanos_since_epoch": 0
+                        },
+                        "offset_ns": 50,
+                        "saturated": false,
+                        "stale": false
+                    }
+                ],
+                "skew_ns": 50
+            }"#,
+            &Message::SensorValue {
+                group_id: 1,
+                readings: ReadingList {
+                    readings: &[SELECTED_READING],
+                    fields: SensorValueFields::Calibrated,
+                },
+                skew_ns: Some(50),
+            },
+        );
+    }
+
+    #[test]
+    /// Test that a reading with both of its values selected is serialized exactly as the reading
+    /// itself.
+    fn serialize_both_sensor_values() {
+        let readings = [SELECTED_READING];
+        assert_eq!(
+            serde_json::to_string(&ReadingList {
+                readings: &readings,
+                fields: SensorValueFields::Both,
+            })
+            .unwrap(),
+            serde_json::to_string(&readings).unwrap()
+        );
+    }
+
     #[test]
     /// Test that a driver value message is serialized correctly.
     fn serialize_driver_value() {
@@ -234,8 +1179,513 @@ mod tests {
                 ]
             }"#,
             &Message::DriverValue {
-                values: &[false, true, false],
+                values: &[
+                    DriverState::Binary(false),
+                    DriverState::Binary(true),
+                    DriverState::Binary(false),
+                ],
             },
         );
     }
+
+    #[test]
+    /// Test that a status report is serialized with every part of the controller's status.
+    fn serialize_status_report() {
+        serialize_helper(
+            r#"{
+                "type": "StatusReport",
+                "state": "Ignite",
+                "armed": true,
+                "uptime_ms": 93000,
+                "drivers": [true, { "Duty": 0.5 }],
+                "time": {
+                    "secs_since_epoch": 1651355351,
+                    "nanos_since_epoch": 0
+                },
+                "sensors": [
+                    [
+                        { "value": 14.7, "saturated": false, "stale": true },
+                        null
+                    ],
+                    [{ "value": 5000.0, "saturated": true, "stale": false }]
+                ],
+                "virtual_sensors": [[12.5], []],
+                "failed_groups": [1],
+                "sequences": [[0, 1]],
+                "held": false,
+                "paused": true,
+                "ready": true,
+                "estop_count": 2,
+                "interlock_overrides": [1]
+            }"#,
+            &Message::StatusReport {
+                state: State::Ignite,
+                armed: true,
+                uptime_ms: 93_000,
+                drivers: &[DriverState::Binary(true), DriverState::Duty(0.5)],
+                time: SystemTime::UNIX_EPOCH + Duration::from_secs(1_651_355_351),
+                sensors: &[
+                    vec![
+                        Some(SensorStatus {
+                            value: 14.7,
+                            saturated: false,
+                            stale: true,
+                        }),
+                        None,
+                    ],
+                    vec![Some(SensorStatus {
+                        value: 5000.0,
+                        saturated: true,
+                        stale: false,
+                    })],
+                ],
+                virtual_sensors: &[vec![Some(12.5)], vec![]],
+                failed_groups: &[1],
+                sequences: &[vec![0, 1]],
+                held: false,
+                paused: true,
+                ready: true,
+                estop_count: 2,
+                interlock_overrides: &[1],
+            },
+        );
+    }
+
+    #[test]
+    /// Test that an inventory message is serialized correctly.
+    fn serialize_inventory() {
+        serialize_helper(
+            r#"{
+                "type": "Inventory",
+                "drivers": [
+                    {
+                        "driver_id": 0,
+                        "label": "IGNITER",
+                        "kind": "Binary",
+                        "hazardous": true,
+                        "state": false
+                    },
+                    {
+                        "driver_id": 1,
+                        "label": "PUMP",
+                        "kind": "Pwm",
+                        "hazardous": false,
+                        "state": { "Duty": 0.5 }
+                    }
+                ],
+                "sensors": [
+                    {
+                        "group_id": 0,
+                        "sensor_id": 0,
+                        "label": "PT1",
+                        "units": "psi",
+                        "calibration": {
+                            "type": "Linear",
+                            "slope": 2.5,
+                            "intercept": -10.0,
+                            "vref": 5.0
+                        }
+                    },
+                    {
+                        "group_id": 1,
+                        "sensor_id": 0,
+                        "label": "TC1",
+                        "units": "C",
+                        "calibration": { "type": "Thermistor" }
+                    }
+                ]
+            }"#,
+            &Message::Inventory {
+                drivers: &[
+                    DriverInfo {
+                        driver_id: 0,
+                        label: "IGNITER",
+                        kind: DriverKind::Binary,
+                        hazardous: true,
+                        state: DriverState::Binary(false),
+                    },
+                    DriverInfo {
+                        driver_id: 1,
+                        label: "PUMP",
+                        kind: DriverKind::Pwm,
+                        hazardous: false,
+                        state: DriverState::Duty(0.5),
+                    },
+                ],
+                sensors: &[
+                    SensorInfo {
+                        group_id: 0,
+                        sensor_id: 0,
+                        label: "PT1",
+                        units: "psi",
+                        calibration: CalibrationSummary::Linear {
+                            slope: 2.5,
+                            intercept: -10.0,
+                            vref: Some(5.0),
+                        },
+                    },
+                    SensorInfo {
+                        group_id: 1,
+                        sensor_id: 0,
+                        label: "TC1",
+                        units: "C",
+                        calibration: CalibrationSummary::Thermistor,
+                    },
+                ],
+            },
+        );
+    }
+
+    #[test]
+    /// Test that a driver change message is serialized correctly.
+    fn serialize_driver_change() {
+        serialize_helper(
+            r#"{
+                "type": "DriverChange",
+                "driver_id": 2,
+                "level": true,
+                "time": {
+                    "secs_since_epoch": 1651355351,
+                    "nanos_since_epoch": 1234
+                }
+            }"#,
+            &Message::DriverChange {
+                driver_id: 2,
+                level: true,
+                time: SystemTime::UNIX_EPOCH + Duration::new(1_651_355_351, 1234),
+            },
+        );
+    }
+
+    #[test]
+    /// Test that a driver value message with PWM and servo drivers is serialized correctly.
+    fn serialize_driver_value_mixed() {
+        serialize_helper(
+            r#"{
+                "type": "DriverValue",
+                "values": [
+                    true,
+                    { "Duty": 0.5 },
+                    { "Position": 1.0 }
+                ]
+            }"#,
+            &Message::DriverValue {
+                values: &[
+                    DriverState::Binary(true),
+                    DriverState::Duty(0.5),
+                    DriverState::Position(1.0),
+                ],
+            },
+        );
+    }
+
+    #[test]
+    /// Test that an acknowledgement message is serialized correctly.
+    fn serialize_ack() {
+        serialize_helper(
+            r#"{
+                "type": "Ack",
+                "command_id": 17,
+                "success": true
+            }"#,
+            &Message::Ack {
+                command_id: 17,
+                success: true,
+            },
+        );
+    }
+
+    #[test]
+    /// Test that a forwarded console entry is serialized correctly.
+    fn serialize_console_entry() {
+        serialize_helper(
+            r#"{
+                "type": "Error",
+                "level": "Critical",
+                "message": "oh no"
+            }"#,
+            &Message::Error {
+                level: LogLevel::Critical,
+                message: "oh no",
+            },
+        );
+    }
+
+    #[test]
+    /// Test that driver states are derived correctly from the driver kind and logic level.
+    fn driver_state_from_kind() {
+        assert_eq!(
+            DriverState::new(DriverKind::Binary, true),
+            DriverState::Binary(true)
+        );
+        assert_eq!(
+            DriverState::new(DriverKind::Pwm, false),
+            DriverState::Duty(0.0)
+        );
+        assert_eq!(
+            DriverState::new(DriverKind::Servo, true),
+            DriverState::Position(1.0)
+        );
+    }
+
+    #[test]
+    /// Test that a message sent over UDP telemetry arrives as a single JSON datagram.
+    fn udp_telemetry() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let telemetry = UdpTelemetry::new(
+            receiver.local_addr().unwrap().to_string(),
+            Duration::from_secs(30),
+        );
+        telemetry.resolve().unwrap();
+
+        let message = Message::SensorValue {
+            group_id: 1,
+            readings: ReadingList {
+                readings: &[SensorReading {
+                    sensor_id: 2,
+                    reading: 1234,
+                    value: 1.234,
+                    time: SystemTime::UNIX_EPOCH,
+                    offset_ns: None,
+                    saturated: false,
+                    stale: false,
+                }],
+                fields: SensorValueFields::Both,
+            },
+            skew_ns: None,
+        };
+        telemetry.send(&message).unwrap();
+
+        let mut buf = [0; 1024];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&buf[..len]).unwrap(),
+            serde_json::to_value(&message).unwrap()
+        );
+    }
+
+    #[test]
+    /// Test that telemetry follows a host name to its new address once it is resolved again, and
+    /// keeps using the old address while it cannot be resolved.
+    /// Sending never resolves the host name itself.
+    fn udp_telemetry_reresolve() {
+        let receive = |socket: &UdpSocket| {
+            socket
+                .set_read_timeout(Some(Duration::from_millis(200)))
+                .unwrap();
+            socket.recv(&mut [0; 1024]).is_ok()
+        };
+        let old = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let new = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolution = Arc::new(Mutex::new(Some(old.local_addr().unwrap())));
+        let telemetry =
+            UdpTelemetry::with_resolver("receiver.local:2708".into(), Duration::from_secs(30), {
+                let resolution = Arc::clone(&resolution);
+                move |host| {
+                    assert_eq!(host, "receiver.local:2708");
+                    resolution
+                        .lock()
+                        .unwrap()
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such host"))
+                }
+            });
+        let message = Message::Ready;
+
+        // nothing can be sent until the host name has been resolved
+        assert!(telemetry.send(&message).is_err());
+        telemetry.resolve().unwrap();
+        telemetry.send(&message).unwrap();
+        assert!(receive(&old));
+
+        // the receiver moves, but the old address is used until it is resolved again
+        *resolution.lock().unwrap() = Some(new.local_addr().unwrap());
+        telemetry.send(&message).unwrap();
+        assert!(receive(&old));
+
+        telemetry.resolve().unwrap();
+        telemetry.send(&message).unwrap();
+        assert!(receive(&new));
+
+        // a failed lookup keeps the last known address
+        *resolution.lock().unwrap() = None;
+        assert!(telemetry.resolve().is_err());
+        telemetry.send(&message).unwrap();
+        assert!(receive(&new));
+        assert!(!receive(&old));
+    }
+
+    #[test]
+    /// Test that a configuration message carries the display metadata of each sensor, with
+    /// defaults for the metadata which was omitted.
+    fn serialize_config_display_metadata() {
+        let config = Configuration::parse(&mut io::Cursor::new(
+            r#"{
+                "frequency_status": 1,
+                "log_buffer_size": 1,
+                "sensor_groups": [
+                    {
+                        "label": "FAST",
+                        "frequency_standby": 10,
+                        "frequency_ignition": 1000,
+                        "frequency_transmission": 10,
+                        "sensors": [
+                            {
+                                "label": "PT1",
+                                "color": "red",
+                                "units": "psi",
+                                "calibration_intercept": 0.0,
+                                "calibration_slope": 1.0,
+                                "display_min": 0.0,
+                                "display_max": 1000.0,
+                                "vref": 3.3,
+                                "adc": 0,
+                                "channel": 0
+                            },
+                            {
+                                "label": "RAW",
+                                "color": "blue",
+                                "calibration_intercept": 0.0,
+                                "calibration_slope": 1.0,
+                                "adc": 0,
+                                "channel": 1
+                            }
+                        ]
+                    }
+                ],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [5],
+                "pin_heartbeat": 0
+            }"#,
+        ))
+        .unwrap();
+
+        let message = serde_json::to_value(Message::Config {
+            config: &config,
+            protocol_version: PROTOCOL_VERSION,
+            controller_version: CONTROLLER_VERSION,
+        })
+        .unwrap();
+        assert_eq!(message["protocol_version"], PROTOCOL_VERSION);
+        let version = message["controller_version"].as_str().unwrap();
+        assert!(version.starts_with(concat!(env!("CARGO_PKG_VERSION"), "+")));
+        assert!(version.len() > env!("CARGO_PKG_VERSION").len() + 1);
+        let sensors = &message["config"]["sensor_groups"][0]["sensors"];
+        assert_eq!(sensors[0]["units"], "psi");
+        assert_eq!(sensors[0]["display_min"], 0.0);
+        assert_eq!(sensors[0]["display_max"], 1000.0);
+        assert_eq!(sensors[0]["vref"], 3.3);
+        assert_eq!(sensors[1]["units"], "");
+        assert_eq!(sensors[1]["display_min"], Value::Null);
+        assert_eq!(sensors[1]["display_max"], Value::Null);
+        assert_eq!(sensors[1]["vref"], Value::Null);
+    }
+
+    #[test]
+    /// Test that closing a channel sends a shutdown message, and that nothing is sent afterward.
+    fn close_sends_shutdown() {
+        let mut sent = Vec::new();
+        let channel = DashChannel::<&mut Vec<u8>, Vec<u8>>::new(Vec::new());
+        channel.set_channel(Some(&mut sent)).unwrap();
+
+        channel.close("controller exiting").unwrap();
+        assert!(!channel.has_target().unwrap());
+        channel.send(&Message::HoldStatus { held: true }).unwrap();
+        drop(channel);
+
+        assert_eq!(
+            serde_json::from_slice::<Value>(&sent).unwrap(),
+            serde_json::json!({
+                "type": "Shutdown",
+                "reason": "controller exiting"
+            })
+        );
+    }
+
+    #[test]
+    /// Test that the raw capture records exactly the bytes sent to the dashboard.
+    fn raw_capture() {
+        let mut sent = Vec::new();
+        let channel = DashChannel::<&mut Vec<u8>, Vec<u8>>::with_capture(Vec::new(), Vec::new());
+        channel.set_channel(Some(&mut sent)).unwrap();
+
+        channel.send(&Message::HoldStatus { held: true }).unwrap();
+        channel.send(&Message::HoldStatus { held: false }).unwrap();
+        let capture = channel.raw_capture.unwrap().into_inner().unwrap();
+
+        // strip the header line and trailing newline from each record
+        let mut captured = Vec::new();
+        let mut rest = capture.as_slice();
+        while !rest.is_empty() {
+            let header_end = rest.iter().position(|&b| b == b'\n').unwrap();
+            let header = std::str::from_utf8(&rest[..header_end]).unwrap();
+            let len: usize = header.split(',').nth(1).unwrap().parse().unwrap();
+            captured.extend_from_slice(&rest[header_end + 1..header_end + 1 + len]);
+            assert_eq!(rest[header_end + 1 + len], b'\n');
+            rest = &rest[header_end + len + 2..];
+        }
+
+        assert_eq!(captured, sent);
+    }
+
+    #[test]
+    /// Test that messages are sent to the dashboard without waiting for a slow message log, and
+    /// that messages which cannot be queued for the log are dropped from it.
+    fn slow_message_log() {
+        /// A message log which takes a long time to write anything.
+        struct SlowLog(Vec<u8>);
+
+        impl Write for SlowLog {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                std::thread::sleep(Duration::from_millis(10));
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut sent = Vec::new();
+        let channel =
+            DashChannel::<&mut Vec<u8>, SlowLog>::new(SlowLog(Vec::new())).with_log_queue(4);
+        channel.set_channel(Some(&mut sent)).unwrap();
+        let state = Guard::new(State::Standby);
+        let user_log = UserLog::new(Vec::new());
+
+        std::thread::scope(|s| {
+            let writer = s.spawn(|| channel.write_queued_log(&user_log, &state));
+
+            let start = std::time::Instant::now();
+            for _ in 0..20 {
+                channel.send(&Message::HoldStatus { held: true }).unwrap();
+            }
+            // logging even one message takes 30 ms
+            assert!(start.elapsed() < Duration::from_millis(30));
+
+            state.move_to(State::Quit).unwrap();
+            writer.join().unwrap().unwrap();
+        });
+        channel.close("test over").unwrap();
+
+        let logged = channel.message_log.into_inner().unwrap().0;
+        let logged_lines = std::str::from_utf8(&logged).unwrap().lines().count();
+        assert!(0 < logged_lines && logged_lines < 21);
+        assert_eq!(
+            serde_json::Deserializer::from_slice(&sent)
+                .into_iter::<Value>()
+                .count(),
+            21
+        );
+    }
 }
@@ -0,0 +1,421 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Hot reloading of the configuration file.
+//!
+//! Only fields which are safe to change while the controller is running (sensor calibrations,
+//! ranges, and rolling average widths) are reloaded.
+//! Changes to any other field, such as pin assignments, require a restart.
+
+use std::{
+    fs::{metadata, File},
+    io::{BufReader, Write},
+    path::Path,
+    sync::{RwLock, RwLockReadGuard},
+    thread::sleep,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    calibration::{self, LinearCalibration},
+    config::{Configuration, Sensor},
+    console::UserLog,
+    outgoing::{DashChannel, Message},
+    state::{Guard, State},
+    ControllerError,
+};
+
+/// The period at which the configuration file is checked for changes.
+const RELOAD_POLL_PERIOD: Duration = Duration::from_secs(1);
+
+/// The keys of a sensor's definition which may be changed without restarting the controller.
+const TUNING_KEYS: [&str; 4] = [
+    "range",
+    "calibration_intercept",
+    "calibration_slope",
+    "rolling_average_width",
+];
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq)]
+/// The parts of a sensor's definition which can be reloaded while the controller is running.
+/// Each field has the same meaning as the field of the same name in `Sensor`.
+pub struct SensorTuning {
+    /// The range of calibrated values outside of which an emergency stop is triggered.
+    pub range: Option<(f64, f64)>,
+    /// The intercept of the linear calibration function.
+    pub calibration_intercept: f64,
+    /// The slope of the linear calibration function.
+    pub calibration_slope: f64,
+    /// The width of the rolling average used to filter readings.
+    pub rolling_average_width: Option<u32>,
+}
+
+impl From<&Sensor> for SensorTuning {
+    fn from(sensor: &Sensor) -> Self {
+        SensorTuning {
+            range: sensor.range,
+            calibration_intercept: sensor.calibration_intercept,
+            calibration_slope: sensor.calibration_slope,
+            rolling_average_width: sensor.rolling_average_width,
+        }
+    }
+}
+
+/// The current tuning of every sensor, which may be replaced when the configuration is reloaded.
+pub struct Tunings {
+    /// The tuning of each sensor, indexed first by group ID and then by sensor ID.
+    tunings: RwLock<Vec<Vec<SensorTuning>>>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+/// The new tuning of a sensor which was changed by a reload.
+pub struct RetunedSensor {
+    /// The ID of the group containing the sensor.
+    pub group_id: u8,
+    /// The ID of the sensor within its group.
+    pub sensor_id: u8,
+    #[serde(flatten)]
+    /// The new tuning of the sensor.
+    pub tuning: SensorTuning,
+}
+
+impl Tunings {
+    #[must_use]
+    /// Construct a new `Tunings` containing the tuning of every sensor in `configuration`.
+    pub fn new(configuration: &Configuration) -> Tunings {
+        Tunings {
+            tunings: RwLock::new(tunings_of(configuration)),
+        }
+    }
+
+    /// Get read access to the tuning of every sensor, indexed first by group ID and then by sensor
+    /// ID.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    pub fn read(&self) -> Result<RwLockReadGuard<Vec<Vec<SensorTuning>>>, ControllerError> {
+        Ok(self.tunings.read()?)
+    }
+
+    /// Replace the tuning of every sensor with the tunings in `configuration`, returning the
+    /// sensors whose tunings changed.
+    ///
+    /// `configuration` must have exactly the same sensors as the configuration used to construct
+    /// this `Tunings`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    pub fn apply(
+        &self,
+        configuration: &Configuration,
+    ) -> Result<Vec<RetunedSensor>, ControllerError> {
+        let new_tunings = tunings_of(configuration);
+        let mut tunings = self.tunings.write()?;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let retuned = new_tunings
+            .iter()
+            .zip(tunings.iter())
+            .enumerate()
+            .flat_map(|(group_id, (new_group, old_group))| {
+                new_group
+                    .iter()
+                    .zip(old_group)
+                    .enumerate()
+                    .filter(|(_, (new, old))| new != old)
+                    .map(move |(sensor_id, (&tuning, _))| RetunedSensor {
+                        group_id: group_id as u8,
+                        sensor_id: sensor_id as u8,
+                        tuning,
+                    })
+            })
+            .collect();
+
+        *tunings = new_tunings;
+        Ok(retuned)
+    }
+
+    /// Replace the linear calibration of sensor `sensor_id` in group `group_id` with
+    /// `calibration`, returning the sensor's new tuning.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the sensor does not exist.
+    pub fn calibrate(
+        &self,
+        group_id: u8,
+        sensor_id: u8,
+        calibration: LinearCalibration,
+    ) -> Result<SensorTuning, ControllerError> {
+        let mut tunings = self.tunings.write()?;
+        let tuning = &mut tunings[usize::from(group_id)][usize::from(sensor_id)];
+        tuning.calibration_intercept = calibration.calibration_intercept;
+        tuning.calibration_slope = calibration.calibration_slope;
+        Ok(*tuning)
+    }
+
+    /// Replace the range of sensor `sensor_id` in group `group_id` with `range`, returning the
+    /// sensor's new tuning.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the internal lock is poisoned.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the sensor does not exist.
+    pub fn set_range(
+        &self,
+        group_id: u8,
+        sensor_id: u8,
+        range: (f64, f64),
+    ) -> Result<SensorTuning, ControllerError> {
+        let mut tunings = self.tunings.write()?;
+        let tuning = &mut tunings[usize::from(group_id)][usize::from(sensor_id)];
+        tuning.range = Some(range);
+        Ok(*tuning)
+    }
+}
+
+/// Extract the tuning of every sensor in `configuration`.
+fn tunings_of(configuration: &Configuration) -> Vec<Vec<SensorTuning>> {
+    configuration
+        .sensor_groups
+        .iter()
+        .map(|group| group.sensors.iter().map(SensorTuning::from).collect())
+        .collect()
+}
+
+/// Determine which top-level fields differ between `running` and `reloaded`, ignoring the fields
+/// of each sensor which can be reloaded.
+/// Changing any of the returned fields requires a restart.
+///
+/// # Panics
+///
+/// This function will panic if either configuration cannot be serialized, which should never
+/// happen.
+fn restart_required(running: &Configuration, reloaded: &Configuration) -> Vec<String> {
+    let (Value::Object(running), Value::Object(reloaded)) =
+        (without_tuning(running), without_tuning(reloaded))
+    else {
+        unreachable!("configurations always serialize to objects");
+    };
+
+    running
+        .keys()
+        .chain(reloaded.keys().filter(|&key| !running.contains_key(key)))
+        .filter(|&key| running.get(key) != reloaded.get(key))
+        .cloned()
+        .collect()
+}
+
+/// Serialize `configuration`, omitting the fields of each sensor which can be reloaded.
+///
+/// # Panics
+///
+/// This function will panic if the configuration cannot be serialized.
+fn without_tuning(configuration: &Configuration) -> Value {
+    let mut value = serde_json::to_value(configuration).unwrap();
+    if let Some(Value::Array(groups)) = value.get_mut("sensor_groups") {
+        for group in groups {
+            if let Some(Value::Array(sensors)) = group.get_mut("sensors") {
+                for sensor in sensors.iter_mut().filter_map(Value::as_object_mut) {
+                    for key in TUNING_KEYS {
+                        sensor.remove(key);
+                    }
+                }
+            }
+        }
+    }
+    value
+}
+
+/// Watch the configuration file at `path`, and reload the tuning of every sensor whenever it
+/// changes.
+/// Will loop until the controller quits.
+///
+/// Reloads only take place in standby, so that a change to a sensor's range never takes effect
+/// partway through an ignition.
+/// If the file changes at any other time, it is reloaded once the controller returns to standby.
+/// Calibrations saved in the calibration file override those in the reloaded configuration, just
+/// as they do at startup.
+///
+/// # Inputs
+///
+/// * `path`: The path to the configuration file.
+/// * `configuration`: The configuration the controller is running with.
+/// * `tunings`: The current tuning of every sensor, which will be updated on a reload.
+/// * `user_log`: The log to which reloads and rejected changes are reported.
+/// * `state`: The overall system state.
+/// * `dashboard`: A channel by which a summary of each reload is sent to the dashboard.
+///
+/// # Errors
+///
+/// This function will return an error if a lock is poisoned or if we are unable to write to the
+/// user log or the dashboard.
+pub fn config_watch(
+    path: &Path,
+    configuration: &Configuration,
+    tunings: &Tunings,
+    user_log: &UserLog<impl Write>,
+    state: &Guard,
+    dashboard: &DashChannel<impl Write, impl Write>,
+) -> Result<(), ControllerError> {
+    let modified = || metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified: Option<SystemTime> = modified();
+
+    while state.status()? != State::Quit {
+        sleep(RELOAD_POLL_PERIOD);
+
+        let now_modified = modified();
+        if now_modified == last_modified || state.status()? != State::Standby {
+            continue;
+        }
+        last_modified = now_modified;
+
+        let reloaded = match File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|f| Configuration::parse(&mut BufReader::new(f)).map_err(|e| e.to_string()))
+            .and_then(|mut reloaded| {
+                calibration::apply_saved(&mut reloaded).map_err(|e| e.to_string())?;
+                Ok(reloaded)
+            }) {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                user_log.warn(&format!(
+                    "Configuration file changed, but could not be reloaded: {e}"
+                ))?;
+                continue;
+            }
+        };
+
+        let restart_required = restart_required(configuration, &reloaded);
+        if !restart_required.is_empty() {
+            user_log.warn(&format!(
+                "Changes to the following configuration fields require a restart and were not applied: {}",
+                restart_required.join(", ")
+            ))?;
+        }
+
+        // sensors can only be retuned if they are all still the same sensors
+        let retuned = if restart_required.iter().any(|key| key == "sensor_groups") {
+            Vec::new()
+        } else {
+            tunings.apply(&reloaded)?
+        };
+        for sensor in &retuned {
+            let label = &configuration.sensor_groups[usize::from(sensor.group_id)].sensors
+                [usize::from(sensor.sensor_id)]
+            .label;
+            user_log.info(&format!(
+                "Reloaded tuning of sensor {label}: {:?}",
+                sensor.tuning
+            ))?;
+        }
+        user_log.info(&format!(
+            "Reloaded configuration: retuned {} sensor(s)",
+            retuned.len()
+        ))?;
+
+        dashboard.send(&Message::ConfigReload {
+            sensors: &retuned,
+            restart_required: &restart_required,
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Construct a configuration with one sensor, whose calibration slope and ADC are given.
+    fn config(slope: f64, adc: u8) -> Configuration {
+        let config = format!(
+            r#"{{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [
+                {{
+                    "label": "FAST",
+                    "frequency_standby": 10,
+                    "frequency_ignition": 1000,
+                    "frequency_transmission": 10,
+                    "sensors": [
+                        {{
+                            "label": "PT1",
+                            "color": "red",
+                            "units": "psi",
+                            "calibration_intercept": 0.0,
+                            "calibration_slope": {slope:?},
+                            "range": [0.0, 100.0],
+                            "adc": {adc},
+                            "channel": 0
+                        }}
+                    ]
+                }}
+            ],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [5, 6],
+            "pin_heartbeat": 0
+        }}"#
+        );
+        Configuration::parse(&mut Cursor::new(config)).unwrap()
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    /// Test that calibration changes are applied, but that wiring changes require a restart.
+    fn reload_tuning() {
+        let running = config(1.0, 0);
+        let tunings = Tunings::new(&running);
+
+        let recalibrated = config(2.0, 0);
+        assert!(restart_required(&running, &recalibrated).is_empty());
+        let retuned = tunings.apply(&recalibrated).unwrap();
+        assert_eq!(retuned.len(), 1);
+        assert_eq!(retuned[0].tuning.calibration_slope, 2.0);
+        assert_eq!(tunings.read().unwrap()[0][0].calibration_slope, 2.0);
+
+        // applying the same configuration again changes nothing
+        assert!(tunings.apply(&recalibrated).unwrap().is_empty());
+
+        let rewired = config(2.0, 1);
+        assert_eq!(restart_required(&running, &rewired), ["sensor_groups"]);
+    }
+}
@@ -0,0 +1,356 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Rotation of sensor logs into segments of bounded size, and compression of the segments which
+//! are no longer being written.
+//!
+//! Compression happens on a dedicated thread, so that a sensor thread never waits on it.
+//! The segment being written is never compressed.
+
+use std::{
+    ffi::OsString,
+    fs::{remove_file, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use flate2::{write::GzEncoder, Compression};
+
+use crate::{
+    console::UserLog,
+    state::{Guard, State},
+    ControllerError,
+};
+
+/// The longest time for which the compression thread waits for a segment before checking whether
+/// the controller is quitting.
+const COMPRESS_POLL_PERIOD: Duration = Duration::from_millis(100);
+
+#[derive(Clone)]
+/// A handle to a log file, used only for syncing it to disk.
+///
+/// The handle given by a `RotatingLog` always refers to the segment currently being written.
+pub struct SyncHandle(Arc<Mutex<File>>);
+
+impl SyncHandle {
+    #[must_use]
+    /// Construct a new `SyncHandle` which syncs `file`.
+    /// `file` may be a clone of the handle used for writing, since syncing a clone syncs the whole
+    /// file.
+    pub fn new(file: File) -> SyncHandle {
+        SyncHandle(Arc::new(Mutex::new(file)))
+    }
+
+    /// Sync the file's contents and metadata to disk.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if syncing fails or if the internal lock is poisoned.
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.0.lock().map_err(|_| poisoned())?.sync_all()
+    }
+
+    /// Sync the file's contents to disk, along with only as much metadata as is needed to read
+    /// them back.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if syncing fails or if the internal lock is poisoned.
+    pub fn sync_data(&self) -> io::Result<()> {
+        self.0.lock().map_err(|_| poisoned())?.sync_data()
+    }
+}
+
+/// Convert a poisoned lock on a `SyncHandle` into an I/O error.
+fn poisoned() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "log sync handle lock was poisoned")
+}
+
+/// A sensor log which is split into segments of bounded size.
+///
+/// The first segment is the log's own file.
+/// Once a segment has grown past the rotation size, the next flush closes it and begins a new
+/// segment.
+/// Every row is written in full before a flush, so a row is never split between two segments.
+/// Each segment after the first has the segment's number inserted before the log's extension, so
+/// that `PT1.csv` is followed by `PT1.1.csv`, `PT1.2.csv`, and so on.
+pub struct RotatingLog {
+    /// The path of the first segment.
+    path: PathBuf,
+    /// The segment being written.
+    file: File,
+    /// The number of the segment being written, counting from 0.
+    segment: u32,
+    /// The number of bytes written to the segment being written.
+    written: u64,
+    /// The size past which a segment is closed, or `None` if the log is never rotated.
+    max_size: Option<u64>,
+    /// A handle to the segment being written, shared with the threads which sync logs to disk.
+    active: SyncHandle,
+    /// The channel on which each closed segment is sent to be compressed, if segments are
+    /// compressed.
+    rotated: Option<Sender<PathBuf>>,
+}
+
+impl RotatingLog {
+    /// Construct a new `RotatingLog` whose first segment is `file`, located at `path`.
+    /// A segment is closed once it is larger than `max_size` bytes, if given, and the path of each
+    /// closed segment is sent on `rotated`, if given.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `file` cannot be cloned for syncing.
+    pub fn new(
+        path: PathBuf,
+        file: File,
+        max_size: Option<u64>,
+        rotated: Option<Sender<PathBuf>>,
+    ) -> io::Result<RotatingLog> {
+        Ok(RotatingLog {
+            path,
+            active: SyncHandle::new(file.try_clone()?),
+            file,
+            segment: 0,
+            written: 0,
+            max_size,
+            rotated,
+        })
+    }
+
+    #[must_use]
+    /// Get a handle which syncs whichever segment of this log is being written.
+    pub fn sync_handle(&self) -> SyncHandle {
+        self.active.clone()
+    }
+
+    /// Close the segment being written and begin the next one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the next segment cannot be created, in which case
+    /// the current segment is kept.
+    fn rotate(&mut self) -> io::Result<()> {
+        let next = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(segment_path(&self.path, self.segment + 1))?;
+        *self.active.0.lock().map_err(|_| poisoned())? = next.try_clone()?;
+        drop(std::mem::replace(&mut self.file, next));
+
+        let closed = segment_path(&self.path, self.segment);
+        self.segment += 1;
+        self.written = 0;
+        if let Some(rotated) = &self.rotated {
+            // if the compression thread is gone, the segment is simply left uncompressed
+            let _ = rotated.send(closed);
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self
+            .max_size
+            .map_or(false, |max_size| self.written > max_size)
+        {
+            if let Err(e) = self.rotate() {
+                // give up on rotating, rather than failing every flush from now on
+                self.max_size = None;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the path of segment number `segment` of the log whose first segment is at `path`.
+fn segment_path(path: &Path, segment: u32) -> PathBuf {
+    if segment == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = path.file_stem().map(OsString::from).unwrap_or_default();
+    name.push(format!(".{segment}"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Compress each closed log segment received on `segments` until the controller quits.
+///
+/// # Errors
+///
+/// This function will return an error if the state lock or the lock on `segments` is poisoned, or
+/// if writing to the user log fails.
+pub fn compress_segments(
+    segments: &Mutex<Receiver<PathBuf>>,
+    state: &Guard,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    let segments = segments.lock()?;
+    while state.status()? != State::Quit {
+        match segments.recv_timeout(COMPRESS_POLL_PERIOD) {
+            Ok(path) => compress_logged(&path, user_log)?,
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Compress every closed log segment still waiting on `segments`, such as those closed while the
+/// controller was quitting.
+///
+/// # Errors
+///
+/// This function will return an error if the lock on `segments` is poisoned or if writing to the
+/// user log fails.
+pub fn compress_remaining(
+    segments: &Mutex<Receiver<PathBuf>>,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    for path in segments.lock()?.try_iter() {
+        compress_logged(&path, user_log)?;
+    }
+
+    Ok(())
+}
+
+/// Compress the log segment at `path`, reporting the outcome to `user_log`.
+/// A segment which cannot be compressed is left as it was.
+///
+/// # Errors
+///
+/// This function will return an error if writing to the user log fails.
+fn compress_logged(path: &Path, user_log: &UserLog<impl Write>) -> io::Result<()> {
+    match compress(path) {
+        Ok(compressed) => user_log.debug(&format!(
+            "Compressed log segment {} into {}",
+            path.display(),
+            compressed.display()
+        )),
+        Err(e) => user_log.warn(&format!(
+            "Unable to compress log segment {}, so it is left uncompressed: {e}",
+            path.display()
+        )),
+    }
+}
+
+/// Compress the file at `path` with gzip into a file of the same name with `.gz` appended, and
+/// then remove the original.
+/// The compressed file is synced to disk before the original is removed, so that no data is lost
+/// if power fails partway through.
+/// Returns the path of the compressed file.
+///
+/// # Errors
+///
+/// This function will return an error if reading the original, writing the compressed file, or
+/// removing the original fails.
+/// A partially written compressed file is removed.
+fn compress(path: &Path) -> io::Result<PathBuf> {
+    let mut compressed_name = path.as_os_str().to_owned();
+    compressed_name.push(".gz");
+    let compressed_path = PathBuf::from(compressed_name);
+
+    let compressed = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&compressed_path)?;
+    let result = File::open(path).and_then(|mut original| {
+        let mut encoder = GzEncoder::new(compressed, Compression::default());
+        io::copy(&mut original, &mut encoder)?;
+        encoder.finish()?.sync_all()
+    });
+    if let Err(e) = result {
+        let _ = remove_file(&compressed_path);
+        return Err(e);
+    }
+    remove_file(path)?;
+
+    Ok(compressed_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read, sync::mpsc::channel};
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[test]
+    /// Test that a log is rotated into numbered segments only at a flush, and that a closed
+    /// segment is compressed into a file which decodes to its original contents.
+    fn rotate_and_compress() {
+        let dir = std::env::temp_dir().join(format!("slonk_rotation_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("PT1.csv");
+        let (sender, segments) = channel();
+        let mut log = RotatingLog::new(
+            path.clone(),
+            File::create(&path).unwrap(),
+            Some(16),
+            Some(sender),
+        )
+        .unwrap();
+
+        // the first segment outgrows the limit, but is only closed at the flush
+        log.write_all(b"1000,2706,1.5\n").unwrap();
+        log.write_all(b"2000,2707,1.6\n").unwrap();
+        assert!(!dir.join("PT1.1.csv").exists());
+        log.flush().unwrap();
+        log.write_all(b"3000,2708,1.7\n").unwrap();
+        log.flush().unwrap();
+        log.sync_handle().sync_all().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("PT1.1.csv")).unwrap(),
+            "3000,2708,1.7\n"
+        );
+        let closed = segments.try_recv().unwrap();
+        assert_eq!(closed, path);
+        assert!(segments.try_recv().is_err());
+
+        let compressed = compress(&closed).unwrap();
+        assert_eq!(compressed, dir.join("PT1.csv.gz"));
+        assert!(!path.exists());
+        let mut contents = String::new();
+        GzDecoder::new(File::open(&compressed).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "1000,2706,1.5\n2000,2707,1.6\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
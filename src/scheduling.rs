@@ -0,0 +1,94 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Scheduling controls for the threads of the controller.
+//!
+//! Each function in this module applies only to the thread which calls it.
+
+use std::{io, mem};
+
+/// The lowest legal real-time priority for the `SCHED_FIFO` policy.
+pub const MIN_PRIORITY: i32 = 1;
+
+/// The highest legal real-time priority for the `SCHED_FIFO` policy.
+pub const MAX_PRIORITY: i32 = 99;
+
+/// Switch the calling thread to the `SCHED_FIFO` real-time scheduling policy with priority
+/// `priority`.
+///
+/// # Errors
+///
+/// This function will return an error if the priority is out of range or if the process lacks the
+/// `CAP_SYS_NICE` capability, in which case the error kind will be `PermissionDenied`.
+pub fn set_realtime_priority(priority: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    // SAFETY: `param` is a valid, initialized scheduling parameter, and PID 0 refers to the
+    // calling thread.
+    let result = unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Pin the calling thread so that it only runs on the CPU core with index `core`.
+///
+/// # Errors
+///
+/// This function will return an error if `core` does not refer to a CPU core available to this
+/// process.
+pub fn pin_to_core(core: usize) -> io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "CPU core index too large",
+        ));
+    }
+
+    // SAFETY: an all-zeros `cpu_set_t` is a valid empty CPU set, and PID 0 refers to the calling
+    // thread.
+    let result = unsafe {
+        let mut cpu_set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(core, &mut cpu_set);
+        libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &cpu_set)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that a thread can be pinned to the first core, but not to a core that doesn't exist.
+    fn pin_thread() {
+        std::thread::spawn(|| {
+            pin_to_core(0).unwrap();
+            assert!(pin_to_core(libc::CPU_SETSIZE as usize).is_err());
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -17,30 +17,59 @@
 */
 
 use std::{
-    fs::{create_dir_all, File},
-    io::{self, BufReader, Read, Write},
-    net::TcpListener,
+    collections::HashSet,
+    fs::{create_dir, create_dir_all, remove_dir, remove_file, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
     path::{Path, PathBuf},
-    sync::Mutex,
-    thread::Scope,
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Mutex, PoisonError,
+    },
+    thread::{sleep, Scope},
+    time::{Duration, Instant, SystemTime},
 };
 
-use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
+use gpio_cdev::{Chip, LineHandle};
+use nix::sys::{
+    signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal},
+    socket::{
+        bind, listen, setsockopt, socket, sockopt, AddressFamily, InetAddr, SockAddr, SockFlag,
+        SockType,
+    },
+};
 
 use crate::{
-    config::Configuration,
-    console::UserLog,
-    data::{driver_status_listen, sensor_listen},
-    execution::handle_command,
+    binary_log,
+    calibration::{self, Calibrator},
+    config::{Configuration, LogFormat, ReusePortFailure, RunInfo, Sensor, SensorGroup},
+    console::{LogEntry, LogLevel, UserLog},
+    data::{
+        driver_status_listen, log_sync, sensor_listen, stall_watch, Flusher, History, Injections,
+        Liveness, Pause, Readiness, Restarts, Snapshots,
+    },
+    execution::{
+        abort, check_protocol, deadman_watch, emergency_stop, handle_command, purge_watch,
+        readback_watch, Annotations, Client, Deadman, Hold, Interlocks, Outcome, PriorityMutex,
+        RateLimit, RecentCommands, Rewiring, Sequences, Throttle, DEDUP_CAPACITY, DEDUP_WINDOW,
+    },
     hardware::{
-        spi::{Bus, Device},
-        Adc, GpioPin, ListenerPin, Mcp3208, ReturnsNumber,
+        iio::IioChannel,
+        spi::{Bus, Device, FairMutex},
+        Adc, GpioChip, GpioPin, ListenerPin, Mcp3208, RecordingPin, Rewire,
     },
     heartbeat::heartbeat,
     incoming::{self, Command},
-    outgoing::{DashChannel, Message},
+    metrics::serve_metrics,
+    outgoing::{DashChannel, Message, UdpTelemetry, CONTROLLER_VERSION, PROTOCOL_VERSION},
+    reload::{config_watch, Tunings},
+    rotation::{compress_remaining, compress_segments, RotatingLog, SyncHandle},
+    scheduling::{pin_to_core, set_realtime_priority},
+    simulation::SimulatedAdc,
     state::{Guard, State},
+    syslog::Syslog,
     ControllerError,
 };
 
@@ -50,20 +79,30 @@ use crate::{
 /// everything on real hardware.
 pub trait MakeHardware {
     /// The type of the chip, which can be used for getting a GPIO pin.
-    type Chip;
+    type Chip: Send + 'static;
     /// The type of GPIO pin that this trait can make.
-    type Pin: GpioPin + Send + Sync;
+    type Pin: GpioPin + Send + Sync + 'static;
     /// The internal bus type.
     type Bus;
     /// The type of ADC reader that this trait can make.
     type Reader<'a>: Adc + Send + Sync;
+    /// The type of reader for sensors read through the Linux IIO subsystem.
+    type IioReader: Adc + Send;
 
-    /// Construct a GPIO chip which can be used to get pins.
+    /// Construct the GPIO chip named in the configuration, which can be used to get pins.
     ///
     /// # Errors
     ///
     /// This function will return an error if constructing the chip fails.
-    fn chip() -> Result<Self::Chip, ControllerError>;
+    fn chip(config: &Configuration) -> Result<Self::Chip, ControllerError>;
+
+    #[must_use]
+    /// Describe the GPIO chips available other than the one named in the configuration, for an
+    /// operator whose lines could not be acquired because they may belong to another chip.
+    /// Returns `None` if this hardware has no other chips.
+    fn other_chips(_config: &Configuration) -> Option<String> {
+        None
+    }
 
     /// Construct a bus for use by the readers based on information from the configuration.
     ///
@@ -72,20 +111,24 @@ pub trait MakeHardware {
     /// This function wil lreturn an error if acquiring the pins for the bus fails.
     fn bus(config: &Configuration, chip: &mut Self::Chip) -> Result<Self::Bus, ControllerError>;
 
-    #[allow(clippy::type_complexity)]
     /// Construct the ADCs using information from the configuration.
     ///
-    /// The length of the vector of `Self::Reader` returned must be equal to the length of `adc_cs`
-    /// in the configuration.
-    ///
-    /// # Errors
-    ///
-    /// This function may return an error if it is unable to acquire the GPIO needed.
+    /// The length of the vector returned must be equal to the length of `adc_cs` in the
+    /// configuration.
+    /// Each ADC is constructed independently, so that a failure to initialize one ADC does not
+    /// prevent the others from being used.
+    /// An entry in the vector is an error if that ADC could not be constructed (for instance, if
+    /// it was unable to acquire the GPIO needed).
+    /// Raw ADC transactions may be written to `user_log` for debugging.
+    /// The raw transactions on each channel of each ADC are also written to the file for that
+    /// channel in `raw_logs`, indexed first by ADC and then by channel, if there is one.
     fn adcs<'a>(
         config: &Configuration,
         chip: &mut Self::Chip,
         bus: &'a Self::Bus,
-    ) -> Result<Vec<Mutex<Self::Reader<'a>>>, ControllerError>;
+        user_log: &'a UserLog<File>,
+        raw_logs: Vec<[Option<BufWriter<File>>; 8]>,
+    ) -> Vec<Result<Mutex<Self::Reader<'a>>, ControllerError>>;
 
     /// Construct the drivers using information from the configuration.
     ///
@@ -97,6 +140,59 @@ pub trait MakeHardware {
         chip: &mut Self::Chip,
     ) -> Result<Vec<Self::Pin>, ControllerError>;
 
+    /// Acquire the line of GPIO pin `pin` as the output of a driver which is being moved to it,
+    /// initially at logic level `value`.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if it is unable to acquire the GPIO needed.
+    fn driver_line(
+        chip: &mut Self::Chip,
+        pin: u8,
+        value: bool,
+    ) -> Result<Self::Pin, ControllerError>;
+
+    /// Construct the input lines which sense the loads of the drivers, using information from the
+    /// configuration.
+    ///
+    /// The lines are in the same order as `config.drivers`; a driver without a sense pin has no
+    /// line.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if it is unable to acquire the GPIO needed.
+    fn driver_senses(
+        config: &Configuration,
+        chip: &mut Self::Chip,
+    ) -> Result<Vec<Option<Self::Pin>>, ControllerError>;
+
+    /// Construct the input lines of the digital inputs using information from the configuration.
+    ///
+    /// The lines are grouped by sensor group, in the same order as `config.sensor_groups`, and
+    /// within each group are in the same order as the group's `digital_inputs`.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if it is unable to acquire the GPIO needed.
+    fn digital_inputs(
+        config: &Configuration,
+        chip: &mut Self::Chip,
+    ) -> Result<Vec<Vec<Self::Pin>>, ControllerError>;
+
+    /// Construct the readers of the sensors read through the Linux IIO subsystem using information
+    /// from the configuration.
+    ///
+    /// The readers are grouped by sensor group, in the same order as `config.sensor_groups`, and
+    /// within each group are in the same order as the group's `sensors`.
+    /// A sensor which is not read through IIO has no reader.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if it is unable to open an IIO channel.
+    fn iio_channels(
+        config: &Configuration,
+    ) -> Result<Vec<Vec<Option<Self::IioReader>>>, ControllerError>;
+
     /// Get a the heartbeat GPIO pin from the configuration.
     ///
     /// # Errors
@@ -106,6 +202,16 @@ pub trait MakeHardware {
         config: &Configuration,
         chip: &mut Self::Chip,
     ) -> Result<Self::Pin, ControllerError>;
+
+    /// Get the armed-state indicator GPIO pin from the configuration, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if it is unable to acquire the GPIO needed.
+    fn armed_indicator(
+        config: &Configuration,
+        chip: &mut Self::Chip,
+    ) -> Result<Option<Self::Pin>, ControllerError>;
 }
 
 /// A hardware maker for actually interfacing with the Raspberry Pi.
@@ -115,78 +221,362 @@ impl MakeHardware for RaspberryPi {
     type Chip = Chip;
     type Pin = LineHandle;
 
-    type Bus = Mutex<Bus<Self::Pin>>;
+    type Bus = FairMutex<Bus<Self::Pin>>;
+
+    type Reader<'a> = Mcp3208<'a, Self::Pin, File, BufWriter<File>>;
 
-    type Reader<'a> = Mcp3208<'a, Self::Pin>;
+    type IioReader = IioChannel;
 
-    fn chip() -> Result<Self::Chip, ControllerError> {
-        Ok(Chip::new("/dev/gpiochip0")?)
+    fn chip(config: &Configuration) -> Result<Self::Chip, ControllerError> {
+        let path = gpio_chip_path(config);
+        Chip::new(path).map_err(|e| {
+            ControllerError::GpioChip(path.to_path_buf(), e, describe_other_chips(path))
+        })
+    }
+
+    fn other_chips(config: &Configuration) -> Option<String> {
+        Some(describe_other_chips(gpio_chip_path(config)))
     }
 
     fn adcs<'a>(
         config: &Configuration,
         chip: &mut Self::Chip,
         bus: &'a Self::Bus,
-    ) -> Result<Vec<Mutex<Self::Reader<'a>>>, ControllerError> {
+        user_log: &'a UserLog<File>,
+        raw_logs: Vec<[Option<BufWriter<File>>; 8]>,
+    ) -> Vec<Result<Mutex<Self::Reader<'a>>, ControllerError>> {
+        acquire_adcs(config, chip, bus, user_log, raw_logs)
+    }
+
+    fn drivers(
+        config: &Configuration,
+        chip: &mut Self::Chip,
+    ) -> Result<Vec<Self::Pin>, ControllerError> {
+        acquire_drivers(config, chip)
+    }
+
+    fn driver_line(
+        chip: &mut Self::Chip,
+        pin: u8,
+        value: bool,
+    ) -> Result<Self::Pin, ControllerError> {
+        chip.request_output(pin, value)
+    }
+
+    fn driver_senses(
+        config: &Configuration,
+        chip: &mut Self::Chip,
+    ) -> Result<Vec<Option<Self::Pin>>, ControllerError> {
         config
-            .adc_cs
+            .drivers
             .iter()
-            .flat_map(|&pin| {
-                chip.get_line(u32::from(pin)).map(|line| {
-                    line.request(LineRequestFlags::OUTPUT, 1, "slonk")
-                        .map(|handle| Mutex::new(Mcp3208::new(Device::new(bus, handle))))
-                })
+            .map(|driver| {
+                driver
+                    .pin_sense
+                    .map(|pin| chip.request_input(pin))
+                    .transpose()
             })
-            .map(|r| r.map_err(std::convert::Into::into))
             .collect()
     }
 
-    fn drivers(
+    fn digital_inputs(
         config: &Configuration,
         chip: &mut Self::Chip,
-    ) -> Result<Vec<Self::Pin>, ControllerError> {
+    ) -> Result<Vec<Vec<Self::Pin>>, ControllerError> {
+        acquire_digital_inputs(config, chip)
+    }
+
+    fn iio_channels(
+        config: &Configuration,
+    ) -> Result<Vec<Vec<Option<Self::IioReader>>>, ControllerError> {
         config
-            .drivers
+            .sensor_groups
             .iter()
-            .flat_map(|driver| {
-                chip.get_line(u32::from(driver.pin))
-                    .map(|l| l.request(LineRequestFlags::OUTPUT, 0, "slonk"))
+            .map(|group| {
+                group
+                    .sensors
+                    .iter()
+                    .map(|sensor| {
+                        sensor
+                            .iio_channel
+                            .as_deref()
+                            .map(IioChannel::open)
+                            .transpose()
+                    })
+                    .collect()
             })
-            .map(|r| r.map_err(std::convert::Into::into))
             .collect()
     }
 
     fn bus(config: &Configuration, chip: &mut Self::Chip) -> Result<Self::Bus, ControllerError> {
-        Ok(Mutex::new(Bus {
-            period: Duration::from_secs(1) / config.spi_frequency_clk,
-            pin_clk: chip.get_line(u32::from(config.spi_clk))?.request(
-                LineRequestFlags::OUTPUT,
-                0,
-                "slonk",
-            )?,
-            pin_mosi: chip.get_line(u32::from(config.spi_mosi))?.request(
-                LineRequestFlags::OUTPUT,
-                0,
-                "slonk",
-            )?,
-            pin_miso: chip.get_line(u32::from(config.spi_miso))?.request(
-                LineRequestFlags::INPUT,
-                0,
-                "slonk",
-            )?,
-        }))
+        Ok(FairMutex::new(acquire_bus(config, chip)?))
     }
 
     fn heartbeat(
         config: &Configuration,
         chip: &mut Self::Chip,
     ) -> Result<Self::Pin, ControllerError> {
-        Ok(chip.get_line(u32::from(config.pin_heartbeat))?.request(
-            LineRequestFlags::OUTPUT,
-            0,
-            "slonk",
-        )?)
+        chip.request_output(config.pin_heartbeat, false)
+    }
+
+    fn armed_indicator(
+        config: &Configuration,
+        chip: &mut Self::Chip,
+    ) -> Result<Option<Self::Pin>, ControllerError> {
+        config
+            .pin_armed_indicator
+            .map(|pin| chip.request_output(pin, false))
+            .transpose()
+    }
+}
+
+#[allow(clippy::type_complexity)]
+/// Acquire the chip-select line for each ADC in the configuration and construct the ADCs on `bus`.
+/// The ADCs are returned in the same order as `config.adc_cs`.
+///
+/// Chip-select lines are active-low, so each one is initially pulled high.
+/// If the chip-select line for an ADC cannot be acquired, its entry is an error.
+/// Every ADC writes its raw transactions to `user_log` when it is recording debug information.
+/// The raw transactions on each channel are also written to its file in `raw_logs`, which is
+/// indexed first by ADC and then by channel.
+fn acquire_adcs<'a, C: GpioChip, W: Write, R: Write>(
+    config: &Configuration,
+    chip: &mut C,
+    bus: &'a FairMutex<Bus<C::Pin>>,
+    user_log: &'a UserLog<W>,
+    raw_logs: Vec<[Option<R>; 8]>,
+) -> Vec<Result<Mutex<Mcp3208<'a, C::Pin, W, R>>, ControllerError>> {
+    config
+        .adc_cs
+        .iter()
+        .zip(raw_logs)
+        .map(|(&pin, raw_logs)| {
+            let handle = chip.request_output(pin, true)?;
+            Ok(Mutex::new(
+                Mcp3208::with_log(
+                    Device::new(
+                        bus,
+                        handle,
+                        Duration::from_secs(1) / config.spi_frequency_clk,
+                    )
+                    .with_cs_settle(Duration::from_micros(config.cs_settle_us.into())),
+                    user_log,
+                )
+                .with_raw_logs(raw_logs),
+            ))
+        })
+        .collect()
+}
+
+/// Check the results of constructing each ADC, logging a critical message for every ADC which
+/// failed to initialize.
+/// The returned ADCs are in the same order as `config.adc_cs`, with `None` in place of each ADC
+/// which failed.
+///
+/// # Inputs
+///
+/// * `config`: The configuration of the controller.
+/// * `adcs`: The result of constructing each ADC.
+/// * `allow_degraded`: Whether the controller may continue running with some of its ADCs
+///     unavailable.
+///     If so, the sensors on those ADCs are disabled.
+/// * `user_log`: The log for the user.
+///
+/// # Errors
+///
+/// This function will return an error if any ADC failed to initialize and `allow_degraded` is
+/// `false`, or if writing to the user log fails.
+fn check_adcs<R>(
+    config: &Configuration,
+    adcs: Vec<Result<R, ControllerError>>,
+    allow_degraded: bool,
+    user_log: &UserLog<impl Write>,
+) -> Result<Vec<Option<R>>, ControllerError> {
+    let mut checked = Vec::with_capacity(adcs.len());
+    let mut n_failed = 0;
+    for (adc_id, adc) in adcs.into_iter().enumerate() {
+        match adc {
+            Ok(adc) => checked.push(Some(adc)),
+            Err(e) => {
+                n_failed += 1;
+                user_log.critical(&format!(
+                    "Failed to initialize ADC {adc_id} (chip select pin {}): {e}",
+                    config.adc_cs[adc_id]
+                ))?;
+                for sensor in config
+                    .sensor_groups
+                    .iter()
+                    .flat_map(|group| &group.sensors)
+                    .filter(|sensor| {
+                        sensor.iio_channel.is_none() && usize::from(sensor.adc) == adc_id
+                    })
+                {
+                    user_log.critical(&format!(
+                        "Sensor {} is disabled because its ADC failed to initialize",
+                        sensor.label
+                    ))?;
+                }
+                checked.push(None);
+            }
+        }
     }
+
+    if n_failed > 0 && !allow_degraded {
+        user_log.critical(&format!(
+            "{n_failed} ADC(s) failed to initialize. Pass --allow-degraded to run without them."
+        ))?;
+        return Err(ControllerError::AdcsFailed(n_failed));
+    }
+
+    Ok(checked)
+}
+
+/// The GPIO chip used if the configuration does not name one.
+const DEFAULT_GPIO_CHIP: &str = "/dev/gpiochip0";
+
+#[must_use]
+/// Get the path of the GPIO chip named in `config`, or the default chip if it names none.
+fn gpio_chip_path(config: &Configuration) -> &Path {
+    config
+        .gpio_chip
+        .as_deref()
+        .unwrap_or_else(|| Path::new(DEFAULT_GPIO_CHIP))
+}
+
+/// List every GPIO chip device in the directory `dev_dir`, in numerical order.
+/// Returns an empty list if the directory cannot be read.
+fn list_gpio_chips(dev_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = dev_dir.read_dir() else {
+        return Vec::new();
+    };
+    let mut chips: Vec<(u32, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let number = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("gpiochip")?
+                .parse()
+                .ok()?;
+            Some((number, entry.path()))
+        })
+        .collect();
+    chips.sort();
+    chips.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Describe every GPIO chip device other than the one at `configured`, for an operator whose
+/// configured chip could not be used.
+fn describe_other_chips(configured: &Path) -> String {
+    let available = list_gpio_chips(Path::new("/dev"))
+        .into_iter()
+        .filter_map(|path| {
+            let label = Chip::new(&path).ok()?.label().to_string();
+            Some((path, label))
+        })
+        .collect::<Vec<_>>();
+    describe_gpio_chips(configured, &available)
+}
+
+/// Describe the GPIO chips in `available`, each given as its path and label, other than the chip
+/// at `configured`, for an operator whose configured chip could not be used.
+///
+/// On a Raspberry Pi, the chip serving the GPIO header is labelled `pinctrl-...`, so such a chip
+/// is suggested in place of the configured one.
+fn describe_gpio_chips(configured: &Path, available: &[(PathBuf, String)]) -> String {
+    let available: Vec<_> = available
+        .iter()
+        .filter(|(path, _)| path != configured)
+        .collect();
+    if available.is_empty() {
+        return "No other GPIO chips were found".into();
+    }
+    let listing = available
+        .iter()
+        .map(|(path, label)| format!("{} ({label})", path.display()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    match available
+        .iter()
+        .find(|(_, label)| label.starts_with("pinctrl-"))
+    {
+        Some((path, _)) => format!(
+            "Available chips are {listing}; try setting gpio_chip to {:?}",
+            path.display().to_string()
+        ),
+        None => format!("Available chips are {listing}"),
+    }
+}
+
+/// Add a description of the GPIO chips other than the configured one to `e`, if it is a failure
+/// to acquire a line, since the line may belong to another chip.
+fn hint_other_chips<M: MakeHardware>(
+    config: &Configuration,
+    e: ControllerError,
+) -> ControllerError {
+    match e {
+        ControllerError::GpioLine(line, e, None) => {
+            ControllerError::GpioLine(line, e, M::other_chips(config))
+        }
+        e => e,
+    }
+}
+
+/// Acquire the output line for each driver in the configuration.
+/// The lines are returned in the same order as `config.drivers`, and are initially at each
+/// driver's startup level.
+///
+/// # Errors
+///
+/// This function will return an error if any of the driver lines cannot be acquired.
+fn acquire_drivers<C: GpioChip>(
+    config: &Configuration,
+    chip: &mut C,
+) -> Result<Vec<C::Pin>, ControllerError> {
+    config
+        .drivers
+        .iter()
+        .map(|driver| chip.request_output(driver.pin, driver.startup_level))
+        .collect()
+}
+
+/// Acquire the input line for each digital input in the configuration.
+/// The lines are grouped by sensor group, in the same order as `config.sensor_groups`.
+///
+/// # Errors
+///
+/// This function will return an error if any of the input lines cannot be acquired.
+fn acquire_digital_inputs<C: GpioChip>(
+    config: &Configuration,
+    chip: &mut C,
+) -> Result<Vec<Vec<C::Pin>>, ControllerError> {
+    config
+        .sensor_groups
+        .iter()
+        .map(|group| {
+            group
+                .digital_inputs
+                .iter()
+                .map(|input| chip.request_input(input.pin))
+                .collect()
+        })
+        .collect()
+}
+
+/// Acquire the lines for the SPI bus described in the configuration.
+///
+/// # Errors
+///
+/// This function will return an error if any of the bus lines cannot be acquired.
+fn acquire_bus<C: GpioChip>(
+    config: &Configuration,
+    chip: &mut C,
+) -> Result<Bus<C::Pin>, ControllerError> {
+    Ok(Bus {
+        pin_clk: chip.request_output(config.spi_clk, false)?,
+        pin_mosi: chip.request_output(config.spi_mosi, false)?,
+        pin_miso: chip.request_input(config.spi_miso)?,
+    })
 }
 
 /// A dummy hardware maker for testing on any Linux computer.
@@ -196,11 +586,13 @@ impl MakeHardware for Dummy {
     type Chip = ();
     type Pin = ListenerPin;
 
-    type Reader<'a> = ReturnsNumber;
+    type Reader<'a> = SimulatedAdc;
+
+    type IioReader = SimulatedAdc;
 
     type Bus = ();
 
-    fn chip() -> Result<(), ControllerError> {
+    fn chip(_: &Configuration) -> Result<(), ControllerError> {
         Ok(())
     }
 
@@ -213,24 +605,87 @@ impl MakeHardware for Dummy {
         config: &Configuration,
         _: &mut Self::Chip,
         _: &'a Self::Bus,
-    ) -> Result<Vec<Mutex<Self::Reader<'a>>>, ControllerError> {
-        Ok((0..config.adc_cs.len())
-            .map(|i| Mutex::new(ReturnsNumber(i as u16)))
-            .collect())
+        _: &'a UserLog<File>,
+        _: Vec<[Option<BufWriter<File>>; 8]>,
+    ) -> Vec<Result<Mutex<Self::Reader<'a>>, ControllerError>> {
+        (0..config.adc_cs.len())
+            .map(|i| SimulatedAdc::new(config, i as u8).map(Mutex::new))
+            .collect()
     }
 
     fn drivers(
         config: &Configuration,
         _: &mut Self::Chip,
     ) -> Result<Vec<Self::Pin>, ControllerError> {
-        Ok((0..config.drivers.len())
-            .map(|_| ListenerPin::new(false))
+        Ok(config
+            .drivers
+            .iter()
+            .map(|driver| ListenerPin::new(driver.startup_level))
+            .collect())
+    }
+
+    fn driver_line(_: &mut Self::Chip, _: u8, value: bool) -> Result<Self::Pin, ControllerError> {
+        Ok(ListenerPin::new(value))
+    }
+
+    fn driver_senses(
+        config: &Configuration,
+        _: &mut Self::Chip,
+    ) -> Result<Vec<Option<Self::Pin>>, ControllerError> {
+        Ok(config
+            .drivers
+            .iter()
+            .map(|driver| driver.pin_sense.map(|_| ListenerPin::new(false)))
+            .collect())
+    }
+
+    fn digital_inputs(
+        config: &Configuration,
+        _: &mut Self::Chip,
+    ) -> Result<Vec<Vec<Self::Pin>>, ControllerError> {
+        Ok(config
+            .sensor_groups
+            .iter()
+            .map(|group| {
+                (0..group.digital_inputs.len())
+                    .map(|_| ListenerPin::new(false))
+                    .collect()
+            })
             .collect())
     }
 
+    fn iio_channels(
+        config: &Configuration,
+    ) -> Result<Vec<Vec<Option<Self::IioReader>>>, ControllerError> {
+        config
+            .sensor_groups
+            .iter()
+            .map(|group| {
+                group
+                    .sensors
+                    .iter()
+                    .map(|sensor| {
+                        sensor
+                            .iio_channel
+                            .as_ref()
+                            .map(|_| SimulatedAdc::for_sensor(sensor))
+                            .transpose()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
     fn heartbeat(_: &Configuration, _: &mut Self::Chip) -> Result<Self::Pin, ControllerError> {
         Ok(ListenerPin::new(false))
     }
+
+    fn armed_indicator(
+        config: &Configuration,
+        _: &mut Self::Chip,
+    ) -> Result<Option<Self::Pin>, ControllerError> {
+        Ok(config.pin_armed_indicator.map(|_| ListenerPin::new(false)))
+    }
 }
 
 #[allow(clippy::too_many_lines, clippy::cast_possible_truncation)]
@@ -246,14 +701,45 @@ impl MakeHardware for Dummy {
 ///
 /// This function may panic if it is unable to correctly set up the controller.
 pub fn run<M: MakeHardware>() -> Result<(), ControllerError> {
-    let args: Vec<String> = std::env::args().skip(1).collect();
-    // Use arguments to get configuration file
-    let json_path = args
-        .get(0)
-        .ok_or(ControllerError::Args("No configuration JSON path given"))?;
-    let logs_path = args
-        .get(1)
-        .ok_or(ControllerError::Args("No logs path given"))?;
+    // flags (such as `--allow-injection`) may appear anywhere among the positional arguments
+    let (flags, args): (Vec<String>, Vec<String>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let allow_injection = flags.iter().any(|flag| flag == "--allow-injection");
+    let run_id = flags.iter().find_map(|flag| flag.strip_prefix("--run-id="));
+    let allow_degraded = flags.iter().any(|flag| flag == "--allow-degraded");
+    let watch_config = flags.iter().any(|flag| flag == "--watch-config");
+    let capture_raw = flags.iter().any(|flag| flag == "--capture-raw");
+
+    if flags.iter().any(|flag| flag == "--decode") {
+        // decode mode: convert a binary sensor log back to CSV, without touching any hardware
+        let binary_path = args.get(0).ok_or(ControllerError::MissingBinaryLogPath)?;
+        let mut binary_log = BufReader::new(File::open(binary_path)?);
+        match args.get(1) {
+            Some(csv_path) => binary_log::decode(
+                &mut binary_log,
+                &mut BufWriter::new(file_create_new(csv_path)?),
+            )?,
+            None => binary_log::decode(&mut binary_log, &mut io::stdout().lock())?,
+        }
+        return Ok(());
+    }
+
+    if flags.iter().any(|flag| flag == "--check") {
+        // check mode: validate a configuration without touching any hardware or the network
+        return check_configuration(args.first().map(String::as_str));
+    }
+
+    // Use arguments to get configuration file.
+    // If only a logs path is given, the configuration is instead read from `SLONK_CONFIG`.
+    let env_config = std::env::var(CONFIG_ENV_VAR).ok();
+    let (json_path, logs_path) = match (args.as_slice(), &env_config) {
+        ([logs_path], Some(_)) => (None, logs_path),
+        ([json_path, logs_path, ..], _) => (Some(json_path.as_str()), logs_path),
+        ([], _) => return Err(ControllerError::MissingConfigPath),
+        ([_], None) => return Err(ControllerError::MissingLogsPath),
+    };
+    let n_expected_args = if json_path.is_some() { 2 } else { 1 };
 
     create_dir_all(logs_path)?;
     let Ok(console_log_file) = file_create_new(PathBuf::from_iter([
@@ -263,242 +749,3018 @@ pub fn run<M: MakeHardware>() -> Result<(), ControllerError> {
         println!("Console log file location already exists. Please delete that file or specify a different log file path.");
         return Err(ControllerError::Console(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "file already exists")));
     };
+    // duplicate handles to every log file, used only for syncing them to disk on request
+    let mut flush_files = vec![SyncHandle::new(console_log_file.try_clone()?)];
     let user_log = UserLog::new(console_log_file);
     let user_log_ref = &user_log;
-    if args.len() > 2 {
+    user_log.info(&format!("slonk version {CONTROLLER_VERSION}"))?;
+    install_termination_handler()?;
+    if args.len() > n_expected_args {
+        user_log
+            .warn("Too many arguments given to controller executable. Ignoring extra arguments.")?;
+    }
+    for flag in flags.iter().filter(|&flag| {
+        flag != "--allow-injection"
+            && flag != "--allow-degraded"
+            && flag != "--watch-config"
+            && flag != "--capture-raw"
+            && !flag.starts_with("--run-id=")
+    }) {
+        user_log.warn(&format!("Ignoring unrecognized flag {flag}"))?;
+    }
+    if allow_injection {
         user_log.warn(
-            "More than two arguments given to controller executable. Ignoring extra arguments.",
+            "Sensor reading injection is enabled. This controller must not be used for a real firing.",
         )?;
     }
 
-    user_log.debug("Parsing configuration file...")?;
-    let config_file = File::open(json_path)?;
-    let config = match Configuration::parse(&mut BufReader::new(config_file)) {
+    let parse_result = match json_path {
+        Some("-") => {
+            user_log.debug("Parsing configuration from standard input...")?;
+            Configuration::parse(&mut io::stdin().lock())
+        }
+        Some(json_path) => {
+            user_log.debug("Parsing configuration file...")?;
+            Configuration::parse(&mut BufReader::new(File::open(json_path)?))
+        }
+        None => {
+            user_log.debug(&format!("Parsing configuration from {CONFIG_ENV_VAR}..."))?;
+            Configuration::parse(&mut env_config.as_deref().unwrap_or_default().as_bytes())
+        }
+    };
+    let mut config = match parse_result {
         Ok(c) => c,
         Err(e) => {
             user_log.critical(&format!("Failed to parse configuration: {e}"))?;
             return Err(e.into());
         }
     };
+    user_log.debug("Successfully parsed configuration")?;
+    if let Some(addr) = config.syslog_addr {
+        match Syslog::new(addr, config.syslog_transport) {
+            Ok(syslog) => {
+                user_log.mirror_to(syslog);
+                user_log.info(&format!("Forwarding log entries to syslog at {addr}"))?;
+            }
+            Err(e) => user_log.warn(&format!("Unable to forward log entries to syslog: {e}"))?,
+        }
+    }
+    match calibration::apply_saved(&mut config) {
+        Ok(0) => (),
+        Ok(n_applied) => user_log.info(&format!(
+            "Applied {n_applied} saved sensor calibration(s) from the calibration file"
+        ))?,
+        Err(e) => {
+            user_log.critical(&format!("Failed to load saved calibrations: {e}"))?;
+            return Err(e.into());
+        }
+    }
+    // the scale of an IIO channel is only known once it is opened
+    let mut iio_channels = M::iio_channels(&config)?;
+    apply_iio_scales(&mut config, &iio_channels);
     let config_ref = &config;
-    user_log.debug("Successfully parsed configuration file")?;
+
+    // only a configuration file can be watched for changes
+    let watch_path = match json_path {
+        Some(path) if watch_config && path != "-" => Some(Path::new(path)),
+        _ => {
+            if watch_config {
+                user_log.warn(
+                    "Ignoring --watch-config, since the configuration was not read from a file",
+                )?;
+            }
+            None
+        }
+    };
 
     user_log.debug("Creating log files")?;
 
-    let mut sensor_log_files: Vec<Vec<File>> = Vec::new();
-    for sensor_group in &config.sensor_groups {
-        let mut group_files = Vec::new();
-        let sensor_group_path = PathBuf::from_iter([logs_path, &sensor_group.label]);
-        // create subfolder for this sensor group
-        create_dir_all(&sensor_group_path)?;
+    let run = RunInfo {
+        timestamp: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        run_id,
+    };
+    // every log file is created at once, so that a failure never leaves a half-built log tree
+    let log_paths = LogPaths::new(&config, &run, capture_raw)?;
+    let log_files = match create_log_tree(Path::new(logs_path), &log_paths) {
+        Ok(files) => files,
+        Err(e) => {
+            user_log.critical(&format!("Failed to create log files: {e}"))?;
+            return Err(e);
+        }
+    };
+    for path in log_paths.all() {
         user_log.info(&format!(
-            "Created directory {:}",
-            sensor_group_path.display()
+            "Created log file {:}",
+            Path::new(logs_path).join(path).display()
         ))?;
+    }
+    let LogTree {
+        sensors: sensor_files,
+        commands: cmd_file,
+        pretrigger: pretrigger_file,
+        drivers: mut drivers_file,
+        snapshots: snapshot_file,
+        events: events_file,
+        sent: sent_file,
+        raw: raw_file,
+        received: received_file,
+        raw_sensors: raw_sensor_files,
+    } = log_files;
+
+    // closed segments of rotated sensor logs are sent to a separate thread to be compressed
+    let (rotated, segments) = mpsc::channel();
+    let segments = Mutex::new(segments);
+    let compress_rotated = config.log_rotate_size.is_some() && config.log_compress_rotated;
+    let mut sensor_log_files: Vec<Vec<RotatingLog>> = Vec::new();
+    // handles to the sensor log files, used only for periodically syncing them to disk
+    let mut sync_files: Vec<SyncHandle> = Vec::new();
+    for (group_paths, group_sensor_files) in log_paths.sensors.iter().zip(sensor_files) {
+        let mut group_files = Vec::new();
 
-        for sensor in &sensor_group.sensors {
-            // create file for this specific sensor
-            let mut sensor_file_path = sensor_group_path.clone();
-            sensor_file_path.push(&format!("{}.csv", sensor.label));
-            group_files.push(file_create_new(&sensor_file_path)?);
+        for (sensor_file_path, sensor_file) in group_paths.iter().zip(group_sensor_files) {
+            if let Some(size) = config.log_preallocate_size {
+                if let Err(e) = preallocate(&sensor_file, size) {
+                    user_log.warn(&format!(
+                        "Unable to preallocate log file {:}: {e}",
+                        Path::new(logs_path).join(sensor_file_path).display()
+                    ))?;
+                }
+            }
+            let sensor_file = RotatingLog::new(
+                Path::new(logs_path).join(sensor_file_path),
+                sensor_file,
+                config.log_rotate_size,
+                compress_rotated.then(|| rotated.clone()),
+            )?;
+            if config.log_sync_period.is_some() {
+                sync_files.push(sensor_file.sync_handle());
+            }
+            flush_files.push(sensor_file.sync_handle());
 
-            user_log.info(&format!("Created log file {:}", sensor_file_path.display()))?;
+            group_files.push(sensor_file);
         }
 
         sensor_log_files.push(group_files);
     }
 
-    // create log file for commands that have been executed
-    let cmd_file = Mutex::new(file_create_new(PathBuf::from_iter([
-        logs_path,
-        "commands.csv",
-    ]))?);
+    // log file for commands that have been executed
+    flush_files.push(SyncHandle::new(cmd_file.try_clone()?));
+    let cmd_file = Mutex::new(cmd_file);
     let cmd_file_ref = &cmd_file;
 
-    let mut drivers_file = file_create_new(PathBuf::from_iter([logs_path, "drivers.csv"]))?;
+    // readings from before an emergency stop are only kept if a pre-trigger duration is configured
+    let pretrigger_file = match pretrigger_file {
+        Some(file) => {
+            flush_files.push(SyncHandle::new(file.try_clone()?));
+            Some(Mutex::new(file))
+        }
+        None => None,
+    };
+    let pretrigger_file_ref = pretrigger_file.as_ref();
+
+    flush_files.push(SyncHandle::new(drivers_file.try_clone()?));
+
+    // snapshots of every sensor are only written when the operator requests one
+    flush_files.push(SyncHandle::new(snapshot_file.try_clone()?));
+    let snapshots = Snapshots::new(&config, snapshot_file);
+    let snapshots_ref = &snapshots;
+
+    // annotations are only written when the operator makes one
+    flush_files.push(SyncHandle::new(events_file.try_clone()?));
+    let annotations = Annotations::new(events_file);
+    let annotations_ref = &annotations;
 
     // when a client connects, the inner value of this mutex will be `Some` containing a TCP stream
     // to the dashboard
-    let to_dash = DashChannel::new(file_create_new(PathBuf::from_iter([
-        logs_path, "sent.csv",
-    ]))?);
+    flush_files.push(SyncHandle::new(sent_file.try_clone()?));
+    let mut to_dash = match raw_file {
+        Some(raw_file) => {
+            flush_files.push(SyncHandle::new(raw_file.try_clone()?));
+            DashChannel::with_capture(sent_file, raw_file)
+        }
+        None => DashChannel::new(sent_file),
+    };
+    if let Some(capacity) = config.message_log_queue_size {
+        to_dash = to_dash.with_log_queue(capacity);
+    }
     let to_dash_ref = &to_dash;
 
+    // the exact bytes of every command received are only captured alongside those sent
+    let received_file = match received_file {
+        Some(file) => {
+            flush_files.push(SyncHandle::new(file.try_clone()?));
+            Some(Mutex::new(file))
+        }
+        None => None,
+    };
+    let received_file_ref = received_file.as_ref();
+
+    // the raw transactions of each ADC channel are only logged for the sensors which ask for them
+    let mut raw_logs: Vec<[Option<BufWriter<File>>; 8]> =
+        config.adc_cs.iter().map(|_| Default::default()).collect();
+    for ((_, sensor), file) in raw_logged_sensors(&config).zip(raw_sensor_files) {
+        flush_files.push(SyncHandle::new(file.try_clone()?));
+        raw_logs[usize::from(sensor.adc)][usize::from(sensor.channel)] = Some(BufWriter::new(file));
+    }
+
+    let flusher = Flusher::new(&config, flush_files);
+    let flusher_ref = &flusher;
+
+    let readiness = Readiness::new(&config);
+    let readiness_ref = &readiness;
+
+    let history = History::new(&config);
+    let history_ref = &history;
+
+    let calibrator = Calibrator::new(&config);
+    let calibrator_ref = &calibrator;
+
+    let sequences = Sequences::new(&config);
+    let sequences_ref = &sequences;
+
+    let liveness = Liveness::new(&config);
+    let liveness_ref = &liveness;
+
     user_log.debug("Successfully created log files")?;
 
-    let state = Guard::new(State::Standby);
-    let state_ref = &state;
+    let injections = Injections::new(&config, allow_injection);
+    let injections_ref = &injections;
+
+    let throttle = Throttle::new(&config);
+    let throttle_ref = &throttle;
+
+    let deadman = Deadman::new(&config);
+    let deadman_ref = &deadman;
+
+    let connections = Connections::new();
+    let connections_ref = &connections;
+
+    // remembered across connections, so that commands resent after a reconnect are not executed
+    // twice
+    let recent_commands = Mutex::new(RecentCommands::new(DEDUP_WINDOW, DEDUP_CAPACITY));
+    let recent_commands_ref = &recent_commands;
+
+    let hold = Hold::new();
+    let hold_ref = &hold;
+
+    let tunings = Tunings::new(&config);
+    let tunings_ref = &tunings;
+
+    let telemetry = match &config.udp_telemetry_addr {
+        Some(addr) => {
+            user_log.info(&format!("Streaming UDP telemetry to {addr}"))?;
+            let resolve_period = config
+                .udp_telemetry_resolve_s
+                .map_or(TELEMETRY_RESOLVE_PERIOD, |s| {
+                    Duration::from_secs(u64::from(s))
+                });
+            Some(UdpTelemetry::new(addr.clone(), resolve_period))
+        }
+        None => None,
+    };
+    let telemetry_ref = telemetry.as_ref();
 
     user_log.debug("Now acquiring GPIO")?;
 
-    let mut gpio_chip = M::chip()?;
-    let bus = M::bus(&config, &mut gpio_chip)?;
-    let adcs = M::adcs(&config, &mut gpio_chip, &bus)?;
+    user_log.info(&format!(
+        "Using GPIO chip {}",
+        gpio_chip_path(&config).display()
+    ))?;
+    let mut gpio_chip = M::chip(&config)?;
+    // a line which cannot be acquired may belong to a chip other than the configured one
+    let hint = |e| hint_other_chips::<M>(&config, e);
+    let bus = M::bus(&config, &mut gpio_chip).map_err(hint)?;
+    let adcs = check_adcs(
+        &config,
+        M::adcs(&config, &mut gpio_chip, &bus, &user_log, raw_logs)
+            .into_iter()
+            .map(|adc| adc.map_err(hint))
+            .collect(),
+        allow_degraded,
+        &user_log,
+    )?;
     let adcs_ref = &adcs;
-    let mut pin_heartbeat = M::heartbeat(&config, &mut gpio_chip)?;
+    let mut pin_heartbeat = M::heartbeat(&config, &mut gpio_chip).map_err(hint)?;
+
+    // the armed-state indicator follows every change of state from here on
+    let state = match M::armed_indicator(&config, &mut gpio_chip).map_err(hint)? {
+        Some(indicator) => Guard::with_indicator(State::Standby, Box::new(indicator))?,
+        None => Guard::new(State::Standby),
+    };
+    let state_ref = &state;
 
-    let driver_lines = Mutex::new(M::drivers(&config, &mut gpio_chip)?);
+    // every change in the level of a driver is reported to the driver status thread as it happens
+    let (edge_sender, edges) = mpsc::channel();
+    #[allow(clippy::cast_possible_truncation)]
+    let driver_lines = PriorityMutex::new(
+        M::drivers(&config, &mut gpio_chip)
+            .map_err(hint)?
+            .into_iter()
+            .enumerate()
+            .map(|(driver_id, pin)| RecordingPin::new(pin, driver_id as u8, edge_sender.clone()))
+            .collect::<Result<Vec<_>, _>>()?,
+    );
+    drop(edge_sender);
     let driver_lines_ref = &driver_lines;
+    // declared before the thread scope, so that the drivers are only shut down once every thread
+    // which could actuate them has been joined, however the controller exits
+    let _shutdown = DriverShutdown {
+        config: &config,
+        driver_lines: &driver_lines,
+        user_log: &user_log,
+    };
+    let mut driver_senses = M::driver_senses(&config, &mut gpio_chip).map_err(hint)?;
+    let mut digital_inputs = M::digital_inputs(&config, &mut gpio_chip).map_err(hint)?;
+    // the chip stays available for moving drivers to new pins while the controller runs
+    let rewiring = Rewiring::new(&config, move |pin, level| {
+        M::driver_line(&mut gpio_chip, pin, level)
+    });
+    let rewiring_ref = &rewiring;
+    let interlocks = Interlocks::new(&config);
+    let interlocks_ref = &interlocks;
+    let pause = Pause::new();
+    let pause_ref = &pause;
+    let restarts = Restarts::new(&config);
+    let restarts_ref = &restarts;
+    // the first error of a thread which the controller cannot run without
+    let fatal: Mutex<Option<ControllerError>> = Mutex::new(None);
+    let fatal_ref = &fatal;
 
     user_log.debug("Successfully acquired GPIO handles")?;
     user_log.debug("Now spawning sensor listener threads...")?;
 
-    std::thread::scope(|s| {
-        for (group_id, log_file_group) in sensor_log_files.iter_mut().enumerate() {
+    let outcome = std::thread::scope(|s| {
+        for (group_id, ((log_file_group, input_group), iio_group)) in sensor_log_files
+            .iter_mut()
+            .zip(digital_inputs.iter_mut())
+            .zip(iio_channels.iter_mut())
+            .enumerate()
+        {
             s.spawn(move || {
-                sensor_listen(
-                    s,
-                    group_id as u8,
-                    config_ref,
-                    driver_lines_ref,
-                    log_file_group,
-                    user_log_ref,
-                    adcs_ref,
-                    state_ref,
-                    to_dash_ref,
-                )
+                supervise("sensor listener", fatal_ref, user_log_ref, || {
+                    apply_scheduling(&config_ref.sensor_groups[group_id], user_log_ref)?;
+                    let _running = restarts_ref.run(group_id as u8);
+                    // a restarted group starts over with the same log files and ADCs
+                    loop {
+                        let outcome = sensor_listen(
+                            s,
+                            group_id as u8,
+                            config_ref,
+                            driver_lines_ref,
+                            log_file_group,
+                            user_log_ref,
+                            adcs_ref,
+                            input_group,
+                            iio_group,
+                            state_ref,
+                            to_dash_ref,
+                            injections_ref,
+                            telemetry_ref,
+                            pretrigger_file_ref,
+                            tunings_ref,
+                            flusher_ref,
+                            readiness_ref,
+                            history_ref,
+                            liveness_ref,
+                            snapshots_ref,
+                            pause_ref,
+                            restarts_ref,
+                        );
+                        if let Err(e) = &outcome {
+                            restarts_ref.fail(group_id as u8);
+                            user_log_ref.critical(&format!(
+                                "Sensor group {} failed, and will not be read until it is restarted: {e:?}",
+                                config_ref.sensor_groups[group_id].label
+                            ))?;
+                            restarts_ref.await_request(group_id as u8, state_ref)?;
+                        }
+                        // a failed group which quits without restarting was already reported
+                        if !restarts_ref.take(group_id as u8) {
+                            return Ok(());
+                        }
+                        user_log_ref.info(&format!(
+                            "Restarting sensor group {}",
+                            config_ref.sensor_groups[group_id].label
+                        ))?;
+                    }
+                });
             });
         }
 
         s.spawn(|| {
-            driver_status_listen(
-                &config,
-                &driver_lines,
-                &mut drivers_file,
-                &user_log,
-                &state,
-                &to_dash,
-            )
+            // the receiver cannot be shared between threads, so it is moved into this one
+            let edges = edges;
+            supervise("driver status", fatal_ref, user_log_ref, || {
+                driver_status_listen(
+                    &config,
+                    &driver_lines,
+                    &mut drivers_file,
+                    &user_log,
+                    &state,
+                    &to_dash,
+                    &edges,
+                    &interlocks,
+                )
+            });
         });
 
-        s.spawn(|| heartbeat(&mut pin_heartbeat, state_ref));
-
+        s.spawn(|| {
+            supervise("heartbeat", fatal_ref, user_log_ref, || {
+                heartbeat(&mut pin_heartbeat, state_ref)
+            });
+        });
+
+        s.spawn(|| {
+            supervise("dashboard log", fatal_ref, user_log_ref, || {
+                to_dash_ref.write_queued_log(user_log_ref, state_ref)
+            });
+        });
+
+        s.spawn(|| {
+            supervise("deadman", fatal_ref, user_log_ref, || {
+                deadman_watch(driver_lines_ref, deadman_ref, user_log_ref, state_ref)
+            });
+        });
+
+        s.spawn(|| {
+            supervise("purge", fatal_ref, user_log_ref, || {
+                purge_watch(
+                    config_ref,
+                    driver_lines_ref,
+                    state_ref,
+                    to_dash_ref,
+                    user_log_ref,
+                )
+            });
+        });
+
+        let driver_senses = &mut driver_senses;
+        s.spawn(move || {
+            supervise("readback", fatal_ref, user_log_ref, || {
+                readback_watch(
+                    config_ref,
+                    driver_lines_ref,
+                    driver_senses,
+                    user_log_ref,
+                    state_ref,
+                )
+            });
+        });
+
+        s.spawn(|| {
+            supervise("stall watch", fatal_ref, user_log_ref, || {
+                stall_watch(
+                    config_ref,
+                    driver_lines_ref,
+                    liveness_ref,
+                    user_log_ref,
+                    state_ref,
+                    to_dash_ref,
+                )
+            });
+        });
+
+        if let Some(watch_path) = watch_path {
+            user_log.info(&format!(
+                "Watching {} for configuration changes",
+                watch_path.display()
+            ))?;
+            s.spawn(move || {
+                supervise("configuration watch", fatal_ref, user_log_ref, || {
+                    config_watch(
+                        watch_path,
+                        config_ref,
+                        tunings_ref,
+                        user_log_ref,
+                        state_ref,
+                        to_dash_ref,
+                    )
+                });
+            });
+        }
+
+        if let Some(port) = config.metrics_port {
+            let address = SocketAddr::from(([0, 0, 0, 0], port));
+            match TcpListener::bind(address) {
+                Ok(listener) => {
+                    user_log.info(&format!("Serving metrics on {address}"))?;
+                    s.spawn(move || {
+                        supervise("metrics", fatal_ref, user_log_ref, || {
+                            serve_metrics(
+                                &listener,
+                                config_ref,
+                                state_ref,
+                                liveness_ref,
+                                driver_lines_ref,
+                                user_log_ref,
+                            )
+                        });
+                    });
+                }
+                // metrics are only for monitoring, so the controller runs without them
+                Err(e) => user_log.warn(&format!("Unable to serve metrics on {address}: {e}"))?,
+            }
+        }
+
+        if let Some(telemetry) = telemetry_ref {
+            s.spawn(move || {
+                supervise("telemetry resolver", fatal_ref, user_log_ref, || {
+                    Ok(telemetry.resolve_watch(state_ref)?)
+                });
+            });
+        }
+
+        if let Some(sync_period) = config.log_sync_period {
+            s.spawn(move || {
+                supervise("log sync", fatal_ref, user_log_ref, || {
+                    log_sync(
+                        Duration::from_millis(u64::from(sync_period)),
+                        &sync_files,
+                        user_log_ref,
+                        state_ref,
+                    )
+                });
+            });
+        }
+
+        if compress_rotated {
+            let segments = &segments;
+            s.spawn(move || {
+                supervise("log compression", fatal_ref, user_log_ref, || {
+                    compress_segments(segments, state_ref, user_log_ref)
+                });
+            });
+        }
+
         user_log.debug("Successfully spawned sensor listener threads.")?;
-        user_log.debug("Opening network...")?;
+        // serve clients until the network fails, so that the dashboard can be told why we stopped
+        let serve = || -> Result<(), ControllerError> {
+            user_log.debug("Opening network...")?;
+
+            // TODO: maybe configure this IP number?
+            let address = SocketAddr::from(([0, 0, 0, 0], 2707));
+            let listener = bind_listener(
+                address,
+                BIND_ATTEMPTS,
+                config.reuse_port_failure,
+                set_reuse_port,
+                &user_log,
+            )?;
+
+            user_log.info(&format!(
+                "Opened TCP listener on address {}",
+                listener.local_addr()?
+            ))?;
+            user_log.debug("Handling clients...")?;
+
+            let mut terminating = false;
+            serve_clients(
+                s,
+                &listener,
+                connections_ref,
+                recent_commands_ref,
+                to_dash_ref,
+                config_ref,
+                driver_lines_ref,
+                cmd_file_ref,
+                received_file_ref,
+                user_log_ref,
+                state_ref,
+                injections_ref,
+                throttle_ref,
+                deadman_ref,
+                hold_ref,
+                flusher_ref,
+                readiness_ref,
+                history_ref,
+                tunings_ref,
+                calibrator_ref,
+                sequences_ref,
+                snapshots_ref,
+                rewiring_ref,
+                interlocks_ref,
+                pause_ref,
+                restarts_ref,
+                annotations_ref,
+                || {
+                    if !terminating && TERMINATION_REQUESTED.load(Ordering::Relaxed) {
+                        terminating = true;
+                        user_log.warn("Received a termination signal, quitting")?;
+                    }
+                    // a failed thread was already reported when it failed
+                    if !terminating && fatal_ref.lock()?.is_some() {
+                        terminating = true;
+                    }
+                    if terminating {
+                        step_toward_quit(config_ref, driver_lines_ref, state_ref, user_log_ref)?;
+                    }
+                    Ok(())
+                },
+            )
+        };
+        let serve_result = serve();
+
+        // every other thread runs until the controller quits, so it must quit even if serving failed
+        wind_down(&config, &driver_lines, &state, &user_log)?;
+        if let Err(e) = &serve_result {
+            user_log.critical(&format!("Stopped serving clients due to error: {e}"))?;
+        }
+        let result = serve_result.and(fatal.lock()?.take().map_or(Ok(()), Err));
+        let reason = match &result {
+            Ok(()) => "controller exiting".to_string(),
+            Err(e) => format!("fatal error: {e}"),
+        };
+        to_dash.close(&reason)?;
+        // release the thread still reading from the last dashboard
+        connections.shutdown()?;
+
+        result
+    });
+    // segments closed while the controller was quitting are compressed once nothing writes to them
+    if compress_rotated {
+        compress_remaining(&segments, &user_log)?;
+    }
+    outcome?;
+    // successful termination!
+    Ok(())
+}
+
+/// Run `work`, the body of the thread `name`, which the controller cannot run without.
+/// If it fails, its error is reported and recorded in `fatal`, so that the controller winds down and
+/// tells the dashboard why rather than running on without the thread.
+/// Only the first error recorded in `fatal` is kept.
+fn supervise(
+    name: &str,
+    fatal: &Mutex<Option<ControllerError>>,
+    user_log: &UserLog<impl Write>,
+    work: impl FnOnce() -> Result<(), ControllerError>,
+) {
+    if let Err(e) = work() {
+        #[allow(unused_must_use)]
+        {
+            user_log.critical(&format!(
+                "The {name} thread failed, so the controller is quitting: {e}"
+            ));
+        }
+        fatal
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert(e);
+    }
+}
+
+/// Set every driver to its configured shutdown level, as the controller exits.
+/// A driver which cannot be set is reported, and the remaining drivers are still set.
+/// The drivers are set even if their lock is poisoned, since a panic is no reason to leave them
+/// live.
+///
+/// # Errors
+///
+/// This function will return an error if writing to the user log fails.
+fn shut_down_drivers(
+    config: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    let failures = {
+        let mut driver_lines = driver_lines
+            .lock_urgent()
+            .unwrap_or_else(PoisonError::into_inner);
+        config
+            .drivers
+            .iter()
+            .zip(driver_lines.iter_mut())
+            .filter_map(|(driver, line)| {
+                line.write(driver.shutdown_level)
+                    .err()
+                    .map(|e| (&driver.label, e))
+            })
+            .collect::<Vec<_>>()
+    };
+    // every driver is set before any failure is reported, so a failing log cannot leave one live
+    for (label, e) in failures {
+        user_log.critical(&format!(
+            "Unable to set driver {label} to its shutdown level: {e}"
+        ))?;
+    }
+    Ok(())
+}
+
+/// A guard which sets every driver to its shutdown level when it is dropped, so that the drivers
+/// are left safe however the controller exits, including by an error or a panic.
+struct DriverShutdown<'a, P: GpioPin, W: Write> {
+    /// The configuration giving the shutdown level of each driver.
+    config: &'a Configuration,
+    /// The lines of the drivers to shut down.
+    driver_lines: &'a PriorityMutex<Vec<P>>,
+    /// The log to which failures to shut down a driver are reported.
+    user_log: &'a UserLog<W>,
+}
+
+impl<P: GpioPin, W: Write> Drop for DriverShutdown<'_, P, W> {
+    fn drop(&mut self) {
+        // there is nowhere left to report a failure to write to the user log
+        let _ = shut_down_drivers(self.config, self.driver_lines, self.user_log);
+    }
+}
+
+/// Whether the controller has been asked to quit by SIGTERM or SIGINT.
+static TERMINATION_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Record that the controller has been asked to quit by a signal.
+/// Almost nothing is safe to do in a signal handler, so this only sets a flag, which is acted on
+/// as the dashboard listener is polled.
+extern "C" fn request_termination(_: libc::c_int) {
+    TERMINATION_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Install `request_termination` as the handler for SIGTERM and SIGINT.
+///
+/// # Errors
+///
+/// This function will return an error if either handler cannot be installed.
+fn install_termination_handler() -> Result<(), ControllerError> {
+    let action = SigAction::new(
+        SigHandler::Handler(request_termination),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+    for signal in [Signal::SIGTERM, Signal::SIGINT] {
+        // the handler only stores to an atomic, which is async-signal-safe
+        unsafe { sigaction(signal, &action) }.map_err(io::Error::from)?;
+    }
+    Ok(())
+}
+
+/// Take one step towards quitting, returning the state of the controller afterwards.
+///
+/// From standby, the controller quits immediately.
+/// While the engine is armed, the controller is emergency stopped first, so that it never quits
+/// with the engine live; while an emergency stop or purge is under way, there is nothing to do but
+/// wait for it to finish.
+/// A failure to emergency stop is logged, but is not an error.
+///
+/// # Errors
+///
+/// This function will return an error if the state lock is poisoned or if writing to the user log
+/// fails.
+fn step_toward_quit(
+    config: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    user_log: &UserLog<impl Write>,
+) -> Result<State, ControllerError> {
+    match state.status()? {
+        State::Standby => {
+            // if the state changed since it was read, the next step handles the new state
+            if state.move_from(State::Standby, State::Quit).is_ok() {
+                user_log.info("Quitting")?;
+            }
+        }
+        State::PreIgnite | State::Ignite | State::PostIgnite => {
+            user_log.critical("Emergency stopping before quitting")?;
+            if let Err(e) = emergency_stop(config, driver_lines, state, user_log) {
+                user_log.critical(&format!("Unable to emergency stop before quitting: {e}"))?;
+            }
+        }
+        State::EStopping | State::Purging | State::Quit => (),
+    }
+    Ok(state.status()?)
+}
+
+/// Bring the controller to the `Quit` state, emergency stopping it and waiting for the purge first
+/// if it is armed.
+/// Every other thread of the controller runs until it quits, so this must be done before they can
+/// be joined.
+///
+/// # Errors
+///
+/// This function will return an error if the state lock is poisoned or if writing to the user log
+/// fails.
+fn wind_down(
+    config: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    loop {
+        let status = step_toward_quit(config, driver_lines, state, user_log)?;
+        if status == State::Quit {
+            return Ok(());
+        }
+        state.wait_while(status, ACCEPT_POLL_PERIOD)?;
+    }
+}
+
+/// Validate a configuration and print a report of every problem with it, without acquiring any
+/// hardware or opening the network.
+///
+/// The configuration is read from `json_path`, from standard input if `json_path` is `-`, or from
+/// the `SLONK_CONFIG` environment variable if no path is given.
+///
+/// # Errors
+///
+/// This function will return an error if the configuration cannot be read or has any problem.
+/// If there are several problems, the error is the first one found.
+fn check_configuration(json_path: Option<&str>) -> Result<(), ControllerError> {
+    let parse_result = match json_path {
+        Some("-") => Configuration::parse_unvalidated(&mut io::stdin().lock()),
+        Some(path) => Configuration::parse_unvalidated(&mut BufReader::new(File::open(path)?)),
+        None => match std::env::var(CONFIG_ENV_VAR) {
+            Ok(json) => Configuration::parse_unvalidated(&mut json.as_bytes()),
+            Err(_) => return Err(ControllerError::MissingConfigPath),
+        },
+    };
+    let mut problems = match parse_result {
+        Ok(config) => config.problems(),
+        Err(e) => vec![e],
+    };
+
+    if problems.is_empty() {
+        println!("Configuration is valid.");
+        return Ok(());
+    }
+    println!(
+        "Found {} problem(s) with the configuration:",
+        problems.len()
+    );
+    for problem in &problems {
+        println!("  - {problem}");
+    }
+    Err(ControllerError::Configuration(problems.swap_remove(0)))
+}
+
+/// Construct a new file with path `p` if there is not a file already there.
+/// Returns a handle to the file if it was created.
+/// IF the file already exists, returns an error.
+///
+/// TODO: remove this method and substitute with `File::create_new()` when it is stabilized.
+fn file_create_new(p: impl AsRef<Path>) -> io::Result<File> {
+    File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(p)
+}
+
+/// One entry for every log file of a run, such as its path or its open file.
+struct LogTree<T> {
+    /// The entry of each sensor's log, indexed first by group ID and then by sensor ID.
+    /// The logs of each group's virtual sensors follow those of its real sensors.
+    sensors: Vec<Vec<T>>,
+    /// The entry of the command log.
+    commands: T,
+    /// The entry of the pre-trigger log, if a pre-trigger duration is configured.
+    pretrigger: Option<T>,
+    /// The entry of the driver log.
+    drivers: T,
+    /// The entry of the snapshot log.
+    snapshots: T,
+    /// The entry of the event log, to which the operator's annotations are written.
+    events: T,
+    /// The entry of the log of messages sent to the dashboard.
+    sent: T,
+    /// The entry of the raw capture of the bytes sent to the dashboard, if raw capture is enabled.
+    raw: Option<T>,
+    /// The entry of the raw capture of the bytes received from the dashboard, if raw capture is
+    /// enabled.
+    received: Option<T>,
+    /// The entry of the raw SPI transaction log of each sensor which logs them, in the order given
+    /// by `raw_logged_sensors`.
+    raw_sensors: Vec<T>,
+}
+
+/// The path of every log file of a run, relative to the logs directory.
+type LogPaths = LogTree<PathBuf>;
+
+impl LogPaths {
+    /// Compute the path of every log file of the run `run` under `configuration`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a log file template cannot be filled in.
+    fn new(
+        configuration: &Configuration,
+        run: &RunInfo,
+        capture_raw: bool,
+    ) -> Result<LogPaths, ControllerError> {
+        let extension = match configuration.log_format {
+            LogFormat::Csv => "csv",
+            LogFormat::Binary => "bin",
+        };
+        let mut sensors = Vec::new();
+        for group in &configuration.sensor_groups {
+            let mut group_paths = Vec::new();
+            for sensor in &group.sensors {
+                let mut path = configuration
+                    .log_layout
+                    .sensor_path(&group.label, &sensor.label, run)?
+                    .into_os_string();
+                path.push(format!(".{extension}"));
+                group_paths.push(PathBuf::from(path));
+            }
+            // virtual sensors are always logged as CSV
+            for virtual_sensor in &group.virtual_sensors {
+                let mut path = configuration
+                    .log_layout
+                    .sensor_path(&group.label, &virtual_sensor.label, run)?
+                    .into_os_string();
+                path.push(".csv");
+                group_paths.push(PathBuf::from(path));
+            }
+            sensors.push(group_paths);
+        }
+
+        let raw_sensors = raw_logged_sensors(configuration)
+            .map(|(group, sensor)| {
+                let mut path = configuration
+                    .log_layout
+                    .sensor_path(&group.label, &sensor.label, run)?
+                    .into_os_string();
+                path.push(".raw.csv");
+                Ok(PathBuf::from(path))
+            })
+            .collect::<Result<_, ControllerError>>()?;
+
+        let layout = &configuration.log_layout;
+        Ok(LogPaths {
+            sensors,
+            commands: layout.other_path("commands.csv", run)?,
+            pretrigger: match configuration.pretrigger_duration_ms {
+                Some(_) => Some(layout.other_path("pretrigger.csv", run)?),
+                None => None,
+            },
+            drivers: layout.other_path("drivers.csv", run)?,
+            snapshots: layout.other_path("snapshots.csv", run)?,
+            events: layout.other_path("events.csv", run)?,
+            sent: layout.other_path("sent.csv", run)?,
+            raw: if capture_raw {
+                Some(layout.other_path("sent.raw", run)?)
+            } else {
+                None
+            },
+            received: if capture_raw {
+                Some(layout.other_path("received.raw", run)?)
+            } else {
+                None
+            },
+            raw_sensors,
+        })
+    }
+}
+
+impl<T> LogTree<T> {
+    /// List every entry, in a fixed order: the sensor logs (by group and then by sensor), followed
+    /// by the command, pre-trigger, driver, snapshot, event, and sent message logs, the raw
+    /// captures of bytes sent and received, and the raw SPI transaction logs of sensors.
+    fn all(&self) -> Vec<&T> {
+        let mut entries: Vec<&T> = self.sensors.iter().flatten().collect();
+        entries.push(&self.commands);
+        entries.extend(self.pretrigger.as_ref());
+        entries.push(&self.drivers);
+        entries.push(&self.snapshots);
+        entries.push(&self.events);
+        entries.push(&self.sent);
+        entries.extend(self.raw.as_ref());
+        entries.extend(self.received.as_ref());
+        entries.extend(&self.raw_sensors);
+        entries
+    }
+
+    /// Build a tree of the same shape by applying `f` to every entry, in the order given by `all`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the first error returned by `f`, without applying it to any later
+    /// entry.
+    fn try_map<U, E>(&self, mut f: impl FnMut(&T) -> Result<U, E>) -> Result<LogTree<U>, E> {
+        Ok(LogTree {
+            sensors: self
+                .sensors
+                .iter()
+                .map(|group| group.iter().map(&mut f).collect())
+                .collect::<Result<_, E>>()?,
+            commands: f(&self.commands)?,
+            pretrigger: self.pretrigger.as_ref().map(&mut f).transpose()?,
+            drivers: f(&self.drivers)?,
+            snapshots: f(&self.snapshots)?,
+            events: f(&self.events)?,
+            sent: f(&self.sent)?,
+            raw: self.raw.as_ref().map(&mut f).transpose()?,
+            received: self.received.as_ref().map(&mut f).transpose()?,
+            raw_sensors: self.raw_sensors.iter().map(f).collect::<Result<_, E>>()?,
+        })
+    }
+}
+
+/// Iterate over every sensor whose raw SPI transactions are logged, along with its group.
+/// Sensors read through IIO have no SPI transactions, so they are never included.
+fn raw_logged_sensors(
+    configuration: &Configuration,
+) -> impl Iterator<Item = (&SensorGroup, &Sensor)> {
+    configuration
+        .sensor_groups
+        .iter()
+        .flat_map(|group| group.sensors.iter().map(move |sensor| (group, sensor)))
+        .filter(|(_, sensor)| sensor.log_raw && sensor.iio_channel.is_none())
+}
+
+/// Record the scale of each opened IIO channel in `iio_channels` (indexed first by group ID and
+/// then by sensor ID) on the sensor which reads it, so that its calibration applies to the scaled
+/// value.
+fn apply_iio_scales(configuration: &mut Configuration, iio_channels: &[Vec<Option<impl Adc>>]) {
+    for (group, channels) in configuration.sensor_groups.iter_mut().zip(iio_channels) {
+        for (sensor, channel) in group.sensors.iter_mut().zip(channels) {
+            sensor.iio_scale = channel.as_ref().map(Adc::scale);
+        }
+    }
+}
+
+/// Create every log file in `relative_paths`, along with any directories they need, inside the
+/// logs directory `logs_dir`.
+/// Each file is returned in the place of its path.
+///
+/// Either every file is created or none are: if anything cannot be created, every file and
+/// directory created so far is removed again, so a failure never leaves a half-built log tree.
+///
+/// # Errors
+///
+/// This function will return `ControllerError::LogFile` naming the path which could not be
+/// created, and why.
+/// A file which already exists, or which is listed twice, is reported before anything is created.
+fn create_log_tree(
+    logs_dir: &Path,
+    relative_paths: &LogPaths,
+) -> Result<LogTree<File>, ControllerError> {
+    let mut seen = HashSet::new();
+    for path in relative_paths.all() {
+        let path = logs_dir.join(path);
+        if !seen.insert(path.clone()) || path.symlink_metadata().is_ok() {
+            return Err(ControllerError::LogFile(
+                path,
+                io::ErrorKind::AlreadyExists.into(),
+            ));
+        }
+    }
+
+    let mut created_dirs = Vec::new();
+    let mut created_files = Vec::new();
+    // the files created so far are closed when the partially built tree is dropped on failure
+    let files = relative_paths.try_map(|relative_path| {
+        let path = logs_dir.join(relative_path);
+        if let Some(parent) = path.parent() {
+            create_dirs(parent, &mut created_dirs)?;
+        }
+        let file = file_create_new(&path).map_err(|e| (path.clone(), e))?;
+        created_files.push(path);
+        Ok(file)
+    });
+
+    files.map_err(|(failed_path, e)| {
+        // roll back, innermost first; a failure to clean up cannot be helped
+        for created in &created_files {
+            let _ = remove_file(created);
+        }
+        for dir in created_dirs.iter().rev() {
+            let _ = remove_dir(dir);
+        }
+        ControllerError::LogFile(failed_path, e)
+    })
+}
+
+/// Create the directory `dir` and any of its missing ancestors, recording each directory created
+/// in `created`, outermost first.
+///
+/// # Errors
+///
+/// This function will return the path of the directory which could not be created, along with the
+/// cause of the failure.
+fn create_dirs(dir: &Path, created: &mut Vec<PathBuf>) -> Result<(), (PathBuf, io::Error)> {
+    let missing: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.is_dir())
+        .collect();
+    for ancestor in missing.into_iter().rev() {
+        match create_dir(ancestor) {
+            Ok(()) => created.push(ancestor.to_path_buf()),
+            Err(e) => return Err((ancestor.to_path_buf(), e)),
+        }
+    }
+    Ok(())
+}
+
+/// The environment variable from which the configuration JSON is read if no configuration path is
+/// given on the command line.
+pub(crate) const CONFIG_ENV_VAR: &str = "SLONK_CONFIG";
+
+/// The number of times to attempt to bind the TCP listener before giving up.
+const BIND_ATTEMPTS: u32 = 5;
+
+/// The period at which the UDP telemetry address is resolved again, if none is configured.
+const TELEMETRY_RESOLVE_PERIOD: Duration = Duration::from_secs(30);
+
+/// The time to wait after the first failed attempt to bind the TCP listener.
+/// The wait doubles after each subsequent failure.
+const BIND_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The number of unanswered TCP keepalive probes after which a dashboard connection is considered
+/// lost.
+const KEEPALIVE_PROBES: u32 = 3;
+
+/// The period at which the TCP listener is checked for new dashboard connections.
+/// Periodic tasks are serviced between checks.
+const ACCEPT_POLL_PERIOD: Duration = Duration::from_millis(50);
+
+/// Bind a TCP listener to `address`, retrying with exponential backoff if binding fails.
+///
+/// The `SO_REUSEADDR` and `SO_REUSEPORT` options are set on the socket before it is bound, so that
+/// the controller can be restarted immediately after it exits.
+/// `SO_REUSEPORT` is set by calling `reuse_port` on the socket.
+/// It is only available on Linux 3.9 and later and on the BSDs, so if it cannot be set and
+/// `on_failure` is `Warn`, a warning is logged and the listener is opened without it.
+///
+/// # Errors
+///
+/// This function will return the error from the last attempt if all `attempts` fail, or an error
+/// if we are unable to write to the user log.
+fn bind_listener(
+    address: SocketAddr,
+    attempts: u32,
+    on_failure: ReusePortFailure,
+    reuse_port: impl Fn(RawFd) -> nix::Result<()>,
+    user_log: &UserLog<impl Write>,
+) -> Result<TcpListener, ControllerError> {
+    let mut backoff = BIND_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match open_listener(address, on_failure, &reuse_port) {
+            Ok((listener, None)) => return Ok(listener),
+            Ok((listener, Some(e))) => {
+                user_log.warn(&format!(
+                    "Unable to set SO_REUSEPORT on the listener for {address}: {e}. Continuing without it"
+                ))?;
+                return Ok(listener);
+            }
+            Err(e) if attempt < attempts => {
+                user_log.warn(&format!(
+                    "Attempt {attempt}/{attempts} to bind to {address} failed: {e}. Retrying in {backoff:?}"
+                ))?;
+            }
+            Err(e) => {
+                user_log.critical(&format!(
+                    "Attempt {attempt}/{attempts} to bind to {address} failed: {e}. Giving up"
+                ))?;
+                return Err(e.into());
+            }
+        }
+
+        sleep(backoff);
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// Make a single attempt to create a TCP listener bound to `address`.
+///
+/// On success, also returns the error from setting `SO_REUSEPORT` with `reuse_port` if that failed
+/// and `on_failure` is `Warn`.
+///
+/// # Errors
+///
+/// This function will return an error if any step of creating, configuring, binding, or listening
+/// on the socket fails.
+fn open_listener(
+    address: SocketAddr,
+    on_failure: ReusePortFailure,
+    reuse_port: impl Fn(RawFd) -> nix::Result<()>,
+) -> io::Result<(TcpListener, Option<nix::Error>)> {
+    let family = match address {
+        SocketAddr::V4(_) => AddressFamily::Inet,
+        SocketAddr::V6(_) => AddressFamily::Inet6,
+    };
+    let fd = socket(family, SockType::Stream, SockFlag::SOCK_CLOEXEC, None)?;
+    // SAFETY: `fd` is a freshly created socket which nothing else owns.
+    // Taking ownership immediately ensures that it is closed if any later step fails.
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+    setsockopt(fd, sockopt::ReuseAddr, &true)?;
+    let reuse_port_error = match reuse_port(fd) {
+        Ok(()) => None,
+        Err(e) if on_failure == ReusePortFailure::Warn => Some(e),
+        Err(e) => return Err(e.into()),
+    };
+    bind(fd, &SockAddr::new_inet(InetAddr::from_std(&address)))?;
+    listen(fd, 128)?;
+
+    Ok((listener, reuse_port_error))
+}
+
+/// Set `SO_REUSEPORT` on the socket `fd`.
+///
+/// # Errors
+///
+/// This function will return an error if the platform does not support the option.
+fn set_reuse_port(fd: RawFd) -> nix::Result<()> {
+    setsockopt(fd, sockopt::ReusePort, &true)
+}
+
+/// Apply the scheduling options for a sensor group to the calling thread.
+///
+/// Failing to apply an option is not fatal: the thread will continue with default scheduling, and
+/// a warning will be logged.
+///
+/// # Errors
+///
+/// This function will return an error if we are unable to write to the user log.
+fn apply_scheduling(
+    group: &SensorGroup,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    if let Some(priority) = group.realtime_priority {
+        match set_realtime_priority(priority) {
+            Ok(()) => user_log.debug(&format!(
+                "Set real-time priority {priority} for sensor group {}",
+                group.label
+            ))?,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => user_log.warn(&format!(
+                "Unable to set real-time priority for sensor group {} (missing CAP_SYS_NICE?)",
+                group.label
+            ))?,
+            Err(e) => user_log.warn(&format!(
+                "Unable to set real-time priority for sensor group {}: {e}",
+                group.label
+            ))?,
+        }
+    }
+
+    if let Some(core) = group.cpu_core {
+        match pin_to_core(core) {
+            Ok(()) => user_log.debug(&format!(
+                "Pinned sensor group {} to CPU core {core}",
+                group.label
+            ))?,
+            Err(e) => user_log.warn(&format!(
+                "Unable to pin sensor group {} to CPU core {core}: {e}",
+                group.label
+            ))?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Preallocate `size` bytes of disk space for `file`.
+///
+/// The reported length of the file is left unchanged, so writes will still begin at the start of
+/// the file.
+///
+/// # Errors
+///
+/// This function will return an error if the filesystem does not support preallocation or if
+/// there is not enough space on disk.
+fn preallocate(file: &File, size: u64) -> io::Result<()> {
+    let len = libc::off_t::try_from(size)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "preallocation size too large"))?;
+    // SAFETY: the file descriptor is valid for as long as `file` is alive.
+    let result = unsafe { libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Enable TCP keepalive on `stream`, so that a dashboard which vanishes without closing its
+/// connection is noticed after `idle_secs` seconds of silence and `KEEPALIVE_PROBES` unanswered
+/// probes, one second apart.
+///
+/// # Errors
+///
+/// This function will return an error if any of the socket options cannot be set.
+fn set_keepalive(stream: &TcpStream, idle_secs: u32) -> io::Result<()> {
+    let fd = stream.as_raw_fd();
+    setsockopt(fd, sockopt::KeepAlive, &true)?;
+    setsockopt(fd, sockopt::TcpKeepIdle, &idle_secs)?;
+    setsockopt(fd, sockopt::TcpKeepInterval, &1)?;
+    setsockopt(fd, sockopt::TcpKeepCount, &KEEPALIVE_PROBES)?;
+    Ok(())
+}
+
+/// The dashboard connection currently being served.
+///
+/// Only one dashboard is served at a time.
+/// When a new dashboard connects, the previous connection is shut down, so that a dashboard which
+/// reconnects after a network drop never has to wait for the controller to notice that its old
+/// connection is gone.
+///
+/// A lost connection is only declared a disconnect once the dashboard has failed to reconnect for
+/// a grace period, so that a momentary network drop does not end the session.
+struct Connections {
+    /// The ID, peer address, and a handle to the stream of the current connection, if there is one.
+    current: Mutex<Option<(u64, SocketAddr, TcpStream)>>,
+    /// The time at which the last connection was lost, if no dashboard has connected since and the
+    /// loss has not yet been declared a disconnect.
+    lost_at: Mutex<Option<Instant>>,
+}
+
+impl Connections {
+    /// Construct a new `Connections` with no current connection.
+    fn new() -> Connections {
+        Connections {
+            current: Mutex::new(None),
+            lost_at: Mutex::new(None),
+        }
+    }
+
+    /// Make the connection with ID `id` to `peer` over `stream` the current connection, and direct
+    /// all messages in `to_dash` to it.
+    /// The previous connection, if any, is shut down, and its peer address is returned.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a lock is poisoned or if `stream` cannot be cloned.
+    fn replace(
+        &self,
+        id: u64,
+        peer: SocketAddr,
+        stream: &TcpStream,
+        to_dash: &DashChannel<TcpStream, impl Write>,
+    ) -> Result<Option<SocketAddr>, ControllerError> {
+        let mut current = self.current.lock()?;
+        let previous = current.replace((id, peer, stream.try_clone()?));
+        to_dash.set_channel(Some(stream.try_clone()?))?;
+
+        Ok(previous.map(|(_, previous_peer, previous)| {
+            // the connection may already be dead, in which case it needs no shutting down
+            let _ = previous.shutdown(Shutdown::Both);
+            previous_peer
+        }))
+    }
+
+    /// Release the connection with ID `id` after its dashboard was lost at time `now`.
+    /// If it is still the current connection, messages in `to_dash` are no longer sent anywhere,
+    /// and the loss is recorded so that it can later be declared a disconnect (see `expire`).
+    /// Returns whether the connection was still current, rather than having been replaced.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a lock is poisoned.
+    fn release(
+        &self,
+        id: u64,
+        to_dash: &DashChannel<TcpStream, impl Write>,
+        now: Instant,
+    ) -> Result<bool, ControllerError> {
+        let mut current = self.current.lock()?;
+        if !matches!(*current, Some((current_id, ..)) if current_id == id) {
+            return Ok(false);
+        }
+        *current = None;
+        to_dash.set_channel(None)?;
+        *self.lost_at.lock()? = Some(now);
+        Ok(true)
+    }
+
+    /// Determine whether the last lost connection has gone without a reconnect for at least
+    /// `grace` as of time `now`, and so must be declared a disconnect.
+    /// Each loss is declared at most once.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a lock is poisoned.
+    fn expire(&self, grace: Duration, now: Instant) -> Result<bool, ControllerError> {
+        let mut lost_at = self.lost_at.lock()?;
+        match *lost_at {
+            Some(time) if now.saturating_duration_since(time) >= grace => {
+                *lost_at = None;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Record that a dashboard has connected at time `now`.
+    /// Returns how long the controller was without a dashboard, if the last connection was lost
+    /// and has not yet been declared a disconnect.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a lock is poisoned.
+    fn resume(&self, now: Instant) -> Result<Option<Duration>, ControllerError> {
+        Ok(self
+            .lost_at
+            .lock()?
+            .take()
+            .map(|time| now.saturating_duration_since(time)))
+    }
+
+    /// Shut down the current connection, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a lock is poisoned.
+    fn shutdown(&self) -> Result<(), ControllerError> {
+        if let Some((.., stream)) = self.current.lock()?.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+/// Accept dashboard connections from `listener`, handling each one on its own thread.
+/// Stops accepting connections once the controller has quit.
+///
+/// The listener is polled rather than blocked on, so that `tick` is called about every
+/// `ACCEPT_POLL_PERIOD` (and after every accepted connection) to service periodic tasks, and so
+/// that quitting is noticed even if no dashboard ever connects.
+///
+/// Each new connection replaces the previous one (see `Connections`).
+/// Every transition (a connection being accepted, replaced, lost, or declared disconnected) is
+/// recorded in `user_log`.
+/// When a lost dashboard is declared disconnected, console streaming is stopped and the
+/// configured disconnect action, if any, is performed.
+///
+/// # Errors
+///
+/// This function will return an error if a lock is poisoned, if we are unable to write to the
+/// user log, if the listener cannot be made non-blocking, or if `tick` returns an error.
+fn serve_clients<'a, P>(
+    thread_scope: &'a Scope<'a, '_>,
+    listener: &TcpListener,
+    connections: &'a Connections,
+    recent_commands: &'a Mutex<RecentCommands>,
+    to_dash: &'a DashChannel<TcpStream, impl Write + Send>,
+    config: &'a Configuration,
+    driver_lines: &'a PriorityMutex<Vec<impl Rewire<P> + Send>>,
+    cmd_log_file: &'a Mutex<impl Write + Send>,
+    received_raw: Option<&'a Mutex<impl Write + Send>>,
+    user_log: &'a UserLog<impl Write + Send + Sync>,
+    state: &'a Guard,
+    injections: &'a Injections,
+    throttle: &'a Throttle,
+    deadman: &'a Deadman,
+    hold: &'a Hold,
+    flusher: &'a Flusher,
+    readiness: &'a Readiness,
+    history: &'a History,
+    tunings: &'a Tunings,
+    calibrator: &'a Calibrator,
+    sequences: &'a Sequences,
+    snapshots: &'a Snapshots<impl Write + Send>,
+    rewiring: &'a Rewiring<P>,
+    interlocks: &'a Interlocks,
+    pause: &'a Pause,
+    restarts: &'a Restarts,
+    annotations: &'a Annotations<impl Write + Send>,
+    mut tick: impl FnMut() -> Result<(), ControllerError>,
+) -> Result<(), ControllerError> {
+    listener.set_nonblocking(true)?;
+    let grace = Duration::from_millis(config.disconnect_grace_ms.map_or(0, u64::from));
+    let mut next_connection_id = 0;
+    while state.status()? != State::Quit {
+        tick()?;
+
+        let accepted = listener.accept();
+        // a loss is checked after accepting so that a dashboard which reconnects within the grace
+        // period is never declared disconnected
+        if connections.expire(grace, Instant::now())? {
+            declare_disconnect(config, driver_lines, state, interlocks, user_log)?;
+        }
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                sleep(ACCEPT_POLL_PERIOD);
+                continue;
+            }
+            Err(e) => {
+                user_log.warn(&format!("failed to collect incoming client: {e}"))?;
+                continue;
+            }
+        };
+        // some platforms make accepted streams inherit the listener's non-blocking mode
+        if let Err(e) = stream.set_nonblocking(false) {
+            user_log.warn(&format!("failed to configure incoming client: {e}"))?;
+            continue;
+        }
+        let connection_id = next_connection_id;
+        next_connection_id += 1;
+        let peer = stream.peer_addr()?;
+        user_log.info(&format!("Accepted client {peer:?}"))?;
+        if let Some(downtime) = connections.resume(Instant::now())? {
+            user_log.info(&format!(
+                "Dashboard reconnected after {downtime:?}, within the disconnect grace period"
+            ))?;
+        }
+        if let Some(idle_secs) = config.dashboard_keepalive_s {
+            if let Err(e) = set_keepalive(&stream, idle_secs) {
+                user_log.warn(&format!("Unable to enable keepalive for {peer:?}: {e}"))?;
+            }
+        }
+        if let Some(previous) = connections.replace(connection_id, peer, &stream, to_dash)? {
+            user_log.warn(&format!(
+                "Dropped connection to {previous:?}, which was replaced by {peer:?}"
+            ))?;
+        }
+
+        user_log.debug("Overwrote to dashboard lock, now reading commands")?;
+
+        thread_scope.spawn(move || -> Result<(), ControllerError> {
+            let mut stream = stream;
+            let result = handle_client(
+                thread_scope,
+                to_dash,
+                &mut stream,
+                peer,
+                config,
+                driver_lines,
+                cmd_log_file,
+                received_raw,
+                user_log,
+                state,
+                injections,
+                throttle,
+                deadman,
+                hold,
+                flusher,
+                readiness,
+                history,
+                tunings,
+                calibrator,
+                sequences,
+                snapshots,
+                rewiring,
+                interlocks,
+                pause,
+                restarts,
+                annotations,
+                recent_commands,
+            );
+            if let Err(e) = &result {
+                user_log.warn(&format!(
+                    "Stopped handling client {peer:?} due to error: {e:?}"
+                ))?;
+            }
+            if connections.release(connection_id, to_dash, Instant::now())? {
+                restore_interlocks(config, interlocks, user_log)?;
+                user_log.info("Waiting for the dashboard to reconnect")?;
+            }
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+/// A reader which keeps a copy of every byte read through it, so that the exact bytes of each
+/// command received can be captured.
+struct RecordingReader<R: Read> {
+    /// The reader from which bytes are actually read.
+    inner: R,
+    /// The bytes read since the last call to `take_recorded`, or `None` if bytes are not being
+    /// recorded.
+    recorded: Option<Vec<u8>>,
+}
 
-        // TODO: maybe configure this IP number?
-        let address = "0.0.0.0:2707";
-        let listener = TcpListener::bind(address)?;
+impl<R: Read> RecordingReader<R> {
+    /// Construct a new `RecordingReader` around `inner`, which only records if `record` is set.
+    fn new(inner: R, record: bool) -> RecordingReader<R> {
+        RecordingReader {
+            inner,
+            recorded: record.then(Vec::new),
+        }
+    }
 
-        user_log.info(&format!(
-            "Opened TCP listener on address {}",
-            listener.local_addr()?
-        ))?;
-        user_log.debug("Handling clients...")?;
+    /// Take every byte recorded since the last call to `take_recorded`.
+    fn take_recorded(&mut self) -> Vec<u8> {
+        self.recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
 
-        for client_res in listener.incoming() {
-            let mut stream = match client_res {
-                Ok(i) => i,
-                Err(e) => {
-                    user_log.warn(&format!("failed to collect incoming client: {e}"))?;
-                    continue;
-                }
-            };
-            user_log.info(&format!("Accepted client {:?}", stream.peer_addr()?))?;
-            to_dash.set_channel(Some(stream.try_clone()?))?;
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(recorded) = &mut self.recorded {
+            recorded.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
 
-            user_log.debug("Overwrote to dashboard lock, now reading commands")?;
+/// Record the bytes of a command received from the dashboard in the raw capture `received_raw`.
+///
+/// The command is recorded in the same format as the raw capture of sent messages: a line
+/// containing the time it was received (in nanoseconds since the UNIX epoch) and the number of
+/// bytes received, separated by a comma, followed by the bytes themselves and then a newline.
+/// Nothing is recorded if `bytes` is empty.
+///
+/// # Errors
+///
+/// This function will return an error if writing to `received_raw` fails.
+///
+/// # Panics
+///
+/// This function will panic if the current time is before the UNIX epoch.
+fn capture_received(received_raw: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    writeln!(received_raw, "{time},{}", bytes.len())?;
+    received_raw.write_all(bytes)?;
+    writeln!(received_raw)
+}
 
-            #[allow(unused_must_use)]
-            {
-                // keep the port open even in error cases
-                handle_client(
-                    s,
-                    to_dash_ref,
-                    &mut stream,
-                    config_ref,
-                    driver_lines_ref,
-                    cmd_file_ref,
-                    user_log_ref,
-                    state_ref,
-                );
-            }
+/// Ignore a command whose type, `command_type`, the controller does not support.
+/// If `config.ack_unsupported_commands` is set, the dashboard is told so with an `Unsupported`
+/// message, followed by a failed acknowledgement if the command had a `command_id`.
+///
+/// # Errors
+///
+/// This function will return an error if writing to the user log or the dashboard fails.
+fn reject_unsupported(
+    config: &Configuration,
+    to_dash: &DashChannel<impl Write, impl Write>,
+    user_log: &UserLog<impl Write>,
+    command_type: &str,
+    command_id: Option<u64>,
+) -> Result<(), ControllerError> {
+    user_log.warn(&format!(
+        "Ignoring command of unsupported type {command_type:?}"
+    ))?;
+    if config.ack_unsupported_commands {
+        to_dash.send(&Message::Unsupported { command_type })?;
+        if let Some(command_id) = command_id {
+            to_dash.send(&Message::Ack {
+                command_id,
+                success: false,
+            })?;
         }
+    }
+    Ok(())
+}
 
-        Ok::<(), ControllerError>(())
-    })?;
-    // successful termination!
+/// Restore every overridden interlock in `interlocks`, since an override only lasts as long as the
+/// dashboard which made it stays connected.
+/// Each restored interlock is logged.
+///
+/// # Errors
+///
+/// This function will return an error if the interlocks' lock is poisoned or writing to the user
+/// log fails.
+fn restore_interlocks(
+    config: &Configuration,
+    interlocks: &Interlocks,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    let restored = interlocks
+        .restore_all()
+        .map_err(|_| ControllerError::Poison)?;
+    for interlock_id in restored {
+        user_log.warn(&format!(
+            "Interlock {} restored, since the dashboard which overrode it is gone",
+            config.interlocks[usize::from(interlock_id)].label
+        ))?;
+    }
     Ok(())
 }
 
-/// Construct a new file with path `p` if there is not a file already there.
-/// Returns a handle to the file if it was created.
-/// IF the file already exists, returns an error.
+/// Declare that the dashboard is disconnected, after it was lost and failed to reconnect within the
+/// grace period.
+/// Console streaming is stopped, every interlock override is cleared, and the disconnect action in
+/// `config`, if any, is performed.
+/// A failure to perform the action is logged, but is not an error.
 ///
-/// TODO: remove this method and substitute with `File::create_new()` when it is stabilized.
-fn file_create_new(p: impl AsRef<Path>) -> io::Result<File> {
-    File::options()
-        .read(true)
-        .write(true)
-        .create_new(true)
-        .open(p)
+/// # Errors
+///
+/// This function will return an error if the interlocks' lock is poisoned or writing to the user
+/// log fails.
+fn declare_disconnect(
+    config: &Configuration,
+    driver_lines: &PriorityMutex<Vec<impl GpioPin>>,
+    state: &Guard,
+    interlocks: &Interlocks,
+    user_log: &UserLog<impl Write>,
+) -> Result<(), ControllerError> {
+    user_log.unsubscribe();
+    restore_interlocks(config, interlocks, user_log)?;
+    let Some(action) = &config.disconnect_action else {
+        user_log.info("Dashboard is disconnected")?;
+        return Ok(());
+    };
+    user_log.warn(&format!(
+        "Dashboard is disconnected, performing abort action {action:?}"
+    ))?;
+    if let Err(e) = abort(config, driver_lines, state, action, user_log) {
+        user_log.critical(&format!(
+            "Unable to perform disconnect action {action:?}: {e}"
+        ))?;
+    }
+    Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
 /// Handle a single dashboard client.
-fn handle_client<'a>(
+fn handle_client<'a, P>(
     thread_scope: &'a Scope<'a, '_>,
-    to_dash: &DashChannel<impl Write + Send, impl Write + Send>,
+    to_dash: &'a DashChannel<impl Write + Send + Sync, impl Write + Send>,
     from_dash: &mut impl Read,
+    peer: SocketAddr,
     config: &'a Configuration,
-    driver_lines: &'a Mutex<Vec<impl GpioPin + Send>>,
+    driver_lines: &'a PriorityMutex<Vec<impl Rewire<P> + Send>>,
     cmd_log_file: &'a Mutex<impl Write + Send>,
+    received_raw: Option<&Mutex<impl Write>>,
     user_log: &'a UserLog<impl Write + Send>,
     state: &'a Guard,
+    injections: &'a Injections,
+    throttle: &'a Throttle,
+    deadman: &'a Deadman,
+    hold: &'a Hold,
+    flusher: &'a Flusher,
+    readiness: &'a Readiness,
+    history: &'a History,
+    tunings: &'a Tunings,
+    calibrator: &'a Calibrator,
+    sequences: &'a Sequences,
+    snapshots: &'a Snapshots<impl Write + Send>,
+    rewiring: &'a Rewiring<P>,
+    interlocks: &'a Interlocks,
+    pause: &'a Pause,
+    restarts: &'a Restarts,
+    annotations: &'a Annotations<impl Write + Send>,
+    recent_commands: &'a Mutex<RecentCommands>,
 ) -> Result<(), ControllerError> {
-    to_dash.send(&Message::Config { config })?;
+    // a choice of sensor values made by an earlier connection does not carry over
+    to_dash.set_sensor_fields(config.sensor_value_fields)?;
+    to_dash.send(&Message::Config {
+        config,
+        protocol_version: PROTOCOL_VERSION,
+        controller_version: CONTROLLER_VERSION,
+    })?;
     user_log.debug("Successfully sent configuration to dashboard.")?;
+    // a dashboard which connects before the controller is ready is told once it becomes ready
+    if readiness.is_ready()? {
+        to_dash.send(&Message::Ready)?;
+    }
+    // commands are attributed to an anonymous operator until the client authenticates
+    let mut client = Client::new(peer);
+    let mut rate_limit = RateLimit::new(config);
+    // whether the client has declared a protocol version which the controller can speak
+    let mut protocol_declared = false;
+    let mut from_dash = RecordingReader::new(from_dash, received_raw.is_some());
     loop {
-        let cmd = match Command::parse(from_dash) {
-            Ok(cmd) => cmd,
+        let parsed = Command::parse(&mut from_dash);
+        // every command is captured exactly as received, whether or not it could be parsed
+        if let Some(received_raw) = received_raw {
+            let bytes = from_dash.take_recorded();
+            if let Err(e) = capture_received(&mut *received_raw.lock()?, &bytes) {
+                user_log.warn(&format!("Unable to capture received command: {e}"))?;
+            }
+        }
+        let (cmd, mut command_id) = match parsed {
+            Ok(parsed) => parsed,
             Err(e) => {
                 match e {
-                    incoming::Error::Malformed(s) => {
+                    incoming::Error::Malformed(s, reason) => {
                         user_log.critical(&format!(
-                            "Received malformed command: {}. Future commands will likely also be invalid",
+                            "Received malformed command: {} ({reason}). Future commands will likely also be invalid",
                             String::from_utf8_lossy(&s)
                         ))?;
                     }
+                    incoming::Error::Unsupported {
+                        command_type,
+                        command_id,
+                    } => {
+                        reject_unsupported(config, to_dash, user_log, &command_type, command_id)?;
+                    }
                     incoming::Error::Io(e) => {
-                        if matches!(
-                            e.kind(),
-                            std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::ConnectionReset
-                        ) {
+                        // interrupted reads are retried while parsing, so any I/O error means the
+                        // connection is gone
+                        if e.kind() == std::io::ErrorKind::UnexpectedEof {
                             // EOF means the dashboard closed the connection
-                            user_log.info("Dashboard disconnected.")?;
-                            return Ok(());
+                            user_log.info(&format!("Dashboard {peer:?} disconnected."))?;
+                        } else {
+                            user_log
+                                .warn(&format!("Lost connection to dashboard {peer:?}: {e}"))?;
                         }
-                        user_log.warn(&format!(
-                            "Encountered I/O error while parsing message: {e:?}"
-                        ))?;
+                        return Ok(());
                     }
                 }
                 continue;
             }
         };
 
-        if matches!(
-            cmd,
-            Command::Actuate {
-                driver_id: _,
-                value: _,
+        // an incompatible dashboard is cut off before its commands can be misunderstood, but an
+        // emergency stop is always understood
+        let protocol = match &cmd {
+            Command::Auth {
+                protocol_version, ..
+            } => check_protocol(config, *protocol_version),
+            Command::EmergencyStop => Ok(()),
+            _ if protocol_declared => Ok(()),
+            _ => check_protocol(config, None),
+        };
+        if let Err(e) = protocol {
+            let message = format!("Closing connection to dashboard {peer}: {e}");
+            user_log.warn(&message)?;
+            to_dash.send(&Message::Error {
+                level: LogLevel::Critical,
+                message: &message,
+            })?;
+            return Ok(());
+        }
+        if let Command::Auth {
+            protocol_version: Some(_),
+            ..
+        } = cmd
+        {
+            protocol_declared = true;
+        }
+
+        // a resent safing command is always executed again, since doing so can never be harmful
+        if let Some(command_id) = command_id.filter(|_| !cmd.is_safing()) {
+            let earlier = recent_commands.lock()?.lookup(command_id, Instant::now());
+            if let Some(outcome) = earlier {
+                // the dashboard resent a command we already executed; don't execute it again
+                user_log.warn(&format!(
+                    "Ignoring duplicate of command {command_id} ({cmd}) from {peer}"
+                ))?;
+                // a command which is still executing is acknowledged once it finishes
+                if let Outcome::Finished(success) = outcome {
+                    to_dash.send(&Message::Ack {
+                        command_id,
+                        success,
+                    })?;
+                }
+                continue;
+            }
+        }
+
+        if !rate_limit.admit(&cmd, Instant::now()) {
+            // the command is not recorded as executed, so the dashboard may resend it later
+            let message =
+                format!("Dropping command {cmd} from {peer}: command rate limit exceeded");
+            user_log.warn(&message)?;
+            to_dash.send(&Message::Error {
+                level: LogLevel::Warn,
+                message: &message,
+            })?;
+            if let Some(command_id) = command_id {
+                to_dash.send(&Message::Ack {
+                    command_id,
+                    success: false,
+                })?;
             }
+            continue;
+        }
+
+        let accepted = match &cmd {
+            Command::Auth { operator, .. } => match client.authenticate(operator) {
+                Ok(()) => {
+                    user_log.info(&format!("Client {peer} authenticated as {operator}"))?;
+                    true
+                }
+                Err(e) => {
+                    user_log.warn(&format!(
+                        "Client {peer} failed to authenticate as {operator:?}: {e}"
+                    ))?;
+                    // the rejected name is left out, since it may not be safe to write as CSV
+                    let time = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap();
+                    if let Err(e) = writeln!(
+                        cmd_log_file.lock()?,
+                        "{},reject,{client},auth",
+                        time.as_nanos()
+                    ) {
+                        user_log.warn(&format!(
+                            "Unable to log rejected authentication to log file: {e:?}"
+                        ))?;
+                    }
+                    false
+                }
+            },
+            _ => true,
+        };
+
+        // quick commands are executed inline; long-running procedures get their own thread
+        let success = if !accepted {
+            false
+        } else if matches!(
+            cmd,
+            Command::Actuate { .. }
+                | Command::ActuateMany { .. }
+                | Command::KeepAlive { .. }
+                | Command::History { .. }
+                | Command::CalibratePoint { .. }
+                | Command::SetThreshold { .. }
+                | Command::RemapDriver { .. }
+                | Command::OverrideInterlock { .. }
+                | Command::RestoreInterlock { .. }
+                | Command::InjectReading { .. }
+                | Command::Auth { .. }
+                | Command::Hold
+                | Command::Resume
+                | Command::Describe
+                | Command::Status
+                | Command::PauseAll
+                | Command::ResumeAll
+                | Command::StreamConsole { .. }
+                | Command::SetLogLevel { .. }
+                | Command::SetSensorFields { .. }
         ) {
-            if let Err(e) =
-                handle_command(&cmd, cmd_log_file, user_log, config, driver_lines, state)
-            {
-                user_log.critical(&format!("Encountered error while executing command: {e:?}"))?;
-                continue;
+            match handle_command(
+                &cmd,
+                &client,
+                cmd_log_file,
+                user_log,
+                config,
+                driver_lines,
+                state,
+                injections,
+                throttle,
+                deadman,
+                hold,
+                to_dash,
+                flusher,
+                readiness,
+                history,
+                tunings,
+                calibrator,
+                sequences,
+                snapshots,
+                rewiring,
+                interlocks,
+                pause,
+                restarts,
+                annotations,
+            ) {
+                Ok(()) => {
+                    if let Command::Hold | Command::Resume = cmd {
+                        to_dash.send(&Message::HoldStatus {
+                            held: cmd == Command::Hold,
+                        })?;
+                    }
+                    if let Command::StreamConsole { min_level } = cmd {
+                        // replacing an earlier subscription ends its forwarding thread
+                        let (sender, receiver) = mpsc::channel();
+                        user_log.subscribe(min_level, sender);
+                        thread_scope.spawn(move || forward_console(&receiver, to_dash));
+                    }
+                    true
+                }
+                Err(e) => {
+                    let message = format!("Encountered error while executing command {cmd}: {e}");
+                    user_log.critical(&message)?;
+                    to_dash.send(&Message::Error {
+                        level: LogLevel::Critical,
+                        message: &message,
+                    })?;
+                    false
+                }
             }
         } else {
             // spawn thread to handle command
+            let client = client.clone();
+            // a procedure is only acknowledged once it has finished, since it may yet be rejected,
+            // so its own thread acknowledges it
+            let deferred_ack = command_id.take();
+            if let Some(command_id) = deferred_ack {
+                recent_commands.lock()?.begin(command_id, Instant::now());
+            }
             #[allow(unused_must_use)]
             thread_scope.spawn(move || {
-                handle_command(&cmd, cmd_log_file, user_log, config, driver_lines, state);
+                let result = handle_command(
+                    &cmd,
+                    &client,
+                    cmd_log_file,
+                    user_log,
+                    config,
+                    driver_lines,
+                    state,
+                    injections,
+                    throttle,
+                    deadman,
+                    hold,
+                    to_dash,
+                    flusher,
+                    readiness,
+                    history,
+                    tunings,
+                    calibrator,
+                    sequences,
+                    snapshots,
+                    rewiring,
+                    interlocks,
+                    pause,
+                    restarts,
+                    annotations,
+                );
+                if let Err(e) = &result {
+                    let message = format!("Encountered error while executing command {cmd}: {e}");
+                    user_log.critical(&message);
+                    to_dash.send(&Message::Error {
+                        level: LogLevel::Critical,
+                        message: &message,
+                    });
+                }
+                if let Some(command_id) = deferred_ack {
+                    if let Ok(mut recent_commands) = recent_commands.lock() {
+                        recent_commands.record(command_id, result.is_ok(), Instant::now());
+                    }
+                    to_dash.send(&Message::Ack {
+                        command_id,
+                        success: result.is_ok(),
+                    });
+                }
                 user_log.debug("Finished executing command.");
             });
+            true
+        };
+
+        if let Some(command_id) = command_id {
+            recent_commands
+                .lock()?
+                .record(command_id, success, Instant::now());
+            to_dash.send(&Message::Ack {
+                command_id,
+                success,
+            })?;
         }
 
         user_log.debug("Finished executing command.")?;
     }
 }
+
+/// Forward console log entries to the dashboard as they arrive.
+/// Will loop until the subscription which sends the entries ends.
+///
+/// # Errors
+///
+/// This function will return an error if we are unable to write to the dashboard.
+fn forward_console(
+    entries: &Receiver<LogEntry>,
+    to_dash: &DashChannel<impl Write, impl Write>,
+) -> Result<(), ControllerError> {
+    for (level, message) in entries {
+        to_dash.send(&match level {
+            LogLevel::Debug | LogLevel::Info => Message::Display {
+                level,
+                message: &message,
+            },
+            LogLevel::Warn | LogLevel::Critical => Message::Error {
+                level,
+                message: &message,
+            },
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Cursor,
+        os::unix::fs::MetadataExt,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{config::Action, outgoing::Capture};
+
+    use super::*;
+
+    /// A GPIO chip spoof which records every line requested from it.
+    struct MockChip {
+        /// Lines which cannot be acquired, as if they were in use by another process.
+        unavailable: Vec<u8>,
+        /// Each line which was requested, along with whether it was requested as an output.
+        requested: Vec<(u8, bool)>,
+    }
+
+    impl MockChip {
+        fn new(unavailable: Vec<u8>) -> MockChip {
+            MockChip {
+                unavailable,
+                requested: Vec::new(),
+            }
+        }
+
+        fn request(&mut self, line: u8, output: bool) -> Result<(), ControllerError> {
+            if self.unavailable.contains(&line) || self.requested.iter().any(|&(l, _)| l == line) {
+                return Err(ControllerError::GpioLine(
+                    line,
+                    io::Error::from(io::ErrorKind::ResourceBusy).into(),
+                    None,
+                ));
+            }
+            self.requested.push((line, output));
+            Ok(())
+        }
+    }
+
+    impl GpioChip for MockChip {
+        type Pin = ListenerPin;
+
+        fn request_output(
+            &mut self,
+            line: u8,
+            value: bool,
+        ) -> Result<ListenerPin, ControllerError> {
+            self.request(line, true)?;
+            Ok(ListenerPin::new(value))
+        }
+
+        fn request_input(&mut self, line: u8) -> Result<ListenerPin, ControllerError> {
+            self.request(line, false)?;
+            Ok(ListenerPin::new(false))
+        }
+    }
+
+    /// Construct a configuration with two drivers, on pins 21 and 22, and two ADCs.
+    fn acquisition_config() -> Configuration {
+        let config = r#"{
+            "frequency_status": 1,
+            "log_buffer_size": 1,
+            "sensor_groups": [],
+            "pre_ignite_time": 0,
+            "post_ignite_time": 0,
+            "drivers": [
+                {
+                    "label": "OXI_FILL",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                },
+                {
+                    "label": "IGNITION",
+                    "label_actuate": "Ignite",
+                    "label_deactuate": "Stop",
+                    "pin": 22,
+                    "protected": true
+                }
+            ],
+            "ignition_sequence": [],
+            "estop_sequence": [],
+            "spi_mosi": 11,
+            "spi_miso": 12,
+            "spi_clk": 13,
+            "spi_frequency_clk": 50000,
+            "adc_cs": [5, 6],
+            "pin_heartbeat": 0
+        }"#;
+        Configuration::parse(&mut Cursor::new(config)).unwrap()
+    }
+
+    /// Construct a set of raw transaction logs for the ADCs in `config` in which no channel is
+    /// logged.
+    fn no_raw_logs(config: &Configuration) -> Vec<[Option<io::Sink>; 8]> {
+        config.adc_cs.iter().map(|_| Default::default()).collect()
+    }
+
+    #[test]
+    /// Test that every piece of hardware is assigned the correct line with the correct direction.
+    fn acquire_pin_assignment() {
+        let config = acquisition_config();
+        let mut chip = MockChip::new(Vec::new());
+
+        let bus = FairMutex::new(acquire_bus(&config, &mut chip).unwrap());
+        let user_log = UserLog::new(Vec::new());
+        let adcs = acquire_adcs(&config, &mut chip, &bus, &user_log, no_raw_logs(&config));
+        let drivers = acquire_drivers(&config, &mut chip).unwrap();
+
+        assert_eq!(adcs.len(), 2);
+        assert!(adcs.iter().all(Result::is_ok));
+        assert_eq!(drivers.len(), 2);
+        assert_eq!(
+            chip.requested,
+            [
+                (13, true),
+                (11, true),
+                (12, false),
+                (5, true),
+                (6, true),
+                (21, true),
+                (22, true)
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that acquisition fails if a driver line is unavailable, rather than silently skipping
+    /// the driver.
+    fn acquire_unavailable_line() {
+        let config = acquisition_config();
+        let mut chip = MockChip::new(vec![22]);
+
+        assert!(matches!(
+            acquire_drivers(&config, &mut chip),
+            Err(ControllerError::GpioLine(22, _, _))
+        ));
+    }
+
+    #[test]
+    /// Test that GPIO chips are listed in numerical order, and that the header chip is suggested
+    /// when the configured chip cannot be opened.
+    fn gpio_chip_suggestion() {
+        let dir = scratch_dir("gpio_chip_suggestion");
+        for name in [
+            "gpiochip10",
+            "gpiochip4",
+            "gpiochip0",
+            "gpiomem",
+            "spidev0.0",
+        ] {
+            File::create(dir.join(name)).unwrap();
+        }
+        assert_eq!(
+            list_gpio_chips(&dir),
+            ["gpiochip0", "gpiochip4", "gpiochip10"].map(|name| dir.join(name))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        let available = [
+            (
+                PathBuf::from("/dev/gpiochip0"),
+                "gpio-brcmstb@107d508500".into(),
+            ),
+            (PathBuf::from("/dev/gpiochip4"), "pinctrl-rp1".into()),
+        ];
+        let missing = Path::new("/dev/gpiochip9");
+        assert_eq!(
+            describe_gpio_chips(missing, &available),
+            "Available chips are /dev/gpiochip0 (gpio-brcmstb@107d508500), \
+            /dev/gpiochip4 (pinctrl-rp1); try setting gpio_chip to \"/dev/gpiochip4\""
+        );
+        assert_eq!(
+            describe_gpio_chips(missing, &available[..1]),
+            "Available chips are /dev/gpiochip0 (gpio-brcmstb@107d508500)"
+        );
+        assert_eq!(
+            describe_gpio_chips(missing, &[]),
+            "No other GPIO chips were found"
+        );
+
+        // the configured chip is never suggested in place of itself
+        assert_eq!(
+            describe_gpio_chips(Path::new("/dev/gpiochip4"), &available),
+            "Available chips are /dev/gpiochip0 (gpio-brcmstb@107d508500)"
+        );
+        assert_eq!(
+            describe_gpio_chips(Path::new("/dev/gpiochip0"), &available[..1]),
+            "No other GPIO chips were found"
+        );
+    }
+
+    #[test]
+    /// Test that each driver is requested at its startup level and left at its shutdown level.
+    fn driver_rest_levels() {
+        let mut config = acquisition_config();
+        config.drivers[0].startup_level = true;
+        config.drivers[1].shutdown_level = true;
+        let mut chip = MockChip::new(Vec::new());
+
+        let drivers = PriorityMutex::new(acquire_drivers(&config, &mut chip).unwrap());
+        assert_eq!(drivers.lock().unwrap()[0].history(), &[true]);
+        assert_eq!(drivers.lock().unwrap()[1].history(), &[false]);
+
+        drop(DriverShutdown {
+            config: &config,
+            driver_lines: &drivers,
+            user_log: &UserLog::new(Vec::new()),
+        });
+        assert_eq!(drivers.lock().unwrap()[0].history(), &[true, false]);
+        assert_eq!(drivers.lock().unwrap()[1].history(), &[false, true]);
+    }
+
+    #[test]
+    /// Test that only the first failure of a supervised thread is recorded, and that a thread which
+    /// succeeds records nothing.
+    fn supervised_failure() {
+        let fatal = Mutex::new(None);
+        let user_log = UserLog::new(Vec::new());
+
+        supervise("quiet", &fatal, &user_log, || Ok(()));
+        assert!(fatal.lock().unwrap().is_none());
+
+        supervise("first", &fatal, &user_log, || {
+            Err(ControllerError::Hardware("first failure"))
+        });
+        supervise("second", &fatal, &user_log, || {
+            Err(ControllerError::Hardware("second failure"))
+        });
+        assert!(matches!(
+            fatal.lock().unwrap().as_ref(),
+            Some(ControllerError::Hardware("first failure"))
+        ));
+    }
+
+    #[test]
+    /// Test that a controller asked to quit during ignition is emergency stopped before it quits.
+    fn wind_down_from_ignition() {
+        let mut config = acquisition_config();
+        config.estop_sequence = vec![Action::Actuate {
+            driver_id: 1,
+            value: false,
+        }];
+        let mut chip = MockChip::new(Vec::new());
+        let drivers = PriorityMutex::new(acquire_drivers(&config, &mut chip).unwrap());
+        let state = Guard::new(State::Standby);
+        state.move_to(State::PreIgnite).unwrap();
+        state.move_to(State::Ignite).unwrap();
+
+        wind_down(&config, &drivers, &state, &UserLog::new(Vec::new())).unwrap();
+
+        assert_eq!(state.status().unwrap(), State::Quit);
+        assert_eq!(state.estop_count(), 1);
+        assert_eq!(drivers.lock().unwrap()[1].history(), &[false, false]);
+    }
+
+    #[test]
+    /// Test that an ADC whose chip-select line is unavailable fails on its own, and that the
+    /// controller only continues without it in degraded mode.
+    fn acquire_unavailable_adc() {
+        let config = acquisition_config();
+        let mut chip = MockChip::new(vec![5]);
+        let bus = FairMutex::new(acquire_bus(&config, &mut chip).unwrap());
+        let user_log = UserLog::new(Vec::new());
+
+        let adcs = acquire_adcs(&config, &mut chip, &bus, &user_log, no_raw_logs(&config));
+        assert!(adcs[0].is_err());
+        assert!(adcs[1].is_ok());
+
+        assert!(matches!(
+            check_adcs(&config, adcs, false, &user_log),
+            Err(ControllerError::AdcsFailed(1))
+        ));
+
+        let adcs = check_adcs(
+            &config,
+            acquire_adcs(
+                &config,
+                &mut MockChip::new(vec![5]),
+                &bus,
+                &user_log,
+                no_raw_logs(&config),
+            ),
+            true,
+            &user_log,
+        )
+        .unwrap();
+        assert!(adcs[0].is_none());
+        assert!(adcs[1].is_some());
+    }
+
+    #[test]
+    /// Test that two listeners can share a port, since both set `SO_REUSEPORT`.
+    fn listeners_share_port() {
+        let user_log = UserLog::new(Vec::new());
+        let first = bind_listener(
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            1,
+            ReusePortFailure::Abort,
+            set_reuse_port,
+            &user_log,
+        )
+        .unwrap();
+        let address = first.local_addr().unwrap();
+
+        let second = bind_listener(
+            address,
+            1,
+            ReusePortFailure::Abort,
+            set_reuse_port,
+            &user_log,
+        )
+        .unwrap();
+        assert_eq!(second.local_addr().unwrap(), address);
+    }
+
+    #[test]
+    /// Test that binding is retried and eventually fails if the port is held without
+    /// `SO_REUSEPORT`.
+    fn bind_retries_then_fails() {
+        let holder = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = holder.local_addr().unwrap();
+
+        let start = std::time::Instant::now();
+        let result = bind_listener(
+            address,
+            3,
+            ReusePortFailure::Warn,
+            set_reuse_port,
+            &UserLog::new(Vec::new()),
+        );
+
+        assert!(result.is_err());
+        // we should have waited for 100 ms and then 200 ms between attempts
+        assert!(start.elapsed() >= BIND_BACKOFF * 3);
+    }
+
+    #[test]
+    /// Test that startup proceeds with a warning when `SO_REUSEPORT` is unavailable, unless it is
+    /// required.
+    fn reuse_port_unavailable() {
+        let unsupported = |_| Err(nix::errno::Errno::ENOPROTOOPT);
+        let address = SocketAddr::from(([127, 0, 0, 1], 0));
+        let user_log = UserLog::new(Vec::new());
+        let (sender, receiver) = mpsc::channel();
+        user_log.subscribe(LogLevel::Warn, sender);
+
+        let listener =
+            bind_listener(address, 1, ReusePortFailure::Warn, unsupported, &user_log).unwrap();
+        assert!(TcpStream::connect(listener.local_addr().unwrap()).is_ok());
+        let warnings: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].0, LogLevel::Warn);
+        assert!(warnings[0].1.contains("SO_REUSEPORT"));
+
+        assert!(
+            bind_listener(address, 1, ReusePortFailure::Abort, unsupported, &user_log).is_err()
+        );
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    /// Test that a dashboard which disconnects partway through a command can reconnect and keep
+    /// commanding the controller, that a new connection replaces a stale one, and that periodic
+    /// tasks are serviced while waiting for connections.
+    fn reconnect_after_disconnect() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+                "frequency_status": 1,
+                "log_buffer_size": 1,
+                "sensor_groups": [],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [{
+                    "label": "OXI_FILL",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                }],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [],
+                "pin_heartbeat": 0
+            }"#,
+        ))
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let to_dash = DashChannel::<TcpStream, Vec<u8>>::new(Vec::new());
+        let driver_lines = PriorityMutex::new(vec![ListenerPin::new(false)]);
+        let cmd_log_file = Mutex::new(Vec::new());
+        let user_log = UserLog::new(Vec::new());
+        let state = Guard::new(State::Standby);
+        let injections = Injections::new(&config, false);
+        let throttle = Throttle::new(&config);
+        let deadman = Deadman::new(&config);
+        let hold = Hold::new();
+        let flusher = Flusher::new(&config, Vec::new());
+        let readiness = Readiness::new(&config);
+        let history = History::new(&config);
+        let tunings = Tunings::new(&config);
+        let calibrator = Calibrator::new(&config);
+        let sequences = Sequences::new(&config);
+        let snapshots = Snapshots::new(&config, Vec::new());
+        let rewiring = Rewiring::new(&config, |_, level| Ok(ListenerPin::new(level)));
+        let interlocks = Interlocks::new(&config);
+        let pause = Pause::new();
+        let restarts = Restarts::new(&config);
+        let annotations = Annotations::new(Vec::new());
+        let connections = Connections::new();
+        let recent_commands = Mutex::new(RecentCommands::new(DEDUP_WINDOW, DEDUP_CAPACITY));
+        let ticks = AtomicUsize::new(0);
+
+        // connect to the controller and wait for it to send its configuration
+        let connect = || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0; 256];
+            while !String::from_utf8_lossy(&received).contains(r#""Config""#) {
+                let n = stream.read(&mut buf).unwrap();
+                assert_ne!(n, 0);
+                received.extend_from_slice(&buf[..n]);
+            }
+            stream
+        };
+        let wait_for_history = |expected: &[bool]| {
+            let start = Instant::now();
+            while driver_lines.lock().unwrap()[0].history() != expected {
+                assert!(start.elapsed() < Duration::from_secs(5));
+                sleep(Duration::from_millis(1));
+            }
+        };
+        let actuate =
+            |value: bool| format!(r#"{{"type": "Actuate", "driver_id": 0, "value": {value}}}"#);
+
+        std::thread::scope(|s| {
+            let listening = s.spawn(|| {
+                serve_clients(
+                    s,
+                    &listener,
+                    &connections,
+                    &recent_commands,
+                    &to_dash,
+                    &config,
+                    &driver_lines,
+                    &cmd_log_file,
+                    None::<&Mutex<Vec<u8>>>,
+                    &user_log,
+                    &state,
+                    &injections,
+                    &throttle,
+                    &deadman,
+                    &hold,
+                    &flusher,
+                    &readiness,
+                    &history,
+                    &tunings,
+                    &calibrator,
+                    &sequences,
+                    &snapshots,
+                    &rewiring,
+                    &interlocks,
+                    &pause,
+                    &restarts,
+                    &annotations,
+                    || {
+                        ticks.fetch_add(1, Ordering::Relaxed);
+                        Ok(())
+                    },
+                )
+            });
+
+            let mut first = connect();
+            first.write_all(actuate(true).as_bytes()).unwrap();
+            wait_for_history(&[false, true]);
+
+            // drop the connection partway through a command
+            first.write_all(&actuate(false).as_bytes()[..10]).unwrap();
+            drop(first);
+
+            let mut second = connect();
+            second.write_all(actuate(false).as_bytes()).unwrap();
+            wait_for_history(&[false, true, false]);
+
+            // a new connection shuts down the one it replaces
+            let mut third = connect();
+            assert!(second.read_to_end(&mut Vec::new()).is_ok());
+            third.write_all(actuate(true).as_bytes()).unwrap();
+            wait_for_history(&[false, true, false, true]);
+
+            // the listener notices the controller quitting without another connection
+            state.move_to(State::Quit).unwrap();
+            listening.join().unwrap().unwrap();
+            drop(third);
+        });
+        // the periodic tasks were serviced while waiting for connections
+        assert!(ticks.load(Ordering::Relaxed) > 3);
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    /// Test the exact sequence of messages sent to a dashboard in response to a scripted set of
+    /// commands, including a resent command, one which fails, and a procedure which is rejected.
+    fn scripted_client() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+                "frequency_status": 1,
+                "log_buffer_size": 1,
+                "sensor_groups": [],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [{
+                    "label": "OXI_FILL",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                }],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [],
+                "pin_heartbeat": 0
+            }"#,
+        ))
+        .unwrap();
+        let script = [
+            r#"{"type": "Auth", "operator": "jo", "command_id": 1}"#,
+            r#"{"type": "Actuate", "driver_id": 0, "value": true, "command_id": 2}"#,
+            r#"{"type": "Actuate", "driver_id": 0, "value": true, "command_id": 2}"#,
+            r#"{"type": "Resume", "command_id": 3}"#,
+            r#"{"type": "Actuate", "driver_id": 7, "value": true, "command_id": 4}"#,
+            r#"{"type": "Auth", "operator": "bad,name", "command_id": 5}"#,
+            r#"{"type": "TestFire", "driver_id": 0, "max_ms": 3600000, "command_id": 6}"#,
+            r#"{"type": "Actuate", "driver_id": "GARBAGE"}"#,
+        ];
+        let capture = Capture::default();
+        let to_dash = DashChannel::<Capture, Vec<u8>>::new(Vec::new());
+        to_dash.set_channel(Some(capture.clone())).unwrap();
+        let driver_lines = PriorityMutex::new(vec![ListenerPin::new(false)]);
+        let cmd_log_file = Mutex::new(Vec::new());
+        let received_raw = Mutex::new(Vec::new());
+        let user_log = UserLog::new(Vec::new());
+        let state = Guard::new(State::Standby);
+        let injections = Injections::new(&config, false);
+        let throttle = Throttle::new(&config);
+        let deadman = Deadman::new(&config);
+        let hold = Hold::new();
+        let flusher = Flusher::new(&config, Vec::new());
+        let readiness = Readiness::new(&config);
+        let history = History::new(&config);
+        let tunings = Tunings::new(&config);
+        let calibrator = Calibrator::new(&config);
+        let sequences = Sequences::new(&config);
+        let snapshots = Snapshots::new(&config, Vec::new());
+        let rewiring = Rewiring::new(&config, |_, level| Ok(ListenerPin::new(level)));
+        let interlocks = Interlocks::new(&config);
+        let pause = Pause::new();
+        let restarts = Restarts::new(&config);
+        let annotations = Annotations::new(Vec::new());
+        let recent_commands = Mutex::new(RecentCommands::new(DEDUP_WINDOW, DEDUP_CAPACITY));
+
+        std::thread::scope(|s| {
+            handle_client(
+                s,
+                &to_dash,
+                &mut Cursor::new(script.concat()),
+                "127.0.0.1:5000".parse().unwrap(),
+                &config,
+                &driver_lines,
+                &cmd_log_file,
+                Some(&received_raw),
+                &user_log,
+                &state,
+                &injections,
+                &throttle,
+                &deadman,
+                &hold,
+                &flusher,
+                &readiness,
+                &history,
+                &tunings,
+                &calibrator,
+                &sequences,
+                &snapshots,
+                &rewiring,
+                &interlocks,
+                &pause,
+                &restarts,
+                &annotations,
+                &recent_commands,
+            )
+            .unwrap();
+        });
+
+        assert_eq!(
+            capture.types(),
+            [
+                "Config",
+                "Ready",
+                "Ack",
+                "Ack",
+                "Ack",
+                "HoldStatus",
+                "Ack",
+                "Error",
+                "Ack",
+                "Ack",
+                "Error",
+                "Ack"
+            ]
+        );
+        let acks: Vec<_> = capture
+            .messages()
+            .into_iter()
+            .filter(|message| message["type"] == "Ack")
+            .map(|message| (message["command_id"].clone(), message["success"].clone()))
+            .collect();
+        assert_eq!(
+            acks,
+            [
+                (1, true),
+                (2, true),
+                (2, true),
+                (3, true),
+                (4, false),
+                (5, false),
+                (6, false)
+            ]
+            .map(|(id, success)| (id.into(), success.into()))
+        );
+        // the rejection of the test fire reached the dashboard
+        let errors: Vec<_> = capture
+            .messages()
+            .into_iter()
+            .filter(|message| message["type"] == "Error")
+            .map(|message| message["message"].as_str().unwrap().to_owned())
+            .collect();
+        assert!(errors[1].ends_with("test fire of 3600000 ms exceeds the ceiling of 5000 ms"));
+        // the resent actuation was not executed again
+        assert_eq!(driver_lines.lock().unwrap()[0].history(), &[false, true]);
+        // the rejected authentication is in the command log, attributed to the previous operator
+        let cmd_log = String::from_utf8(cmd_log_file.into_inner().unwrap()).unwrap();
+        assert!(cmd_log
+            .lines()
+            .any(|line| line.ends_with(",reject,jo,127.0.0.1:5000,auth")));
+
+        // every command was captured verbatim, including the malformed one
+        let received_raw = String::from_utf8(received_raw.into_inner().unwrap()).unwrap();
+        let mut lines = received_raw.lines();
+        for command in script {
+            let header = lines.next().unwrap();
+            let (time, len) = header.split_once(',').unwrap();
+            assert!(time.parse::<u128>().is_ok());
+            assert_eq!(len.parse::<usize>().unwrap(), command.len());
+            assert_eq!(lines.next(), Some(command));
+        }
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    /// Test that a lost dashboard is only declared disconnected once the grace period passes
+    /// without a reconnect, and that the disconnect action is then performed and every interlock
+    /// override cleared.
+    fn disconnect_grace() {
+        let config = Configuration::parse(&mut Cursor::new(
+            r#"{
+                "frequency_status": 1,
+                "log_buffer_size": 1,
+                "sensor_groups": [],
+                "pre_ignite_time": 0,
+                "post_ignite_time": 0,
+                "drivers": [{
+                    "label": "OXI_VENT",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 21,
+                    "protected": false
+                }, {
+                    "label": "FUEL_VENT",
+                    "label_actuate": "Open",
+                    "label_deactuate": "Close",
+                    "pin": 22,
+                    "protected": false
+                }],
+                "ignition_sequence": [],
+                "estop_sequence": [],
+                "spi_mosi": 11,
+                "spi_miso": 12,
+                "spi_clk": 13,
+                "spi_frequency_clk": 50000,
+                "adc_cs": [],
+                "pin_heartbeat": 0,
+                "disconnect_grace_ms": 1000,
+                "disconnect_action": "vent",
+                "abort_actions": {
+                    "vent": [{ "driver_id": 0, "value": true }]
+                },
+                "interlocks": [{ "label": "vents", "drivers": [0, 1] }]
+            }"#,
+        ))
+        .unwrap();
+        let grace = Duration::from_secs(1);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let peer = stream.peer_addr().unwrap();
+        let to_dash = DashChannel::<TcpStream, Vec<u8>>::new(Vec::new());
+        let connections = Connections::new();
+        let start = Instant::now();
+
+        // a dashboard which reconnects in time is never declared disconnected
+        connections.replace(0, peer, &stream, &to_dash).unwrap();
+        assert!(connections.release(0, &to_dash, start).unwrap());
+        assert!(!connections
+            .expire(grace, start + Duration::from_millis(500))
+            .unwrap());
+        assert_eq!(
+            connections
+                .resume(start + Duration::from_millis(600))
+                .unwrap(),
+            Some(Duration::from_millis(600))
+        );
+        assert!(!connections.expire(grace, start + grace * 2).unwrap());
+
+        // a replaced connection is not a loss
+        connections.replace(1, peer, &stream, &to_dash).unwrap();
+        assert!(!connections.release(0, &to_dash, start).unwrap());
+        assert!(!connections.expire(grace, start + grace * 2).unwrap());
+
+        // a dashboard which stays away is declared disconnected exactly once
+        assert!(connections.release(1, &to_dash, start).unwrap());
+        assert!(connections.expire(grace, start + grace).unwrap());
+        assert!(!connections.expire(grace, start + grace * 2).unwrap());
+        assert_eq!(connections.resume(start + grace * 2).unwrap(), None);
+
+        let driver_lines =
+            PriorityMutex::new(vec![ListenerPin::new(false), ListenerPin::new(false)]);
+        let state = Guard::new(State::Standby);
+        let interlocks = Interlocks::new(&config);
+        interlocks.set_overridden(0, true).unwrap();
+        declare_disconnect(
+            &config,
+            &driver_lines,
+            &state,
+            &interlocks,
+            &UserLog::new(Vec::new()),
+        )
+        .unwrap();
+        assert_eq!(driver_lines.lock().unwrap()[0].history(), &[false, true]);
+        assert_eq!(state.status().unwrap(), State::Standby);
+        assert!(interlocks.overridden().unwrap().is_empty());
+    }
+
+    #[test]
+    /// Test that an unsupported command is only answered if the configuration asks for it.
+    fn unsupported_reply() {
+        let mut config = acquisition_config();
+        let user_log = UserLog::new(Vec::new());
+        let capture = Capture::default();
+        let to_dash = DashChannel::<Capture, Vec<u8>>::new(Vec::new());
+        to_dash.set_channel(Some(capture.clone())).unwrap();
+
+        reject_unsupported(&config, &to_dash, &user_log, "Levitate", Some(3)).unwrap();
+        assert!(capture.types().is_empty());
+
+        config.ack_unsupported_commands = true;
+        reject_unsupported(&config, &to_dash, &user_log, "Levitate", Some(3)).unwrap();
+        reject_unsupported(&config, &to_dash, &user_log, "Levitate", None).unwrap();
+        let messages = capture.messages();
+        assert_eq!(capture.types(), ["Unsupported", "Ack", "Unsupported"]);
+        assert_eq!(messages[0]["command_type"], "Levitate");
+        assert_eq!(messages[1]["command_id"], 3);
+        assert_eq!(messages[1]["success"], false);
+    }
+
+    #[test]
+    /// Test that preallocating a log file reserves space without changing its length.
+    fn preallocate_keeps_size() {
+        let path = std::env::temp_dir().join(format!("slonk_prealloc_{}", std::process::id()));
+        let file = file_create_new(&path).unwrap();
+
+        preallocate(&file, 1 << 16).unwrap();
+        let metadata = file.metadata().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(metadata.len(), 0);
+        // `blocks` is counted in units of 512 bytes
+        assert!(metadata.blocks() * 512 >= 1 << 16);
+    }
+
+    /// Make an empty scratch directory unique to this process and `name`.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("slonk_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Make the paths of a log tree whose sensor logs are at `sensors`, with every other log at
+    /// the top of the logs directory under the name prefixed by `prefix`.
+    fn log_paths(prefix: &str, sensors: &[&str]) -> LogPaths {
+        LogTree {
+            sensors: vec![sensors.iter().map(PathBuf::from).collect()],
+            commands: PathBuf::from(format!("{prefix}commands.csv")),
+            pretrigger: None,
+            drivers: PathBuf::from(format!("{prefix}drivers.csv")),
+            snapshots: PathBuf::from(format!("{prefix}snapshots.csv")),
+            events: PathBuf::from(format!("{prefix}events.csv")),
+            sent: PathBuf::from(format!("{prefix}sent.csv")),
+            raw: None,
+            received: None,
+            raw_sensors: Vec::new(),
+        }
+    }
+
+    #[test]
+    /// Test that the whole log tree is created, with each file in the place of its path, and that
+    /// an existing file stops anything from being created.
+    fn log_tree_exists() {
+        let dir = scratch_dir("log_tree_exists");
+        let paths = log_paths("", &["a/b/x.csv", "a/y.csv", "z.csv"]);
+        let mut files = create_log_tree(&dir, &paths).unwrap();
+        assert_eq!(files.sensors[0].len(), 3);
+        assert!(paths.all().iter().all(|p| dir.join(p).is_file()));
+        files.commands.write_all(b"commands").unwrap();
+        files.sensors[0][1].write_all(b"y").unwrap();
+        assert_eq!(fs::read(dir.join("commands.csv")).unwrap(), b"commands");
+        assert_eq!(fs::read(dir.join("a/y.csv")).unwrap(), b"y");
+
+        let again = log_paths("again_", &["c/w.csv", "z.csv"]);
+        match create_log_tree(&dir, &again) {
+            Err(ControllerError::LogFile(path, e)) => {
+                assert_eq!(path, dir.join("z.csv"));
+                assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+            }
+            other => panic!(
+                "expected an existing file to be reported, got {:?}",
+                other.err()
+            ),
+        }
+        assert!(!dir.join("c").exists());
+        assert!(!dir.join("again_commands.csv").exists());
+
+        let duplicated = log_paths("duplicated_", &["d/v.csv", "d/v.csv"]);
+        match create_log_tree(&dir, &duplicated) {
+            Err(ControllerError::LogFile(path, _)) => assert_eq!(path, dir.join("d/v.csv")),
+            other => panic!("expected a log file error, got {:?}", other.err()),
+        }
+        assert!(!dir.join("d").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    /// Test that a failure partway through creating the log tree removes everything created so far.
+    fn log_tree_rollback() {
+        let dir = scratch_dir("log_tree_rollback");
+        // a file standing where a directory is needed
+        File::create(dir.join("blocked")).unwrap();
+        let mut paths = log_paths("", &["a/b/x.csv", "a/y.csv"]);
+        paths.drivers = PathBuf::from("blocked/drivers.csv");
+        match create_log_tree(&dir, &paths) {
+            Err(ControllerError::LogFile(path, _)) => assert_eq!(path, dir.join("blocked")),
+            other => panic!("expected a log file error, got {:?}", other.err()),
+        }
+        assert!(!dir.join("a").exists());
+        assert!(!dir.join("commands.csv").exists());
+        assert!(dir.join("blocked").is_file());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Run `f` with the file permissions of an unprivileged user, even if the tests are run as
+    /// root, who would otherwise ignore them.
+    fn unprivileged<T>(f: impl FnOnce() -> T) -> T {
+        /// The user ID of `nobody`.
+        const NOBODY: libc::c_long = 65534;
+        /// The argument to `setresuid` which leaves an ID unchanged.
+        const UNCHANGED: libc::c_long = -1;
+
+        // the raw system call changes the IDs of this thread alone, whereas `seteuid` would change
+        // them for every test running alongside this one
+        let root = unsafe { libc::geteuid() } == 0;
+        if root {
+            let result =
+                unsafe { libc::syscall(libc::SYS_setresuid, UNCHANGED, NOBODY, UNCHANGED) };
+            assert_eq!(result, 0, "unable to drop root privileges");
+        }
+        let result = f();
+        if root {
+            let result = unsafe { libc::syscall(libc::SYS_setresuid, UNCHANGED, 0, UNCHANGED) };
+            assert_eq!(result, 0, "unable to restore root privileges");
+        }
+        result
+    }
+
+    #[test]
+    /// Test that a directory which cannot be written to is reported and leaves nothing behind.
+    fn log_tree_permission_denied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("log_tree_permission_denied");
+        // the unprivileged user must still be able to write everywhere but the locked directory
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        create_dir(dir.join("locked")).unwrap();
+        fs::set_permissions(dir.join("locked"), fs::Permissions::from_mode(0o555)).unwrap();
+        let mut paths = log_paths("", &["a/x.csv"]);
+        paths.drivers = PathBuf::from("locked/b/drivers.csv");
+        match unprivileged(|| create_log_tree(&dir, &paths)) {
+            Err(ControllerError::LogFile(path, e)) => {
+                assert_eq!(path, dir.join("locked/b"));
+                assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+            }
+            other => panic!("expected a log file error, got {:?}", other.err()),
+        }
+        assert!(!dir.join("a").exists());
+        assert!(!dir.join("commands.csv").exists());
+
+        fs::set_permissions(dir.join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Simulated sensors, which stand in for real ADCs when the controller is run without hardware.
+//!
+//! Each sensor may define a synthetic waveform in its configuration, giving its raw reading as a
+//! function of the time since the controller started.
+//! Simulated readings are handed to the sensor listeners just like real ones, so they are
+//! calibrated, logged, range-checked, and sent to the dashboard in exactly the same way.
+
+use std::{
+    f64::consts::TAU,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{Configuration, Sensor, Waveform},
+    hardware::{Adc, ADC_MAX_READING},
+    ControllerError,
+};
+
+/// A recorded sequence of raw readings, which is replayed in a loop.
+struct Recording {
+    /// Each reading, paired with the time since the first reading.
+    /// Sorted by time, and never empty.
+    samples: Vec<(Duration, u16)>,
+    /// The time taken to play the recording once.
+    period: Duration,
+}
+
+/// The source of the readings on one channel of a simulated ADC.
+enum Profile {
+    /// A waveform other than a replay.
+    Waveform(Waveform),
+    /// A replay of a recorded sensor log.
+    Replay(Recording),
+}
+
+/// An ADC whose channels read synthetic waveforms.
+pub struct SimulatedAdc {
+    /// The time at which the simulation started.
+    start: Instant,
+    /// The profile of each channel, or `None` if the channel has no waveform.
+    channels: Vec<Option<Profile>>,
+    /// The reading of each channel without a waveform.
+    default: u16,
+}
+
+impl SimulatedAdc {
+    /// Construct a simulated ADC with the waveforms of every sensor on the ADC with ID `adc_id`.
+    /// Channels without a waveform always read `adc_id`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a recorded sensor log to be replayed cannot be read or
+    /// contains no readings.
+    pub fn new(configuration: &Configuration, adc_id: u8) -> Result<SimulatedAdc, ControllerError> {
+        let mut channels: Vec<Option<Profile>> = (0..8).map(|_| None).collect();
+        for sensor in configuration
+            .sensor_groups
+            .iter()
+            .flat_map(|group| &group.sensors)
+            .filter(|sensor| sensor.iio_channel.is_none() && sensor.adc == adc_id)
+        {
+            channels[usize::from(sensor.channel)] = Profile::of(sensor)?;
+        }
+
+        Ok(SimulatedAdc {
+            start: Instant::now(),
+            channels,
+            default: u16::from(adc_id),
+        })
+    }
+
+    /// Construct a simulated ADC whose only channel, channel 0, reads the waveform of `sensor`.
+    /// This stands in for a sensor which is read through IIO.
+    /// If the sensor has no waveform, the channel always reads 0.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a recorded sensor log to be replayed cannot be read or
+    /// contains no readings.
+    pub fn for_sensor(sensor: &Sensor) -> Result<SimulatedAdc, ControllerError> {
+        Ok(SimulatedAdc {
+            start: Instant::now(),
+            channels: vec![Profile::of(sensor)?],
+            default: 0,
+        })
+    }
+}
+
+impl Profile {
+    /// Construct the profile of the waveform of `sensor`, or `None` if it has no waveform.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a recorded sensor log to be replayed cannot be read or
+    /// contains no readings.
+    fn of(sensor: &Sensor) -> Result<Option<Profile>, ControllerError> {
+        Ok(match &sensor.simulation {
+            Some(Waveform::Replay { path }) => Some(Profile::Replay(Recording::load(path)?)),
+            Some(waveform) => Some(Profile::Waveform(waveform.clone())),
+            None => None,
+        })
+    }
+}
+
+impl Adc for SimulatedAdc {
+    fn read(&mut self, channel: u8) -> Result<u16, ControllerError> {
+        let elapsed = self.start.elapsed();
+        Ok(
+            match self
+                .channels
+                .get(usize::from(channel))
+                .and_then(Option::as_ref)
+            {
+                Some(Profile::Waveform(waveform)) => waveform_reading(waveform, elapsed),
+                Some(Profile::Replay(recording)) => recording.reading(elapsed),
+                None => self.default,
+            },
+        )
+    }
+}
+
+impl Recording {
+    /// Load the raw readings from a CSV sensor log at `path`.
+    /// Injected readings are skipped, since they were never actually measured, as are marker rows,
+    /// which have no reading.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the log cannot be read, is malformed, or contains no
+    /// readings.
+    fn load(path: &Path) -> Result<Recording, ControllerError> {
+        let mut samples = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let mut fields = line.split(',');
+            let (Some(time), Some(reading)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            if reading.is_empty() || fields.nth(1) == Some("injected") {
+                continue;
+            }
+            let (Ok(time), Ok(reading)) = (time.parse::<u64>(), reading.parse::<u16>()) else {
+                return Err(ControllerError::Hardware(
+                    "simulated sensor log to replay is malformed",
+                ));
+            };
+            samples.push((Duration::from_nanos(time), reading));
+        }
+
+        let Some(&(first, _)) = samples.first() else {
+            return Err(ControllerError::Hardware(
+                "simulated sensor log to replay has no readings",
+            ));
+        };
+        for sample in &mut samples {
+            sample.0 = sample.0.saturating_sub(first);
+        }
+        samples.sort_by_key(|&(time, _)| time);
+
+        // leave the average gap between readings between the end of one loop and the next
+        let last = samples[samples.len() - 1].0;
+        let period = match u32::try_from(samples.len() - 1) {
+            Ok(0) => Duration::from_secs(1),
+            Ok(n_gaps) => last + last / n_gaps,
+            Err(_) => last,
+        };
+
+        Ok(Recording { samples, period })
+    }
+
+    /// Get the reading played back `elapsed` after the replay started.
+    fn reading(&self, elapsed: Duration) -> u16 {
+        #[allow(clippy::cast_possible_truncation)]
+        let offset =
+            Duration::from_nanos((elapsed.as_nanos() % self.period.as_nanos().max(1)) as u64);
+        let idx = self
+            .samples
+            .partition_point(|&(time, _)| time <= offset)
+            .max(1);
+        self.samples[idx - 1].1
+    }
+}
+
+/// Compute the raw reading of `waveform` at `elapsed` after the simulation started.
+/// The reading is rounded and clamped to the range of the ADC.
+///
+/// # Panics
+///
+/// This function will panic if `waveform` is a replay, which must be loaded into a `Recording`
+/// instead.
+fn waveform_reading(waveform: &Waveform, elapsed: Duration) -> u16 {
+    let t = elapsed.as_secs_f64();
+    let reading = match *waveform {
+        Waveform::Constant { reading } => reading,
+        Waveform::Sine {
+            offset,
+            amplitude,
+            period,
+        } => offset + amplitude * (TAU * t / period).sin(),
+        Waveform::Ramp {
+            start,
+            end,
+            duration,
+        } => {
+            if t >= duration {
+                end
+            } else {
+                start + (end - start) * t / duration
+            }
+        }
+        Waveform::Step {
+            before,
+            after,
+            time,
+        } => {
+            if t < time {
+                before
+            } else {
+                after
+            }
+        }
+        Waveform::Replay { .. } => unreachable!("replays are loaded into recordings"),
+    };
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let clamped = reading.round().clamp(0.0, f64::from(ADC_MAX_READING)) as u16;
+    clamped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::remove_file, io::Write};
+
+    use super::*;
+
+    #[test]
+    /// Test that each waveform gives the expected reading at a few points in time.
+    fn waveform_shapes() {
+        let sine = Waveform::Sine {
+            offset: 2000.0,
+            amplitude: 1000.0,
+            period: 4.0,
+        };
+        assert_eq!(waveform_reading(&sine, Duration::ZERO), 2000);
+        assert_eq!(waveform_reading(&sine, Duration::from_secs(1)), 3000);
+        assert_eq!(waveform_reading(&sine, Duration::from_secs(3)), 1000);
+
+        let ramp = Waveform::Ramp {
+            start: 0.0,
+            end: 100.0,
+            duration: 10.0,
+        };
+        assert_eq!(waveform_reading(&ramp, Duration::from_secs(5)), 50);
+        assert_eq!(waveform_reading(&ramp, Duration::from_secs(20)), 100);
+
+        let step = Waveform::Step {
+            before: 10.0,
+            after: 20.0,
+            time: 1.0,
+        };
+        assert_eq!(waveform_reading(&step, Duration::from_millis(999)), 10);
+        assert_eq!(waveform_reading(&step, Duration::from_secs(1)), 20);
+
+        // readings are clamped to what the ADC can produce
+        let constant = Waveform::Constant { reading: 1e6 };
+        assert_eq!(waveform_reading(&constant, Duration::ZERO), ADC_MAX_READING);
+    }
+
+    #[test]
+    /// Test that a recorded sensor log is replayed in a loop, skipping injected readings and marker
+    /// rows.
+    fn replay_loops() {
+        let path = std::env::temp_dir().join(format!("slonk_replay_{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        write!(
+            file,
+            "1000000000,10,1.0\n1000000050,,,paused\n1000000100,99,9.9,injected\n1000000100,20,2.0\n1000000200,30,3.0\n"
+        )
+        .unwrap();
+        drop(file);
+
+        let recording = Recording::load(&path);
+        remove_file(&path).unwrap();
+        let recording = recording.unwrap();
+
+        assert_eq!(recording.period, Duration::from_nanos(300));
+        assert_eq!(recording.reading(Duration::from_nanos(0)), 10);
+        assert_eq!(recording.reading(Duration::from_nanos(150)), 20);
+        assert_eq!(recording.reading(Duration::from_nanos(250)), 30);
+        assert_eq!(recording.reading(Duration::from_nanos(310)), 10);
+    }
+}
@@ -1,4 +1,14 @@
-use std::sync::RwLock;
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Condvar, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::hardware::GpioPin;
 
 #[derive(Debug)]
 /// The set of errors that can be caused from working with a `Guard`.
@@ -12,6 +22,9 @@ pub enum Error {
         /// The state that the transistion was attempted into.
         to: State,
     },
+    /// The armed-state indicator could not be driven to match a new state, so the state was not
+    /// changed.
+    Indicator(gpio_cdev::Error),
 }
 
 /// A guard for controller state which can be used to notify other threads of changes to controller
@@ -19,9 +32,23 @@ pub enum Error {
 pub struct Guard {
     /// The current state.
     state: RwLock<State>,
+    /// The number of times that the state has moved to `State::EStopping`.
+    n_estops: AtomicU32,
+    /// The time at which the last emergency stop ended (by returning to `State::Standby`, after any
+    /// purge), if one has ended.
+    estop_ended: Mutex<Option<Instant>>,
+    /// The line driving the armed-state indicator (a warning light or siren), if there is one.
+    /// It is active exactly when the current state is armed.
+    indicator: Option<Mutex<Box<dyn GpioPin + Send>>>,
+    /// The lock paired with `changed`.
+    change_lock: Mutex<()>,
+    /// A condition variable notified after every change of state.
+    changed: Condvar,
+    /// The time at which this guard was created, which is when the controller started.
+    created: Instant,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
 /// The set of all states the engine controller can be in.
 pub enum State {
     /// The engine is in standby - passively logging and awating commands.
@@ -45,12 +72,35 @@ pub enum State {
     /// Data logging should be fast, since anything that is worth e-stopping over is probably very
     /// interesting.
     EStopping,
+    /// The purge sequence is running after an emergency stop or abort action.
+    /// This state can only be reached from the `EStopping` state, and a new emergency stop may
+    /// interrupt it at any time.
+    Purging,
     /// The engine controller is shutting down.
     /// This state can only be reached from the `Standby` state.
     /// During this state, each thread will "wrap up" its work and then exit as soon as possible.
     Quit,
 }
 
+impl State {
+    #[must_use]
+    /// Determine whether the controller is armed in this state, so that the armed-state indicator
+    /// must be active.
+    ///
+    /// An emergency stop (and the purge after it) counts as armed, since the hazard it responds to
+    /// may not yet be over.
+    pub fn armed(self) -> bool {
+        matches!(
+            self,
+            State::PreIgnite
+                | State::Ignite
+                | State::PostIgnite
+                | State::EStopping
+                | State::Purging
+        )
+    }
+}
+
 impl Guard {
     #[must_use]
     /// Construct a new `Guard`.
@@ -58,9 +108,60 @@ impl Guard {
     pub fn new(state: State) -> Guard {
         Guard {
             state: RwLock::new(state),
+            n_estops: AtomicU32::new(0),
+            estop_ended: Mutex::new(None),
+            indicator: None,
+            change_lock: Mutex::new(()),
+            changed: Condvar::new(),
+            created: Instant::now(),
         }
     }
 
+    /// Construct a new `Guard` whose state is shown on the armed-state indicator `indicator`.
+    /// Initializes its state to the value of `state`, and drives `indicator` to match.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `indicator` cannot be written to.
+    pub fn with_indicator(
+        state: State,
+        mut indicator: Box<dyn GpioPin + Send>,
+    ) -> Result<Guard, Error> {
+        indicator.write(state.armed()).map_err(Error::Indicator)?;
+        Ok(Guard {
+            indicator: Some(Mutex::new(indicator)),
+            ..Guard::new(state)
+        })
+    }
+
+    #[must_use]
+    /// Get the number of emergency stops which have begun since this guard was created.
+    ///
+    /// An emergency stop may be over very quickly, so threads which need to react to every
+    /// emergency stop should compare this count rather than polling for `State::EStopping`.
+    pub fn estop_count(&self) -> u32 {
+        self.n_estops.load(Ordering::Acquire)
+    }
+
+    #[must_use]
+    /// Get the time since this guard was created.
+    pub fn uptime(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    /// Get the time remaining until `cooldown` has passed since the last emergency stop ended.
+    /// Returns zero if no emergency stop has ended, or if the cooldown is already over.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error in the case that an internal lock of this guard is poisoned.
+    pub fn cooldown_remaining(&self, cooldown: Duration) -> Result<Duration, Error> {
+        let estop_ended = *self.estop_ended.lock().map_err(|_| Error::Poison)?;
+        Ok(estop_ended.map_or(Duration::ZERO, |ended| {
+            cooldown.saturating_sub(ended.elapsed())
+        }))
+    }
+
     /// Get the status of this guard.
     /// This operation is blocking.
     ///
@@ -74,6 +175,27 @@ impl Guard {
         }
     }
 
+    /// Wait until the state is no longer `state`, or until `timeout` has passed, whichever is
+    /// first.
+    /// Returns the state at the end of the wait.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error in the case that an internal lock of this guard is poisoned.
+    pub fn wait_while(&self, state: State, timeout: Duration) -> Result<State, Error> {
+        let change_lock = self.change_lock.lock().map_err(|_| Error::Poison)?;
+        let mut status = Ok(state);
+        drop(
+            self.changed
+                .wait_timeout_while(change_lock, timeout, |_| {
+                    status = self.status();
+                    matches!(status, Ok(current) if current == state)
+                })
+                .map_err(|_| Error::Poison)?,
+        );
+        status
+    }
+
     /// Move this guard into a new state.
     ///
     /// # Errors
@@ -82,18 +204,48 @@ impl Guard {
     /// lock is poisoned.
     /// If `new_state` is not reachable from the current state, an
     /// `Err(ControllerError::IllegalTransition)` will be returned.
+    /// If the armed-state indicator cannot be driven to match `new_state`, an
+    /// `Err(ControllerError::Indicator)` will be returned and the state is unchanged, except that an
+    /// emergency stop is never prevented by a faulty indicator.
     pub fn move_to(&self, new_state: State) -> Result<(), Error> {
+        self.transition(None, new_state)
+    }
+
+    /// Attempt to move from state `expected` to `new_state`.
+    /// Unlike `move_to`, this fails if the state has changed from `expected` in the meantime.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the current state is not `expected`, if the
+    /// transition is illegal, or if the internal lock is poisoned.
+    pub fn move_from(&self, expected: State, new_state: State) -> Result<(), Error> {
+        self.transition(Some(expected), new_state)
+    }
+
+    /// Move to `new_state`, provided that the current state is `expected` (if given) and that the
+    /// transition is legal.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the transition is not allowed or if the internal lock
+    /// is poisoned.
+    fn transition(&self, expected: Option<State>, new_state: State) -> Result<(), Error> {
         let mut write_guard = self.state.write().map_err(|_| Error::Poison)?;
         let old_state = *write_guard;
 
         // determine whether the transition is valid
-        let valid_transition = match new_state {
-            State::Standby => old_state == State::EStopping || old_state == State::PostIgnite,
-            State::PreIgnite | State::Quit => old_state == State::Standby,
-            State::Ignite => old_state == State::PreIgnite,
-            State::PostIgnite => old_state == State::Ignite,
-            State::EStopping => old_state != State::Quit,
-        };
+        let valid_transition = expected.map_or(true, |expected| expected == old_state)
+            && match new_state {
+                State::Standby => matches!(
+                    old_state,
+                    State::EStopping | State::Purging | State::PostIgnite
+                ),
+                State::PreIgnite | State::Quit => old_state == State::Standby,
+                State::Ignite => old_state == State::PreIgnite,
+                State::PostIgnite => old_state == State::Ignite,
+                State::EStopping => old_state != State::Quit,
+                State::Purging => old_state == State::EStopping,
+            };
 
         if !valid_transition {
             return Err(Error::IllegalTransition {
@@ -102,7 +254,137 @@ impl Guard {
             });
         }
 
+        // the indicator is driven while holding the state lock, so it always agrees with the state
+        if let Some(indicator) = &self.indicator {
+            let result = indicator
+                .lock()
+                .map_err(|_| Error::Poison)?
+                .write(new_state.armed());
+            match result {
+                Err(e) if new_state != State::EStopping => return Err(Error::Indicator(e)),
+                _ => (),
+            }
+        }
+
         *write_guard = new_state;
+        if new_state == State::EStopping {
+            self.n_estops.fetch_add(1, Ordering::AcqRel);
+        }
+        if matches!(old_state, State::EStopping | State::Purging) && new_state == State::Standby {
+            *self.estop_ended.lock().map_err(|_| Error::Poison)? = Some(Instant::now());
+        }
+        // waiters read the state while holding the change lock, so the state lock must be released
+        // before taking it
+        drop(write_guard);
+        drop(self.change_lock.lock().map_err(|_| Error::Poison)?);
+        self.changed.notify_all();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// A GPIO pin whose writes are recorded where a test can still see them after the pin is handed
+    /// to a `Guard`.
+    struct SharedPin(Arc<Mutex<Vec<bool>>>);
+
+    impl GpioPin for SharedPin {
+        fn read(&mut self) -> Result<bool, gpio_cdev::Error> {
+            Ok(*self.0.lock().unwrap().last().unwrap())
+        }
+
+        fn write(&mut self, value: bool) -> Result<(), gpio_cdev::Error> {
+            self.0.lock().unwrap().push(value);
+            Ok(())
+        }
+    }
+
+    #[test]
+    /// Test that the armed-state indicator is active exactly while the controller is igniting or
+    /// emergency stopping, and that illegal transitions leave it alone.
+    fn indicator_tracks_state() {
+        let history = Arc::new(Mutex::new(Vec::new()));
+        let guard =
+            Guard::with_indicator(State::Standby, Box::new(SharedPin(history.clone()))).unwrap();
+
+        for new_state in [
+            State::PreIgnite,
+            State::Ignite,
+            State::PostIgnite,
+            State::Standby,
+            State::EStopping,
+            State::Standby,
+        ] {
+            guard.move_to(new_state).unwrap();
+        }
+        assert!(matches!(
+            guard.move_to(State::Ignite),
+            Err(Error::IllegalTransition { .. })
+        ));
+        guard.move_to(State::Quit).unwrap();
+
+        assert_eq!(
+            *history.lock().unwrap(),
+            [false, true, true, true, false, true, false, false]
+        );
+    }
+
+    #[test]
+    /// Test that a cooldown only begins once an emergency stop ends.
+    fn cooldown_after_estop() {
+        let cooldown = Duration::from_secs(60);
+        let guard = Guard::new(State::Standby);
+        assert_eq!(guard.cooldown_remaining(cooldown).unwrap(), Duration::ZERO);
+
+        guard.move_to(State::PreIgnite).unwrap();
+        guard.move_to(State::EStopping).unwrap();
+        assert_eq!(guard.cooldown_remaining(cooldown).unwrap(), Duration::ZERO);
+
+        guard.move_to(State::Standby).unwrap();
+        let remaining = guard.cooldown_remaining(cooldown).unwrap();
+        assert!(Duration::from_secs(59) < remaining && remaining <= cooldown);
+        assert_eq!(
+            guard.cooldown_remaining(Duration::ZERO).unwrap(),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    /// Test that a wait for the state to change ends as soon as it changes, and otherwise times
+    /// out.
+    fn wait_for_change() {
+        let guard = Guard::new(State::Standby);
+        assert_eq!(
+            guard
+                .wait_while(State::Standby, Duration::from_millis(10))
+                .unwrap(),
+            State::Standby
+        );
+        // a state which has already changed does not wait at all
+        assert_eq!(
+            guard
+                .wait_while(State::Ignite, Duration::from_secs(60))
+                .unwrap(),
+            State::Standby
+        );
+
+        std::thread::scope(|s| {
+            let start = Instant::now();
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                guard.move_to(State::PreIgnite).unwrap();
+            });
+            assert_eq!(
+                guard
+                    .wait_while(State::Standby, Duration::from_secs(60))
+                    .unwrap(),
+                State::PreIgnite
+            );
+            assert!(start.elapsed() < Duration::from_secs(1));
+        });
+    }
+}
@@ -0,0 +1,292 @@
+/*
+  slonk, a rocket engine controller.
+  Copyright (C) 2022 Rice Eclipse.
+
+  slonk is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  slonk is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Forwarding of user log entries to a remote syslog server.
+//!
+//! Entries are formatted as described in RFC 5424 and sent by a dedicated thread, so that a slow or
+//! unreachable server never delays logging.
+//! Entries which cannot be sent are dropped, and the user log is told how many were lost.
+
+use std::{
+    fs::read_to_string,
+    io::{self, Write},
+    net::{SocketAddr, TcpStream, UdpSocket},
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{config::SyslogTransport, console::LogLevel};
+
+/// The number of entries which may wait to be forwarded before new entries are dropped.
+const QUEUE_LENGTH: usize = 1024;
+
+/// The time allowed for connecting to or writing to a TCP syslog server.
+const TCP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The minimum time between attempts to connect to a TCP syslog server.
+const RECONNECT_PERIOD: Duration = Duration::from_secs(5);
+
+/// The syslog facility of every forwarded entry: user-level messages.
+const FACILITY: u8 = 1;
+
+/// A remote syslog server to which log entries are forwarded.
+pub struct Syslog {
+    /// The queue of formatted entries waiting to be sent by the forwarding thread.
+    queue: SyncSender<Vec<u8>>,
+    /// The entries which could not be forwarded since the last note was taken.
+    failures: Arc<Mutex<Failures>>,
+    /// The address of the syslog server.
+    addr: SocketAddr,
+    /// The name of this host, as given in every entry.
+    hostname: String,
+}
+
+#[derive(Default)]
+/// A record of the entries which could not be forwarded.
+struct Failures {
+    /// The number of entries dropped.
+    dropped: usize,
+    /// The most recent reason an entry was dropped.
+    reason: Option<String>,
+}
+
+impl Syslog {
+    /// Start forwarding entries to the syslog server at `addr` over `transport`.
+    ///
+    /// No connection is made until the first entry is forwarded, so a server which is not yet
+    /// running is not an error.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the forwarding thread cannot be started.
+    pub fn new(addr: SocketAddr, transport: SyslogTransport) -> io::Result<Syslog> {
+        let (queue, entries) = sync_channel(QUEUE_LENGTH);
+        let failures = Arc::new(Mutex::new(Failures::default()));
+        let thread_failures = Arc::clone(&failures);
+        thread::Builder::new()
+            .name("syslog".into())
+            .spawn(move || forward(addr, transport, &entries, &thread_failures))?;
+
+        let hostname = read_to_string("/proc/sys/kernel/hostname")
+            .map(|name| name.trim().to_string())
+            .ok()
+            .filter(|name| !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic()))
+            .unwrap_or_else(|| "-".into());
+
+        Ok(Syslog {
+            queue,
+            failures,
+            addr,
+            hostname,
+        })
+    }
+
+    /// Queue an entry of level `level`, logged at `time`, to be forwarded.
+    /// Never blocks: if the queue is full, the entry is dropped.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the internal lock is poisoned.
+    pub fn forward(&self, level: LogLevel, time: SystemTime, string: &str) {
+        let entry = format!(
+            "<{}>1 {} {} slonk {} - - {string}",
+            FACILITY * 8 + severity(level),
+            timestamp(time),
+            self.hostname,
+            std::process::id()
+        );
+        match self.queue.try_send(entry.into_bytes()) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => record_failure(&self.failures, "the queue is full"),
+            Err(TrySendError::Disconnected(_)) => {
+                record_failure(&self.failures, "the forwarding thread has stopped");
+            }
+        }
+    }
+
+    #[must_use]
+    /// Get a note describing the entries which could not be forwarded since the last note was
+    /// taken, if any were dropped.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the internal lock is poisoned.
+    pub fn take_note(&self) -> Option<String> {
+        let mut failures = self.failures.lock().unwrap();
+        if failures.dropped == 0 {
+            return None;
+        }
+        let note = format!(
+            "{} log entries could not be forwarded to syslog at {}: {}",
+            failures.dropped,
+            self.addr,
+            failures.reason.as_deref().unwrap_or("unknown error")
+        );
+        *failures = Failures::default();
+        Some(note)
+    }
+}
+
+/// Record that an entry was dropped because of `reason`.
+///
+/// # Panics
+///
+/// This function will panic if the internal lock is poisoned.
+fn record_failure(failures: &Mutex<Failures>, reason: &str) {
+    let mut failures = failures.lock().unwrap();
+    failures.dropped += 1;
+    failures.reason = Some(reason.to_string());
+}
+
+/// Send every entry received from `entries` to the syslog server at `addr` until the sending end
+/// of `entries` is dropped.
+///
+/// Over TCP, entries are framed by octet counting, as described in RFC 6587, and a lost connection
+/// is re-established at most once per `RECONNECT_PERIOD`.
+/// Entries which cannot be sent are recorded in `failures`.
+fn forward(
+    addr: SocketAddr,
+    transport: SyslogTransport,
+    entries: &Receiver<Vec<u8>>,
+    failures: &Mutex<Failures>,
+) {
+    match transport {
+        SyslogTransport::Udp => {
+            let local_addr: SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0; 16], 0).into()
+            };
+            let socket = UdpSocket::bind(local_addr);
+            for entry in entries {
+                let result = match &socket {
+                    Ok(socket) => socket
+                        .send_to(&entry, addr)
+                        .map(|_| ())
+                        .map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                if let Err(reason) = result {
+                    record_failure(failures, &reason);
+                }
+            }
+        }
+        SyslogTransport::Tcp => {
+            let mut stream: Option<TcpStream> = None;
+            let mut last_attempt: Option<Instant> = None;
+            for entry in entries {
+                if stream.is_none()
+                    && last_attempt.map_or(true, |t| t.elapsed() >= RECONNECT_PERIOD)
+                {
+                    last_attempt = Some(Instant::now());
+                    match TcpStream::connect_timeout(&addr, TCP_TIMEOUT)
+                        .and_then(|s| s.set_write_timeout(Some(TCP_TIMEOUT)).map(|()| s))
+                    {
+                        Ok(s) => stream = Some(s),
+                        Err(e) => {
+                            record_failure(failures, &e.to_string());
+                            continue;
+                        }
+                    }
+                }
+                let Some(s) = &mut stream else {
+                    // still waiting to reconnect, so the reason for the failure is unchanged
+                    failures.lock().unwrap().dropped += 1;
+                    continue;
+                };
+                let mut frame = format!("{} ", entry.len()).into_bytes();
+                frame.extend_from_slice(&entry);
+                if let Err(e) = s.write_all(&frame) {
+                    record_failure(failures, &e.to_string());
+                    stream = None;
+                }
+            }
+        }
+    }
+}
+
+/// Get the syslog severity corresponding to `level`.
+fn severity(level: LogLevel) -> u8 {
+    match level {
+        LogLevel::Debug => 7,
+        LogLevel::Info => 6,
+        LogLevel::Warn => 4,
+        LogLevel::Critical => 2,
+    }
+}
+
+/// Format `time` as an RFC 3339 timestamp in UTC, with microsecond precision.
+///
+/// # Panics
+///
+/// This function will panic if `time` is before the UNIX epoch.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // convert days since the epoch into a civil date, treating March as the first month of the year
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that timestamps are formatted as RFC 3339 dates in UTC.
+    fn rfc3339_timestamp() {
+        let at = |secs, micros| {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_micros(micros)
+        };
+        assert_eq!(timestamp(at(0, 0)), "1970-01-01T00:00:00.000000Z");
+        assert_eq!(
+            timestamp(at(951_827_696, 12)),
+            "2000-02-29T12:34:56.000012Z"
+        );
+        assert_eq!(
+            timestamp(at(1_798_761_599, 999_999)),
+            "2026-12-31T23:59:59.999999Z"
+        );
+    }
+}